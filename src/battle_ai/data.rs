@@ -1,6 +1,9 @@
 use core::cmp::max;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io;
+use std::ops::Range;
 use std::intrinsics::transmute;
-use rand::rngs::StdRng;
 use rand::Rng;
 use crate::battle_ai::move_::MoveCategory;
 
@@ -8,7 +11,7 @@ pub static mut GAME_VERSION: GameVersion = GameVersion::SS;
 
 fn game_version() -> &'static GameVersion { unsafe { &GAME_VERSION } }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 #[repr(u8)]
 pub enum FieldPosition {
     Min,
@@ -39,7 +42,45 @@ impl FieldPosition {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+/// One of the two agents in a battle. The minimizer's Pokemon have ids 0-5 and the maximizer's have ids 6-11.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Side {
+    Min,
+    Max
+}
+
+impl Side {
+    /// The side that the Pokemon with the given id is on.
+    pub const fn of(pokemon_id: u8) -> Side {
+        if pokemon_id < 6 { Side::Min } else { Side::Max }
+    }
+
+    /// Ids of the side's Pokemon.
+    pub const fn id_range(&self) -> Range<u8> {
+        match self {
+            Side::Min => 0..6,
+            Side::Max => 6..12
+        }
+    }
+
+    /// The field position where the side's Pokemon battle.
+    pub const fn field_position(&self) -> FieldPosition {
+        match self {
+            Side::Min => FieldPosition::Min,
+            Side::Max => FieldPosition::Max
+        }
+    }
+
+    /// The other agent's side.
+    pub const fn opponent(&self) -> Side {
+        match self {
+            Side::Min => Side::Max,
+            Side::Max => Side::Min
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Deserialize, Serialize)]
 #[repr(u8)]
 pub enum Type {
     None,
@@ -64,6 +105,15 @@ pub enum Type {
 }
 
 impl Type {
+    /// Every real type present in the current game version.
+    pub fn all() -> &'static [Type] {
+        const TYPES: [Type; 18] = [
+            Type::Normal, Type::Fighting, Type::Flying, Type::Poison, Type::Ground, Type::Rock, Type::Bug, Type::Ghost, Type::Steel,
+            Type::Fire, Type::Water, Type::Grass, Type::Electric, Type::Psychic, Type::Ice, Type::Dragon, Type::Dark, Type::Fairy
+        ];
+        if game_version().gen() <= 5 { &TYPES[..17] } else { &TYPES }
+    }
+
     pub const fn category(&self) -> MoveCategory {
         match self {
             Type::None | Type::Normal | Type::Fighting | Type::Flying | Type::Poison | Type::Ground | Type::Rock | Type::Bug | Type::Ghost | Type::Steel => MoveCategory::Physical,
@@ -75,6 +125,8 @@ impl Type {
         self.effectiveness_single_type(defending_type1) * self.effectiveness_single_type(defending_type2)
     }
 
+    /// Each row is an attacking type; columns are defending types in declaration order. Steel resisted Ghost and
+    /// Dark before gen 6, which is handled only in those two rows.
     fn effectiveness_single_type(&self, defending_type: Type) -> f64 {
         unsafe {
             match self {
@@ -106,7 +158,7 @@ impl Default for Type {
     fn default() -> Self { Type::None }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 #[repr(u8)]
 pub enum Terrain {
     Normal,
@@ -120,7 +172,7 @@ impl Default for Terrain {
     fn default() -> Self { Terrain::Normal }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 #[repr(u8)]
 pub enum Weather {
     None,
@@ -190,11 +242,99 @@ impl Ability {
     }
 }
 
-const ABILITIES: [Ability; 2] = [
+const ABILITIES: [Ability; 20] = [
     Ability { name: "Chlorophyll" },
-    Ability { name: "Overgrow" }
+    Ability { name: "Overgrow" },
+    Ability { name: "Iron Barbs" },
+    Ability { name: "Rough Skin" },
+    Ability { name: "Flame Body" },
+    Ability { name: "Poison Point" },
+    Ability { name: "Static" },
+    Ability { name: "Filter" },
+    Ability { name: "Solid Rock" },
+    Ability { name: "Thick Fat" },
+    Ability { name: "Tinted Lens" },
+    Ability { name: "Arena Trap" },
+    Ability { name: "Levitate" },
+    Ability { name: "Mold Breaker" },
+    Ability { name: "No Guard" },
+    Ability { name: "Compound Eyes" },
+    Ability { name: "Hustle" },
+    // Stands in for the abilities taken away by the pure_stats battle rule; no species has it
+    Ability { name: "No Ability" },
+    Ability { name: "Clear Body" },
+    Ability { name: "Contrary" }
 ];
 
+type ItemID = u8;
+
+#[derive(Deserialize, Serialize)]
+struct Item {
+    name: &'static str
+}
+
+impl Item {
+    fn id_by_name(name: &str) -> Result<ItemID, String> {
+        for (item_id, item) in ITEMS.iter().enumerate() {
+            if item.name.eq_ignore_ascii_case(name) {
+                return Ok(item_id as ItemID);
+            }
+        }
+        Err(format!("invalid item '{}'", name))
+    }
+
+    const fn name(item: ItemID) -> &'static str {
+        ITEMS[item as usize].name
+    }
+
+    /// Holding no item is one of the possible choices.
+    fn random_item<R: Rng>(rng: &mut R) -> Option<ItemID> {
+        let i = rng.gen_range(0, ITEMS.len() + 1);
+        if i < ITEMS.len() { Some(i as ItemID) } else { None }
+    }
+
+    fn count() -> usize {
+        ITEMS.len()
+    }
+}
+
+const ITEMS: [Item; 5] = [
+    Item { name: "Rocky Helmet" },
+    Item { name: "Quick Claw" },
+    Item { name: "Choice Band" },
+    Item { name: "Choice Scarf" },
+    Item { name: "Choice Specs" }
+];
+
+#[derive(Debug)]
+pub enum DataLoadError {
+    FileNotFound { path: String },
+    /// The file exists but could not be read or its contents are invalid.
+    Parse { path: String, message: String }
+}
+
+impl Display for DataLoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DataLoadError::FileNotFound { path } => write!(f, "could not find {}", path),
+            DataLoadError::Parse { path, message } => write!(f, "error in {}: {}", path, message)
+        }
+    }
+}
+
+impl std::error::Error for DataLoadError {}
+
+/// Reads a file from the resource directory of the current game version.
+fn read_resource_file(file_name: &str) -> Result<(String, String), DataLoadError> {
+    let path = format!("resources/{}/{}", game_version().name(), file_name);
+    match fs::read_to_string(path.as_str()) {
+        Ok(contents) => Ok((path, contents)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Err(DataLoadError::FileNotFound { path }),
+        Err(err) => Err(DataLoadError::Parse { message: err.to_string(), path })
+    }
+}
+
+/// Generations 1 and 2 are not supported.
 #[derive(Debug, Eq, PartialEq)]
 pub enum GameVersion {
     RS,
@@ -275,7 +415,7 @@ impl Gender {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Deserialize)]
 #[repr(u8)]
 pub enum MajorStatusAilment {
     Okay,
@@ -325,6 +465,33 @@ impl Default for MajorStatusAilment {
     fn default() -> Self { MajorStatusAilment::Okay }
 }
 
+/// The semi-invulnerable state a Pokemon is in while charging Fly, Dig or Dive.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize)]
+pub enum InvulnKind {
+    Airborne,
+    Underground,
+    Underwater
+}
+
+impl InvulnKind {
+    pub const fn display_text_on_charge(&self) -> &'static str {
+        match self {
+            InvulnKind::Airborne => " flew up high!",
+            InvulnKind::Underground => " burrowed its way under the ground!",
+            InvulnKind::Underwater => " hid underwater!"
+        }
+    }
+
+    /// Names of the moves that can still hit a Pokemon in this state.
+    pub const fn hit_by(&self) -> &'static [&'static str] {
+        match self {
+            InvulnKind::Airborne => &["Gust", "Hurricane", "Sky Uppercut", "Smack Down", "Thunder", "Twister"],
+            InvulnKind::Underground => &["Earthquake", "Fissure", "Magnitude"],
+            InvulnKind::Underwater => &["Surf", "Whirlpool"]
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Ord, PartialOrd, Deserialize, Serialize)]
 #[repr(u8)]
 pub enum Nature {
@@ -356,7 +523,17 @@ pub enum Nature {
 }
 
 impl Nature {
-    fn random_nature(rng: &mut StdRng) -> Nature {
+    pub fn all() -> &'static [Nature] {
+        const NATURES: [Nature; 25] = [
+            Nature::Adamant, Nature::Bashful, Nature::Bold, Nature::Brave, Nature::Calm, Nature::Careful, Nature::Docile,
+            Nature::Gentle, Nature::Hardy, Nature::Hasty, Nature::Impish, Nature::Jolly, Nature::Lax, Nature::Lonely,
+            Nature::Mild, Nature::Modest, Nature::Naive, Nature::Naughty, Nature::Quiet, Nature::Quirky, Nature::Rash,
+            Nature::Relaxed, Nature::Sassy, Nature::Serious, Nature::Timid
+        ];
+        &NATURES
+    }
+
+    fn random_nature<R: Rng>(rng: &mut R) -> Nature {
         unsafe {
             transmute::<u8, Nature>(rng.gen_range(0, 25))
         }
@@ -392,7 +569,7 @@ impl Nature {
     }
 }
 
-#[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq, Deserialize)]
+#[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq, Deserialize, Serialize)]
 #[repr(u8)]
 pub enum StatIndex {
     Hp,
@@ -419,6 +596,20 @@ impl StatIndex {
         }
     }
 
+    /// Parses the short stat names used in team files: "hp", "atk", "def", "spa", "spd" and "spe". Note that
+    /// "spd" is Special Defense, not Speed.
+    fn by_short_name(name: &str) -> Result<StatIndex, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "hp" => Ok(StatIndex::Hp),
+            "atk" => Ok(StatIndex::Atk),
+            "def" => Ok(StatIndex::Def),
+            "spa" => Ok(StatIndex::SpAtk),
+            "spd" => Ok(StatIndex::SpDef),
+            "spe" => Ok(StatIndex::Spd),
+            _ => Err(format!("invalid stat '{}'", name))
+        }
+    }
+
     const fn as_usize(&self) -> usize {
         match self {
             StatIndex::Hp => 0,
@@ -432,3 +623,69 @@ impl StatIndex {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_side_has_the_upper_ids_and_flips_to_min() {
+        assert_eq!(Side::Max.id_range(), 6..12);
+        assert_eq!(Side::Min.id_range(), 0..6);
+        assert_eq!(Side::Max.opponent(), Side::Min);
+        assert_eq!(Side::Min.opponent(), Side::Max);
+    }
+
+    #[test]
+    fn type_chart_matches_canonical_matchups() {
+        let _data = crate::load_test_data();
+        let matchups = [
+            (Type::Fighting, Type::Ghost, 0.0),
+            (Type::Water, Type::Fire, 2.0),
+            (Type::Electric, Type::Ground, 0.0),
+            (Type::Fairy, Type::Dragon, 2.0),
+            (Type::Dragon, Type::Fairy, 0.0),
+            (Type::Ground, Type::Flying, 0.0),
+            (Type::Normal, Type::Ghost, 0.0),
+            (Type::Ghost, Type::Normal, 0.0),
+            (Type::Poison, Type::Steel, 0.0),
+            (Type::Psychic, Type::Dark, 0.0),
+            (Type::Fire, Type::Water, 0.5),
+            (Type::Grass, Type::Water, 2.0),
+            (Type::Normal, Type::Normal, 1.0),
+            (Type::None, Type::Ghost, 1.0)
+        ];
+        for (attacking, defending, expected) in matchups {
+            assert_eq!(attacking.effectiveness_single_type(defending), expected, "{:?} vs {:?}", attacking, defending);
+        }
+    }
+
+    #[test]
+    fn dual_types_multiply() {
+        let _data = crate::load_test_data();
+        assert_eq!(Type::Ice.effectiveness(Type::Dragon, Type::Flying), 4.0);
+        assert_eq!(Type::Electric.effectiveness(Type::Water, Type::Ground), 0.0);
+        assert_eq!(Type::Fire.effectiveness(Type::Grass, Type::None), 2.0);
+    }
+
+    #[test]
+    fn steel_resists_ghost_and_dark_only_before_gen_6() {
+        let _data = crate::load_test_data();
+        let gen_6 = (Type::Ghost.effectiveness_single_type(Type::Steel), Type::Dark.effectiveness_single_type(Type::Steel), Type::all().len());
+        unsafe { GAME_VERSION = GameVersion::BW; }
+        let gen_5 = (Type::Ghost.effectiveness_single_type(Type::Steel), Type::Dark.effectiveness_single_type(Type::Steel), Type::all().len());
+        unsafe { GAME_VERSION = GameVersion::XY; }
+
+        assert_eq!(gen_6, (1.0, 1.0, 18));
+        assert_eq!(gen_5, (0.5, 0.5, 17));
+    }
+
+    #[test]
+    fn missing_resource_file_is_not_found() {
+        let _data = crate::load_test_data();
+        match read_resource_file("missing.json") {
+            Err(DataLoadError::FileNotFound { path }) => assert_eq!(path, "resources/x_y/missing.json"),
+            result => panic!("expected FileNotFound, got {:?}", result)
+        }
+    }
+}
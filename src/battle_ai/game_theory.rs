@@ -11,6 +11,22 @@ pub struct ZeroSumNashEq {
     pub expected_payoff: f64
 }
 
+impl ZeroSumNashEq {
+    /// Shannon entropy in bits of the maximizing and minimizing players' strategies, in that order. Near zero means
+    /// the player is all but forced into one action; the highest possible value, log2 of the number of actions,
+    /// means every action is equally good.
+    pub fn entropy(&self) -> (f64, f64) {
+        (strategy_entropy(&self.max_player_strategy), strategy_entropy(&self.min_player_strategy))
+    }
+}
+
+fn strategy_entropy(strategy: &[f64]) -> f64 {
+    -strategy.iter()
+        .filter(|p| **p > 0.0)
+        .map(|p| p * p.log2())
+        .sum::<f64>()
+}
+
 /// Calculates the Nash equilibrium of a zero-sum payoff matrix.
 /// The algorithm follows [Game Theory](docs/Game_Theory.pdf), section 4.5.
 /// It requires that all elements be positive, so supply `added_constant` to ensure this; it will
@@ -189,3 +205,32 @@ impl Debug for Matrix {
         write!(f, "{}", formatted)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix(rows: &[[f64; 2]; 2]) -> Matrix {
+        let mut matrix = Matrix::of(0.0, 2, 2);
+        for (i, row) in rows.iter().enumerate() {
+            for (j, entry) in row.iter().enumerate() {
+                *matrix.get_mut(i, j) = *entry;
+            }
+        }
+        matrix
+    }
+
+    #[test]
+    fn matching_pennies_has_maximum_entropy() {
+        let nash_eq = calc_nash_eq(&matrix(&[[1.0, -1.0], [-1.0, 1.0]]), &[false, false], &[false, false], 2.0);
+        let (max_entropy, min_entropy) = nash_eq.entropy();
+        assert!((max_entropy - 1.0).abs() < 1e-9, "{}", max_entropy);
+        assert!((min_entropy - 1.0).abs() < 1e-9, "{}", min_entropy);
+    }
+
+    #[test]
+    fn dominated_action_leaves_zero_entropy() {
+        let nash_eq = calc_nash_eq(&matrix(&[[1.0, 0.5], [-1.0, -0.5]]), &[false, true], &[false, false], 2.0);
+        assert_eq!(nash_eq.entropy().0, 0.0);
+    }
+}
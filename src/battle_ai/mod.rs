@@ -1,7 +1,13 @@
+//! The battle engine. This is the crate's only battle implementation and the canonical one: the analysis in the
+//! crate root and `combinatorial_optim` plays battles through it and nothing else, so new battle mechanics belong
+//! here.
+
 mod data;
-mod game_theory;
+pub mod game_theory;
 mod move_;
 pub mod move_effects;
 mod search_tree;
 mod species;
 mod state;
+
+pub use crate::{evaluate_matchup_batch, MatchupStats};
@@ -1,11 +1,12 @@
 use serde::Deserialize;
 use std::fmt::Debug;
-use std::fs;
+use std::cmp::min;
+use std::collections::HashMap;
+use std::mem;
 use pokemon_battle_analysis_v5::battle_ai::move_effects::{MoveEffect, MoveAccuracy};
-use rand::rngs::StdRng;
 use rand::Rng;
 use crate::battle_ai::state::State;
-use crate::battle_ai::data::{Type, StatIndex, FieldPosition};
+use crate::battle_ai::data::{Type, StatIndex, FieldPosition, DataLoadError, Ability};
 use crate::battle_ai::move_effects::MoveEffect;
 
 #[derive(Debug, Deserialize)]
@@ -17,25 +18,46 @@ pub enum MoveAccuracy {
 }
 
 impl MoveAccuracy {
-    fn do_accuracy_check(&self, state: &mut State, user_id: u8, target_id: u8, rng: &mut StdRng) -> bool {
+    fn do_accuracy_check<R: Rng>(&self, move_: MoveID, state: &mut State, user_id: u8, target_id: u8, rng: &mut R) -> bool {
+        // No Guard on either side makes every move hit, even semi-invulnerable targets
+        let no_guard = Ability::id_by_name("No Guard").unwrap();
+        if state.pokemon_by_id(user_id).ability() == no_guard || state.pokemon_by_id(target_id).ability() == no_guard {
+            return true;
+        }
+
+        // Semi-invulnerable Pokemon can only be hit by a few specific moves, regardless of accuracy
+        if let Some(invuln_kind) = state.pokemon_by_id(target_id).semi_invulnerable {
+            if target_id != user_id && !invuln_kind.hit_by().contains(&Move::name(move_)) {
+                return false;
+            }
+        }
+
         match self {
             MoveAccuracy::Ignore => true,
-            MoveAccuracy::Standard(accuracy) => MoveAccuracy::std_accuracy_check(state, *accuracy, user_id, target_id, rng),
+            MoveAccuracy::Standard(accuracy) => MoveAccuracy::std_accuracy_check(move_, state, *accuracy, user_id, target_id, rng),
             MoveAccuracy::Toxic => {
                 (game_version().gen() >= 6 && state.pokemon_by_id(user_id).is_type(Type::Poison))
-                    || MoveAccuracy::std_accuracy_check(state, if game_version().gen() <= 4 { 85 } else { 90 }, user_id, target_id, rng)
+                    || MoveAccuracy::std_accuracy_check(move_, state, if game_version().gen() <= 4 { 85 } else { 90 }, user_id, target_id, rng)
             }
         }
     }
 
-    fn std_accuracy_check(state: &mut State, accuracy: u8, user_id: u8, target_id: u8, rng: &mut StdRng) -> bool {
+    fn std_accuracy_check<R: Rng>(move_: MoveID, state: &mut State, accuracy: u8, user_id: u8, target_id: u8, rng: &mut R) -> bool {
         let user = state.pokemon_by_id(user_id);
         let target = state.pokemon_by_id(target_id);
-        rng.gen_range::<u8, u8, u8>(0, 100) < (accuracy as f64 * accuracy_stat_stage_multiplier(num::clamp(user.stat_stage(StatIndex::Acc) - target.stat_stage(StatIndex::Eva), -6, 6))) as u8
+        let target_evasion = if target.is_identified { min(target.stat_stage(StatIndex::Eva), 0) } else { target.stat_stage(StatIndex::Eva) };
+        let ability_modifier = if user.ability() == Ability::id_by_name("Compound Eyes").unwrap() {
+            1.3
+        } else if user.ability() == Ability::id_by_name("Hustle").unwrap() && Move::category(move_) == MoveCategory::Physical {
+            0.8
+        } else {
+            1.0
+        };
+        rng.gen_range::<u8, u8, u8>(0, 100) < (accuracy as f64 * ability_modifier * accuracy_stat_stage_multiplier(num::clamp(user.stat_stage(StatIndex::Acc) - target_evasion, -6, 6))) as u8
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Deserialize)]
 pub enum MoveCategory {
     Physical,
     Special,
@@ -107,7 +129,14 @@ pub struct Move {
     max_pp: u8,
     priority_stage: i8,
     sound_based: bool,
-    effects: Vec<MoveEffect>
+    /// Moves that don't list this attribute are assumed to not make contact.
+    #[serde(default)]
+    contact: bool,
+    effects: Vec<MoveEffect>,
+    /// Replaces the display text of the move's effects, keyed by effect name (e.g. "LeechSeed"). "{user}" and
+    /// "{target}" are replaced with the user's and target's species names.
+    #[serde(default)]
+    effect_display_text: HashMap<String, String>
 }
 
 impl Move {
@@ -138,6 +167,15 @@ impl Move {
         Move::by_id(move_).name.as_str()
     }
 
+    pub fn type_(move_: MoveID) -> Type {
+        Move::by_id(move_).type_
+    }
+
+    /// Power of the move's first damaging effect; see `MoveEffect::power`.
+    pub fn base_power(move_: MoveID) -> Option<u8> {
+        Move::effects(move_).iter().find(|effect| effect.is_damaging()).and_then(MoveEffect::power)
+    }
+
     pub fn category(move_: MoveID) -> MoveCategory {
         let move_ = Move::by_id(move_);
         let category = move_.category;
@@ -163,23 +201,287 @@ impl Move {
         Move::by_id(move_).priority_stage
     }
 
+    pub fn sound_based(move_: MoveID) -> bool {
+        Move::by_id(move_).sound_based
+    }
+
+    pub fn makes_contact(move_: MoveID) -> bool {
+        Move::by_id(move_).contact
+    }
+
     pub fn effects(move_: MoveID) -> &'static [MoveEffect] {
         &Move::by_id(move_).effects
     }
+
+    pub fn effect_display_text(move_: MoveID, effect_name: &str) -> Option<&'static str> {
+        Move::by_id(move_).effect_display_text.get(effect_name).map(String::as_str)
+    }
+
+    /// Splits a move's effects into primary effects, which are applied when the move hits, and secondary effects,
+    /// which are only applied if the primary effects succeeded and the user and target are both still standing. The
+    /// damage effects of a damaging move are its primary effects; every effect of a non-damaging move is primary.
+    pub fn primary_and_secondary_effects(move_: MoveID) -> (&'static [MoveEffect], &'static [MoveEffect]) {
+        let effects = Move::effects(move_);
+        match effects.iter().rposition(|effect| effect.is_damaging()) {
+            Some(last_damaging) => effects.split_at(last_damaging + 1),
+            None => (effects, &[])
+        }
+    }
 }
 
 static mut MOVES: Vec<Move> = Vec::new();
 
 /// # Safety
 /// Should be called after the game version has been set from the program input and before the species are initialized.
-pub fn initialize_moves() {
-    let mut path = String::from("resources/");
-    path.push_str(game_version().name());
-    path.push_str("/moves.json");
-    let moves_json = fs::read_to_string(path.as_str())
-        .unwrap_or_else(|_| panic!("Failed to read {}.", path));
+pub fn initialize_moves() -> Result<(), DataLoadError> {
+    let (path, moves_json) = read_resource_file("moves.json")?;
+    let moves = parse_moves(path, moves_json.as_str())?;
     unsafe {
-        MOVES = serde_json::from_str(moves_json.as_str())
-            .unwrap_or_else(|err| panic!("Error parsing moves.json: {}", err));
+        MOVES = moves;
+    }
+    Ok(())
+}
+
+/// Parses the contents of the moves file at `path`.
+fn parse_moves(path: String, moves_json: &str) -> Result<Vec<Move>, DataLoadError> {
+    let moves: Vec<Move> = serde_json::from_str(moves_json)
+        .map_err(|err| DataLoadError::Parse { path: path.clone(), message: err.to_string() })?;
+    // Moves that intentionally do nothing should use the Nothing effect instead.
+    if let Some(move_) = moves.iter().find(|move_| move_.effects.is_empty()) {
+        return Err(DataLoadError::Parse { message: format!("{} has no effects", move_.name), path });
+    }
+    Ok(moves)
+}
+
+/// Exchanges the loaded moves with `moves`. Used to switch between game versions; see `GameData`.
+pub fn swap_moves(moves: &mut Vec<Move>) {
+    unsafe {
+        mem::swap(&mut MOVES, moves);
+    }
+}
+
+/// Returns a description of every problem found in the loaded moves.
+pub fn validate_moves() -> Vec<String> {
+    let mut problems = Vec::new();
+    unsafe {
+        for move_ in MOVES.iter() {
+            if move_.priority_stage < -7 || move_.priority_stage > 5 {
+                problems.push(format!("{}: priority stage {} is out of range", move_.name, move_.priority_stage));
+            }
+            if move_.max_pp == 0 {
+                problems.push(format!("{}: max PP is 0", move_.name));
+            }
+            if let MoveAccuracy::Standard(accuracy) = move_.accuracy {
+                if accuracy > 100 {
+                    problems.push(format!("{}: accuracy of {} is greater than 100", move_.name, accuracy));
+                }
+            }
+            for effect in &move_.effects {
+                if let Err(problem) = effect.validate() {
+                    problems.push(format!("{}: {}", move_.name, problem));
+                }
+                if !effect.available_in(game_version().gen()) {
+                    problems.push(format!("{}: {} does not exist in gen {} and will do nothing", move_.name, effect.name(), game_version().gen()));
+                }
+            }
+            for effect_name in move_.effect_display_text.keys() {
+                if !move_.effects.iter().any(|effect| effect.name() == *effect_name) {
+                    problems.push(format!("{}: has display text for '{}', which is not one of its effects", move_.name, effect_name));
+                }
+            }
+            let num_leading_damaging = move_.effects.iter().take_while(|effect| effect.is_damaging()).count();
+            if move_.effects[num_leading_damaging..].iter().any(|effect| effect.is_damaging()) {
+                problems.push(format!("{}: damage effects must come before all other effects", move_.name));
+            }
+        }
+    }
+    if Move::id_by_name("Struggle").is_err() {
+        problems.push(String::from("Struggle is missing"));
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battle_ai::data::InvulnKind;
+    use crate::battle_ai::state::Action;
+    use crate::LogLevel;
+    use rand::rngs::mock::StepRng;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn contact_comes_from_the_move_data() {
+        let _data = crate::load_test_data();
+        assert!(Move::makes_contact(Move::id_by_name("Tackle").unwrap()));
+        assert!(!Move::makes_contact(Move::id_by_name("Sludge Bomb").unwrap()));
+    }
+
+    #[test]
+    fn foresight_negates_evasion_boosts() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.pokemon_by_id_mut(0).set_stat_stages([0, 0, 0, 0, 0, 0, 0, 6]).unwrap();
+        let tackle = Move::id_by_name("Tackle").unwrap();
+        let num_hits = |state: &mut State| (0..100)
+            .filter(|&seed| MoveAccuracy::Standard(100).do_accuracy_check(tackle, state, 6, 0, &mut StdRng::seed_from_u64(seed)))
+            .count();
+
+        assert!(num_hits(&mut state) < 100);
+        state.pokemon_by_id_mut(0).is_identified = true;
+        assert_eq!(num_hits(&mut state), 100);
+    }
+
+    #[test]
+    fn move_without_effects_is_rejected() {
+        let moves_json = r#"[{"name": "Splash", "type": "Normal", "category": "Status", "accuracy": "Ignore", "targeting": "User",
+            "max_pp": 40, "priority_stage": 0, "sound_based": false, "effects": []}]"#;
+
+        match parse_moves(String::from("moves.json"), moves_json) {
+            Err(DataLoadError::Parse { message, .. }) => assert_eq!(message, "Splash has no effects"),
+            result => panic!("expected a parse error, got {:?}", result)
+        }
+    }
+
+    #[test]
+    fn validation_reports_each_broken_attribute() {
+        let _data = crate::load_test_data();
+        let moves_json = r#"[{"name": "Broken", "type": "Normal", "category": "Physical", "accuracy": {"Standard": 120},
+            "targeting": "SingleAdjacentPokemon", "max_pp": 0, "priority_stage": 9, "sound_based": false,
+            "effects": [{"StdDamage": ["Normal", 40, 0]}], "effect_display_text": {"Flinch": "{target} flinched!"}}]"#;
+        let mut moves = parse_moves(String::from("moves.json"), moves_json).unwrap();
+
+        swap_moves(&mut moves);
+        let problems = validate_moves();
+        swap_moves(&mut moves);
+        assert_eq!(problems, vec![
+            "Broken: priority stage 9 is out of range",
+            "Broken: max PP is 0",
+            "Broken: accuracy of 120 is greater than 100",
+            "Broken: has display text for 'Flinch', which is not one of its effects",
+            "Struggle is missing"
+        ]);
+    }
+
+    #[test]
+    fn only_effects_after_the_damage_are_secondary() {
+        let _data = crate::load_test_data();
+        let (primary, secondary) = Move::primary_and_secondary_effects(Move::id_by_name("Sludge Bomb").unwrap());
+        assert!(matches!(primary, [MoveEffect::StdDamage(..)]));
+        assert!(matches!(secondary, [MoveEffect::Poison(false, 30)]));
+
+        let (primary, secondary) = Move::primary_and_secondary_effects(Move::id_by_name("Toxic").unwrap());
+        assert_eq!(primary.len(), 1);
+        assert!(secondary.is_empty());
+    }
+
+    #[test]
+    fn flying_pokemon_dodge_all_but_the_moves_that_reach_the_sky() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.pokemon_by_id_mut(0).semi_invulnerable = Some(InvulnKind::Airborne);
+        let moves_json = r#"[
+            {"name": "Tackle", "type": "Normal", "category": "Physical", "accuracy": {"Standard": 100}, "targeting": "SingleAdjacentPokemon",
+                "max_pp": 35, "priority_stage": 0, "sound_based": false, "effects": [{"StdDamage": ["Normal", 50, 0]}]},
+            {"name": "Thunder", "type": "Electric", "category": "Special", "accuracy": {"Standard": 70}, "targeting": "SingleAdjacentPokemon",
+                "max_pp": 10, "priority_stage": 0, "sound_based": false, "effects": [{"StdDamage": ["Electric", 110, 0]}]}]"#;
+        let mut moves = parse_moves(String::from("moves.json"), moves_json).unwrap();
+
+        swap_moves(&mut moves);
+        let hits = |state: &mut State, name| {
+            let move_ = Move::id_by_name(name).unwrap();
+            Move::accuracy(move_).do_accuracy_check(move_, state, 6, 0, &mut StepRng::new(0, 0))
+        };
+        let (tackle_hits, thunder_hits) = (hits(&mut state, "Tackle"), hits(&mut state, "Thunder"));
+        swap_moves(&mut moves);
+        assert!(!tackle_hits);
+        assert!(thunder_hits);
+    }
+
+    #[test]
+    fn effect_text_from_the_move_data_replaces_the_default() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.rules.log_level = LogLevel::Verbose;
+        state.pokemon_by_id_mut(0).first_type = Type::Normal;
+        state.pokemon_by_id_mut(0).second_type = Type::None;
+        let moves_json = r#"[{"name": "Leech Seed", "type": "Grass", "category": "Status", "accuracy": {"Standard": 90},
+            "targeting": "SingleAdjacentOpponent", "max_pp": 10, "priority_stage": 0, "sound_based": false, "effects": ["LeechSeed"],
+            "effect_display_text": {"LeechSeed": "{user} sowed a seed on {target}!"}}]"#;
+        let mut moves = parse_moves(String::from("moves.json"), moves_json).unwrap();
+
+        swap_moves(&mut moves);
+        let leech_seed = Action::Move { user_id: 6, move_: Move::id_by_name("Leech Seed").unwrap(), move_index: None, target_positions: vec![FieldPosition::Min] };
+        leech_seed.perform(&mut state, &[], &mut StepRng::new(0, 0));
+        swap_moves(&mut moves);
+        assert!(state.display_text.iter().any(|text| text == "Bulbasaur sowed a seed on Bulbasaur!"), "{:?}", state.display_text);
+        assert!(!state.display_text.iter().any(|text| text.starts_with("A seed was planted")), "{:?}", state.display_text);
+    }
+
+    #[test]
+    fn no_guard_on_either_side_makes_every_move_hit() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let moves_json = r#"[{"name": "Thunder", "type": "Electric", "category": "Special", "accuracy": {"Standard": 70},
+            "targeting": "SingleAdjacentPokemon", "max_pp": 10, "priority_stage": 0, "sound_based": false, "effects": [{"StdDamage": ["Electric", 110, 0]}]}]"#;
+        let mut moves = parse_moves(String::from("moves.json"), moves_json).unwrap();
+
+        swap_moves(&mut moves);
+        let thunder = Move::id_by_name("Thunder").unwrap();
+        // The highest roll misses any move below 100% accuracy
+        let hits = |state: &mut State| Move::accuracy(thunder).do_accuracy_check(thunder, state, 6, 0, &mut StepRng::new(u64::MAX, 0));
+        let without = hits(&mut state);
+        state.pokemon_by_id_mut(6).ability = Ability::id_by_name("No Guard").unwrap();
+        let user_has_it = hits(&mut state);
+        state.pokemon_by_id_mut(6).ability = Ability::id_by_name("Chlorophyll").unwrap();
+        state.pokemon_by_id_mut(0).ability = Ability::id_by_name("No Guard").unwrap();
+        let target_has_it = hits(&mut state);
+        swap_moves(&mut moves);
+        assert!(!without);
+        assert!(user_has_it);
+        assert!(target_has_it);
+    }
+
+    #[test]
+    fn hustle_lowers_the_accuracy_of_physical_moves_only() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.pokemon_by_id_mut(6).ability = Ability::id_by_name("Hustle").unwrap();
+        let moves_json = r#"[
+            {"name": "Tackle", "type": "Normal", "category": "Physical", "accuracy": {"Standard": 100}, "targeting": "SingleAdjacentPokemon",
+                "max_pp": 35, "priority_stage": 0, "sound_based": false, "effects": [{"StdDamage": ["Normal", 50, 0]}]},
+            {"name": "Swift", "type": "Normal", "category": "Special", "accuracy": {"Standard": 100}, "targeting": "SingleAdjacentPokemon",
+                "max_pp": 20, "priority_stage": 0, "sound_based": false, "effects": [{"StdDamage": ["Normal", 60, 0]}]}]"#;
+        let mut moves = parse_moves(String::from("moves.json"), moves_json).unwrap();
+
+        swap_moves(&mut moves);
+        let hits = |state: &mut State, name| {
+            let move_ = Move::id_by_name(name).unwrap();
+            let mut rng = StdRng::seed_from_u64(0);
+            (0..1000).filter(|_| Move::accuracy(move_).do_accuracy_check(move_, state, 6, 0, &mut rng)).count()
+        };
+        let (tackle_hits, swift_hits) = (hits(&mut state, "Tackle"), hits(&mut state, "Swift"));
+        swap_moves(&mut moves);
+        assert!((750..=850).contains(&tackle_hits), "{}", tackle_hits);
+        assert_eq!(swift_hits, 1000);
+    }
+
+    #[test]
+    fn base_power_comes_from_the_first_damaging_effect() {
+        let _data = crate::load_test_data();
+        let moves_json = r#"[
+            {"name": "Tackle", "type": "Normal", "category": "Physical", "accuracy": {"Standard": 100}, "targeting": "SingleAdjacentPokemon",
+                "max_pp": 35, "priority_stage": 0, "sound_based": false, "effects": [{"StdDamage": ["Normal", 50, 0]}]},
+            {"name": "Growl", "type": "Normal", "category": "Status", "accuracy": {"Standard": 100}, "targeting": "AllAdjacentOpponents",
+                "max_pp": 40, "priority_stage": 0, "sound_based": true, "effects": [{"IncTargetStatStage": ["Atk", -1]}]}]"#;
+        let mut moves = parse_moves(String::from("moves.json"), moves_json).unwrap();
+
+        swap_moves(&mut moves);
+        let power = |name| Move::base_power(Move::id_by_name(name).unwrap());
+        let (tackle_power, growl_power) = (power("Tackle"), power("Growl"));
+        swap_moves(&mut moves);
+        assert_eq!(tackle_power, Some(50));
+        assert_eq!(growl_power, None);
     }
 }
@@ -1,45 +1,255 @@
 use crate::battle_ai::pokemon;
-use rand::prelude::StdRng;
 use rand::Rng;
 use serde::Deserialize;
 use std::cmp::{min, max, Ordering};
 use std::fmt::Debug;
-use crate::battle_ai::data::{StatIndex, Type, Weather, MajorStatusAilment, Gender};
+use crate::battle_ai::data::{StatIndex, Type, Weather, MajorStatusAilment, Gender, Item, InvulnKind, FieldPosition, Side};
 use crate::battle_ai::move_::{MoveID, Move, MoveCategory};
-use crate::battle_ai::state::{State, Action, Counter};
+use crate::battle_ai::state::{State, Action, Counter, BatonPass, DelayedHit};
 use crate::battle_ai::species::Species;
+use crate::{ChancePolicy, LogLevel};
 
 #[derive(Debug, Deserialize)]
 pub enum MoveEffect {
     Attract,
+    BatonPass,
+    /// Halves the user's HP to maximize its Attack stage.
+    BellyDrum,
+    /// Rollout and Fury Cutter; power doubles with each consecutive successful use, up to the given multiple of its
+    /// base power.
+    /// (damage_type: Type, power: u8, max_multiplier: u8)
+    ConsecutiveBoost(Type, u8, u8),
+    /// Future Sight and Doom Desire; the damage lands on whichever Pokemon is in the target's position at the end of
+    /// the turn after next. Typeless before gen 5.
+    /// (damage_type: Type, power: u8)
+    DelayedDamage(Type, u8),
+    /// Photon Geyser; physical if the user's Attack is higher than its Special Attack, counting stat stages, and
+    /// special otherwise.
+    /// (damage_type: Type, power: u8)
+    DynamicCategoryDamage(Type, u8),
+    /// Hits through Protect and lifts it for the rest of the turn.
+    Feint,
+    /// Fake Out and First Impression; the wrapped effect fails unless this is the user's first turn on the field.
+    /// (effect: Box<MoveEffect>)
+    FirstTurnOnly(Box<MoveEffect>),
+    /// Makes the target flinch, which only does anything if the target has yet to move this turn. Usually wrapped in
+    /// a `SecondaryChance`.
+    Flinch,
+    /// Roar and Whirlwind; the replacement is chosen at random.
+    ForceSwitch,
+    Foresight,
     GigaDrain,
     Growth,
+    /// Heavy Slam and Heat Crash; power depends on the ratio of the user's weight to the target's.
+    /// (damage_type: Type)
+    HeavySlamDamage(Type),
+    /// Eruption and Water Spout scale power with the user's remaining HP. Flail and Reversal, the inverse ones, follow
+    /// the games' table of HP brackets instead, which reaches the max power below about 4% HP.
+    /// (damage_type: Type, max_power: u8, inverse: bool)
+    HpScaledPower(Type, u8, bool),
     /// (stat_index: StatIndex, amount: i8)
     IncTargetStatStage(StatIndex, i8),
     LeechSeed,
+    /// Uses a random move from among all loaded moves; see `can_be_called_by_metronome`.
+    Metronome,
+    /// Copies the target's last used move into the slot of this move until the user leaves the field.
+    Mimic,
+    /// Uses the target's last used move.
+    MirrorMove,
+    Nothing,
+    /// Bind, Fire Spin, Whirlpool, etc.; deals damage, then hurts the target each turn and prevents it from
+    /// switching for several turns.
+    /// (damage_type: Type, power: u8)
+    PartialTrap(Type, u8),
     /// (toxic: bool, chance: u8)
     Poison(bool, u8),
     PoisonPowder,
+    /// Protect and Detect; fails if the user moves last, and becomes less likely to succeed when used repeatedly.
+    Protect,
+    /// Acupressure; raises a stat of the target other than HP, chosen uniformly at random from among those not
+    /// already at +6. Fails if they all are. Accuracy and evasion are candidates along with the five main stats, as
+    /// they are for Acupressure in the games.
+    /// (amount: i8)
+    RaiseRandomStat(i8),
+    /// Outrage, Thrash and Petal Dance; deals damage and locks the user into the move for 2-3 turns, after which it
+    /// becomes confused. A turn that misses, fails or is stopped ends the lock early; see `disrupt_rampage`.
+    /// (damage_type: Type, power: u8)
+    RampageLock(Type, u8),
+    /// Tri Attack; inflicts one of the given major status ailments, chosen uniformly at random. Fails silently like a
+    /// secondary effect.
+    /// (chance: u8, candidates: Vec<MajorStatusAilment>)
+    RandomStatus(u8, Vec<MajorStatusAilment>),
+    /// Heals half of the user's max HP.
+    Recover,
+    /// Fully heals the user and cures its major status ailment, then puts it to sleep for exactly two turns.
+    Rest,
+    /// Counter, Mirror Coat and Metal Burst; hits whichever Pokemon last damaged the user this turn, instead of the
+    /// targeted position, for a percentage of the damage taken. Fails if the user hasn't taken damage of the given
+    /// category (or of any category, if None) this turn.
+    /// (category: Option<MoveCategory>, percent: u16)
+    Retaliate(Option<MoveCategory>, u16),
+    /// Heals half of the user's max HP and removes its Flying type until the end of the turn.
+    Roost,
+    /// (chance: u8, effect: Box<MoveEffect>)
+    SecondaryChance(u8, Box<MoveEffect>),
+    /// Fly, Dig and Dive; the user spends the first turn charging in the given semi-invulnerable state and the move's
+    /// other effects happen on the second turn.
+    /// (invuln_kind: InvulnKind)
+    SemiInvulnerableCharge(InvulnKind),
+    /// (stat_index: StatIndex, stage: i8)
+    SetUserStatStage(StatIndex, i8),
     SleepPowder,
+    /// Adds a layer of spikes to the target's side of the field, up to three.
+    Spikes,
+    /// Stored Power and Punishment; power goes up by 20 for each positive stat stage of the user or the target,
+    /// respectively. Punishment's power is capped at 200 and Stored Power's at 255.
+    /// (damage_type: Type, base_power: u8, uses_user_stages: bool)
+    StatStageScaledPower(Type, u8, bool),
     /// (damage_type: Type, power: u8, critical_hit_stage_bonus: u8)
     StdDamage(Type, u8, u8),
     Struggle,
+    Substitute,
     SunnyDay,
-    Synthesis
+    Synthesis,
+    /// Doubles the Speed of the user's side for a few turns.
+    Tailwind,
+    /// Adds a layer of poison spikes to the target's side of the field, up to two.
+    ToxicSpikes,
+    /// The user takes on the target's types, stats other than HP, stat stages, ability and moves, with 5 PP each,
+    /// until it leaves the field. Fails if either is already transformed or the target is behind a substitute.
+    Transform,
+    /// Mean Look and Block.
+    TrapTarget,
+    /// Type and power depend on the weather when the move is used: Fire in sun, Water in rain, Ice in hail and Rock in
+    /// a sandstorm, each with double power.
+    WeatherBall,
+    /// Low Kick and Grass Knot; power depends on the target's weight.
+    /// (damage_type: Type)
+    WeightBasedDamage(Type),
+    /// The target falls asleep at the end of the next turn unless it leaves the field first.
+    Yawn
 }
 
 impl MoveEffect {
-    fn do_effect(&self, move_: MoveID, state: &mut State, action_queue: &[&Action], user_id: u8, target_id: u8, rng: &mut StdRng) -> EffectResult {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            MoveEffect::Poison(_, chance) | MoveEffect::RandomStatus(chance, _) | MoveEffect::SecondaryChance(chance, _) if *chance > 100 => {
+                Err(format!("chance of {} is greater than 100", chance))
+            },
+            MoveEffect::RandomStatus(_, candidates) if candidates.is_empty() || candidates.contains(&MajorStatusAilment::Okay) => {
+                Err(format!("invalid status candidates {:?}", candidates))
+            },
+            MoveEffect::FirstTurnOnly(effect) | MoveEffect::SecondaryChance(_, effect) => effect.validate(),
+            MoveEffect::ConsecutiveBoost(_, _, max_multiplier) if *max_multiplier == 0 => {
+                Err(String::from("max multiplier is 0"))
+            },
+            MoveEffect::RaiseRandomStat(amount) if *amount <= 0 => {
+                Err(format!("stat stage increase of {} is not positive", amount))
+            },
+            MoveEffect::SetUserStatStage(_, stage) if *stage < -6 || *stage > 6 => {
+                Err(format!("stat stage of {} is outside of -6 to 6", stage))
+            },
+            _ => Ok(())
+        }
+    }
+
+    /// See `Move::primary_and_secondary_effects`.
+    pub fn is_damaging(&self) -> bool {
+        matches!(self, MoveEffect::ConsecutiveBoost(..) | MoveEffect::DynamicCategoryDamage(..) | MoveEffect::Feint | MoveEffect::GigaDrain | MoveEffect::HeavySlamDamage(_) | MoveEffect::HpScaledPower(..) | MoveEffect::PartialTrap(..) | MoveEffect::RampageLock(..) | MoveEffect::Retaliate(..) | MoveEffect::StatStageScaledPower(..) | MoveEffect::StdDamage(..) | MoveEffect::Struggle | MoveEffect::WeatherBall | MoveEffect::WeightBasedDamage(_))
+            || matches!(self, MoveEffect::FirstTurnOnly(effect) if effect.is_damaging())
+    }
+
+    /// Fixed base power of a damaging effect. None for non-damaging effects and ones whose power depends on the
+    /// Pokemon involved, such as Low Kick.
+    pub fn power(&self) -> Option<u8> {
+        match self {
+            MoveEffect::Feint => Some(if game_version().gen() <= 4 { 50 } else { 30 }),
+            MoveEffect::GigaDrain => Some(if game_version().gen() <= 4 { 60 } else { 75 }),
+            MoveEffect::ConsecutiveBoost(_, power, _) | MoveEffect::DynamicCategoryDamage(_, power) | MoveEffect::PartialTrap(_, power) | MoveEffect::RampageLock(_, power) | MoveEffect::StdDamage(_, power, _) => Some(*power),
+            MoveEffect::FirstTurnOnly(effect) => effect.power(),
+            MoveEffect::Struggle => Some(50),
+            _ => None
+        }
+    }
+
+    /// Whether the mechanic exists in the given generation. Effects that are missing from it do nothing, so that
+    /// move data written for later games can't leak their mechanics into earlier ones.
+    pub fn available_in(&self, gen: u32) -> bool {
+        match self {
+            // Punishment, which counts the target's stages, came a generation before Stored Power
+            MoveEffect::Feint | MoveEffect::RaiseRandomStat(_) | MoveEffect::Roost | MoveEffect::StatStageScaledPower(_, _, false) | MoveEffect::Tailwind | MoveEffect::ToxicSpikes => gen >= 4,
+            MoveEffect::WeatherBall | MoveEffect::Yawn => gen >= 3,
+            MoveEffect::HeavySlamDamage(_) | MoveEffect::StatStageScaledPower(_, _, true) => gen >= 5,
+            MoveEffect::DynamicCategoryDamage(..) => gen >= 7,
+            MoveEffect::FirstTurnOnly(effect) | MoveEffect::SecondaryChance(_, effect) => effect.available_in(gen),
+            _ => true
+        }
+    }
+
+    /// Name of the effect as written in move data, without its parameters.
+    pub fn name(&self) -> String {
+        let debug_text = format!("{:?}", self);
+        debug_text.split('(').next().unwrap().to_owned()
+    }
+
+    /// Does the effect, then swaps whatever display text it showed for the move's override, if it has one.
+    fn do_effect<R: Rng>(&self, move_: MoveID, state: &mut State, action_queue: &[&Action], user_id: u8, target_id: u8, rng: &mut R) -> EffectResult {
+        if !self.available_in(game_version().gen()) {
+            return EffectResult::Skip;
+        }
+        let display_text_start = state.display_text_len();
+        let result = self.do_effect_with_default_text(move_, state, action_queue, user_id, target_id, rng);
+
+        if state.logs(LogLevel::Verbose) && state.display_text_len() > display_text_start {
+            if let Some(template) = Move::effect_display_text(move_, &self.name()) {
+                let text = template
+                    .replace("{user}", Species::name(state.pokemon_by_id(user_id).species()))
+                    .replace("{target}", Species::name(state.pokemon_by_id(target_id).species()));
+                state.replace_display_text_from(display_text_start, text);
+            }
+        }
+        result
+    }
+
+    fn do_effect_with_default_text<R: Rng>(&self, move_: MoveID, state: &mut State, action_queue: &[&Action], user_id: u8, target_id: u8, rng: &mut R) -> EffectResult {
         match self {
             MoveEffect::Attract => attract(state, user_id, target_id),
-            MoveEffect::GigaDrain => giga_drain(state, user_id, target_id, rng),
+            MoveEffect::BatonPass => baton_pass(state, user_id),
+            MoveEffect::BellyDrum => belly_drum(state, user_id),
+            MoveEffect::ConsecutiveBoost(damage_type, power, max_multiplier) => {
+                consecutive_boost(VariableAttack { move_, damage_type: *damage_type, power: *power }, state, user_id, target_id, *max_multiplier, rng)
+            },
+            MoveEffect::DelayedDamage(damage_type, power) => delayed_damage(move_, state, user_id, target_id, *damage_type, *power),
+            MoveEffect::DynamicCategoryDamage(damage_type, power) => dynamic_category_damage(move_, state, user_id, target_id, *damage_type, *power, rng),
+            MoveEffect::Feint => feint(move_, state, user_id, target_id, rng),
+            MoveEffect::FirstTurnOnly(effect) => {
+                if state.pokemon_by_id(user_id).turns_on_field == 0 {
+                    effect.do_effect(move_, state, action_queue, user_id, target_id, rng)
+                } else {
+                    EffectResult::Fail
+                }
+            },
+            MoveEffect::Flinch => flinch(state, action_queue, target_id),
+            MoveEffect::ForceSwitch => force_switch(state, target_id, rng),
+            MoveEffect::Foresight => foresight(state, target_id),
+            MoveEffect::GigaDrain => giga_drain(move_, state, user_id, target_id, rng),
             MoveEffect::Growth => growth(state, user_id),
+            MoveEffect::HeavySlamDamage(damage_type) => heavy_slam_damage(move_, state, user_id, target_id, *damage_type, rng),
+            MoveEffect::HpScaledPower(damage_type, max_power, inverse) => {
+                hp_scaled_power(VariableAttack { move_, damage_type: *damage_type, power: *max_power }, state, user_id, target_id, *inverse, rng)
+            },
             MoveEffect::IncTargetStatStage(stat_index, amount) => {
-                pokemon::increment_stat_stage(state, target_id, *stat_index, *amount);
+                pokemon::increment_stat_stage(state, target_id, *stat_index, *amount, Side::of(user_id));
                 EffectResult::Success
             },
             MoveEffect::LeechSeed => leech_seed(state, user_id, target_id),
+            MoveEffect::Metronome => metronome(state, action_queue, user_id, rng),
+            MoveEffect::Mimic => mimic(move_, state, user_id, target_id),
+            MoveEffect::MirrorMove => mirror_move(state, action_queue, user_id, target_id, rng),
+            MoveEffect::Nothing => nothing(state),
+            MoveEffect::PartialTrap(damage_type, power) => partial_trap(move_, state, user_id, target_id, *damage_type, *power, rng),
             MoveEffect::Poison(toxic, chance) => {
+                state.rng_draws.status_rolls += 1;
                 if rng.gen_range(0, 100) < *chance {
                     pokemon::poison(state, target_id, *toxic, false)
                 } else {
@@ -47,13 +257,45 @@ impl MoveEffect {
                 }
             },
             MoveEffect::PoisonPowder => poison_powder(state, target_id),
+            MoveEffect::Protect => protect(state, action_queue, user_id, rng),
+            MoveEffect::RaiseRandomStat(amount) => raise_random_stat(state, user_id, target_id, *amount, rng),
+            MoveEffect::RampageLock(damage_type, power) => rampage_lock(move_, state, user_id, target_id, *damage_type, *power, rng),
+            MoveEffect::RandomStatus(chance, candidates) => random_status(state, target_id, *chance, candidates, rng),
+            MoveEffect::Recover => recover(state, user_id),
+            MoveEffect::Rest => rest(state, user_id),
+            MoveEffect::Retaliate(category, percent) => retaliate(move_, state, user_id, *category, *percent),
+            MoveEffect::Roost => roost(state, user_id),
+            MoveEffect::SecondaryChance(chance, effect) => {
+                // Secondary effects fail silently
+                state.rng_draws.status_rolls += 1;
+                if rng.gen_range(0, 100) < *chance && effect.do_effect(move_, state, action_queue, user_id, target_id, rng) == EffectResult::Success {
+                    EffectResult::Success
+                } else {
+                    EffectResult::Skip
+                }
+            },
+            // The charge turn is handled by Action::perform
+            MoveEffect::SemiInvulnerableCharge(_) => EffectResult::Skip,
+            MoveEffect::SetUserStatStage(stat_index, stage) => set_user_stat_stage(state, user_id, *stat_index, *stage),
             MoveEffect::SleepPowder => sleep_powder(state, target_id, rng),
+            MoveEffect::Spikes => spikes(state, target_id),
+            MoveEffect::StatStageScaledPower(damage_type, base_power, uses_user_stages) => {
+                stat_stage_scaled_power(VariableAttack { move_, damage_type: *damage_type, power: *base_power }, state, user_id, target_id, *uses_user_stages, rng)
+            },
             MoveEffect::StdDamage(damage_type, power, critical_hit_stage_bonus) => {
-                std_damage(state, user_id, target_id, *damage_type, Move::category(move_), *power, *critical_hit_stage_bonus, rng).0
+                std_damage(move_, state, user_id, target_id, *damage_type, Move::category(move_), *power as u16, *critical_hit_stage_bonus, rng).0
             },
-            MoveEffect::Struggle => struggle(state, user_id, target_id, rng),
+            MoveEffect::Struggle => struggle(move_, state, user_id, target_id, rng),
+            MoveEffect::Substitute => substitute(state, user_id),
             MoveEffect::SunnyDay => sunny_day(state),
-            MoveEffect::Synthesis => synthesis(state, user_id)
+            MoveEffect::Synthesis => synthesis(state, user_id),
+            MoveEffect::Tailwind => tailwind(state, user_id),
+            MoveEffect::ToxicSpikes => toxic_spikes(state, target_id),
+            MoveEffect::Transform => transform(state, user_id, target_id),
+            MoveEffect::TrapTarget => trap_target(state, target_id),
+            MoveEffect::WeatherBall => weather_ball(move_, state, user_id, target_id, rng),
+            MoveEffect::WeightBasedDamage(damage_type) => weight_based_damage(move_, state, user_id, target_id, *damage_type, rng),
+            MoveEffect::Yawn => yawn(state, user_id, target_id)
         }
     }
 }
@@ -85,120 +327,324 @@ impl EffectResult {
     }
 }
 
+/// Damage multiplier of a critical hit in the given generation.
+pub const fn crit_multiplier(gen: u32) -> f64 {
+    if gen < 6 { 2.0 } else { 1.5 }
+}
+
 fn critical_hit_chance(critical_hit_stage_bonus: u8) -> f64 {
     let mut c = 0;
     c += critical_hit_stage_bonus as usize;
     c = min(c, 4);
     match game_version().gen() {
-        1..=5 => [1.0 / 16.0, 1.0 / 8.0, 1.0 / 4.0, 1.0 / 3.0, 1.0 / 2.0][c],
+        3..=5 => [1.0 / 16.0, 1.0 / 8.0, 1.0 / 4.0, 1.0 / 3.0, 1.0 / 2.0][c],
         6 => [1.0 / 16.0, 1.0 / 8.0, 1.0 / 2.0, 1.0, 1.0][c],
         _ => [1.0 / 24.0, 1.0 / 8.0, 1.0 / 2.0, 1.0, 1.0][c]
     }
 }
 
-fn main_stat_stage_multiplier(stat_stage: i8) -> f64 {
+/// Multiplier that a stat stage from -6 to 6 applies to Attack, Defense, Special Attack, Special Defense or Speed,
+/// from 0.25 at -6 to 4.0 at +6.
+pub fn main_stat_stage_multiplier(stat_stage: i8) -> f64 {
+    debug_assert!((-6..=6).contains(&stat_stage), "Stat stage {} is outside of -6 to 6", stat_stage);
     max(2, 2 + stat_stage) as f64 / max(2, 2 - stat_stage) as f64
 }
 
-fn accuracy_stat_stage_multiplier(stat_stage: i8) -> f64 {
+/// Multiplier that the difference between the user's accuracy stage and the target's evasion stage, clamped to -6 to
+/// 6, applies to a move's accuracy, from 1/3 at -6 to 3.0 at +6.
+pub fn accuracy_stat_stage_multiplier(stat_stage: i8) -> f64 {
+    debug_assert!((-6..=6).contains(&stat_stage), "Stat stage {} is outside of -6 to 6", stat_stage);
     max(3, 3 + stat_stage) as f64 / max(3, 3 - stat_stage) as f64
 }
 
-fn std_base_damage(power: u8, calculated_atk: u32, calculated_def: u32, offensive_stat_stage: i8, defensive_stat_stage: i8, critical_hit: bool) -> u32 {
+fn std_base_damage(power: u16, calculated_atk: u32, calculated_def: u32, offensive_stat_stage: i8, defensive_stat_stage: i8, critical_hit: bool) -> u32 {
     let attack_multiplier = if critical_hit && offensive_stat_stage < 0 { 1.0 } else { main_stat_stage_multiplier(offensive_stat_stage) };
     let defense_multiplier = if critical_hit && defensive_stat_stage > 0 { 1.0 } else { main_stat_stage_multiplier(defensive_stat_stage) };
     (42 * power as u32 * (calculated_atk as f64 * attack_multiplier) as u32 / (calculated_def as f64 * defense_multiplier) as u32) / 50 + 2
 }
 
-// ---- MOVE EFFECTS ---- //
+/// Damage a confused Pokemon deals to itself: a typeless 40 power physical attack that can't be a critical hit.
+pub fn confusion_damage<R: Rng>(state: &State, pokemon_id: u8, rng: &mut R) -> u16 {
+    let pokemon = state.pokemon_by_id(pokemon_id);
+    let calculated_atk = pokemon::calculated_stat(state, pokemon_id, StatIndex::Atk);
+    let calculated_def = pokemon::calculated_stat(state, pokemon_id, StatIndex::Def);
+    let base_damage = std_base_damage(40, calculated_atk, calculated_def, pokemon.stat_stage(StatIndex::Atk), pokemon.stat_stage(StatIndex::Def), false);
+    max(base_damage * (100 - rng.gen_range(0, 16)) / 100, 1) as u16
+}
 
-fn std_damage(state: &mut State, user_id: u8, target_id: u8, damage_type: Type, category: MoveCategory, power: u8, critical_hit_stage_bonus: u8, rng: &mut StdRng) -> (EffectResult, u16) {
-    let target_first_type;
-    let target_second_type;
-    let offensive_stat_index = if category == MoveCategory::Physical { StatIndex::Atk } else { StatIndex::SpAtk };
-    let defensive_stat_index = if category == MoveCategory::Physical { StatIndex::Def } else { StatIndex::SpDef };
-    let user_ability;
-    let user_current_hp;
-    let user_max_hp;
-    let offensive_stat_stage;
-    let defensive_stat_stage;
-    let user_major_status_ailment;
-    {
-        let user = state.pokemon_by_id(user_id);
-        let target = state.pokemon_by_id(target_id);
-        target_first_type = target.first_type();
-        target_second_type = target.second_type();
-        user_ability = user.ability();
-        user_current_hp = user.current_hp();
-        user_max_hp = user.max_hp();
-        offensive_stat_stage = user.stat_stage(offensive_stat_index);
-        defensive_stat_stage = target.stat_stage(defensive_stat_index);
-        user_major_status_ailment = user.major_status_ailment();
-    }
-
-    let type_effectiveness = damage_type.effectiveness(target_first_type, target_second_type);
-    if almost::zero(type_effectiveness) {
-        return (EffectResult::NoEffect, 0);
+/// A standard attack whose power is worked out when it's used, starting from `power`.
+#[derive(Clone, Copy)]
+struct VariableAttack {
+    move_: MoveID,
+    damage_type: Type,
+    power: u8
+}
+
+impl VariableAttack {
+    /// Deals the attack's damage with the power it worked out.
+    fn with_power<R: Rng>(&self, power: u16, state: &mut State, user_id: u8, target_id: u8, rng: &mut R) -> EffectResult {
+        std_damage(self.move_, state, user_id, target_id, self.damage_type, Move::category(self.move_), power, 0, rng).0
     }
+}
+
+/// The parts of a standard attack's damage that don't depend on chance, so that the damage can be worked out for any
+/// critical hit and damage roll.
+struct DamageCalc {
+    power: u16,
+    calculated_atk: u32,
+    calculated_def: u32,
+    offensive_stat_stage: i8,
+    defensive_stat_stage: i8,
+    weather_modifier: f64,
+    stab: bool,
+    type_effectiveness: f64,
+    ability_modifier: f64,
+    user_burned: bool
+}
 
-    let mut calculated_atk = pokemon::calculated_stat(state, user_id, offensive_stat_index);
-    let calculated_def = pokemon::calculated_stat(state, target_id, defensive_stat_index);
+impl DamageCalc {
+    /// None if the target is immune to the attack.
+    fn new(state: &State, user_id: u8, target_id: u8, damage_type: Type, category: MoveCategory, power: u16) -> Option<DamageCalc> {
+        let target_first_type;
+        let target_second_type;
+        let offensive_stat_index = if category == MoveCategory::Physical { StatIndex::Atk } else { StatIndex::SpAtk };
+        let defensive_stat_index = if category == MoveCategory::Physical { StatIndex::Def } else { StatIndex::SpDef };
+        let user_ability;
+        // None if the user has Mold Breaker, which ignores the target's ability
+        let target_ability;
+        let user_current_hp;
+        let user_max_hp;
+        let offensive_stat_stage;
+        let defensive_stat_stage;
+        let user_major_status_ailment;
+        let target_identified;
+        {
+            let user = state.pokemon_by_id(user_id);
+            let target = state.pokemon_by_id(target_id);
+            target_first_type = target.first_type();
+            target_second_type = target.second_type();
+            user_ability = user.ability();
+            target_ability = if user_ability == Ability::id_by_name("Mold Breaker").unwrap() { None } else { Some(target.ability()) };
+            user_current_hp = user.current_hp();
+            user_max_hp = user.max_hp();
+            offensive_stat_stage = user.stat_stage(offensive_stat_index);
+            defensive_stat_stage = target.stat_stage(defensive_stat_index);
+            user_major_status_ailment = user.major_status_ailment();
+            target_identified = target.is_identified;
+        }
 
-    if damage_type == Type::Grass && user_ability == Ability::id_by_name("Overgrow").unwrap() && user_current_hp < user_max_hp / 3 {
-        calculated_atk = (calculated_atk as f64 * 1.5) as u32;
-    }
+        let type_effectiveness = if target_identified && matches!(damage_type, Type::Normal | Type::Fighting) {
+            // Identified Ghost types lose their immunity
+            let ignore_ghost = |type_: Type| if type_ == Type::Ghost { Type::None } else { type_ };
+            damage_type.effectiveness(ignore_ghost(target_first_type), ignore_ghost(target_second_type))
+        } else {
+            damage_type.effectiveness(target_first_type, target_second_type)
+        };
+        // Mold Breaker lets Ground moves hit Levitate holders; Flying types are still immune through the type chart
+        if almost::zero(type_effectiveness) || (damage_type == Type::Ground && target_ability.is_some() && !state.pokemon_by_id(target_id).is_grounded()) {
+            return None;
+        }
 
-    /*
-     Multiply base damage by the following modifiers (in no particular order), rounding up/down at the end
-     - Multi-target modifier (TODO?)
-     - Weather modifier (TODO)
-     - If critical hit, multiply by 1.5 (by 2 prior to 6th gen)
-     - Random integer between 85 and 100 divided by 100
-     - STAB
-     - Type effectiveness
-     - Halve damage if user is burned
-     - damage = max(damage, 1)
-     */
+        let mut calculated_atk = pokemon::calculated_stat(state, user_id, offensive_stat_index);
+        let calculated_def = pokemon::calculated_stat(state, target_id, defensive_stat_index);
 
-    let mut modified_damage: f64 = if rng.gen_bool(critical_hit_chance(critical_hit_stage_bonus)) {
-        if cfg!(feature = "print-battle") {
-            state.add_display_text(String::from("It's a critical hit!"));
+        if damage_type == Type::Grass && user_ability == Ability::id_by_name("Overgrow").unwrap() && user_current_hp < user_max_hp / 3 {
+            calculated_atk = (calculated_atk as f64 * 1.5) as u32;
         }
-        std_base_damage(power, calculated_atk, calculated_def, offensive_stat_stage, defensive_stat_stage, true) as f64 * if game_version().gen() < 6 { 2.0 } else { 1.5 }
-    } else {
-        std_base_damage(power, calculated_atk, calculated_def, offensive_stat_stage, defensive_stat_stage, false) as f64
-    };
+        if offensive_stat_index == StatIndex::Atk && user_ability == Ability::id_by_name("Hustle").unwrap() {
+            calculated_atk = (calculated_atk as f64 * 1.5) as u32;
+        }
+        let choice_boost_item = if offensive_stat_index == StatIndex::Atk { "Choice Band" } else { "Choice Specs" };
+        if state.pokemon_by_id(user_id).item() == Some(Item::id_by_name(choice_boost_item).unwrap()) {
+            calculated_atk = (calculated_atk as f64 * 1.5) as u32;
+        }
+        if matches!(damage_type, Type::Fire | Type::Ice) && target_ability == Some(Ability::id_by_name("Thick Fat").unwrap()) {
+            calculated_atk /= 2;
+        }
+
+        let ability_modifier = if type_effectiveness < 0.9 && user_ability == Ability::id_by_name("Tinted Lens").unwrap() {
+            2.0
+        } else if type_effectiveness > 1.1 && (target_ability == Some(Ability::id_by_name("Filter").unwrap()) || target_ability == Some(Ability::id_by_name("Solid Rock").unwrap())) {
+            0.75
+        } else {
+            1.0
+        };
 
-    if state.weather == Weather::HarshSunshine {
-        modified_damage *= match damage_type {
-            Type::Fire => 1.5,
-            Type::Water => 0.5,
-            _ => 1.0
+        /*
+         Multiply base damage by the following modifiers (in no particular order), rounding up/down at the end
+         - Multi-target modifier (TODO?)
+         - Weather modifier (TODO)
+         - If critical hit, multiply by 1.5 (by 2 prior to 6th gen)
+         - Random integer between 85 and 100 divided by 100
+         - STAB
+         - Type effectiveness
+         - Abilities that depend on type effectiveness
+         - Halve damage if user is burned
+         - damage = max(damage, 1)
+         */
+
+        let weather_modifier = if state.weather == Weather::HarshSunshine {
+            match damage_type {
+                Type::Fire => 1.5,
+                Type::Water => 0.5,
+                _ => 1.0
+            }
+        } else {
+            1.0
         };
+        Some(DamageCalc {
+            power,
+            calculated_atk,
+            calculated_def,
+            offensive_stat_stage,
+            defensive_stat_stage,
+            weather_modifier,
+            stab: damage_type != Type::None && state.pokemon_by_id(user_id).is_type(damage_type),
+            type_effectiveness,
+            ability_modifier,
+            user_burned: user_major_status_ailment == MajorStatusAilment::Burned
+        })
+    }
+
+    /// Damage for the given critical hit outcome and damage roll, from 85 to 100.
+    fn damage(&self, critical_hit: bool, random_percent: f64) -> f64 {
+        let mut modified_damage = std_base_damage(self.power, self.calculated_atk, self.calculated_def, self.offensive_stat_stage, self.defensive_stat_stage, critical_hit) as f64;
+        if critical_hit { modified_damage *= crit_multiplier(game_version().gen()); }
+        modified_damage *= self.weather_modifier;
+        modified_damage *= random_percent / 100.0;
+        if self.stab { modified_damage *= 1.5; }
+        modified_damage *= self.type_effectiveness;
+        modified_damage *= self.ability_modifier;
+        if self.user_burned { modified_damage *= 0.5; }
+        modified_damage.max(1.0).round()
     }
 
-    modified_damage *= (100 - rng.gen_range(0, 16)) as f64 / 100.0;
-    if damage_type != Type::None && state.pokemon_by_id(user_id).is_type(damage_type) {
-        modified_damage *= 1.5;
+    /// Expected damage over critical hits and the damage roll.
+    fn expected_damage(&self, crit_chance: f64) -> f64 {
+        // The random percentage is uniform over 85-100, so each bucket covers four of its values
+        let random_percent_buckets = [86.5, 90.5, 94.5, 98.5];
+        random_percent_buckets.iter()
+            .map(|&random_percent| crit_chance * self.damage(true, random_percent) + (1.0 - crit_chance) * self.damage(false, random_percent))
+            .sum::<f64>() / random_percent_buckets.len() as f64
     }
-    modified_damage *= type_effectiveness;
-    if cfg!(feature = "print-battle") {
+}
+
+/// Expected damage of a standard attack from the user to the target in their current condition, over critical hits
+/// and the damage roll, without applying it. 0 if the target is immune.
+pub fn expected_damage(state: &State, user_id: u8, target_id: u8, damage_type: Type, category: MoveCategory, power: u16, critical_hit_stage_bonus: u8) -> f64 {
+    DamageCalc::new(state, user_id, target_id, damage_type, category, power)
+        .map_or(0.0, |damage_calc| damage_calc.expected_damage(critical_hit_chance(critical_hit_stage_bonus)))
+}
+
+// ---- MOVE EFFECTS ---- //
+
+fn std_damage<R: Rng>(move_: MoveID, state: &mut State, user_id: u8, target_id: u8, damage_type: Type, category: MoveCategory, power: u16, critical_hit_stage_bonus: u8, rng: &mut R) -> (EffectResult, u16) {
+    let damage_calc = match DamageCalc::new(state, user_id, target_id, damage_type, category, power) {
+        Some(damage_calc) => damage_calc,
+        None => return (EffectResult::NoEffect, 0)
+    };
+    let type_effectiveness = damage_calc.type_effectiveness;
+
+    let damage_dealt = match state.rules.chance_policy {
+        ChancePolicy::SampleOnce => {
+            state.rng_draws.critical_hits += 1;
+            state.rng_draws.damage_rolls += 1;
+            let critical_hit = rng.gen_bool(critical_hit_chance(critical_hit_stage_bonus));
+            if critical_hit && state.logs(LogLevel::Verbose) {
+                state.add_display_text(String::from("It's a critical hit!"));
+            }
+            damage_calc.damage(critical_hit, (100 - rng.gen_range(0, 16)) as f64) as u16
+        },
+        ChancePolicy::Expectation => damage_calc.expected_damage(critical_hit_chance(critical_hit_stage_bonus)).round() as u16,
+        ChancePolicy::Pessimistic | ChancePolicy::Optimistic => {
+            let best_for_user = (state.rules.chance_policy == ChancePolicy::Optimistic) == (user_id >= 6);
+            if best_for_user {
+                damage_calc.damage(true, 100.0) as u16
+            } else {
+                damage_calc.damage(false, 85.0) as u16
+            }
+        },
+        ChancePolicy::MinimumDamage => damage_calc.damage(false, 85.0) as u16,
+        ChancePolicy::AllCriticalHits => {
+            state.rng_draws.damage_rolls += 1;
+            if state.logs(LogLevel::Verbose) {
+                state.add_display_text(String::from("It's a critical hit!"));
+            }
+            damage_calc.damage(true, (100 - rng.gen_range(0, 16)) as f64) as u16
+        }
+    };
+
+    if state.logs(LogLevel::Verbose) {
         if type_effectiveness < 0.9 {
             state.add_display_text(String::from("It's not very effective..."));
         } else if type_effectiveness > 1.1 {
             state.add_display_text(String::from("It's super effective!"));
         }
     }
-    if user_major_status_ailment == MajorStatusAilment::Burned { modified_damage *= 0.5; }
-    modified_damage = modified_damage.max(1.0);
-
-    let damage_dealt = modified_damage.round() as u16;
-    pokemon::apply_damage(state, target_id, damage_dealt as i16);
+    if pokemon::is_behind_substitute(state, target_id, user_id, move_) {
+        return (EffectResult::Success, pokemon::damage_substitute(state, target_id, damage_dealt));
+    }
+    if target_id != user_id {
+        let target = state.pokemon_by_id_mut(target_id);
+        target.last_attacker = Some(user_id);
+        target.last_attack_damage = Some((category, min(damage_dealt, target.current_hp())));
+    }
+    if !pokemon::apply_damage(state, target_id, damage_dealt as i16) && Move::makes_contact(move_) {
+        contact_recoil(state, user_id, target_id);
+        contact_status(state, user_id, target_id, rng);
+    }
     (EffectResult::Success, damage_dealt)
 }
 
+/// Damages an attacker that made contact with a target holding Rocky Helmet or having Rough Skin/Iron Barbs.
+/// These still activate if the target fainted from the hit.
+fn contact_recoil(state: &mut State, user_id: u8, target_id: u8) {
+    let target_ability = state.pokemon_by_id(target_id).ability();
+    if target_ability == Ability::id_by_name("Rough Skin").unwrap() || target_ability == Ability::id_by_name("Iron Barbs").unwrap() {
+        if state.logs(LogLevel::Verbose) {
+            let user_name = Species::name(state.pokemon_by_id(user_id).species());
+            let target_name = Species::name(state.pokemon_by_id(target_id).species());
+            state.add_display_text(format!("{} was hurt by {}'s {}!", user_name, target_name, Ability::name(target_ability)));
+        }
+        let denominator = if game_version().gen() <= 4 { 16 } else { 8 };
+        if pokemon::apply_damage(state, user_id, max(state.pokemon_by_id(user_id).max_hp() / denominator, 1) as i16) {
+            return;
+        }
+    }
+
+    if !state.pokemon_by_id(user_id).is_fainted() && state.pokemon_by_id(target_id).item() == Some(Item::id_by_name("Rocky Helmet").unwrap()) {
+        if state.logs(LogLevel::Verbose) {
+            let user_name = Species::name(state.pokemon_by_id(user_id).species());
+            let target_name = Species::name(state.pokemon_by_id(target_id).species());
+            state.add_display_text(format!("{} was hurt by {}'s Rocky Helmet!", user_name, target_name));
+        }
+        pokemon::apply_damage(state, user_id, max(state.pokemon_by_id(user_id).max_hp() / 6, 1) as i16);
+    }
+}
+
+/// Static, Flame Body and Poison Point have a chance to inflict a major status ailment on an attacker
+/// that made contact.
+fn contact_status<R: Rng>(state: &mut State, user_id: u8, target_id: u8, rng: &mut R) {
+    if state.has_battle_ended() || state.pokemon_by_id(user_id).is_fainted() {
+        return;
+    }
+
+    let target_ability = state.pokemon_by_id(target_id).ability();
+    let chance = if game_version().gen() <= 4 { 1.0 / 3.0 } else { 0.3 };
+    if ["Static", "Flame Body", "Poison Point"].iter().any(|&name| target_ability == Ability::id_by_name(name).unwrap()) {
+        state.rng_draws.status_rolls += 1;
+    }
+    if target_ability == Ability::id_by_name("Static").unwrap() && rng.gen_bool(chance) {
+        pokemon::paralyze(state, user_id);
+    } else if target_ability == Ability::id_by_name("Flame Body").unwrap() && rng.gen_bool(chance) {
+        pokemon::burn(state, user_id);
+    } else if target_ability == Ability::id_by_name("Poison Point").unwrap() && rng.gen_bool(chance) {
+        pokemon::poison(state, user_id, false, false);
+    }
+}
+
 fn recoil(state: &mut State, user_id: u8, numerator: u16, denominator: u8) -> EffectResult {
-    if cfg!(feature = "print-battle") {
+    if state.logs(LogLevel::Verbose) {
         let user_name = Species::name(state.pokemon_by_id(user_id).species());
         state.add_display_text(format!("{} took recoil damage!", user_name));
     }
@@ -223,11 +669,217 @@ fn attract(state: &mut State, user_id: u8, target_id: u8) -> EffectResult {
     }
 }
 
-fn giga_drain(state: &mut State, user_id: u8, target_id: u8, rng: &mut StdRng) -> EffectResult {
-    let (result, damage_dealt) = std_damage(state, user_id, target_id, Type::Grass, MoveCategory::Special, if game_version().gen() <= 4 { 60 } else { 75 }, 0, rng);
+/// The user leaves the field, and its agent sends out a replacement at the next opportunity.
+fn baton_pass(state: &mut State, user_id: u8) -> EffectResult {
+    let has_recipient = Side::of(user_id).id_range()
+        .filter(|&id| id != user_id)
+        .any(|id| !state.pokemon_by_id(id).is_fainted() && !state.pokemon_by_id(id).is_on_field());
+    if !has_recipient {
+        return EffectResult::Fail;
+    }
+
+    let baton_pass = BatonPass::new(state.pokemon_by_id(user_id), user_id);
+    if user_id < 6 {
+        state.min.baton_pass = Some(baton_pass);
+    } else {
+        state.max.baton_pass = Some(baton_pass);
+    }
+    pokemon::remove_from_field(state, user_id);
+    EffectResult::Success
+}
+
+fn belly_drum(state: &mut State, user_id: u8) -> EffectResult {
+    let cost;
+    {
+        let user = state.pokemon_by_id(user_id);
+        cost = user.max_hp() / 2;
+        if user.current_hp() <= cost || user.stat_stage(StatIndex::Atk) == 6 {
+            return EffectResult::Fail;
+        }
+    }
+
+    pokemon::apply_damage(state, user_id, cost as i16);
+    pokemon::set_stat_stage(state, user_id, StatIndex::Atk, 6);
+    if state.logs(LogLevel::Verbose) {
+        let user_name = Species::name(state.pokemon_by_id(user_id).species());
+        state.add_display_text(format!("{} cut its own HP and maximized its attack!", user_name));
+    }
+    EffectResult::Success
+}
+
+fn consecutive_boost<R: Rng>(attack: VariableAttack, state: &mut State, user_id: u8, target_id: u8, max_multiplier: u8, rng: &mut R) -> EffectResult {
+    let consecutive_uses = state.pokemon_by_id(user_id).consecutive_move_counter;
+    let multiplier = min(1u32 << min(consecutive_uses, 7), max_multiplier as u32);
+    let boosted_power = attack.power as u16 * multiplier as u16;
+    let result = attack.with_power(boosted_power, state, user_id, target_id, rng);
+
+    let user = state.pokemon_by_id_mut(user_id);
+    if result == EffectResult::Success {
+        user.consecutive_move_counter = user.consecutive_move_counter.saturating_add(1);
+    } else {
+        user.consecutive_move_counter = 0;
+    }
+    result
+}
+
+fn delayed_damage(move_: MoveID, state: &mut State, user_id: u8, target_id: u8, damage_type: Type, power: u8) -> EffectResult {
+    let hit = DelayedHit {
+        turn: state.turn_number() + 2,
+        user_id,
+        move_,
+        damage_type,
+        power,
+        calculated_sp_atk: pokemon::calculated_stat(state, user_id, StatIndex::SpAtk),
+        sp_atk_stage: state.pokemon_by_id(user_id).stat_stage(StatIndex::SpAtk),
+        stab: game_version().gen() >= 5 && state.pokemon_by_id(user_id).is_type(damage_type)
+    };
+    let side_conditions = if target_id < 6 { &mut state.min.side_conditions } else { &mut state.max.side_conditions };
+    if side_conditions.delayed_hit.is_some() {
+        return EffectResult::Fail;
+    }
+
+    side_conditions.delayed_hit = Some(hit);
+    if state.logs(LogLevel::Verbose) {
+        let user_name = Species::name(state.pokemon_by_id(user_id).species());
+        state.add_display_text(format!("{} foresaw an attack!", user_name));
+    }
+    EffectResult::Success
+}
+
+/// Lands the delayed hit aimed at the given side if it is due. The hit is wasted if nothing is there to take it.
+/// Returns whether the battle ended as a result.
+pub fn land_delayed_hit<R: Rng>(state: &mut State, field_position: FieldPosition, rng: &mut R) -> bool {
+    let turn_number = state.turn_number();
+    let (side_conditions, on_field) = match field_position {
+        FieldPosition::Min => (&mut state.min.side_conditions, state.min.on_field),
+        FieldPosition::Max => (&mut state.max.side_conditions, state.max.on_field)
+    };
+    let hit = match side_conditions.delayed_hit.take() {
+        Some(hit) if hit.turn <= turn_number => hit,
+        hit => {
+            side_conditions.delayed_hit = hit;
+            return false;
+        }
+    };
+    let target_id = match on_field {
+        Some(target_id) => target_id,
+        None => return false
+    };
+
+    if state.logs(LogLevel::Verbose) {
+        let target_name = Species::name(state.pokemon_by_id(target_id).species());
+        state.add_display_text(format!("{} took the {} attack!", target_name, Move::name(hit.move_)));
+    }
+    let target = state.pokemon_by_id(target_id);
+    let type_effectiveness = if game_version().gen() >= 5 {
+        hit.damage_type.effectiveness(target.first_type(), target.second_type())
+    } else {
+        1.0
+    };
+    if almost::zero(type_effectiveness) {
+        if state.logs(LogLevel::Verbose) {
+            state.add_display_text(EffectResult::NoEffect.display_text().to_owned());
+        }
+        return false;
+    }
+
+    let calculated_sp_def = pokemon::calculated_stat(state, target_id, StatIndex::SpDef);
+    let base_damage = std_base_damage(hit.power as u16, hit.calculated_sp_atk, calculated_sp_def, hit.sp_atk_stage, target.stat_stage(StatIndex::SpDef), false);
+    let random_percent = match state.rules.chance_policy {
+        ChancePolicy::Expectation => 92.5,
+        ChancePolicy::Pessimistic | ChancePolicy::Optimistic => {
+            let best_for_user = (state.rules.chance_policy == ChancePolicy::Optimistic) == (hit.user_id >= 6);
+            if best_for_user { 100.0 } else { 85.0 }
+        },
+        ChancePolicy::MinimumDamage => 85.0,
+        ChancePolicy::SampleOnce | ChancePolicy::AllCriticalHits => {
+            state.rng_draws.damage_rolls += 1;
+            (100 - rng.gen_range(0, 16)) as f64
+        }
+    };
+    let mut damage = base_damage as f64 * random_percent / 100.0 * type_effectiveness;
+    if hit.stab { damage *= 1.5; }
+    let damage = damage.max(1.0).round() as u16;
+
+    if pokemon::is_behind_substitute(state, target_id, hit.user_id, hit.move_) {
+        pokemon::damage_substitute(state, target_id, damage);
+        return false;
+    }
+    pokemon::apply_damage(state, target_id, damage as i16)
+}
+
+fn dynamic_category_damage<R: Rng>(move_: MoveID, state: &mut State, user_id: u8, target_id: u8, damage_type: Type, power: u8, rng: &mut R) -> EffectResult {
+    let user = state.pokemon_by_id(user_id);
+    let staged_stat = |stat_index: StatIndex| {
+        pokemon::calculated_stat(state, user_id, stat_index) as f64 * main_stat_stage_multiplier(user.stat_stage(stat_index))
+    };
+    let category = if staged_stat(StatIndex::Atk) > staged_stat(StatIndex::SpAtk) { MoveCategory::Physical } else { MoveCategory::Special };
+    std_damage(move_, state, user_id, target_id, damage_type, category, power as u16, 0, rng).0
+}
+
+fn feint<R: Rng>(move_: MoveID, state: &mut State, user_id: u8, target_id: u8, rng: &mut R) -> EffectResult {
+    let was_protected = state.pokemon_by_id(target_id).is_protected;
+    // Before gen 5, Feint only works on a protected target
+    if game_version().gen() <= 4 && !was_protected {
+        return EffectResult::Fail;
+    }
+
+    let target = state.pokemon_by_id_mut(target_id);
+    target.is_protected = false;
+    target.protect_streak = 0;
+    if was_protected && state.logs(LogLevel::Verbose) {
+        let target_name = Species::name(state.pokemon_by_id(target_id).species());
+        state.add_display_text(format!("{} fell for the feint!", target_name));
+    }
+    let power = MoveEffect::Feint.power().unwrap();
+    std_damage(move_, state, user_id, target_id, Type::Normal, Move::category(move_), power as u16, 0, rng).0
+}
+
+fn flinch(state: &mut State, action_queue: &[&Action], target_id: u8) -> EffectResult {
+    // The action queue only holds the actions that have yet to be performed this turn
+    let target_has_yet_to_move = action_queue.iter().any(|action| matches!(action, Action::Move { user_id, .. } if *user_id == target_id));
+    if !target_has_yet_to_move {
+        return EffectResult::Skip;
+    }
+
+    state.pokemon_by_id_mut(target_id).is_flinching = true;
+    EffectResult::Success
+}
+
+fn force_switch<R: Rng>(state: &mut State, target_id: u8, rng: &mut R) -> EffectResult {
+    let replacements: Vec<u8> = Side::of(target_id).id_range()
+        .filter(|&id| !state.pokemon_by_id(id).is_fainted() && !state.pokemon_by_id(id).is_on_field())
+        .collect();
+    if replacements.is_empty() {
+        return EffectResult::Fail;
+    }
+
+    let field_position = state.pokemon_by_id(target_id).field_position().unwrap();
+    pokemon::remove_from_field(state, target_id);
+    let replacement_id = replacements[rng.gen_range(0, replacements.len())];
+    if state.logs(LogLevel::Verbose) {
+        let replacement_name = Species::name(state.pokemon_by_id(replacement_id).species());
+        state.add_display_text(format!("{} was dragged out!", replacement_name));
+    }
+    pokemon::add_to_field(state, replacement_id, field_position);
+    EffectResult::Success
+}
+
+fn foresight(state: &mut State, target_id: u8) -> EffectResult {
+    state.pokemon_by_id_mut(target_id).is_identified = true;
+    if state.logs(LogLevel::Verbose) {
+        let target_name = Species::name(state.pokemon_by_id(target_id).species());
+        state.add_display_text(format!("{} was identified!", target_name));
+    }
+    EffectResult::Success
+}
+
+fn giga_drain<R: Rng>(move_: MoveID, state: &mut State, user_id: u8, target_id: u8, rng: &mut R) -> EffectResult {
+    let (result, damage_dealt) = std_damage(move_, state, user_id, target_id, Type::Grass, MoveCategory::Special, MoveEffect::GigaDrain.power().unwrap() as u16, 0, rng);
 
-    if result == EffectResult::Success && !state.has_battle_ended() {
-        if cfg!(feature = "print-battle") {
+    // The user may have fainted from contact recoil
+    if result == EffectResult::Success && !state.has_battle_ended() && !state.pokemon_by_id(user_id).is_fainted() {
+        if state.logs(LogLevel::Verbose) {
             let target_name = Species::name(state.pokemon_by_id(target_id).species());
             state.add_display_text(format!("{} had its health drained!", target_name));
         }
@@ -239,15 +891,59 @@ fn giga_drain(state: &mut State, user_id: u8, target_id: u8, rng: &mut StdRng) -
 
 fn growth(state: &mut State, user_id: u8) -> EffectResult {
     if game_version().gen() <= 4 {
-        pokemon::increment_stat_stage(state, user_id, StatIndex::SpAtk, 1);
+        pokemon::increment_stat_stage(state, user_id, StatIndex::SpAtk, 1, Side::of(user_id));
     } else {
         let requested_amount = if state.weather == Weather::HarshSunshine { 2 } else { 1 };
-        pokemon::increment_stat_stage(state, user_id, StatIndex::Atk, requested_amount);
-        pokemon::increment_stat_stage(state, user_id, StatIndex::SpAtk, requested_amount);
+        pokemon::increment_stat_stage(state, user_id, StatIndex::Atk, requested_amount, Side::of(user_id));
+        pokemon::increment_stat_stage(state, user_id, StatIndex::SpAtk, requested_amount, Side::of(user_id));
     }
     EffectResult::Success
 }
 
+fn heavy_slam_damage<R: Rng>(move_: MoveID, state: &mut State, user_id: u8, target_id: u8, damage_type: Type, rng: &mut R) -> EffectResult {
+    let user_weight = Species::weight(state.pokemon_by_id(user_id).species());
+    let target_weight = Species::weight(state.pokemon_by_id(target_id).species());
+    let power = heavy_slam_power(user_weight, target_weight);
+    std_damage(move_, state, user_id, target_id, damage_type, Move::category(move_), power as u16, 0, rng).0
+}
+
+/// Weights are in tenths of a kilogram.
+fn heavy_slam_power(user_weight: u16, target_weight: u16) -> u8 {
+    match user_weight as u32 / max(target_weight, 1) as u32 {
+        0..=1 => 40,
+        2 => 60,
+        3 => 80,
+        4 => 100,
+        _ => 120
+    }
+}
+
+/// `attack.power` is the power at full HP, or at the lowest HP with `inverse`.
+fn hp_scaled_power<R: Rng>(attack: VariableAttack, state: &mut State, user_id: u8, target_id: u8, inverse: bool, rng: &mut R) -> EffectResult {
+    let user = state.pokemon_by_id(user_id);
+    let power = scaled_power(attack.power, user.current_hp(), user.max_hp(), inverse);
+    attack.with_power(power as u16, state, user_id, target_id, rng)
+}
+
+/// Power in proportion to the user's HP, or for `inverse`, from the bracketed table of Flail and Reversal, where
+/// `max_power` is reached below about 4% HP.
+fn scaled_power(max_power: u8, current_hp: u16, max_hp: u16, inverse: bool) -> u8 {
+    let power = if inverse {
+        let table_power: u32 = match current_hp as u32 * 48 / max_hp as u32 {
+            0..=1 => 200,
+            2..=4 => 150,
+            5..=9 => 100,
+            10..=16 => 80,
+            17..=32 => 40,
+            _ => 20
+        };
+        table_power * max_power as u32 / 200
+    } else {
+        max_power as u32 * current_hp as u32 / max_hp as u32
+    };
+    max(power, 1) as u8
+}
+
 fn leech_seed(state: &mut State, user_id: u8, target_id: u8) -> EffectResult {
     match state.pokemon_by_id(target_id).seeded_by {
         Some(_) => EffectResult::Fail,
@@ -256,7 +952,7 @@ fn leech_seed(state: &mut State, user_id: u8, target_id: u8) -> EffectResult {
                 EffectResult::NoEffect
             } else {
                 state.pokemon_by_id_mut(target_id).seeded_by = state.pokemon_by_id(user_id).field_position();
-                if cfg!(feature = "print-battle") {
+                if state.logs(LogLevel::Verbose) {
                     let target_name = Species::name(state.pokemon_by_id(target_id).species());
                     state.add_display_text(format!("A seed was planted on {}!", target_name));
                 }
@@ -266,57 +962,1151 @@ fn leech_seed(state: &mut State, user_id: u8, target_id: u8) -> EffectResult {
     }
 }
 
-fn poison_powder(state: &mut State, target_id: u8) -> EffectResult {
-    if game_version().gen() >= 6 && state.pokemon_by_id(target_id).is_type(Type::Grass) {
-        return EffectResult::NoEffect;
+/// Moves that call other moves are left out so that Metronome can't chain, as is Struggle, which is only used when
+/// there is no other choice.
+fn can_be_called_by_metronome(move_: MoveID) -> bool {
+    !Move::effects(move_).iter().any(|effect| matches!(effect, MoveEffect::Metronome | MoveEffect::MirrorMove | MoveEffect::Struggle))
+}
+
+fn metronome<R: Rng>(state: &mut State, action_queue: &[&Action], user_id: u8, rng: &mut R) -> EffectResult {
+    let callable_moves: Vec<MoveID> = (0..Move::count()).filter(|&move_| can_be_called_by_metronome(move_)).collect();
+    if callable_moves.is_empty() {
+        return EffectResult::Fail;
     }
-    pokemon::poison(state, target_id, false, false)
+    let called_move = callable_moves[rng.gen_range(0, callable_moves.len())];
+
+    if state.logs(LogLevel::Verbose) {
+        state.add_display_text(format!("Waggling a finger let it use {}!", Move::name(called_move)));
+    }
+    let user_pos = state.pokemon_by_id(user_id).field_position().unwrap();
+    let called_action = Action::Move {
+        user_id,
+        move_: called_move,
+        move_index: None,
+        target_positions: [FieldPosition::Min, FieldPosition::Max].iter().copied()
+            .filter(|field_pos| Move::targeting(called_move).can_hit(user_pos, *field_pos))
+            .collect()
+    };
+    // The called move shows its own failure text. Its result only says whether the battle ended, which the caller
+    // checks after every effect anyway
+    called_action.perform(state, action_queue, rng);
+    EffectResult::Success
 }
 
-fn sleep_powder(state: &mut State, target_id: u8, rng: &mut StdRng) -> EffectResult {
-    if game_version().gen() >= 6 && state.pokemon_by_id(target_id).is_type(Type::Grass) {
-        return EffectResult::NoEffect;
+fn mimic(move_: MoveID, state: &mut State, user_id: u8, target_id: u8) -> EffectResult {
+    let copied_move = match state.pokemon_by_id(target_id).last_move_used {
+        Some(copied_move) => copied_move,
+        None => return EffectResult::Fail
+    };
+    let uncopyable = Move::effects(copied_move).iter().any(|effect| matches!(effect, MoveEffect::Mimic | MoveEffect::Struggle));
+    if uncopyable || state.pokemon_by_id(user_id).known_moves().iter().any(|move_instance| move_instance.move_() == copied_move) {
+        return EffectResult::Fail;
     }
-    pokemon::put_to_sleep(state, target_id, rng)
+
+    if !pokemon::mimic(state, user_id, move_, copied_move) {
+        return EffectResult::Fail;
+    }
+    if state.logs(LogLevel::Verbose) {
+        let user_name = Species::name(state.pokemon_by_id(user_id).species());
+        state.add_display_text(format!("{} learned {}!", user_name, Move::name(copied_move)));
+    }
+    EffectResult::Success
 }
 
-fn struggle(state: &mut State, user_id: u8, target_id: u8, rng: &mut StdRng) -> EffectResult {
-    let (result, damage_dealt) = std_damage(state, user_id, target_id, Type::None, MoveCategory::Physical, 50, 0, rng);
-    if result != EffectResult::Success {
+fn mirror_move<R: Rng>(state: &mut State, action_queue: &[&Action], user_id: u8, target_id: u8, rng: &mut R) -> EffectResult {
+    let mirrored_move = match state.pokemon_by_id(target_id).last_move_used {
+        Some(mirrored_move) => mirrored_move,
+        None => return EffectResult::Fail
+    };
+    if Move::effects(mirrored_move).iter().any(|effect| matches!(effect, MoveEffect::MirrorMove)) {
+        return EffectResult::Fail;
+    }
+
+    let user_pos = state.pokemon_by_id(user_id).field_position().unwrap();
+    let mirrored_action = Action::Move {
+        user_id,
+        move_: mirrored_move,
+        move_index: None,
+        target_positions: [FieldPosition::Min, FieldPosition::Max].iter().copied()
+            .filter(|field_pos| Move::targeting(mirrored_move).can_hit(user_pos, *field_pos))
+            .collect()
+    };
+    mirrored_action.perform(state, action_queue, rng);
+    EffectResult::Success
+}
+
+fn nothing(state: &mut State) -> EffectResult {
+    if state.logs(LogLevel::Verbose) {
+        state.add_display_text(String::from("But nothing happened!"));
+    }
+    EffectResult::Success
+}
+
+fn partial_trap<R: Rng>(move_: MoveID, state: &mut State, user_id: u8, target_id: u8, damage_type: Type, power: u8, rng: &mut R) -> EffectResult {
+    let behind_substitute = pokemon::is_behind_substitute(state, target_id, user_id, move_);
+    let result = std_damage(move_, state, user_id, target_id, damage_type, Move::category(move_), power as u16, 0, rng).0;
+    let target = state.pokemon_by_id(target_id);
+    if result != EffectResult::Success || behind_substitute || target.is_fainted() || target.partial_trap_counter.has_target() {
         return result;
     }
 
-    match game_version().gen() {
-        1..=3 => recoil(state, user_id, damage_dealt, 4),
-        _ => recoil(state, user_id, state.pokemon_by_id(user_id).max_hp(), 4)
+    let num_turns = if game_version().gen() >= 5 {
+        rng.gen_range(4, 6)
+    } else {
+        [2, 2, 2, 3, 3, 3, 4, 5][rng.gen_range(0, 8)]
+    };
+    state.pokemon_by_id_mut(target_id).partial_trap_counter = Counter::new(Some(num_turns));
+    if state.logs(LogLevel::Verbose) {
+        let target_name = Species::name(state.pokemon_by_id(target_id).species());
+        state.add_display_text(format!("{} was trapped!", target_name));
     }
+    result
 }
 
-fn sunny_day(state: &mut State) -> EffectResult {
-    if (game_version().gen() >= 3 && state.weather == Weather::HarshSunshine) || (game_version().gen() >= 5 && matches!(state.weather, Weather::HeavyRain | Weather::ExtremelyHarshSunshine | Weather::StrongWinds)) {
+fn poison_powder(state: &mut State, target_id: u8) -> EffectResult {
+    if game_version().gen() >= 6 && state.pokemon_by_id(target_id).is_type(Type::Grass) {
+        return EffectResult::NoEffect;
+    }
+    pokemon::poison(state, target_id, false, false)
+}
+
+fn protect<R: Rng>(state: &mut State, action_queue: &[&Action], user_id: u8, rng: &mut R) -> EffectResult {
+    // There is nothing left to protect against if the user moves last
+    let protect_streak = state.pokemon_by_id(user_id).protect_streak;
+    let success_chance = if game_version().gen() <= 5 { 0.5 } else { 1.0 / 3.0 };
+    if action_queue.is_empty() || !rng.gen_bool(f64::powi(success_chance, protect_streak as i32)) {
+        state.pokemon_by_id_mut(user_id).protect_streak = 0;
         return EffectResult::Fail;
     }
 
-    state.weather = Weather::HarshSunshine;
-    state.weather_counter = Counter::new(Some(5));
-    if cfg!(feature = "print-battle") {
-        state.add_display_text(Weather::HarshSunshine.display_text_on_appearance().to_owned());
+    let user = state.pokemon_by_id_mut(user_id);
+    user.is_protected = true;
+    user.protect_streak = protect_streak.saturating_add(1);
+    if state.logs(LogLevel::Verbose) {
+        let user_name = Species::name(state.pokemon_by_id(user_id).species());
+        state.add_display_text(format!("{} protected itself!", user_name));
     }
     EffectResult::Success
 }
 
-fn synthesis(state: &mut State, user_id: u8) -> EffectResult {
-    if cfg!(feature = "print-battle") {
-        let species_name = Species::name(state.pokemon_by_id(user_id).species());
-        state.add_display_text(format!("{} restored its HP!", species_name));
+fn raise_random_stat<R: Rng>(state: &mut State, user_id: u8, target_id: u8, amount: i8, rng: &mut R) -> EffectResult {
+    let target = state.pokemon_by_id(target_id);
+    let candidates: Vec<StatIndex> = [StatIndex::Atk, StatIndex::Def, StatIndex::SpAtk, StatIndex::SpDef, StatIndex::Spd, StatIndex::Acc, StatIndex::Eva]
+        .iter()
+        .copied()
+        .filter(|stat_index| target.stat_stage(*stat_index) < 6)
+        .collect();
+    if candidates.is_empty() {
+        return EffectResult::Fail;
     }
 
-    let mut max_hp = state.pokemon_by_id(user_id).current_hp() as i16;
-    match state.weather {
-        Weather::None | Weather::StrongWinds => max_hp /= 2,
-        Weather::HarshSunshine => max_hp = max_hp * 2 / 3,
-        _ => max_hp /= 4
-    }
-    pokemon::apply_damage(state, user_id, -max_hp);
+    state.rng_draws.stat_picks += 1;
+    let stat_index = candidates[rng.gen_range(0, candidates.len())];
+    pokemon::increment_stat_stage(state, target_id, stat_index, amount, Side::of(user_id));
     EffectResult::Success
 }
+
+fn rampage_lock<R: Rng>(move_: MoveID, state: &mut State, user_id: u8, target_id: u8, damage_type: Type, power: u8, rng: &mut R) -> EffectResult {
+    let result = std_damage(move_, state, user_id, target_id, damage_type, Move::category(move_), power as u16, 0, rng).0;
+    if state.has_battle_ended() || state.pokemon_by_id(user_id).is_fainted() {
+        return result;
+    }
+    // A target immune to the move stops the rampage just like a miss, and keeps it from starting
+    if result != EffectResult::Success {
+        disrupt_rampage(state, user_id, rng);
+        return result;
+    }
+
+    let user = state.pokemon_by_id_mut(user_id);
+    if !user.rampage_counter.has_target() {
+        user.rampage_counter = Counter::new(Some(rng.gen_range(2, 4)));
+    }
+    if user.rampage_counter.inc() {
+        confuse_from_fatigue(state, user_id, rng);
+    } else {
+        // The repeats cost no PP and hit whichever Pokemon is on the opposing side
+        user.next_move_action = Some(Action::Move {
+            user_id,
+            move_,
+            move_index: None,
+            target_positions: vec![if user_id < 6 { FieldPosition::Max } else { FieldPosition::Min }]
+        });
+    }
+    result
+}
+
+/// Ends the user's rampage early, as happens when a turn of it misses, fails or is stopped. The user only becomes
+/// confused if it was the rampage's last turn anyway. Does nothing if the user isn't rampaging.
+pub fn disrupt_rampage<R: Rng>(state: &mut State, user_id: u8, rng: &mut R) {
+    let user = state.pokemon_by_id_mut(user_id);
+    if !user.rampage_counter.has_target() {
+        return;
+    }
+    user.next_move_action = None;
+    if user.rampage_counter.inc() {
+        confuse_from_fatigue(state, user_id, rng);
+    } else {
+        user.rampage_counter.clear();
+    }
+}
+
+fn confuse_from_fatigue<R: Rng>(state: &mut State, user_id: u8, rng: &mut R) {
+    if state.logs(LogLevel::Verbose) {
+        let user_name = Species::name(state.pokemon_by_id(user_id).species());
+        state.add_display_text(format!("{} became confused due to fatigue!", user_name));
+    }
+    pokemon::confuse(state, user_id, rng);
+}
+
+fn random_status<R: Rng>(state: &mut State, target_id: u8, chance: u8, candidates: &[MajorStatusAilment], rng: &mut R) -> EffectResult {
+    state.rng_draws.status_rolls += 1;
+    if rng.gen_range(0, 100) >= chance {
+        return EffectResult::Skip;
+    }
+
+    let result = match candidates[rng.gen_range(0, candidates.len())] {
+        MajorStatusAilment::Okay => EffectResult::Skip,
+        MajorStatusAilment::Asleep => pokemon::put_to_sleep(state, target_id, rng),
+        MajorStatusAilment::Poisoned => pokemon::poison(state, target_id, false, false),
+        MajorStatusAilment::BadlyPoisoned => pokemon::poison(state, target_id, true, false),
+        MajorStatusAilment::Paralyzed => pokemon::paralyze(state, target_id),
+        MajorStatusAilment::Burned => pokemon::burn(state, target_id),
+        MajorStatusAilment::Frozen => pokemon::freeze(state, target_id)
+    };
+    if result == EffectResult::Success { EffectResult::Success } else { EffectResult::Skip }
+}
+
+fn recover(state: &mut State, user_id: u8) -> EffectResult {
+    let max_hp = state.pokemon_by_id(user_id).max_hp();
+    if state.pokemon_by_id(user_id).current_hp() == max_hp {
+        return EffectResult::Fail;
+    }
+
+    if state.logs(LogLevel::Verbose) {
+        let species_name = Species::name(state.pokemon_by_id(user_id).species());
+        state.add_display_text(format!("{} restored its HP!", species_name));
+    }
+    pokemon::apply_damage(state, user_id, -max(max_hp as i16 / 2, 1));
+    EffectResult::Success
+}
+
+fn rest(state: &mut State, user_id: u8) -> EffectResult {
+    let user = state.pokemon_by_id(user_id);
+    if user.current_hp() == user.max_hp() || user.major_status_ailment() == MajorStatusAilment::Asleep {
+        return EffectResult::Fail;
+    }
+
+    pokemon::sleep_from_rest(state, user_id);
+    EffectResult::Success
+}
+
+fn retaliate(move_: MoveID, state: &mut State, user_id: u8, category: Option<MoveCategory>, percent: u16) -> EffectResult {
+    let user = state.pokemon_by_id(user_id);
+    let (attacker_id, damage_taken) = match (user.last_attacker, user.last_attack_damage) {
+        (Some(attacker_id), Some((damage_category, damage_taken))) if category.is_none_or(|category| category == damage_category) => (attacker_id, damage_taken),
+        _ => return EffectResult::Fail
+    };
+    let attacker = state.pokemon_by_id(attacker_id);
+    if attacker.is_fainted() || !attacker.is_on_field() {
+        return EffectResult::Fail;
+    }
+    if almost::zero(Move::type_(move_).effectiveness(attacker.first_type(), attacker.second_type())) {
+        return EffectResult::NoEffect;
+    }
+
+    let damage = max(damage_taken as u32 * percent as u32 / 100, 1);
+    if pokemon::is_behind_substitute(state, attacker_id, user_id, move_) {
+        pokemon::damage_substitute(state, attacker_id, min(damage, u16::MAX as u32) as u16);
+    } else {
+        pokemon::apply_damage(state, attacker_id, min(damage, i16::MAX as u32) as i16);
+    }
+    EffectResult::Success
+}
+
+fn roost(state: &mut State, user_id: u8) -> EffectResult {
+    let result = recover(state, user_id);
+    if result == EffectResult::Success {
+        pokemon::start_roost(state, user_id);
+    }
+    result
+}
+
+fn set_user_stat_stage(state: &mut State, user_id: u8, stat_index: StatIndex, stage: i8) -> EffectResult {
+    if state.pokemon_by_id(user_id).stat_stage(stat_index) == stage {
+        return EffectResult::Fail;
+    }
+
+    pokemon::set_stat_stage(state, user_id, stat_index, stage);
+    if state.logs(LogLevel::Verbose) {
+        let user_name = Species::name(state.pokemon_by_id(user_id).species());
+        state.add_display_text(format!("{}'s {} stage became {:+}!", user_name, stat_index.name(), stage));
+    }
+    EffectResult::Success
+}
+
+fn sleep_powder<R: Rng>(state: &mut State, target_id: u8, rng: &mut R) -> EffectResult {
+    if game_version().gen() >= 6 && state.pokemon_by_id(target_id).is_type(Type::Grass) {
+        return EffectResult::NoEffect;
+    }
+    pokemon::put_to_sleep(state, target_id, rng)
+}
+
+fn spikes(state: &mut State, target_id: u8) -> EffectResult {
+    let side_conditions = if target_id < 6 { &mut state.min.side_conditions } else { &mut state.max.side_conditions };
+    if side_conditions.spikes_layers >= 3 {
+        return EffectResult::Fail;
+    }
+
+    side_conditions.spikes_layers += 1;
+    if state.logs(LogLevel::Verbose) {
+        state.add_display_text(String::from("Spikes were scattered all around the feet of the foe's team!"));
+    }
+    EffectResult::Success
+}
+
+fn stat_stage_scaled_power<R: Rng>(attack: VariableAttack, state: &mut State, user_id: u8, target_id: u8, uses_user_stages: bool, rng: &mut R) -> EffectResult {
+    let counted = state.pokemon_by_id(if uses_user_stages { user_id } else { target_id });
+    let stat_indices = [StatIndex::Atk, StatIndex::Def, StatIndex::SpAtk, StatIndex::SpDef, StatIndex::Spd, StatIndex::Acc, StatIndex::Eva];
+    let positive_stages: u32 = stat_indices.iter().map(|stat_index| max(counted.stat_stage(*stat_index), 0) as u32).sum();
+    // Stored Power has no cap in the games, but its power only fits in a u8 up to 11 positive stages
+    let max_power = if uses_user_stages { u8::MAX as u32 } else { 200 };
+    let power = min(attack.power as u32 + 20 * positive_stages, max_power);
+    attack.with_power(power as u16, state, user_id, target_id, rng)
+}
+
+fn struggle<R: Rng>(move_: MoveID, state: &mut State, user_id: u8, target_id: u8, rng: &mut R) -> EffectResult {
+    let (result, damage_dealt) = std_damage(move_, state, user_id, target_id, Type::None, MoveCategory::Physical, 50, 0, rng);
+    if result != EffectResult::Success || state.pokemon_by_id(user_id).is_fainted() {
+        return result;
+    }
+
+    match game_version().gen() {
+        3 => recoil(state, user_id, damage_dealt, 4),
+        _ => recoil(state, user_id, state.pokemon_by_id(user_id).max_hp(), 4)
+    }
+}
+
+fn substitute(state: &mut State, user_id: u8) -> EffectResult {
+    let cost;
+    {
+        let user = state.pokemon_by_id(user_id);
+        cost = user.max_hp() / 4;
+        if user.substitute_hp.is_some() || user.current_hp() <= cost {
+            return EffectResult::Fail;
+        }
+    }
+
+    pokemon::apply_damage(state, user_id, cost as i16);
+    state.pokemon_by_id_mut(user_id).substitute_hp = Some(cost);
+    if state.logs(LogLevel::Verbose) {
+        let user_name = Species::name(state.pokemon_by_id(user_id).species());
+        state.add_display_text(format!("{} put in a substitute!", user_name));
+    }
+    EffectResult::Success
+}
+
+fn sunny_day(state: &mut State) -> EffectResult {
+    if state.rules.pure_stats {
+        return EffectResult::Fail;
+    }
+    if (game_version().gen() >= 3 && state.weather == Weather::HarshSunshine) || (game_version().gen() >= 5 && matches!(state.weather, Weather::HeavyRain | Weather::ExtremelyHarshSunshine | Weather::StrongWinds)) {
+        return EffectResult::Fail;
+    }
+
+    state.weather = Weather::HarshSunshine;
+    state.weather_counter = Counter::new(Some(5));
+    if state.logs(LogLevel::Verbose) {
+        state.add_display_text(Weather::HarshSunshine.display_text_on_appearance().to_owned());
+    }
+    EffectResult::Success
+}
+
+fn synthesis(state: &mut State, user_id: u8) -> EffectResult {
+    if state.logs(LogLevel::Verbose) {
+        let species_name = Species::name(state.pokemon_by_id(user_id).species());
+        state.add_display_text(format!("{} restored its HP!", species_name));
+    }
+
+    let max_hp = state.pokemon_by_id(user_id).max_hp() as i16;
+    let amount = match state.weather {
+        Weather::None | Weather::StrongWinds => max_hp / 2,
+        Weather::HarshSunshine => max_hp * 2 / 3,
+        _ => max_hp / 4
+    };
+    pokemon::apply_damage(state, user_id, -max(amount, 1));
+    EffectResult::Success
+}
+
+/// Lasts for the turn it's used and the next two in gen 4, or the next three in later generations.
+fn tailwind(state: &mut State, user_id: u8) -> EffectResult {
+    let side_conditions = if user_id < 6 { &mut state.min.side_conditions } else { &mut state.max.side_conditions };
+    if side_conditions.tailwind_counter.has_target() {
+        return EffectResult::Fail;
+    }
+
+    side_conditions.tailwind_counter = Counter::new(Some(if game_version().gen() == 4 { 3 } else { 4 }));
+    if state.logs(LogLevel::Verbose) {
+        state.add_display_text(String::from("The tailwind blew from behind the team!"));
+    }
+    EffectResult::Success
+}
+
+fn toxic_spikes(state: &mut State, target_id: u8) -> EffectResult {
+    let side_conditions = if target_id < 6 { &mut state.min.side_conditions } else { &mut state.max.side_conditions };
+    if side_conditions.toxic_spikes_layers >= 2 {
+        return EffectResult::Fail;
+    }
+
+    side_conditions.toxic_spikes_layers += 1;
+    if state.logs(LogLevel::Verbose) {
+        state.add_display_text(String::from("Poison spikes were scattered all around the feet of the foe's team!"));
+    }
+    EffectResult::Success
+}
+
+fn transform(state: &mut State, user_id: u8, target_id: u8) -> EffectResult {
+    let target = state.pokemon_by_id(target_id);
+    if state.pokemon_by_id(user_id).is_transformed() || target.is_transformed() || target.substitute_hp.is_some() {
+        return EffectResult::Fail;
+    }
+
+    pokemon::transform(state, user_id, target_id);
+    if state.logs(LogLevel::Verbose) {
+        let user_name = Species::name(state.pokemon_by_id(user_id).species());
+        let target_name = Species::name(state.pokemon_by_id(target_id).species());
+        state.add_display_text(format!("{} transformed into {}!", user_name, target_name));
+    }
+    EffectResult::Success
+}
+
+fn trap_target(state: &mut State, target_id: u8) -> EffectResult {
+    if state.pokemon_by_id(target_id).is_trapped {
+        return EffectResult::Fail;
+    }
+
+    state.pokemon_by_id_mut(target_id).is_trapped = true;
+    if state.logs(LogLevel::Verbose) {
+        let target_name = Species::name(state.pokemon_by_id(target_id).species());
+        state.add_display_text(format!("{} can no longer escape!", target_name));
+    }
+    EffectResult::Success
+}
+
+fn weather_ball<R: Rng>(move_: MoveID, state: &mut State, user_id: u8, target_id: u8, rng: &mut R) -> EffectResult {
+    let (damage_type, power) = match state.weather {
+        Weather::HarshSunshine | Weather::ExtremelyHarshSunshine => (Type::Fire, 100),
+        Weather::Rain | Weather::HeavyRain => (Type::Water, 100),
+        Weather::Hail => (Type::Ice, 100),
+        Weather::Sandstorm => (Type::Rock, 100),
+        Weather::StrongWinds | Weather::Fog => (Type::Normal, 100),
+        Weather::None => (Type::Normal, 50)
+    };
+    // Before the physical/special split, the category follows the type the move turned into
+    let category = if game_version().gen() <= 3 { damage_type.category() } else { Move::category(move_) };
+    std_damage(move_, state, user_id, target_id, damage_type, category, power, 0, rng).0
+}
+
+fn weight_based_damage<R: Rng>(move_: MoveID, state: &mut State, user_id: u8, target_id: u8, damage_type: Type, rng: &mut R) -> EffectResult {
+    let power = weight_based_power(Species::weight(state.pokemon_by_id(target_id).species()));
+    std_damage(move_, state, user_id, target_id, damage_type, Move::category(move_), power as u16, 0, rng).0
+}
+
+/// The target's weight is in tenths of a kilogram.
+fn weight_based_power(target_weight: u16) -> u8 {
+    match target_weight {
+        0..=99 => 20,
+        100..=249 => 40,
+        250..=499 => 60,
+        500..=999 => 80,
+        1000..=1999 => 100,
+        _ => 120
+    }
+}
+
+fn yawn(state: &mut State, user_id: u8, target_id: u8) -> EffectResult {
+    let target = state.pokemon_by_id(target_id);
+    if target.major_status_ailment() != MajorStatusAilment::Okay || target.drowsy_counter > 0 {
+        return EffectResult::Fail;
+    }
+
+    // Counts down once at the end of this turn and once at the end of the next
+    state.pokemon_by_id_mut(target_id).drowsy_counter = 2;
+    if state.logs(LogLevel::Verbose) {
+        let display_text = format!("{} made {} drowsy!", state.pokemon_by_id(user_id), state.pokemon_by_id(target_id));
+        state.add_display_text(display_text);
+    }
+    EffectResult::Success
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::mock::StepRng;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashMap;
+
+    #[test]
+    fn main_stat_stage_multipliers() {
+        let table = [
+            (-6, 2.0 / 8.0), (-5, 2.0 / 7.0), (-4, 2.0 / 6.0), (-3, 2.0 / 5.0), (-2, 2.0 / 4.0), (-1, 2.0 / 3.0), (0, 1.0),
+            (1, 1.5), (2, 2.0), (3, 2.5), (4, 3.0), (5, 3.5), (6, 4.0)
+        ];
+        for (stage, multiplier) in table {
+            assert_eq!(main_stat_stage_multiplier(stage), multiplier, "stage {}", stage);
+        }
+    }
+
+    #[test]
+    fn accuracy_stat_stage_multipliers() {
+        let table = [
+            (-6, 3.0 / 9.0), (-5, 3.0 / 8.0), (-4, 3.0 / 7.0), (-3, 3.0 / 6.0), (-2, 3.0 / 5.0), (-1, 3.0 / 4.0), (0, 1.0),
+            (1, 4.0 / 3.0), (2, 5.0 / 3.0), (3, 2.0), (4, 7.0 / 3.0), (5, 8.0 / 3.0), (6, 3.0)
+        ];
+        for (stage, multiplier) in table {
+            assert_eq!(accuracy_stat_stage_multiplier(stage), multiplier, "stage {}", stage);
+        }
+    }
+
+    #[test]
+    fn critical_hit_ignores_attack_drops_but_not_boosts() {
+        let damage = |attack_stage, critical_hit| std_base_damage(80, 200, 100, attack_stage, 0, critical_hit);
+        assert_eq!(damage(-2, true), damage(0, false));
+        assert_eq!(damage(2, true), damage(2, false));
+        assert_eq!(damage(-2, false), std_base_damage(80, 100, 100, 0, 0, false));
+        assert_eq!(damage(2, false), std_base_damage(80, 400, 100, 0, 0, false));
+    }
+
+    #[test]
+    fn critical_hit_ignores_defense_boosts_but_not_drops() {
+        let damage = |defense_stage, critical_hit| std_base_damage(80, 100, 200, 0, defense_stage, critical_hit);
+        assert_eq!(damage(2, true), damage(0, false));
+        assert_eq!(damage(-2, true), damage(-2, false));
+        assert_eq!(damage(-2, false), std_base_damage(80, 100, 100, 0, 0, false));
+        assert_eq!(damage(2, false), std_base_damage(80, 100, 400, 0, 0, false));
+    }
+
+    /// HP the defender (ID 0) is left with after `attack` from the attacker (ID 6).
+    fn hp_after<F: FnOnce(&mut State, &mut StepRng)>(state: &State, attack: F) -> u16 {
+        let mut state = state.clone();
+        attack(&mut state, &mut StepRng::new(0, 0));
+        state.pokemon_by_id(0).current_hp()
+    }
+
+    #[test]
+    fn stored_power_gains_20_per_positive_user_stage() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        // Stages that don't change the damage calculation itself; the drop doesn't count
+        state.pokemon_by_id_mut(6).set_stat_stages([0, 0, 1, 0, -2, 0, 1, 1]).unwrap();
+        let attack = VariableAttack { move_: Move::id_by_name("Tackle").unwrap(), damage_type: Type::Normal, power: 20 };
+
+        let stored_power = hp_after(&state, |state, rng| { stat_stage_scaled_power(attack, state, 6, 0, true, rng); });
+        assert_eq!(stored_power, hp_after(&state, |state, rng| { attack.with_power(80, state, 6, 0, rng); }));
+    }
+
+    #[test]
+    fn punishment_gains_20_per_positive_target_stage() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.pokemon_by_id_mut(0).set_stat_stages([0, 2, 0, 0, 0, 1, 0, 0]).unwrap();
+        let attack = VariableAttack { move_: Move::id_by_name("Tackle").unwrap(), damage_type: Type::Dark, power: 60 };
+
+        let punishment = hp_after(&state, |state, rng| { stat_stage_scaled_power(attack, state, 6, 0, false, rng); });
+        assert_eq!(punishment, hp_after(&state, |state, rng| { attack.with_power(120, state, 6, 0, rng); }));
+        assert!(punishment < hp_after(&state, |state, rng| { attack.with_power(60, state, 6, 0, rng); }));
+    }
+
+    /// HP the defender is left with after a Giga Drain-based attack of the given type and power.
+    fn hp_after_special_attack(state: &State, damage_type: Type, power: u16) -> u16 {
+        let giga_drain = Move::id_by_name("Giga Drain").unwrap();
+        hp_after(state, |state, rng| { std_damage(giga_drain, state, 6, 0, damage_type, MoveCategory::Special, power, 0, rng); })
+    }
+
+    #[test]
+    fn weather_ball_is_a_doubled_fire_move_in_harsh_sun() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.weather = Weather::HarshSunshine;
+        let giga_drain = Move::id_by_name("Giga Drain").unwrap();
+
+        let weather_ball = hp_after(&state, |state, rng| { weather_ball(giga_drain, state, 6, 0, rng); });
+        assert_eq!(weather_ball, hp_after_special_attack(&state, Type::Fire, 100));
+    }
+
+    #[test]
+    fn weather_ball_is_a_single_power_normal_move_in_clear_weather() {
+        let _data = crate::load_test_data();
+        let state = crate::test_matchup();
+        let giga_drain = Move::id_by_name("Giga Drain").unwrap();
+
+        let weather_ball = hp_after(&state, |state, rng| { weather_ball(giga_drain, state, 6, 0, rng); });
+        assert_eq!(weather_ball, hp_after_special_attack(&state, Type::Normal, 50));
+    }
+
+    #[test]
+    fn weather_ball_doubles_in_weather_that_does_not_change_its_type() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let giga_drain = Move::id_by_name("Giga Drain").unwrap();
+        for weather in [Weather::StrongWinds, Weather::Fog] {
+            state.weather = weather;
+            let weather_ball = hp_after(&state, |state, rng| { weather_ball(giga_drain, state, 6, 0, rng); });
+            assert_eq!(weather_ball, hp_after_special_attack(&state, Type::Normal, 100), "{:?}", weather);
+        }
+    }
+
+    #[test]
+    fn raise_random_stat_raises_one_stat_chosen_by_the_seed() {
+        let _data = crate::load_test_data();
+        let state = crate::test_matchup();
+        let raised_stages = |seed| {
+            let mut state = state.clone();
+            let result = raise_random_stat(&mut state, 6, 6, 2, &mut StdRng::seed_from_u64(seed));
+            assert!(result == EffectResult::Success);
+            assert_eq!(state.rng_draws.stat_picks, 1);
+            state.pokemon_by_id(6).stat_stages()
+        };
+
+        let stages = raised_stages(0);
+        assert_eq!(stages[0], 0);
+        assert_eq!(stages.iter().filter(|&&stage| stage == 2).count(), 1);
+        assert_eq!(stages.iter().filter(|&&stage| stage == 0).count(), 7);
+        assert_eq!(raised_stages(0), stages);
+    }
+
+    #[test]
+    fn raise_random_stat_skips_maxed_stats() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.pokemon_by_id_mut(6).set_stat_stages([0, 6, 6, 6, 0, 6, 6, 6]).unwrap();
+        for seed in 0..20 {
+            let mut state = state.clone();
+            raise_random_stat(&mut state, 6, 6, 2, &mut StdRng::seed_from_u64(seed));
+            assert_eq!(state.pokemon_by_id(6).stat_stage(StatIndex::SpDef), 2, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn raise_random_stat_fails_when_every_stat_is_maxed() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.pokemon_by_id_mut(6).set_stat_stages([0, 6, 6, 6, 6, 6, 6, 6]).unwrap();
+
+        let result = raise_random_stat(&mut state, 6, 6, 2, &mut StdRng::seed_from_u64(0));
+        assert!(result == EffectResult::Fail);
+        assert_eq!(state.rng_draws.stat_picks, 0);
+    }
+
+    #[test]
+    fn force_switch_drags_in_a_benched_pokemon_onto_the_hazards() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(2), &crate::test_team(1));
+        state.min.side_conditions.spikes_layers = 1;
+
+        let result = force_switch(&mut state, 0, &mut StepRng::new(0, 0));
+        assert!(result == EffectResult::Success);
+        assert!(!state.pokemon_by_id(0).is_on_field());
+        assert_eq!(state.min.on_field, Some(1));
+        let replacement = state.pokemon_by_id(1);
+        assert_eq!(replacement.current_hp(), replacement.max_hp() - replacement.max_hp() / 8);
+    }
+
+    #[test]
+    fn force_switch_fails_without_a_bench() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+
+        let result = force_switch(&mut state, 0, &mut StepRng::new(0, 0));
+        assert!(result == EffectResult::Fail);
+        assert_eq!(state.min.on_field, Some(0));
+    }
+
+    #[test]
+    fn metronome_uses_a_callable_move_under_any_seed() {
+        let _data = crate::load_test_data();
+        let state = crate::test_matchup();
+        for seed in 0..200 {
+            let mut state = state.clone();
+            let result = metronome(&mut state, &[], 6, &mut StdRng::seed_from_u64(seed));
+            assert!(result == EffectResult::Success, "seed {}", seed);
+            let called_move = state.pokemon_by_id(6).last_move_used.unwrap();
+            assert!(can_be_called_by_metronome(called_move), "seed {} called {}", seed, Move::name(called_move));
+        }
+    }
+
+    #[test]
+    fn punishment_comes_a_generation_before_stored_power() {
+        assert!(MoveEffect::StatStageScaledPower(Type::Dark, 60, false).available_in(4));
+        assert!(!MoveEffect::StatStageScaledPower(Type::Psychic, 20, true).available_in(4));
+        assert!(MoveEffect::StatStageScaledPower(Type::Psychic, 20, true).available_in(5));
+    }
+
+    /// HP the attacker (ID 6) is left with after hitting the defender (ID 0) with the named physical Normal move.
+    fn attacker_hp_after_physical_hit(state: &State, move_name: &str) -> u16 {
+        let mut state = state.clone();
+        let move_ = Move::id_by_name(move_name).unwrap();
+        std_damage(move_, &mut state, 6, 0, Type::Normal, MoveCategory::Physical, 40, 0, &mut StepRng::new(0, 0));
+        state.pokemon_by_id(6).current_hp()
+    }
+
+    #[test]
+    fn rocky_helmet_hurts_a_contact_attacker() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.pokemon_by_id_mut(0).item = Some(Item::id_by_name("Rocky Helmet").unwrap());
+        let max_hp = state.pokemon_by_id(6).max_hp();
+
+        assert_eq!(attacker_hp_after_physical_hit(&state, "Tackle"), max_hp - max_hp / 6);
+        assert_eq!(attacker_hp_after_physical_hit(&state, "Razor Leaf"), max_hp);
+    }
+
+    #[test]
+    fn rough_skin_hurts_a_contact_attacker() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.pokemon_by_id_mut(0).ability = Ability::id_by_name("Rough Skin").unwrap();
+        let max_hp = state.pokemon_by_id(6).max_hp();
+
+        assert_eq!(attacker_hp_after_physical_hit(&state, "Tackle"), max_hp - max_hp / 8);
+        assert_eq!(attacker_hp_after_physical_hit(&state, "Razor Leaf"), max_hp);
+    }
+
+    #[test]
+    fn static_sometimes_paralyzes_a_contact_attacker() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.pokemon_by_id_mut(0).ability = Ability::id_by_name("Static").unwrap();
+        let num_paralyzed = |move_name| (0..100).filter(|&seed| {
+            let mut state = state.clone();
+            let move_ = Move::id_by_name(move_name).unwrap();
+            std_damage(move_, &mut state, 6, 0, Type::Normal, MoveCategory::Physical, 40, 0, &mut StdRng::seed_from_u64(seed));
+            state.pokemon_by_id(6).major_status_ailment() == MajorStatusAilment::Paralyzed
+        }).count();
+
+        let contact = num_paralyzed("Tackle");
+        assert!(contact > 0 && contact < 100, "{} of 100 paralyzed", contact);
+        assert_eq!(num_paralyzed("Razor Leaf"), 0);
+    }
+
+    #[test]
+    fn foresight_lets_normal_moves_hit_a_ghost() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.pokemon_by_id_mut(0).first_type = Type::Ghost;
+        let tackle = Move::id_by_name("Tackle").unwrap();
+        let hit = |state: &mut State| std_damage(tackle, state, 6, 0, Type::Normal, MoveCategory::Physical, 40, 0, &mut StepRng::new(0, 0)).0;
+
+        assert!(hit(&mut state.clone()) == EffectResult::NoEffect);
+        assert!(foresight(&mut state, 0) == EffectResult::Success);
+        assert!(hit(&mut state) == EffectResult::Success);
+    }
+
+    #[test]
+    fn critical_hit_chance_follows_the_generation() {
+        let _data = crate::load_test_data();
+        let mut dp = crate::GameData::load(GameVersion::DP).unwrap();
+        let mut usum = crate::GameData::load(GameVersion::USUM).unwrap();
+
+        assert_eq!(critical_hit_chance(0), 1.0 / 16.0);
+        assert_eq!(critical_hit_chance(2), 1.0 / 2.0);
+        assert_eq!(dp.with_active(|| critical_hit_chance(2)), 1.0 / 4.0);
+        assert_eq!(usum.with_active(|| critical_hit_chance(0)), 1.0 / 24.0);
+    }
+
+    #[test]
+    fn secondary_chance_sometimes_boosts_the_user() {
+        let _data = crate::load_test_data();
+        let state = crate::test_matchup();
+        let tackle = Move::id_by_name("Tackle").unwrap();
+        let secondary = MoveEffect::SecondaryChance(10, Box::new(MoveEffect::Growth));
+
+        let mut num_boosted = 0;
+        for seed in 0..200 {
+            let mut state = state.clone();
+            let result = secondary.do_effect(tackle, &mut state, &[], 6, 0, &mut StdRng::seed_from_u64(seed));
+            let boosted = state.pokemon_by_id(6).stat_stage(StatIndex::SpAtk) > 0;
+            assert_eq!(result == EffectResult::Success, boosted, "seed {}", seed);
+            assert!(boosted || result == EffectResult::Skip, "seed {}", seed);
+            num_boosted += boosted as usize;
+        }
+        assert!(num_boosted > 0 && num_boosted < 50, "{} of 200 boosted", num_boosted);
+    }
+
+    #[test]
+    fn substitute_costs_a_quarter_of_max_hp() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let max_hp = state.pokemon_by_id(0).max_hp();
+
+        assert!(substitute(&mut state, 0) == EffectResult::Success);
+        assert_eq!(state.pokemon_by_id(0).current_hp(), max_hp - max_hp / 4);
+        assert_eq!(state.pokemon_by_id(0).substitute_hp, Some(max_hp / 4));
+        assert!(substitute(&mut state, 0) == EffectResult::Fail);
+    }
+
+    #[test]
+    fn substitute_takes_the_damage_instead() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        substitute(&mut state, 0);
+        let hp = state.pokemon_by_id(0).current_hp();
+        let substitute_hp = state.pokemon_by_id(0).substitute_hp.unwrap();
+        let tackle = Move::id_by_name("Tackle").unwrap();
+
+        let (_, damage) = std_damage(tackle, &mut state, 6, 0, Type::Normal, MoveCategory::Physical, 10, 0, &mut StepRng::new(0, 0));
+        assert!(damage > 0);
+        assert_eq!(state.pokemon_by_id(0).current_hp(), hp);
+        assert_eq!(state.pokemon_by_id(0).substitute_hp, Some(substitute_hp - damage));
+    }
+
+    #[test]
+    fn sound_moves_bypass_a_substitute() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        substitute(&mut state, 0);
+
+        assert!(pokemon::is_behind_substitute(&state, 0, 6, Move::id_by_name("Tackle").unwrap()));
+        assert!(!pokemon::is_behind_substitute(&state, 0, 6, Move::id_by_name("Growl").unwrap()));
+    }
+
+    #[test]
+    fn baton_pass_hands_the_stat_stages_to_the_replacement() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(1), &crate::test_team(2));
+        pokemon::increment_stat_stage(&mut state, 6, StatIndex::Atk, 2, Side::Max);
+
+        assert!(baton_pass(&mut state, 6) == EffectResult::Success);
+        assert!(!state.pokemon_by_id(6).is_on_field());
+        pokemon::add_to_field(&mut state, 7, FieldPosition::Max);
+        assert_eq!(state.pokemon_by_id(7).stat_stage(StatIndex::Atk), 2);
+    }
+
+    #[test]
+    fn tinted_lens_doubles_a_resisted_hit() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.pokemon_by_id_mut(6).ability = Ability::id_by_name("Tinted Lens").unwrap();
+
+        let resisted = DamageCalc::new(&state, 6, 0, Type::Grass, MoveCategory::Special, 90).unwrap();
+        assert_eq!(resisted.type_effectiveness, 0.25);
+        assert_eq!(resisted.ability_modifier, 2.0);
+        let neutral = DamageCalc::new(&state, 6, 0, Type::Normal, MoveCategory::Special, 90).unwrap();
+        assert_eq!(neutral.ability_modifier, 1.0);
+    }
+
+    #[test]
+    fn filter_and_solid_rock_soften_a_super_effective_hit() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        for ability in ["Filter", "Solid Rock"] {
+            state.pokemon_by_id_mut(0).ability = Ability::id_by_name(ability).unwrap();
+
+            let super_effective = DamageCalc::new(&state, 6, 0, Type::Fire, MoveCategory::Special, 90).unwrap();
+            assert_eq!(super_effective.type_effectiveness, 2.0);
+            assert_eq!(super_effective.ability_modifier, 0.75, "{}", ability);
+            let neutral = DamageCalc::new(&state, 6, 0, Type::Normal, MoveCategory::Special, 90).unwrap();
+            assert_eq!(neutral.ability_modifier, 1.0, "{}", ability);
+        }
+    }
+
+    #[test]
+    fn thick_fat_halves_fire_and_ice_attacks() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let attack = |state: &State, damage_type| DamageCalc::new(state, 6, 0, damage_type, MoveCategory::Special, 90).unwrap().calculated_atk;
+        let full_attack = attack(&state, Type::Fire);
+
+        state.pokemon_by_id_mut(0).ability = Ability::id_by_name("Thick Fat").unwrap();
+        assert_eq!(attack(&state, Type::Fire), full_attack / 2);
+        assert_eq!(attack(&state, Type::Ice), full_attack / 2);
+        assert_eq!(attack(&state, Type::Water), full_attack);
+    }
+
+    #[test]
+    fn low_kick_power_follows_the_target_weight_brackets() {
+        // Bulbasaur, Pikachu, Charizard, Snorlax and Groudon, then the edges of the brackets
+        let table = [(69, 20), (60, 20), (905, 80), (4600, 120), (9500, 120), (100, 40), (499, 60), (1000, 100)];
+        for (target_weight, power) in table {
+            assert_eq!(weight_based_power(target_weight), power, "weight {}", target_weight);
+        }
+    }
+
+    #[test]
+    fn heavy_slam_power_follows_the_weight_ratio() {
+        // Snorlax against Bulbasaur, Charizard and Snorlax, then against targets at the edges of the brackets
+        let table = [(4600, 69, 120), (4600, 905, 120), (4600, 4600, 40), (4600, 1150, 100), (4600, 1534, 60), (4600, 1533, 80)];
+        for (user_weight, target_weight, power) in table {
+            assert_eq!(heavy_slam_power(user_weight, target_weight), power, "{} against {}", user_weight, target_weight);
+        }
+        assert_eq!(heavy_slam_power(100, 0), 120);
+    }
+
+    #[test]
+    fn mold_breaker_ground_move_hits_a_levitate_holder_normally() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let earthquake_damage = |state: &State| expected_damage(state, 6, 0, Type::Ground, MoveCategory::Physical, 100, 0);
+        let against_grounded_target = earthquake_damage(&state);
+        assert!(against_grounded_target > 0.0);
+
+        state.pokemon_by_id_mut(0).ability = Ability::id_by_name("Levitate").unwrap();
+        assert_eq!(earthquake_damage(&state), 0.0);
+        state.pokemon_by_id_mut(6).ability = Ability::id_by_name("Mold Breaker").unwrap();
+        assert_eq!(earthquake_damage(&state), against_grounded_target);
+    }
+
+    #[test]
+    fn rampage_repeats_until_the_lock_ends_then_confuses_the_user() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        // The lowest roll locks the user in for 2 turns
+        let mut rng = StepRng::new(0, 0);
+        let outrage = MoveEffect::RampageLock(Type::Dragon, 60);
+        let tackle = Move::id_by_name("Tackle").unwrap();
+
+        outrage.do_effect(tackle, &mut state, &[], 6, 0, &mut rng);
+        let repeat = state.pokemon_by_id_mut(6).next_move_action.take();
+        assert_eq!(repeat, Some(Action::Move { user_id: 6, move_: tackle, move_index: None, target_positions: vec![FieldPosition::Min] }));
+        assert!(!state.pokemon_by_id(6).confusion_counter.has_target());
+
+        outrage.do_effect(tackle, &mut state, &[], 6, 0, &mut rng);
+        assert_eq!(state.pokemon_by_id(6).next_move_action, None);
+        assert!(state.pokemon_by_id(6).confusion_counter.has_target());
+    }
+
+    #[test]
+    fn mirror_move_turns_the_targets_last_attack_back_on_it() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let mirror_move = Move::id_by_name("Growl").unwrap();
+        assert!(MoveEffect::MirrorMove.do_effect(mirror_move, &mut state, &[], 6, 0, &mut StepRng::new(0, 0)) == EffectResult::Fail);
+
+        state.pokemon_by_id_mut(0).last_move_used = Some(Move::id_by_name("Tackle").unwrap());
+        assert!(MoveEffect::MirrorMove.do_effect(mirror_move, &mut state, &[], 6, 0, &mut StepRng::new(0, 0)) == EffectResult::Success);
+        assert!(state.pokemon_by_id(0).current_hp() < state.pokemon_by_id(0).max_hp());
+        assert_eq!(state.pokemon_by_id(6).current_hp(), state.pokemon_by_id(6).max_hp());
+    }
+
+    #[test]
+    fn mimic_replaces_its_slot_with_a_usable_copy() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let (mimic, tackle) = (Move::id_by_name("Growl").unwrap(), Move::id_by_name("Tackle").unwrap());
+        let mimic_slot = state.pokemon_by_id(6).known_moves().iter().position(|move_instance| move_instance.move_() == mimic).unwrap();
+        state.pokemon_by_id_mut(0).last_move_used = Some(tackle);
+
+        assert!(MoveEffect::Mimic.do_effect(mimic, &mut state, &[], 6, 0, &mut StepRng::new(0, 0)) == EffectResult::Success);
+        let user = state.pokemon_by_id(6);
+        assert_eq!(user.known_moves()[mimic_slot].move_(), tackle);
+        assert!(user.can_choose_move(mimic_slot));
+    }
+
+    #[test]
+    fn choice_band_and_specs_boost_their_attacking_stat() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let attack = |state: &State, category| DamageCalc::new(state, 6, 0, Type::Normal, category, 50).unwrap().calculated_atk;
+        let (atk, sp_atk) = (attack(&state, MoveCategory::Physical), attack(&state, MoveCategory::Special));
+
+        state.pokemon_by_id_mut(6).item = Some(Item::id_by_name("Choice Band").unwrap());
+        assert_eq!(attack(&state, MoveCategory::Physical), (atk as f64 * 1.5) as u32);
+        assert_eq!(attack(&state, MoveCategory::Special), sp_atk);
+        state.pokemon_by_id_mut(6).item = Some(Item::id_by_name("Choice Specs").unwrap());
+        assert_eq!(attack(&state, MoveCategory::Physical), atk);
+        assert_eq!(attack(&state, MoveCategory::Special), (sp_atk as f64 * 1.5) as u32);
+    }
+
+    #[test]
+    fn random_status_picks_evenly_among_its_candidates() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.pokemon_by_id_mut(0).first_type = Type::Normal;
+        state.pokemon_by_id_mut(0).second_type = Type::None;
+        let candidates = [MajorStatusAilment::Burned, MajorStatusAilment::Frozen, MajorStatusAilment::Paralyzed];
+
+        let mut counts = HashMap::new();
+        for seed in 0..300 {
+            let mut state = state.clone();
+            random_status(&mut state, 0, 100, &candidates, &mut StdRng::seed_from_u64(seed));
+            *counts.entry(state.pokemon_by_id(0).major_status_ailment()).or_insert(0) += 1;
+        }
+        assert_eq!(counts.len(), 3, "{:?}", counts);
+        for candidate in &candidates {
+            assert!((70..=130).contains(&counts[candidate]), "{:?}", counts);
+        }
+    }
+
+    #[test]
+    fn critical_hits_deal_double_until_gen_6() {
+        let _data = crate::load_test_data();
+        assert_eq!(crit_multiplier(3), 2.0);
+        assert_eq!(crit_multiplier(5), 2.0);
+        assert_eq!(crit_multiplier(6), 1.5);
+        assert_eq!(crit_multiplier(8), 1.5);
+    }
+
+    #[test]
+    fn minimum_damage_always_deals_the_lowest_non_critical_roll() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.rules.chance_policy = ChancePolicy::MinimumDamage;
+        let tackle = Move::id_by_name("Tackle").unwrap();
+        let expected = DamageCalc::new(&state, 6, 0, Type::Normal, MoveCategory::Physical, 90).unwrap().damage(false, 85.0) as u16;
+
+        // This generator would crit and roll the highest damage if it were drawn from
+        let (_, damage) = std_damage(tackle, &mut state, 6, 0, Type::Normal, MoveCategory::Physical, 90, 0, &mut StepRng::new(0, 0));
+        assert_eq!(damage, expected);
+        assert_eq!((state.rng_draws.critical_hits, state.rng_draws.damage_rolls), (0, 0));
+    }
+
+    #[test]
+    fn belly_drum_trades_half_the_users_hp_for_maximum_attack() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let max_hp = state.pokemon_by_id(6).max_hp();
+
+        assert!(belly_drum(&mut state, 6) == EffectResult::Success);
+        assert_eq!(state.pokemon_by_id(6).current_hp(), max_hp - max_hp / 2);
+        assert_eq!(state.pokemon_by_id(6).stat_stage(StatIndex::Atk), 6);
+    }
+
+    #[test]
+    fn belly_drum_fails_at_half_hp_or_maximum_attack() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let max_hp = state.pokemon_by_id(6).max_hp();
+
+        state.pokemon_by_id_mut(6).current_hp = max_hp / 2;
+        assert!(belly_drum(&mut state, 6) == EffectResult::Fail);
+        assert_eq!(state.pokemon_by_id(6).current_hp(), max_hp / 2);
+        assert_eq!(state.pokemon_by_id(6).stat_stage(StatIndex::Atk), 0);
+
+        state.pokemon_by_id_mut(6).current_hp = max_hp;
+        state.pokemon_by_id_mut(6).set_stat_stages([0, 6, 0, 0, 0, 0, 0, 0]).unwrap();
+        assert!(belly_drum(&mut state, 6) == EffectResult::Fail);
+        assert_eq!(state.pokemon_by_id(6).current_hp(), max_hp);
+    }
+
+    #[test]
+    fn forced_critical_hits_ignore_the_attackers_drops() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.rules.chance_policy = ChancePolicy::AllCriticalHits;
+        let tackle = Move::id_by_name("Tackle").unwrap();
+        let damage = |state: &mut State| std_damage(tackle, state, 6, 0, Type::Normal, MoveCategory::Physical, 90, 0, &mut StepRng::new(0, 0)).1;
+        let unpenalized = damage(&mut state.clone());
+
+        state.pokemon_by_id_mut(6).set_stat_stages([0, -2, 0, 0, 0, 0, 0, 0]).unwrap();
+        assert_eq!(damage(&mut state), unpenalized);
+    }
+
+    #[test]
+    fn flinch_only_lands_on_a_target_that_has_yet_to_move() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let tackle = Move::id_by_name("Tackle").unwrap();
+        let target_move = Action::Move { user_id: 0, move_: tackle, move_index: None, target_positions: vec![FieldPosition::Max] };
+
+        // Moving second, the target's move is already gone from the queue
+        assert!(MoveEffect::Flinch.do_effect(tackle, &mut state, &[], 6, 0, &mut StepRng::new(0, 0)) == EffectResult::Skip);
+        assert!(!state.pokemon_by_id(0).is_flinching);
+
+        assert!(MoveEffect::Flinch.do_effect(tackle, &mut state, &[&target_move], 6, 0, &mut StepRng::new(0, 0)) == EffectResult::Success);
+        assert!(state.pokemon_by_id(0).is_flinching);
+    }
+
+    #[test]
+    fn later_generation_effect_does_nothing_in_gen_3() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let growl = Move::id_by_name("Growl").unwrap();
+        let before = state.snapshot();
+
+        unsafe { crate::battle_ai::data::GAME_VERSION = crate::battle_ai::data::GameVersion::FRLG; }
+        let result = MoveEffect::Tailwind.do_effect(growl, &mut state, &[], 6, 6, &mut StepRng::new(0, 0));
+        unsafe { crate::battle_ai::data::GAME_VERSION = crate::battle_ai::data::GameVersion::XY; }
+        assert!(result == EffectResult::Skip);
+        assert_eq!(state.snapshot(), before);
+
+        assert!(MoveEffect::Tailwind.do_effect(growl, &mut state, &[], 6, 6, &mut StepRng::new(0, 0)) == EffectResult::Success);
+        assert_ne!(state.snapshot(), before);
+    }
+
+    #[test]
+    fn counter_strikes_whoever_last_hit_the_user() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let tackle = Move::id_by_name("Tackle").unwrap();
+        let (_, damage_taken) = std_damage(tackle, &mut state, 6, 0, Type::Normal, MoveCategory::Physical, 40, 0, &mut StepRng::new(0, 0));
+        let attacker_hp = state.pokemon_by_id(6).current_hp();
+
+        // Aimed at the user itself, it still lands on the attacker
+        let counter = MoveEffect::Retaliate(Some(MoveCategory::Physical), 200);
+        assert!(counter.do_effect(tackle, &mut state, &[], 0, 0, &mut StepRng::new(0, 0)) == EffectResult::Success);
+        assert_eq!(state.pokemon_by_id(6).current_hp(), attacker_hp.saturating_sub(2 * damage_taken));
+    }
+
+    #[test]
+    fn eruption_power_follows_the_users_hp() {
+        let table = [(100, 150), (50, 75), (25, 37), (1, 1)];
+        for (current_hp, power) in table {
+            assert_eq!(scaled_power(150, current_hp, 100, false), power, "{} HP", current_hp);
+        }
+    }
+
+    #[test]
+    fn flail_power_follows_the_hp_brackets() {
+        // Out of 96 HP, so that each bracket edge is a whole number of HP
+        let table = [(96, 20), (66, 20), (65, 40), (34, 40), (33, 80), (20, 80), (19, 100), (10, 100), (9, 150), (4, 150), (3, 200), (1, 200)];
+        for (current_hp, power) in table {
+            assert_eq!(scaled_power(200, current_hp, 96, true), power, "{} HP", current_hp);
+        }
+    }
+
+    #[test]
+    fn dynamic_category_follows_the_users_higher_attacking_stat() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let tackle = Move::id_by_name("Tackle").unwrap();
+        let hp_after_category = |state: &State, category| hp_after(state, |state, rng| { std_damage(tackle, state, 6, 0, Type::Normal, category, 80, 0, rng); });
+        let hp_after_dynamic = |state: &State| hp_after(state, |state, rng| { dynamic_category_damage(tackle, state, 6, 0, Type::Normal, 80, rng); });
+
+        // Bulbasaur's Special Attack is the higher of the two until its Attack is boosted
+        assert_eq!(hp_after_dynamic(&state), hp_after_category(&state, MoveCategory::Special));
+        state.pokemon_by_id_mut(6).set_stat_stages([0, 2, 0, 0, 0, 0, 0, 0]).unwrap();
+        assert_eq!(hp_after_dynamic(&state), hp_after_category(&state, MoveCategory::Physical));
+        assert_ne!(hp_after_category(&state, MoveCategory::Physical), hp_after_category(&state, MoveCategory::Special));
+    }
+
+    #[test]
+    fn plus_six_attack_quadruples_the_attack_stat() {
+        let _data = crate::load_test_data();
+        let state = crate::test_matchup();
+        let tackle = Move::id_by_name("Tackle").unwrap();
+        let tackle_hp = |state: &State| hp_after(state, |state, rng| { std_damage(tackle, state, 6, 0, Type::Normal, MoveCategory::Physical, 40, 0, rng); });
+
+        let mut boosted = state.clone();
+        boosted.pokemon_by_id_mut(6).set_stat_stages([0, 6, 0, 0, 0, 0, 0, 0]).unwrap();
+        let mut quadrupled = state.clone();
+        quadrupled.pokemon_by_id_mut(6).unstaged_stats[0] *= 4;
+        assert!(tackle_hp(&boosted) < tackle_hp(&state));
+        assert_eq!(tackle_hp(&boosted), tackle_hp(&quadrupled));
+    }
+
+    #[test]
+    fn fifth_consecutive_rollout_hit_has_sixteen_times_the_power() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        // Weak enough that none of the hits below knock the target out, and never a critical hit that ignores the stages
+        state.rules.chance_policy = ChancePolicy::MinimumDamage;
+        state.pokemon_by_id_mut(6).set_stat_stages([0, -6, 0, 0, 0, 0, 0, 0]).unwrap();
+        state.pokemon_by_id_mut(0).set_stat_stages([0, 0, 6, 0, 0, 0, 0, 0]).unwrap();
+        state.pokemon_by_id_mut(6).consecutive_move_counter = 4;
+        let attack = VariableAttack { move_: Move::id_by_name("Tackle").unwrap(), damage_type: Type::Normal, power: 30 };
+
+        let fifth_hit = hp_after(&state, |state, rng| { consecutive_boost(attack, state, 6, 0, 16, rng); });
+        assert_eq!(fifth_hit, hp_after(&state, |state, rng| { attack.with_power(480, state, 6, 0, rng); }));
+        assert!(fifth_hit < hp_after(&state, |state, rng| { attack.with_power(255, state, 6, 0, rng); }));
+    }
+}
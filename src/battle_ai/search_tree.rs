@@ -1,10 +1,14 @@
-use crate::battle_ai::state::{State, Agent};
+use crate::battle_ai::state::{State, Agent, Pokemon};
 use crate::battle_ai::state;
+use crate::battle_ai::data::Side;
+use std::time::Instant;
+use std::sync::atomic;
+use rand::Rng;
 
 impl State {
     /// Copies only the game state into a new State instance; doesn't copy the child matrix or display text.
     fn copy_game_state(&self) -> State {
-        unsafe { state::NUM_STATE_COPIES += 1; }
+        state::NUM_STATE_COPIES.fetch_add(1, atomic::Ordering::Relaxed);
 
         State {
             pokemon: self.pokemon.clone(),
@@ -12,81 +16,266 @@ impl State {
                 on_field: self.max.on_field,
                 actions: Vec::new(),
                 action_order: Vec::new(),
-                consecutive_switches: self.max.consecutive_switches
+                consecutive_switches: self.max.consecutive_switches,
+                baton_pass: self.max.baton_pass.clone(),
+                side_conditions: self.max.side_conditions.clone()
             },
             min: Agent {
                 on_field: self.min.on_field,
                 actions: Vec::new(),
                 action_order: Vec::new(),
-                consecutive_switches: self.min.consecutive_switches
+                consecutive_switches: self.min.consecutive_switches,
+                baton_pass: self.min.baton_pass.clone(),
+                side_conditions: self.min.side_conditions.clone()
             },
             weather: self.weather,
             weather_counter: self.weather_counter.clone(),
             terrain: self.terrain,
+            rules: self.rules,
             turn_number: self.turn_number,
+            rng_draws: self.rng_draws,
             display_text: Vec::new(),
             children: Vec::new(),
+            speed_tie_children: Vec::new(),
+            min_totals: self.min_totals,
+            max_totals: self.max_totals
         }
     }
 
+    /// Index into the child matrices of the child reached by the given actions.
+    fn child_index(&self, max_action_index: usize, min_action_index: usize) -> usize {
+        debug_assert_eq!(self.children.len(), self.max.actions.len() * self.min.actions.len(), "Child matrix size does not match the number of actions.");
+        debug_assert!(max_action_index < self.max.actions.len() && min_action_index < self.min.actions.len(), "Action index out of range.");
+        max_action_index * self.min.actions.len() + min_action_index
+    }
+
     /// Gets the specified child or generates it using this state's actions if it does not exist.
     ///
     /// Accesses children through the action orderings, giving the appearance that the child
     /// matrix is sorted by whichever actions are expected to produce the best outcome.
-    fn get_or_gen_child(&mut self, i: usize, j: usize, rng: &mut StdRng) -> &mut State {
+    ///
+    /// With the `branch-speed-ties` feature, the maximizer wins any speed tie in this child; see
+    /// `get_or_gen_speed_tie_child`.
+    fn get_or_gen_child<R: Rng>(&mut self, i: usize, j: usize, rng: &mut R) -> &mut State {
         let max_action_index = self.max.action_order[i];
         let min_action_index = self.min.action_order[j];
-        let child_index = max_action_index * self.min.actions.len() + min_action_index;
+        let child_index = self.child_index(max_action_index, min_action_index);
 
         if self.children[child_index].is_none() {
-            let mut child = self.copy_game_state();
-            let max_action = &self.max.actions[max_action_index];
-            let min_action = &self.min.actions[min_action_index];
-            match max_action {
-                Action::Move { .. } => child.max.consecutive_switches = 0,
-                Action::Switch { .. } => child.max.consecutive_switches += 1,
-                Action::Nop => {}
-            }
-            match min_action {
-                Action::Move { .. } => child.min.consecutive_switches = 0,
-                Action::Switch { .. } => child.min.consecutive_switches += 1,
-                Action::Nop => {}
-            }
-            play_out_turn(&mut child, vec![max_action, min_action], rng);
-            generate_actions(&mut child, rng);
+            let max_wins_speed_tie = if cfg!(feature = "branch-speed-ties") { Some(true) } else { None };
+            let child = self.gen_child(max_action_index, min_action_index, max_wins_speed_tie, rng);
             self.children[child_index] = Some(Box::new(child));
         }
 
         self.children[child_index].as_mut().unwrap()
     }
 
-    /// Removes the specified child from this state.
+    /// Whether the specified child has a counterpart where the minimizer wins the speed tie, i.e. whether the
+    /// `branch-speed-ties` feature is on and the chosen actions are speed tied.
+    fn has_speed_tie_child(&self, i: usize, j: usize) -> bool {
+        let max_action = &self.max.actions[self.max.action_order[i]];
+        let min_action = &self.min.actions[self.min.action_order[j]];
+        cfg!(feature = "branch-speed-ties") && Action::is_speed_tie(self, max_action, min_action)
+    }
+
+    /// With the `branch-speed-ties` feature, gets or generates the specified child where the minimizer
+    /// wins the speed tie between the chosen actions. Returns None if the actions aren't speed tied.
+    fn get_or_gen_speed_tie_child<R: Rng>(&mut self, i: usize, j: usize, rng: &mut R) -> Option<&mut State> {
+        if !self.has_speed_tie_child(i, j) {
+            return None;
+        }
+        let max_action_index = self.max.action_order[i];
+        let min_action_index = self.min.action_order[j];
+        let child_index = self.child_index(max_action_index, min_action_index);
+
+        if self.speed_tie_children[child_index].is_none() {
+            let child = self.gen_child(max_action_index, min_action_index, Some(false), rng);
+            self.speed_tie_children[child_index] = Some(Box::new(child));
+        }
+
+        self.speed_tie_children[child_index].as_deref_mut()
+    }
+
+    fn gen_child<R: Rng>(&self, max_action_index: usize, min_action_index: usize, max_wins_speed_tie: Option<bool>, rng: &mut R) -> State {
+        let mut child = self.copy_game_state();
+        let max_action = &self.max.actions[max_action_index];
+        let min_action = &self.min.actions[min_action_index];
+        match max_action {
+            Action::Move { .. } => child.max.consecutive_switches = 0,
+            Action::Switch { .. } => child.max.consecutive_switches += 1,
+            Action::Nop => {}
+        }
+        match min_action {
+            Action::Move { .. } => child.min.consecutive_switches = 0,
+            Action::Switch { .. } => child.min.consecutive_switches += 1,
+            Action::Nop => {}
+        }
+        play_out_turn(&mut child, vec![max_action, min_action], max_wins_speed_tie, rng);
+        generate_actions(&mut child);
+        child
+    }
+
+    /// Plays out one turn with the given actions, which must be among the agents' current actions, and moves to the
+    /// resulting state. Doesn't search or keep any children. Returns whether the battle has ended.
+    pub fn step<R: Rng>(&mut self, max_action: Action, min_action: Action, rng: &mut R) -> bool {
+        let max_action_index = match self.max.actions.iter().position(|action| *action == max_action) {
+            Some(index) => index,
+            None => panic!("Illegal action for the maximizer: {:?}", max_action)
+        };
+        let min_action_index = match self.min.actions.iter().position(|action| *action == min_action) {
+            Some(index) => index,
+            None => panic!("Illegal action for the minimizer: {:?}", min_action)
+        };
+
+        *self = self.gen_child(max_action_index, min_action_index, None, rng);
+        self.has_battle_ended()
+    }
+
+    /// Whether the agent on the given side could choose the action here, checked directly rather than by generating
+    /// every action. Agrees with the actions the search would generate, except that a queued second turn of a move
+    /// counts as legal even if it would turn out not to be performable.
+    pub fn is_legal(&self, side: Side, action: &Action) -> bool {
+        let (agent, opponent) = match side {
+            Side::Min => (&self.min, &self.max),
+            Side::Max => (&self.max, &self.min)
+        };
+        let team_ids = side.id_range();
+
+        match action {
+            // Only when the opponent has to send out a replacement and this agent doesn't
+            Action::Nop => agent.on_field.is_some() && opponent.on_field.is_none(),
+            Action::Switch { user_id: None, switching_in_id, target_position } => {
+                agent.on_field.is_none()
+                    && *target_position == side.field_position()
+                    && team_ids.contains(switching_in_id)
+                    && !self.pokemon_by_id(*switching_in_id).is_fainted()
+                    && agent.baton_pass.as_ref().map(|bp| bp.user_id) != Some(*switching_in_id)
+            },
+            Action::Switch { user_id: Some(user_id), switching_in_id, target_position } => {
+                if agent.on_field != Some(*user_id) || opponent.on_field.is_none() || *target_position != side.field_position() || !team_ids.contains(switching_in_id) {
+                    return false;
+                }
+                let switching_in = self.pokemon_by_id(*switching_in_id);
+                self.pokemon_by_id(*user_id).next_move_action.is_none()
+                    && self.rules.allow_switching
+                    && agent.consecutive_switches < self.rules.consecutive_switch_cap
+                    && pokemon::can_switch_out(self, *user_id)
+                    && !switching_in.is_fainted()
+                    && !switching_in.is_on_field()
+                    && switching_in.known_moves().iter().map(|known_move| known_move.pp).sum::<u8>() > 0
+            },
+            Action::Move { user_id, move_, move_index, target_positions } => {
+                if agent.on_field != Some(*user_id) || opponent.on_field.is_none() {
+                    return false;
+                }
+                let user = self.pokemon_by_id(*user_id);
+                if let Some(next_move_action) = &user.next_move_action {
+                    return action == next_move_action;
+                }
+                let choosable = |move_index: usize| {
+                    let known_move = user.known_move(move_index).move_();
+                    user.can_choose_move(move_index)
+                        && user.choice_locked.is_none_or(|choice_locked| choice_locked == known_move)
+                        && !move_target_positions(user, known_move).is_empty()
+                };
+                let move_chosen_legally = match move_index {
+                    Some(move_index) => {
+                        let move_index = *move_index as usize;
+                        move_index < user.known_moves().len() && user.known_move(move_index).move_() == *move_ && choosable(move_index)
+                    },
+                    // Struggle is the only move that isn't in a move slot, and only when nothing else can be chosen
+                    None => *move_ == Move::id_by_name("Struggle").unwrap() && !(0..user.known_moves().len()).any(choosable)
+                };
+                move_chosen_legally && *target_positions == move_target_positions(user, *move_)
+            }
+        }
+    }
+
+    /// Expected payoff of every pair of actions the agents can take from here, each searched `depth` turns ahead in
+    /// total with a full window. Row `i` and column `j` of the matrix correspond to the `i`th maximizer action and
+    /// the `j`th minimizer action returned. Useful for seeing which matchups the agents expect to go well. Panics if
+    /// `depth` is 0.
+    pub fn evaluate_matrix<R: Rng>(&mut self, depth: u8, rng: &mut R) -> (Vec<Action>, Vec<Action>, Matrix) {
+        if depth == 0 {
+            panic!("Search depth must be positive");
+        }
+
+        let m = self.max.actions.len();
+        let n = self.min.actions.len();
+        let mut payoff_matrix = Matrix::of(0.0, m, n);
+        for i in 0..m {
+            for j in 0..n {
+                let speed_tie_value = self.get_or_gen_speed_tie_child(i, j, rng)
+                    .map(|speed_tie_child| smab_search(speed_tie_child, -1.0, 1.0, depth - 1, rng).expected_payoff);
+                let value = smab_search(self.get_or_gen_child(i, j, rng), -1.0, 1.0, depth - 1, rng).expected_payoff;
+                *payoff_matrix.get_mut(i, j) = match speed_tie_value {
+                    Some(speed_tie_value) => (value + speed_tie_value) / 2.0,
+                    None => value
+                };
+            }
+        }
+
+        let (max_actions, min_actions) = self.ordered_actions();
+        (max_actions, min_actions, payoff_matrix)
+    }
+
+    /// The maximizer's and minimizer's actions, in the order that the strategies found by searching this state refer
+    /// to them.
+    pub fn ordered_actions(&self) -> (Vec<Action>, Vec<Action>) {
+        let max_actions = self.max.action_order.iter().map(|&action_index| self.max.actions[action_index].clone()).collect();
+        let min_actions = self.min.action_order.iter().map(|&action_index| self.min.actions[action_index].clone()).collect();
+        (max_actions, min_actions)
+    }
+
+    /// Removes the specified child from this state. If both outcomes of a speed tie were generated,
+    /// one of them is chosen at random.
     ///
     /// Accesses children through the action orderings, giving the appearance that the child
     /// matrix is sorted by whichever actions are expected to produce the best outcome.
-    fn remove_child(&mut self, i: usize, j: usize, rng: &mut StdRng) -> Box<State> {
+    fn remove_child<R: Rng>(&mut self, i: usize, j: usize, rng: &mut R) -> Box<State> {
         self.get_or_gen_child(i, j, rng);
 
         let max_action_index = self.max.action_order[i];
         let min_action_index = self.min.action_order[j];
-        let child_index = max_action_index * self.min.actions.len() + min_action_index;
+        let child_index = self.child_index(max_action_index, min_action_index);
+        // Only flip for the speed tie when there is one, so that the draws made otherwise don't depend on the feature
+        if self.has_speed_tie_child(i, j) && rng.gen_bool(0.5) {
+            self.get_or_gen_speed_tie_child(i, j, rng);
+            return self.speed_tie_children.remove(child_index).unwrap();
+        }
         self.children.remove(child_index).unwrap()
     }
 }
 
 /// Simultaneous move alpha-beta search, implemented as a simplification of
 /// [Alpha-Beta Pruning for Games with Simultaneous Moves](docs/Alpha-Beta_Pruning_for_Games_with_Simultaneous_Moves.pdf).
-fn smab_search(state: &mut State, mut alpha: f64, mut beta: f64, recursions: u8, rng: &mut StdRng) -> ZeroSumNashEq {
+fn smab_search<R: Rng>(state: &mut State, alpha: f64, beta: f64, recursions: u8, rng: &mut R) -> ZeroSumNashEq {
+    let node_limit = node_limit(state);
+    smab_search_until(state, alpha, beta, recursions, None, node_limit, rng)
+}
+
+/// Value of `state::NUM_STATE_COPIES` at which a search starting now has used up the state's `max_search_nodes`
+/// budget, if it has one.
+fn node_limit(state: &State) -> Option<u64> {
+    state.rules.max_search_nodes.map(|max_search_nodes| state::NUM_STATE_COPIES.load(atomic::Ordering::Relaxed) + max_search_nodes)
+}
+
+/// Same as `smab_search`, except that once `deadline` passes or `state::NUM_STATE_COPIES` reaches `node_limit`, every
+/// state still to be searched is evaluated with the heuristic as if the depth limit had been reached. Past the
+/// deadline, the result is only a rough estimate; see `search_with_deadline` for getting a sound result under a time
+/// limit.
+fn smab_search_until<R: Rng>(state: &mut State, mut alpha: f64, mut beta: f64, recursions: u8, deadline: Option<Instant>, node_limit: Option<u64>, rng: &mut R) -> ZeroSumNashEq {
     let m = state.max.actions.len();
     let n = state.min.actions.len();
+    let out_of_time = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+    let out_of_nodes = node_limit.is_some_and(|node_limit| state::NUM_STATE_COPIES.load(atomic::Ordering::Relaxed) >= node_limit);
 
-    // If depth limit reached or either agent has no actions, stop search.
-    if recursions < 1 || m == 0 || n == 0 {
+    // If depth limit reached, time or the node budget is up or either agent has no actions, stop search.
+    if recursions < 1 || m == 0 || n == 0 || out_of_time || out_of_nodes {
         return ZeroSumNashEq {
             max_player_strategy: vec![1.0 / m as f64; m],
             min_player_strategy: vec![1.0 / n as f64; n],
-            expected_payoff: (state.pokemon[6..12].iter().map(|pokemon| pokemon.current_hp() as f64 / pokemon.max_hp() as f64).sum::<f64>()
-                - state.pokemon[0..6].iter().map(|pokemon| pokemon.current_hp() as f64 / pokemon.max_hp() as f64).sum::<f64>()) / 6.0,
+            expected_payoff: heuristic_value(state),
         };
     }
 
@@ -98,7 +287,14 @@ fn smab_search(state: &mut State, mut alpha: f64, mut beta: f64, recursions: u8,
 
     let mut explore_child = |i: usize, j: usize| {
         if !row_domination[i] && !col_domination[j] {
-            let child_value = smab_search(state.get_or_gen_child(i, j, rng), alpha, beta, recursions - 1, rng).expected_payoff;
+            let child_value = if state.get_or_gen_speed_tie_child(i, j, rng).is_some() {
+                // Both outcomes of the speed tie are equally likely. Pruning within either one would make
+                // the average meaningless, so they are searched with a full window.
+                let speed_tie_value = smab_search_until(state.get_or_gen_speed_tie_child(i, j, rng).unwrap(), -1.0, 1.0, recursions - 1, deadline, node_limit, rng).expected_payoff;
+                (smab_search_until(state.get_or_gen_child(i, j, rng), -1.0, 1.0, recursions - 1, deadline, node_limit, rng).expected_payoff + speed_tie_value) / 2.0
+            } else {
+                smab_search_until(state.get_or_gen_child(i, j, rng), alpha, beta, recursions - 1, deadline, node_limit, rng).expected_payoff
+            };
             if child_value <= alpha {
                 row_domination[i] = true;
             } else if child_value >= beta {
@@ -123,29 +319,42 @@ fn smab_search(state: &mut State, mut alpha: f64, mut beta: f64, recursions: u8,
         }
     }
 
+    // Every action of one agent is equally bad for it, so any strategy will do. The strategies can't be left empty,
+    // since the agents sample from them when this is the root, e.g. when every option loses the battle.
     if row_domination.iter().all(|b| *b) {
         return ZeroSumNashEq {
-            max_player_strategy: Vec::new(),
-            min_player_strategy: Vec::new(),
+            max_player_strategy: vec![1.0 / m as f64; m],
+            min_player_strategy: vec![1.0 / n as f64; n],
             expected_payoff: alpha,
         };
     }
 
     if col_domination.iter().all(|b| *b) {
         return ZeroSumNashEq {
-            max_player_strategy: Vec::new(),
-            min_player_strategy: Vec::new(),
+            max_player_strategy: vec![1.0 / m as f64; m],
+            min_player_strategy: vec![1.0 / n as f64; n],
             expected_payoff: beta,
         };
     }
 
-    let nash_eq = game_theory::calc_nash_eq(&payoff_matrix, &row_domination, &col_domination, 2.0);
+    let mut nash_eq = game_theory::calc_nash_eq(&payoff_matrix, &row_domination, &col_domination, 2.0);
 
     // Exploiting iterative deepening: sort actions from highest probability of being played to
     // lowest probability so that alpha-beta is more likely to prune children on the next pass
     // through this state.
-    let action_order_cmp = |strategy: &[f64], act_index1: &usize, act_index2: &usize| {
-        let diff = strategy[*act_index1] - strategy[*act_index2];
+    reorder_actions(&mut state.max.action_order, &mut nash_eq.max_player_strategy);
+    reorder_actions(&mut state.min.action_order, &mut nash_eq.min_player_strategy);
+
+    nash_eq
+}
+
+/// Sorts an agent's action order by the given strategy, from highest to lowest probability. The strategy is indexed
+/// by position in the action order, not by action index, so it is permuted along with the order; this way, indices
+/// sampled from the returned strategy still refer to the intended children. Ties keep their previous relative order.
+fn reorder_actions(action_order: &mut Vec<usize>, strategy: &mut Vec<f64>) {
+    let mut positions: Vec<usize> = (0..action_order.len()).collect();
+    positions.sort_by(|pos1, pos2| {
+        let diff = strategy[*pos1] - strategy[*pos2];
         if almost::zero(diff) {
             Ordering::Equal
         } else if diff < 0.0 {
@@ -153,81 +362,193 @@ fn smab_search(state: &mut State, mut alpha: f64, mut beta: f64, recursions: u8,
         } else {
             Ordering::Less
         }
-    };
+    });
+    *action_order = positions.iter().map(|pos| action_order[*pos]).collect();
+    *strategy = positions.iter().map(|pos| strategy[*pos]).collect();
+}
 
-    state.max.action_order.sort_unstable_by(|act_index1, act_index2| action_order_cmp(&nash_eq.max_player_strategy, act_index1, act_index2));
-    state.min.action_order.sort_unstable_by(|act_index1, act_index2| action_order_cmp(&nash_eq.min_player_strategy, act_index1, act_index2));
+/// Weighted average of several comparisons between the two sides, each from -1 to 1; see `HeuristicWeights`.
+fn heuristic_value(state: &State) -> f64 {
+    let (min_totals, max_totals) = state.team_totals();
+    // Both teams going down at once, e.g. to recoil on the last Pokemon standing, is a genuine draw
+    if min_totals.standing == 0 && max_totals.standing == 0 {
+        return 0.0;
+    }
 
-    nash_eq
+    let weights = &state.rules.heuristic_weights;
+    let mut value = weights.hp * (max_totals.hp_fraction_sum - min_totals.hp_fraction_sum) / 6.0
+        + weights.faint_count * (max_totals.standing as f64 - min_totals.standing as f64) / 6.0;
+    if weights.status > 0.0 {
+        value -= weights.status * (max_totals.statused as f64 - min_totals.statused as f64) / 6.0;
+    }
+    if let Some((max_pokemon_id, min_pokemon_id)) = state.max.on_field.zip(state.min.on_field) {
+        let max_pokemon = state.pokemon_by_id(max_pokemon_id);
+        let min_pokemon = state.pokemon_by_id(min_pokemon_id);
+        if weights.stages > 0.0 {
+            let stage_sum = |pokemon: &Pokemon| {
+                [StatIndex::Atk, StatIndex::Def, StatIndex::SpAtk, StatIndex::SpDef, StatIndex::Spd].iter()
+                    .map(|stat_index| pokemon.stat_stage(*stat_index) as f64)
+                    .sum::<f64>()
+            };
+            value += weights.stages * (stage_sum(max_pokemon) - stage_sum(min_pokemon)) / 60.0;
+        }
+        if weights.type_matchup > 0.0 {
+            value += weights.type_matchup * (type_advantage(max_pokemon, min_pokemon) - type_advantage(min_pokemon, max_pokemon)) / 4.0;
+        }
+    }
+
+    let total_weight = weights.hp + weights.status + weights.stages + weights.type_matchup + weights.faint_count;
+    value / total_weight
+}
+
+/// Effectiveness of the attacker's best type against the defender, from 0 to 4.
+fn type_advantage(attacker: &Pokemon, defender: &Pokemon) -> f64 {
+    [attacker.first_type(), attacker.second_type()].iter()
+        .filter(|type_| **type_ != Type::None)
+        .map(|type_| type_.effectiveness(defender.first_type(), defender.second_type()))
+        .fold(0.0, f64::max)
 }
 
-fn generate_actions(state: &mut State, rng: &mut StdRng) {
+fn generate_actions(state: &mut State) {
     match state.max.on_field.zip(state.min.on_field) {
         None => agents_choose_pokemon_to_send_out(state),
         Some((max_pokemon_id, min_pokemon_id)) => { // Agents must choose actions for each Pokemon
-            let max_actions = gen_actions_for_user(state, rng, max_pokemon_id);
-            let min_actions = gen_actions_for_user(state, rng, min_pokemon_id);
+            let mut max_actions = gen_actions_for_user(state, max_pokemon_id);
+            let mut min_actions = gen_actions_for_user(state, min_pokemon_id);
+            // Within the static ordering, try the moves expected to deal the most damage first so that even the
+            // first pass of the search prunes well
+            let seeded_cmp = |act1: &Action, act2: &Action| action_cmp(act1, act2)
+                .then_with(|| estimated_damage(state, act2).partial_cmp(&estimated_damage(state, act1)).unwrap_or(Ordering::Equal));
+            max_actions.sort_unstable_by(seeded_cmp);
+            min_actions.sort_unstable_by(seeded_cmp);
             state.max.actions = max_actions;
             state.min.actions = min_actions;
-
-            state.max.actions.sort_unstable_by(|act1, act2| action_cmp(act1, act2));
-            state.min.actions.sort_unstable_by(|act1, act2| action_cmp(act1, act2));
         }
     }
 
     state.max.action_order = (0..state.max.actions.len()).collect();
     state.min.action_order = (0..state.min.actions.len()).collect();
     state.children = vec![None; state.max.actions.len() * state.min.actions.len()];
+    if cfg!(feature = "branch-speed-ties") {
+        state.speed_tie_children = vec![None; state.max.actions.len() * state.min.actions.len()];
+    }
+}
+
+/// Rough damage of a move against the opposing on-field Pokemon, from its power, STAB and type effectiveness.
+/// Actions other than damaging moves estimate to 0.
+fn estimated_damage(state: &State, action: &Action) -> f64 {
+    match action {
+        Action::Move { user_id, move_, .. } => {
+            let power = match Move::base_power(*move_) {
+                Some(power) => power as f64,
+                None => return 0.0
+            };
+            let target_id = if *user_id < 6 { state.max.on_field } else { state.min.on_field };
+            let target = match target_id {
+                Some(target_id) => state.pokemon_by_id(target_id),
+                None => return 0.0
+            };
+            let move_type = Move::type_(*move_);
+            let stab = if state.pokemon_by_id(*user_id).is_type(move_type) { 1.5 } else { 1.0 };
+            power * stab * move_type.effectiveness(target.first_type(), target.second_type())
+        },
+        _ => 0.0
+    }
 }
 
+/// Any Pokemon left standing can be sent out as a replacement, even one with no PP left, which will just Struggle. Unlike
+/// voluntary switches, PP is not considered, so an agent that still has a Pokemon standing always has a send-out.
+/// Replacements are ordered by `replacement_cmp` so that the search tries the likely best ones first.
 fn agents_choose_pokemon_to_send_out(state: &mut State) {
     state.max.actions = match state.max.on_field {
-        None => (6..12)
-            .filter(|id| state.pokemon_by_id(*id).current_hp() > 0)
-            .map(|id| Action::Switch {
-                user_id: None,
-                switching_in_id: id,
-                target_position: FieldPosition::Max,
-            }).collect(),
+        None => {
+            let mut replacement_ids: Vec<u8> = (6..12)
+                .filter(|id| !state.pokemon_by_id(*id).is_fainted() && state.max.baton_pass.as_ref().map(|bp| bp.user_id) != Some(*id))
+                .collect();
+            replacement_ids.sort_by(|id1, id2| replacement_cmp(state, state.min.on_field, *id1, *id2));
+            replacement_ids.into_iter()
+                .map(|id| Action::Switch {
+                    user_id: None,
+                    switching_in_id: id,
+                    target_position: FieldPosition::Max,
+                }).collect()
+        },
         Some(_) => vec![Action::Nop]
     };
 
     state.min.actions = match state.min.on_field {
-        None => (0..6)
-            .filter(|id| state.pokemon_by_id(*id).current_hp() > 0)
-            .map(|id| Action::Switch {
-                user_id: None,
-                switching_in_id: id,
-                target_position: FieldPosition::Min,
-            }).collect(),
+        None => {
+            let mut replacement_ids: Vec<u8> = (0..6)
+                .filter(|id| !state.pokemon_by_id(*id).is_fainted() && state.min.baton_pass.as_ref().map(|bp| bp.user_id) != Some(*id))
+                .collect();
+            replacement_ids.sort_by(|id1, id2| replacement_cmp(state, state.max.on_field, *id1, *id2));
+            replacement_ids.into_iter()
+                .map(|id| Action::Switch {
+                    user_id: None,
+                    switching_in_id: id,
+                    target_position: FieldPosition::Min,
+                }).collect()
+        },
         Some(_) => vec![Action::Nop]
     };
+
+    debug_assert!(state.has_battle_ended() || (!state.max.actions.is_empty() && !state.min.actions.is_empty()), "An agent with a Pokemon left standing must be able to send one out.");
+}
+
+/// Orders replacements from most to least promising against the opposing Pokemon, if there is one: by type matchup
+/// weighted by the fraction of HP left, so that a good matchup counts for less the closer the Pokemon is to fainting,
+/// then by effective Speed.
+fn replacement_cmp(state: &State, opponent_id: Option<u8>, id1: u8, id2: u8) -> Ordering {
+    let score = |id: u8| {
+        let candidate = state.pokemon_by_id(id);
+        let matchup = opponent_id.map_or(0.0, |opponent_id| {
+            let opponent = state.pokemon_by_id(opponent_id);
+            type_advantage(candidate, opponent) - type_advantage(opponent, candidate)
+        });
+        // Shift the matchup, which is from -4 to 4, so that losing HP never makes a Pokemon more promising
+        (matchup + 4.0) * candidate.hp_fraction()
+    };
+    score(id2).partial_cmp(&score(id1)).unwrap_or(Ordering::Equal)
+        .then_with(|| pokemon::effective_speed(state, id2).cmp(&pokemon::effective_speed(state, id1)))
+}
+
+/// Every position that the move can hit from the user's position, which must be on the field.
+fn move_target_positions(user: &Pokemon, move_: MoveID) -> Vec<FieldPosition> {
+    [FieldPosition::Min, FieldPosition::Max].iter().copied()
+        .filter(|field_pos| Move::targeting(move_).can_hit(user.field_position().unwrap(), *field_pos))
+        .collect()
 }
 
-fn gen_actions_for_user(state: &mut State, rng: &mut StdRng, user_id: u8) -> Vec<Action> {
+/// Only called once both sides have a Pokemon on the field; until then, the agents can only send Pokemon out.
+fn gen_actions_for_user(state: &mut State, user_id: u8) -> Vec<Action> {
+    debug_assert!(state.max.on_field.is_some() && state.min.on_field.is_some(), "Moves must not be generated while a side of the field is empty.");
+
     let mut actions: Vec<Action> = Vec::new();
 
     // TODO: Is this actually what should happen?
     if let Some(next_move_action) = state.pokemon_by_id(user_id).next_move_action.clone() {
-        if next_move_action.can_be_performed(state, rng) {
+        if next_move_action.can_be_performed(state) {
             actions.push(next_move_action);
             state.pokemon_by_id_mut(user_id).next_move_action = None;
             return actions;
         } else {
-            state.pokemon_by_id_mut(user_id).next_move_action = None;
+            let user = state.pokemon_by_id_mut(user_id);
+            user.next_move_action = None;
+            user.semi_invulnerable = None;
         }
     }
 
     let user = state.pokemon_by_id(user_id);
     for move_index in 0..user.known_moves().len() {
-        if user.can_choose_move(move_index) {
-            let move_ = user.known_move(move_index).move_();
+        let move_ = user.known_move(move_index).move_();
+        // Moves that only target allies have nothing to hit in a single battle, so they would do nothing but use PP
+        let target_positions = move_target_positions(user, move_);
+        if user.can_choose_move(move_index) && user.choice_locked.is_none_or(|choice_locked| choice_locked == move_) && !target_positions.is_empty() {
             actions.push(Action::Move {
                 user_id,
                 move_,
                 move_index: Some(move_index as u8),
-                target_positions: [FieldPosition::Min, FieldPosition::Max].iter().copied()
-                    .filter(|field_pos| Move::targeting(move_).can_hit(user.field_position().unwrap(), *field_pos)).collect(),
+                target_positions,
             });
         }
     }
@@ -239,15 +560,16 @@ fn gen_actions_for_user(state: &mut State, rng: &mut StdRng, user_id: u8) -> Vec
             user_id,
             move_: struggle,
             move_index: None,
-            target_positions: [FieldPosition::Min, FieldPosition::Max].iter().copied()
-                .filter(|field_pos| Move::targeting(struggle).can_hit(user.field_position().unwrap(), *field_pos)).collect(),
+            target_positions: move_target_positions(user, struggle),
         });
     }
 
-    if (user_id < 6 && state.min.consecutive_switches < CONSECUTIVE_SWITCH_CAP) || (user_id >= 6 && state.max.consecutive_switches < CONSECUTIVE_SWITCH_CAP) {
-        for team_member_id in if user_id < 6 { 0..6 } else { 6..12 } {
+    let switch_cap = state.rules.consecutive_switch_cap;
+    let under_switch_cap = (user_id < 6 && state.min.consecutive_switches < switch_cap) || (user_id >= 6 && state.max.consecutive_switches < switch_cap);
+    if state.rules.allow_switching && under_switch_cap && pokemon::can_switch_out(state, user_id) {
+        for team_member_id in Side::of(user_id).id_range() {
             let team_member = state.pokemon_by_id(team_member_id);
-            if team_member.current_hp() > 0 && team_member.field_position() == None && team_member.known_moves().iter().map(|known_move| known_move.pp).sum::<u8>() > 0 {
+            if !team_member.is_fainted() && !team_member.is_on_field() && team_member.known_moves().iter().map(|known_move| known_move.pp).sum::<u8>() > 0 {
                 actions.push(Action::Switch {
                     user_id: Some(user_id),
                     switching_in_id: team_member_id as u8,
@@ -259,3 +581,388 @@ fn gen_actions_for_user(state: &mut State, rng: &mut StdRng, user_id: u8) -> Vec
 
     actions
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battle_ai::data::MajorStatusAilment;
+    use crate::HeuristicWeights;
+    use rand::rngs::mock::StepRng;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn generating_a_queued_move_does_not_roll_for_confusion() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        // A generator that only returns 0 would make a confused Pokemon hurt itself on every roll
+        let mut rng = StepRng::new(0, 0);
+        pokemon::confuse(&mut state, 6, &mut rng);
+        let queued = Action::Move {
+            user_id: 6,
+            move_: Move::id_by_name("Tackle").unwrap(),
+            move_index: None,
+            target_positions: vec![FieldPosition::Min]
+        };
+        state.pokemon_by_id_mut(6).next_move_action = Some(queued.clone());
+
+        assert_eq!(gen_actions_for_user(&mut state, 6), vec![queued]);
+        assert_eq!(state.pokemon_by_id(6).current_hp(), state.pokemon_by_id(6).max_hp());
+    }
+
+    #[test]
+    fn last_pokemon_standing_is_sent_out_without_pp_and_struggles() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(2), &crate::test_team(1));
+        for move_index in 0..state.pokemon_by_id(1).known_moves().len() {
+            pokemon::increment_move_pp(&mut state, 1, move_index as u8, -64);
+        }
+        pokemon::apply_damage(&mut state, 0, 1000);
+
+        agents_choose_pokemon_to_send_out(&mut state);
+        let send_out = Action::Switch { user_id: None, switching_in_id: 1, target_position: FieldPosition::Min };
+        assert_eq!(state.min.actions, vec![send_out.clone()]);
+
+        send_out.perform(&mut state, &[], &mut StepRng::new(0, 0));
+        let actions = gen_actions_for_user(&mut state, 1);
+        assert_eq!(actions.len(), 1);
+        assert!(matches!(actions[0], Action::Move { move_, .. } if Move::name(move_) == "Struggle"), "{:?}", actions);
+    }
+
+    #[test]
+    fn damage_seeded_ordering_searches_fewer_nodes() {
+        let _data = crate::load_test_data();
+        let mut team = crate::test_team(2);
+        for bulbasaur in team.iter_mut() {
+            // Generated weakest first against another Bulbasaur, so the static ordering alone tries them in the worst order
+            bulbasaur.moves = ["Vine Whip", "Razor Leaf", "Tackle", "Sludge Bomb"].iter().map(|name| Move::id_by_name(name).unwrap()).collect();
+        }
+        let mut seeded = crate::test_battle(&team, &team);
+        generate_actions(&mut seeded);
+        let mut unseeded = seeded.clone();
+        unseeded.max.actions = gen_actions_for_user(&mut unseeded, 6);
+        unseeded.min.actions = gen_actions_for_user(&mut unseeded, 0);
+        unseeded.max.actions.sort_by(action_cmp);
+        unseeded.min.actions.sort_by(action_cmp);
+        assert_ne!(unseeded.max.actions, seeded.max.actions);
+
+        let nodes_searched = |state: &mut State| {
+            let before = state::NUM_STATE_COPIES.load(atomic::Ordering::Relaxed);
+            smab_search(state, -1.0, 1.0, 2, &mut StdRng::seed_from_u64(0));
+            state::NUM_STATE_COPIES.load(atomic::Ordering::Relaxed) - before
+        };
+        let (seeded_nodes, unseeded_nodes) = (nodes_searched(&mut seeded), nodes_searched(&mut unseeded));
+        assert!(seeded_nodes < unseeded_nodes, "{} vs {}", seeded_nodes, unseeded_nodes);
+    }
+
+    #[cfg(not(feature = "branch-speed-ties"))]
+    #[test]
+    fn removing_a_child_without_a_speed_tie_child_does_not_flip_a_coin() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        generate_actions(&mut state);
+        let mut removed_from = state.clone();
+        let (mut rng, mut removal_rng) = (StdRng::seed_from_u64(0), StdRng::seed_from_u64(0));
+
+        state.get_or_gen_child(0, 0, &mut rng);
+        removed_from.remove_child(0, 0, &mut removal_rng);
+        assert_eq!(rng.gen::<u64>(), removal_rng.gen::<u64>());
+    }
+
+    #[cfg(feature = "branch-speed-ties")]
+    #[test]
+    fn speed_tie_branches_into_both_orders() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.rules.log_level = crate::LogLevel::Verbose;
+        generate_actions(&mut state);
+        let mut rng = StepRng::new(0, 0);
+        let first_mover = |child: &State| child.display_text.iter().find(|text| text.contains(" used ")).cloned().unwrap();
+
+        let max_first = first_mover(state.get_or_gen_child(0, 0, &mut rng));
+        let min_first = first_mover(state.get_or_gen_speed_tie_child(0, 0, &mut rng).unwrap());
+        assert!(max_first.contains("(max)"), "{}", max_first);
+        assert!(min_first.contains("(min)"), "{}", min_first);
+    }
+
+    #[test]
+    fn fainted_pokemon_count_against_a_team_with_the_same_hp() {
+        let _data = crate::load_test_data();
+        let mut one_fainted = crate::test_battle(&crate::test_team(2), &crate::test_team(2));
+        let mut both_halved = one_fainted.clone();
+        let max_hp = one_fainted.pokemon_by_id(1).max_hp();
+        pokemon::set_hp(&mut one_fainted, 1, 0);
+        pokemon::set_hp(&mut both_halved, 0, max_hp / 2);
+        pokemon::set_hp(&mut both_halved, 1, max_hp - max_hp / 2);
+
+        assert_eq!(one_fainted.team_totals().0.hp_fraction_sum, both_halved.team_totals().0.hp_fraction_sum);
+        assert!(heuristic_value(&one_fainted) > heuristic_value(&both_halved));
+    }
+
+    #[test]
+    fn only_moves_are_generated_when_switching_is_disabled() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(2), &crate::test_team(2));
+        state.rules.allow_switching = false;
+
+        generate_actions(&mut state);
+        assert!(!state.max.actions.is_empty() && !state.min.actions.is_empty());
+        assert!(state.max.actions.iter().chain(&state.min.actions).all(|action| matches!(action, Action::Move { .. })));
+    }
+
+    #[test]
+    fn removed_child_follows_the_reordered_actions() {
+        let _data = crate::load_test_data();
+        let mut team = crate::test_team(1);
+        team.members[0].moves = vec![Move::id_by_name("Tackle").unwrap(), Move::id_by_name("Growl").unwrap()];
+        let mut state = crate::test_battle(&team, &team);
+        generate_actions(&mut state);
+        state.max.action_order.reverse();
+        state.min.action_order.reverse();
+        let is_move = |action: &Action, name: &str| matches!(action, Action::Move { move_, .. } if Move::name(*move_) == name);
+
+        for i in 0..2 {
+            for j in 0..2 {
+                let max_tackles = is_move(&state.max.actions[state.max.action_order[i]], "Tackle");
+                let min_growls = is_move(&state.min.actions[state.min.action_order[j]], "Growl");
+                let child = state.clone().remove_child(i, j, &mut StepRng::new(0, 0));
+                assert_eq!(child.pokemon_by_id(0).current_hp() < child.pokemon_by_id(0).max_hp(), max_tackles, "child ({}, {})", i, j);
+                assert_eq!(child.pokemon_by_id(6).stat_stage(StatIndex::Atk) < 0, min_growls, "child ({}, {})", i, j);
+            }
+        }
+    }
+
+    #[test]
+    fn trapped_pokemon_cannot_switch_unless_it_is_a_ghost() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(2), &crate::test_team(2));
+        let has_switch = |state: &mut State| gen_actions_for_user(state, 0).iter().any(|action| matches!(action, Action::Switch { .. }));
+        assert!(has_switch(&mut state));
+
+        state.pokemon_by_id_mut(0).is_trapped = true;
+        assert!(!has_switch(&mut state));
+        state.pokemon_by_id_mut(0).first_type = Type::Ghost;
+        assert!(has_switch(&mut state));
+    }
+
+    #[test]
+    fn arena_trap_keeps_a_grounded_opponent_in() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(2), &crate::test_team(2));
+        state.pokemon_by_id_mut(6).ability = Ability::id_by_name("Arena Trap").unwrap();
+        let has_switch = |state: &mut State, user_id| gen_actions_for_user(state, user_id).iter().any(|action| matches!(action, Action::Switch { .. }));
+
+        assert!(!has_switch(&mut state, 0));
+        assert!(has_switch(&mut state, 6));
+    }
+
+    #[test]
+    fn child_matrix_follows_a_queued_move_shrinking_the_actions() {
+        let _data = crate::load_test_data();
+        let mut team = crate::test_team(1);
+        team.members[0].moves = vec![Move::id_by_name("Tackle").unwrap(), Move::id_by_name("Growl").unwrap()];
+        let mut state = crate::test_battle(&team, &team);
+        let queued = Action::Move { user_id: 6, move_: Move::id_by_name("Tackle").unwrap(), move_index: None, target_positions: vec![FieldPosition::Min] };
+        state.pokemon_by_id_mut(6).next_move_action = Some(queued);
+        let mut rng = StepRng::new(0, 0);
+
+        generate_actions(&mut state);
+        assert_eq!((state.max.actions.len(), state.min.actions.len()), (1, 2));
+        assert_eq!(state.children.len(), 2);
+        let child = state.get_or_gen_child(0, 1, &mut rng);
+
+        // The queued move was used up, so the child's maximizer has its full choice of moves again
+        generate_actions(child);
+        assert_eq!((child.max.actions.len(), child.min.actions.len()), (2, 2));
+        assert_eq!(child.children.len(), 4);
+        child.get_or_gen_child(1, 1, &mut rng);
+    }
+
+    #[test]
+    fn choice_lock_lasts_until_the_holder_switches_out() {
+        let _data = crate::load_test_data();
+        let mut team = crate::test_team(2);
+        for bulbasaur in team.iter_mut() {
+            bulbasaur.moves = vec![Move::id_by_name("Tackle").unwrap(), Move::id_by_name("Growl").unwrap()];
+        }
+        let mut state = crate::test_battle(&team, &crate::test_team(1));
+        state.pokemon_by_id_mut(0).item = Some(Item::id_by_name("Choice Scarf").unwrap());
+        let mut rng = StepRng::new(0, 0);
+        let usable_moves = |state: &mut State| gen_actions_for_user(state, 0).iter()
+            .filter_map(|action| match action { Action::Move { move_, .. } => Some(Move::name(*move_)), _ => None })
+            .collect::<Vec<_>>();
+        assert_eq!(usable_moves(&mut state), ["Tackle", "Growl"]);
+
+        let tackle = Action::Move { user_id: 0, move_: Move::id_by_name("Tackle").unwrap(), move_index: Some(0), target_positions: vec![FieldPosition::Max] };
+        tackle.perform(&mut state, &[], &mut rng);
+        assert_eq!(usable_moves(&mut state), ["Tackle"]);
+
+        Action::Switch { user_id: Some(0), switching_in_id: 1, target_position: FieldPosition::Min }.perform(&mut state, &[], &mut rng);
+        Action::Switch { user_id: Some(1), switching_in_id: 0, target_position: FieldPosition::Min }.perform(&mut state, &[], &mut rng);
+        assert_eq!(usable_moves(&mut state), ["Tackle", "Growl"]);
+    }
+
+    #[test]
+    fn stepping_a_turn_plays_out_both_chosen_moves() {
+        let _data = crate::load_test_data();
+        let mut team = crate::test_team(1);
+        team.members[0].moves = vec![Move::id_by_name("Tackle").unwrap(), Move::id_by_name("Growl").unwrap()];
+        let mut faster = team.clone();
+        faster.members[0].evs[5] = 252;
+        let mut state = crate::test_battle(&team, &faster);
+        generate_actions(&mut state);
+        let chosen = |actions: &[Action], name| actions.iter()
+            .find(|action| matches!(action, Action::Move { move_, .. } if Move::name(*move_) == name))
+            .unwrap().clone();
+        let (tackle, growl) = (chosen(&state.max.actions, "Tackle"), chosen(&state.min.actions, "Growl"));
+
+        let mut tackled = state.clone();
+        tackle.perform(&mut tackled, &[], &mut StepRng::new(0, 0));
+        let max_hp = state.pokemon_by_id(6).max_hp();
+
+        assert!(!state.step(tackle, growl, &mut StepRng::new(0, 0)));
+        assert_eq!(state.pokemon_by_id(0).current_hp(), tackled.pokemon_by_id(0).current_hp());
+        assert!(state.pokemon_by_id(0).current_hp() < state.pokemon_by_id(0).max_hp());
+        assert_eq!(state.pokemon_by_id(6).current_hp(), max_hp);
+        assert_eq!(state.pokemon_by_id(6).stat_stage(StatIndex::Atk), -1);
+    }
+
+    #[test]
+    fn hp_weight_alone_gives_the_hp_fraction_difference() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(2), &crate::test_team(2));
+        state.rules.heuristic_weights = HeuristicWeights { hp: 1.0, status: 0.0, stages: 0.0, type_matchup: 0.0, faint_count: 0.0 };
+        let max_hp = state.pokemon_by_id(0).max_hp();
+        state.pokemon_by_id_mut(0).current_hp = max_hp / 2;
+        // None of these count with only the HP weight
+        state.pokemon_by_id_mut(6).major_status_ailment = MajorStatusAilment::Burned;
+        state.pokemon_by_id_mut(0).set_stat_stages([0, 2, 0, 0, 0, 0, 0, 0]).unwrap();
+        state.pokemon_by_id_mut(7).current_hp = 0;
+        state.recompute_team_totals();
+
+        let hp_fraction_difference = (1.0 - (1.0 + state.pokemon_by_id(0).hp_fraction())) / 6.0;
+        assert_eq!(heuristic_value(&state), hp_fraction_difference);
+    }
+
+    #[test]
+    fn faint_count_weight_favors_the_side_with_more_standing() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(2), &crate::test_team(2));
+        let max_hp = state.pokemon_by_id(6).max_hp();
+        // Both sides have one full Pokemon's worth of HP, but the minimizer's is all on one Pokemon
+        state.pokemon_by_id_mut(1).current_hp = 0;
+        state.pokemon_by_id_mut(6).current_hp = max_hp / 2;
+        state.pokemon_by_id_mut(7).current_hp = max_hp - max_hp / 2;
+        state.recompute_team_totals();
+        state.rules.heuristic_weights = HeuristicWeights { hp: 1.0, status: 0.0, stages: 0.0, type_matchup: 0.0, faint_count: 0.0 };
+        let hp_only = heuristic_value(&state);
+
+        state.rules.heuristic_weights.faint_count = 1.0;
+        assert!(heuristic_value(&state) > hp_only + 0.01);
+    }
+
+    #[test]
+    fn evaluated_matrix_has_a_payoff_per_action_pair() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(2), &crate::test_team(3));
+        generate_actions(&mut state);
+
+        let (max_actions, min_actions, matrix) = state.evaluate_matrix(2, &mut StdRng::seed_from_u64(0));
+        assert_eq!((matrix.num_rows(), matrix.num_cols()), (max_actions.len(), min_actions.len()));
+        assert_eq!((max_actions.len(), min_actions.len()), (state.max.actions.len(), state.min.actions.len()));
+        for i in 0..matrix.num_rows() {
+            for j in 0..matrix.num_cols() {
+                assert!((-1.0..=1.0).contains(&matrix.get(i, j)), "{:?}", matrix);
+            }
+        }
+    }
+
+    #[test]
+    fn move_out_of_pp_is_illegal_and_a_free_switch_is_legal() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(2), &crate::test_team(1));
+        let growl = Action::move_(&state, 0, Move::id_by_name("Growl").unwrap(), vec![FieldPosition::Max]).unwrap();
+        let growl_index = match growl { Action::Move { move_index, .. } => move_index.unwrap(), _ => unreachable!() };
+        assert!(state.is_legal(Side::Min, &growl));
+
+        state::increment_move_pp(&mut state, 0, growl_index, -100);
+        assert!(!state.is_legal(Side::Min, &growl));
+        let switch = Action::Switch { user_id: Some(0), switching_in_id: 1, target_position: FieldPosition::Min };
+        assert!(state.is_legal(Side::Min, &switch));
+    }
+
+    #[test]
+    fn tiny_node_budget_cuts_the_search_short_with_a_valid_result() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(3), &crate::test_team(3));
+        generate_actions(&mut state);
+        let copies_made = |state: &mut State| {
+            let copies_before = state::NUM_STATE_COPIES.load(atomic::Ordering::Relaxed);
+            let nash_eq = smab_search(state, -1.0, 1.0, 3, &mut StdRng::seed_from_u64(0));
+            (nash_eq, state::NUM_STATE_COPIES.load(atomic::Ordering::Relaxed) - copies_before)
+        };
+        let (_, unlimited_copies) = copies_made(&mut state.clone());
+
+        state.rules.max_search_nodes = Some(1);
+        let (nash_eq, budgeted_copies) = copies_made(&mut state);
+        assert!(budgeted_copies * 10 < unlimited_copies, "{} vs {}", budgeted_copies, unlimited_copies);
+        assert!((-1.0..=1.0).contains(&nash_eq.expected_payoff));
+        assert!((nash_eq.max_player_strategy.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!((nash_eq.min_player_strategy.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn replacement_with_the_better_matchup_is_tried_first() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(1), &crate::test_team(3));
+        state::set_hp(&mut state, 6, 0);
+        pokemon::remove_from_field(&mut state, 6);
+        // Fire resists Bulbasaur's Grass moves and hits it super effectively
+        state.pokemon_by_id_mut(8).first_type = Type::Fire;
+        state.pokemon_by_id_mut(8).second_type = Type::None;
+
+        agents_choose_pokemon_to_send_out(&mut state);
+        let replacement_ids: Vec<u8> = state.max.actions.iter().map(|action| match action {
+            Action::Switch { switching_in_id, .. } => *switching_in_id,
+            _ => panic!("Expected only replacements, got {:?}", action)
+        }).collect();
+        assert_eq!(replacement_ids, vec![8, 7]);
+    }
+
+    #[test]
+    fn ally_only_move_is_not_offered_in_a_single_battle() {
+        let _data = crate::load_test_data();
+        let mut team = crate::test_team(1);
+        let moves_json = r#"[
+            {"name": "Aromatic Mist", "type": "Fairy", "category": "Status", "accuracy": "Ignore", "targeting": "SingleAdjacentAlly",
+                "max_pp": 20, "priority_stage": 0, "sound_based": false, "effects": [{"IncTargetStatStage": ["SpDef", 1]}]},
+            {"name": "Tackle", "type": "Normal", "category": "Physical", "accuracy": {"Standard": 100}, "targeting": "SingleAdjacentPokemon",
+                "max_pp": 35, "priority_stage": 0, "sound_based": false, "effects": [{"StdDamage": ["Normal", 50, 0]}]}
+        ]"#;
+        let mut moves = crate::battle_ai::move_::parse_moves(String::from("moves.json"), moves_json).unwrap();
+
+        crate::battle_ai::move_::swap_moves(&mut moves);
+        let (aromatic_mist, tackle) = (Move::id_by_name("Aromatic Mist").unwrap(), Move::id_by_name("Tackle").unwrap());
+        team.members[0].moves = vec![aromatic_mist, tackle];
+        let mut state = crate::test_battle(&team, &team);
+        let actions = gen_actions_for_user(&mut state, 6);
+        crate::battle_ai::move_::swap_moves(&mut moves);
+        assert!(actions.iter().any(|action| matches!(action, Action::Move { move_, .. } if *move_ == tackle)));
+        assert!(!actions.iter().any(|action| matches!(action, Action::Move { move_, .. } if *move_ == aromatic_mist)));
+    }
+
+    #[test]
+    fn mutual_knockout_of_the_last_pokemon_is_an_exact_draw() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(1), &crate::test_team(1));
+        state::set_hp(&mut state, 0, 1);
+        state::set_hp(&mut state, 6, 1);
+        let struggle = Action::Move { user_id: 6, move_: Move::id_by_name("Struggle").unwrap(), move_index: None, target_positions: vec![FieldPosition::Min] };
+
+        // Struggle's recoil takes down the user right after it knocks out the target
+        play_out_turn(&mut state, vec![&struggle], None, &mut StepRng::new(0, 0));
+        assert!(state.pokemon_by_id(0).is_fainted() && state.pokemon_by_id(6).is_fainted());
+        assert!(state.has_battle_ended());
+        assert_eq!(heuristic_value(&state), 0.0);
+    }
+}
@@ -1,11 +1,10 @@
 use crate::move_::{MoveID, Move};
-use rand::prelude::StdRng;
 use rand::Rng;
 use serde::Deserialize;
 use serde::export::TryFrom;
-use std::fs;
 use std::cmp::min;
-use crate::battle_ai::data::data::{Type, StatIndex, Gender};
+use std::mem;
+use crate::battle_ai::data::data::{Type, StatIndex, Gender, DataLoadError};
 use crate::battle_ai::data::move_::{MoveID, Move};
 
 pub type SpeciesID = u8;
@@ -73,6 +72,19 @@ impl Species {
         Species::by_id(species).base_stats[stat_index.as_usize()]
     }
 
+    /// Fraction of the loaded species, including this one, whose base stat is at most this species'. The species
+    /// with the highest base stat gets 1.0.
+    pub fn stat_percentile(species: SpeciesID, stat_index: StatIndex) -> f64 {
+        let base_stat = Species::base_stat(species, stat_index);
+        let num_at_most = (0..Species::count()).filter(|&other| Species::base_stat(other, stat_index) <= base_stat).count();
+        num_at_most as f64 / Species::count() as f64
+    }
+
+    /// In tenths of a kg.
+    pub fn weight(species: SpeciesID) -> u16 {
+        Species::by_id(species).weight
+    }
+
     pub fn allow_duplicates(species: SpeciesID) -> bool {
         Species::by_id(species).allow_duplicates
     }
@@ -81,13 +93,21 @@ impl Species {
         &Species::by_id(species).move_pool
     }
 
-    pub fn random_species(rng: &mut StdRng) -> SpeciesID {
-        unsafe {
-            rng.gen_range(0, SPECIES.len() as SpeciesID)
+    /// Species that can know at least one move. No legal build exists for the others, so they are never chosen
+    /// when generating builds.
+    pub fn buildable_species() -> Vec<SpeciesID> {
+        (0..Species::count()).filter(|&species| !Species::move_pool(species).is_empty()).collect()
+    }
+
+    pub fn random_species<R: Rng>(rng: &mut R) -> SpeciesID {
+        let buildable_species = Species::buildable_species();
+        if buildable_species.is_empty() {
+            panic!("No species can know any moves");
         }
+        buildable_species[rng.gen_range(0, buildable_species.len())]
     }
 
-    pub fn random_gender(species: SpeciesID, rng: &mut StdRng) -> Gender {
+    pub fn random_gender<R: Rng>(species: SpeciesID, rng: &mut R) -> Gender {
         let female_chance = Species::by_id(species).female_chance;
         let male_chance = Species::by_id(species).male_chance;
         let i = rng.gen_range(0, 1000);
@@ -100,7 +120,7 @@ impl Species {
         }
     }
 
-    pub fn random_ability(species: SpeciesID, rng: &mut StdRng) -> AbilityID {
+    pub fn random_ability<R: Rng>(species: SpeciesID, rng: &mut R) -> AbilityID {
         let abilities = &Species::by_id(species).abilities;
         if abilities.len() == 2 && rng.gen_bool(0.5) {
             abilities[1]
@@ -109,7 +129,7 @@ impl Species {
         }
     }
 
-    pub fn random_move_set(species: SpeciesID, rng: &mut StdRng) -> Vec<MoveID> {
+    pub fn random_move_set<R: Rng>(species: SpeciesID, rng: &mut R) -> Vec<MoveID> {
         let move_pool = &Species::by_id(species).move_pool;
         let mut move_set = Vec::with_capacity(4);
         while move_set.len() < min(4, move_pool.len()) {
@@ -121,6 +141,15 @@ impl Species {
         move_set
     }
 
+    pub fn can_have_gender(species: SpeciesID, gender: Gender) -> bool {
+        let s = Species::by_id(species);
+        match gender {
+            Gender::Female => s.female_chance > 0,
+            Gender::Male => s.male_chance > 0,
+            Gender::None => s.female_chance + s.male_chance < 1000
+        }
+    }
+
     pub fn has_male_and_female(species: SpeciesID) -> bool {
         let s = Species::by_id(species);
         s.female_chance > 0 && s.male_chance > 0
@@ -175,14 +204,101 @@ struct SpeciesSerde<'d> {
 
 /// # Safety
 /// Should be called after the game version has been set from the program input and the moves have been initialized.
-pub fn initialize_species() {
-    let mut path = String::from("resources/");
-    path.push_str(game_version().name());
-    path.push_str("/species.json");
-    let species_json = fs::read_to_string(path.as_str())
-        .unwrap_or_else(|_| panic!("Failed to read {}.", path));
+pub fn initialize_species() -> Result<(), DataLoadError> {
+    let (path, species_json) = read_resource_file("species.json")?;
+    let species = serde_json::from_str(species_json.as_str())
+        .map_err(|err| DataLoadError::Parse { path, message: err.to_string() })?;
     unsafe {
-        SPECIES = serde_json::from_str(species_json.as_str())
-            .unwrap_or_else(|err| panic!("Error parsing species.json: {}", err));
+        SPECIES = species;
+    }
+    Ok(())
+}
+
+/// Exchanges the loaded species with `species`. Used to switch between game versions; see `GameData`.
+pub fn swap_species(species: &mut Vec<Species>) {
+    unsafe {
+        mem::swap(&mut SPECIES, species);
+    }
+}
+
+/// Returns a description of every problem found in the loaded species.
+pub fn validate_species() -> Vec<String> {
+    let mut problems = Vec::new();
+    unsafe {
+        for species in SPECIES.iter() {
+            if species.abilities.is_empty() {
+                problems.push(format!("{}: no abilities", species.name));
+            }
+            if species.move_pool.is_empty() {
+                problems.push(format!("{}: empty move pool", species.name));
+            }
+            for (i, move_) in species.move_pool.iter().enumerate() {
+                if species.move_pool[..i].contains(move_) {
+                    problems.push(format!("{}: '{}' is in the move pool more than once", species.name, Move::name(*move_)));
+                }
+            }
+            if species.male_chance + species.female_chance > 1000 {
+                problems.push(format!("{}: gender chances add up to more than 1000", species.name));
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fastest_species_tops_the_speed_percentiles() {
+        let _data = crate::load_test_data();
+        let species_json = r#"[
+            {"name": "Bulbasaur", "type1": "Grass", "type2": "Poison", "abilities": ["Overgrow"], "base_stats": [45, 49, 49, 65, 65, 45],
+                "weight": 69, "male_chance": 875, "female_chance": 125, "allow_duplicates": true, "move_pool": ["Tackle"]},
+            {"name": "Pikachu", "type1": "Electric", "type2": "None", "abilities": ["Static"], "base_stats": [35, 55, 40, 50, 50, 90],
+                "weight": 60, "male_chance": 500, "female_chance": 500, "allow_duplicates": true, "move_pool": ["Tackle"]},
+            {"name": "Snorlax", "type1": "Normal", "type2": "None", "abilities": ["Thick Fat"], "base_stats": [160, 110, 65, 65, 110, 30],
+                "weight": 4600, "male_chance": 875, "female_chance": 125, "allow_duplicates": true, "move_pool": ["Tackle"]}]"#;
+        let mut species: Vec<Species> = serde_json::from_str(species_json).unwrap();
+
+        swap_species(&mut species);
+        let percentiles: Vec<f64> = (0..3).map(|species| Species::stat_percentile(species, StatIndex::Spd)).collect();
+        swap_species(&mut species);
+        assert_eq!(percentiles[1], 1.0);
+        assert!(percentiles[0] < percentiles[1] && percentiles[2] < percentiles[0], "{:?}", percentiles);
+    }
+
+    #[test]
+    fn validation_reports_each_broken_species() {
+        let _data = crate::load_test_data();
+        let species_json = r#"[
+            {"name": "Bulbasaur", "type1": "Grass", "type2": "Poison", "abilities": ["Overgrow"], "base_stats": [45, 49, 49, 65, 65, 45],
+                "weight": 69, "male_chance": 875, "female_chance": 125, "allow_duplicates": true, "move_pool": ["Tackle"]},
+            {"name": "Broken", "type1": "Normal", "type2": "None", "abilities": [], "base_stats": [50, 50, 50, 50, 50, 50],
+                "weight": 100, "male_chance": 900, "female_chance": 200, "allow_duplicates": true, "move_pool": ["Tackle", "Growl", "Tackle"]},
+            {"name": "Moveless", "type1": "Normal", "type2": "None", "abilities": ["Static"], "base_stats": [50, 50, 50, 50, 50, 50],
+                "weight": 100, "male_chance": 500, "female_chance": 500, "allow_duplicates": true, "move_pool": []}]"#;
+        let mut species: Vec<Species> = serde_json::from_str(species_json).unwrap();
+
+        swap_species(&mut species);
+        let problems = validate_species();
+        swap_species(&mut species);
+        assert_eq!(problems, vec![
+            "Broken: no abilities",
+            "Broken: 'Tackle' is in the move pool more than once",
+            "Broken: gender chances add up to more than 1000",
+            "Moveless: empty move pool"
+        ]);
+    }
+
+    #[test]
+    fn species_with_an_unknown_move_fails_to_load() {
+        let _data = crate::load_test_data();
+        let species_json = r#"[{"name": "Bulbasaur", "type1": "Grass", "type2": "Poison", "abilities": ["Overgrow"],
+            "base_stats": [45, 49, 49, 65, 65, 45], "weight": 69, "male_chance": 875, "female_chance": 125, "allow_duplicates": true,
+            "move_pool": ["Tackle", "Not A Move"]}]"#;
+
+        let error = serde_json::from_str::<Vec<Species>>(species_json).unwrap_err().to_string();
+        assert!(error.contains("Not A Move"), "{}", error);
     }
 }
@@ -1,15 +1,20 @@
 use std::cmp::{max, min, Ordering};
 
-use rand::prelude::StdRng;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 use crate::battle_ai::game_theory::{Matrix, ZeroSumNashEq};
 use crate::battle_ai::move_effects::Action;
+use crate::battle_ai::move_effects::MoveEffect;
+use crate::battle_ai::move_effects;
 use crate::move_::{Move, MoveCategory};
+use std::mem;
+use std::sync::atomic::AtomicU64;
 use std::ops::AddAssign;
 use num::{One, Zero};
-use crate::battle_ai::data::{Weather, Terrain, FieldPosition, Type, Gender, Nature, MajorStatusAilment, StatIndex};
+use crate::battle_ai::data::{Weather, Terrain, FieldPosition, Type, Gender, Nature, MajorStatusAilment, StatIndex, ItemID, InvulnKind, Side};
 use crate::battle_ai::species::{SpeciesID, Species};
+use crate::LogLevel;
 use crate::battle_ai::move_::{MoveID, Move};
 
 /// How many turns ahead the agents compute
@@ -19,7 +24,8 @@ pub const AI_LEVEL: u8 = 3;
 /// (does not count switching one in to replace a fainted team member)
 const CONSECUTIVE_SWITCH_CAP: u16 = 1;
 
-pub static mut NUM_STATE_COPIES: u64 = 0;
+/// Number of states the searches have generated so far, counted across every thread.
+pub static NUM_STATE_COPIES: AtomicU64 = AtomicU64::new(0);
 
 /// Represents the entire game state of a battle.
 #[derive(Clone, Debug)]
@@ -29,16 +35,35 @@ pub struct State {
     pub max: Agent,
     pub min: Agent,
     pub weather: Weather,
+    /// Counts the turns of weather set by a move. Weather with no target lasts indefinitely, as with Drought and
+    /// Drizzle before gen 6.
     pub weather_counter: Counter<u16>,
     pub terrain: Terrain,
+    pub rules: BattleRules,
     turn_number: u16,
+    /// Random draws made on the way to this state.
+    pub rng_draws: RngDrawCounts,
     /// Battle print-out that is shown when this state is entered; useful for sanity checks.
     display_text: Vec<String>,
+    /// Row-major matrix of the states reached by each pair of actions, indexed by the maximizer's action and then the
+    /// minimizer's. Its length is always the product of the agents' numbers of actions, so it must be reallocated
+    /// whenever the actions are regenerated.
     children: Vec<Option<Box<State>>>,
+    /// Only used with the `branch-speed-ties` feature.
+    speed_tie_children: Vec<Option<Box<State>>>,
+    /// Totals over the minimizer's Pokemon that evaluating a position needs.
+    min_totals: TeamTotals,
+    /// Totals over the maximizer's Pokemon that evaluating a position needs.
+    max_totals: TeamTotals
 }
 
 impl State {
-    fn new(pokemon: [Pokemon; 12], weather: Weather, terrain: Terrain) -> State {
+    fn new(mut pokemon: [Pokemon; 12], weather: Weather, terrain: Terrain, rules: BattleRules) -> State {
+        for (id, pokemon) in pokemon.iter_mut().enumerate() {
+            pokemon.id = id as u8;
+        }
+        let min_totals = TeamTotals::of(&pokemon[0..6]);
+        let max_totals = TeamTotals::of(&pokemon[6..12]);
         State {
             pokemon,
             max: Agent {
@@ -46,46 +71,94 @@ impl State {
                 actions: vec![
                     Action::Switch {
                         user_id: None,
-                        switching_in_id: 6,
+                        switching_in_id: rules.max_lead as u8 + 6,
                         target_position: FieldPosition::Max
                     }
                 ],
                 action_order: vec![0],
-                consecutive_switches: 0
+                consecutive_switches: 0,
+                baton_pass: None,
+                side_conditions: SideConditions::default()
             },
             min: Agent {
                 on_field: None,
                 actions: vec![
                     Action::Switch {
                         user_id: None,
-                        switching_in_id: 0,
+                        switching_in_id: rules.min_lead as u8,
                         target_position: FieldPosition::Min
                     }
                 ],
                 action_order: vec![0],
-                consecutive_switches: 0
+                consecutive_switches: 0,
+                baton_pass: None,
+                side_conditions: SideConditions::default()
             },
             weather,
             weather_counter: Counter::new(None),
             terrain,
+            rules,
             turn_number: 0,
+            rng_draws: RngDrawCounts::default(),
             display_text: Vec::new(),
-            children: vec![None; 1]
+            children: vec![None; 1],
+            speed_tie_children: vec![None; 1],
+            min_totals,
+            max_totals
         }
     }
 
+    /// Totals over the minimizer's and maximizer's Pokemon, in that order.
+    pub const fn team_totals(&self) -> (TeamTotals, TeamTotals) {
+        (self.min_totals, self.max_totals)
+    }
+
+    /// Adds up the team totals from scratch. Only needed after changing HP or major status ailments without going
+    /// through `pokemon::set_hp` and `pokemon::set_major_status_ailment`.
+    pub fn recompute_team_totals(&mut self) {
+        self.min_totals = TeamTotals::of(&self.pokemon[0..6]);
+        self.max_totals = TeamTotals::of(&self.pokemon[6..12]);
+    }
+
+    fn team_totals_mut(&mut self, pokemon_id: u8) -> &mut TeamTotals {
+        if pokemon_id < 6 { &mut self.min_totals } else { &mut self.max_totals }
+    }
+
     pub const fn pokemon_by_id(&self, pokemon_id: u8) -> &Pokemon {
         &self.pokemon[pokemon_id as usize]
     }
 
+    pub const fn turn_number(&self) -> u16 {
+        self.turn_number
+    }
+
     pub fn pokemon_by_id_mut(&mut self, pokemon_id: u8) -> &mut Pokemon {
         &mut self.pokemon[pokemon_id as usize]
     }
 
+    /// Whether display text of the given level is recorded under the battle rules.
+    pub fn logs(&self, level: LogLevel) -> bool {
+        self.rules.log_level >= level
+    }
+
     pub fn add_display_text(&mut self, text: String) {
         self.display_text.push(text);
     }
 
+    pub fn display_text(&self) -> &[String] {
+        &self.display_text
+    }
+
+    pub fn display_text_len(&self) -> usize {
+        self.display_text.len()
+    }
+
+    /// Replaces all display text from index `start` onwards with `text`.
+    pub fn replace_display_text_from(&mut self, start: usize, text: String) {
+        self.display_text.truncate(start);
+        self.display_text.push(text);
+    }
+
     fn print_display_text(&self) {
         self.display_text.iter().for_each(|text| {
             text.lines().for_each(|line| println!("  {}", line));
@@ -93,33 +166,289 @@ impl State {
     }
 
     pub fn has_battle_ended(&self) -> bool {
-        self.pokemon[0..6].iter().all(|pokemon| pokemon.current_hp() == 0) || self.pokemon[6..12].iter().all(|pokemon| pokemon.current_hp() == 0)
+        self.pokemon[0..6].iter().all(|pokemon| pokemon.is_fainted()) || self.pokemon[6..12].iter().all(|pokemon| pokemon.is_fainted())
+    }
+
+    /// Summaries of the minimizer's and maximizer's on-field Pokemon, in that order.
+    pub fn field_summary(&self) -> [Option<FieldSummary>; 2] {
+        let summarize = |on_field: Option<u8>| on_field.map(|pokemon_id| {
+            let pokemon = self.pokemon_by_id(pokemon_id);
+            FieldSummary {
+                species: pokemon.species(),
+                hp_fraction: pokemon.hp_fraction(),
+                major_status_ailment: pokemon.major_status_ailment(),
+                stat_stages: pokemon.stat_stages
+            }
+        });
+        [summarize(self.min.on_field), summarize(self.max.on_field)]
+    }
+
+    pub fn snapshot(&self) -> StateSnapshot {
+        let side = |agent: &Agent| SideSnapshot {
+            consecutive_switches: agent.consecutive_switches,
+            baton_pass: agent.baton_pass.clone(),
+            spikes_layers: agent.side_conditions.spikes_layers,
+            toxic_spikes_layers: agent.side_conditions.toxic_spikes_layers,
+            tailwind_counter: running(&agent.side_conditions.tailwind_counter),
+            delayed_hit: agent.side_conditions.delayed_hit.clone()
+        };
+        StateSnapshot {
+            pokemon: self.pokemon.iter().map(|pokemon| PokemonSnapshot {
+                current_hp: pokemon.current_hp,
+                first_type: pokemon.first_type,
+                second_type: pokemon.second_type,
+                roost_types: pokemon.roost_types,
+                ability: pokemon.ability,
+                item: pokemon.item,
+                unstaged_stats: pokemon.unstaged_stats,
+                stat_stages: pokemon.stat_stages,
+                major_status_ailment: pokemon.major_status_ailment,
+                msa_counter: (pokemon.major_status_ailment != MajorStatusAilment::Okay).then(|| pokemon.msa_counter.clone()),
+                snore_sleep_talk_counter: pokemon.snore_sleep_talk_counter,
+                confusion_counter: running(&pokemon.confusion_counter),
+                is_flinching: pokemon.is_flinching,
+                seeded_by: pokemon.seeded_by,
+                is_infatuated: pokemon.is_infatuated,
+                is_cursed: pokemon.is_cursed,
+                has_nightmare: pokemon.has_nightmare,
+                is_identified: pokemon.is_identified,
+                is_trapped: pokemon.is_trapped,
+                partial_trap_counter: running(&pokemon.partial_trap_counter),
+                drowsy_counter: pokemon.drowsy_counter,
+                is_first_turn_on_field: pokemon.turns_on_field == 0,
+                is_protected: pokemon.is_protected,
+                protect_streak: pokemon.protect_streak,
+                consecutive_move_counter: pokemon.consecutive_move_counter,
+                semi_invulnerable: pokemon.semi_invulnerable,
+                rampage_counter: running(&pokemon.rampage_counter),
+                last_move_used: pokemon.last_move_used,
+                last_attacker: pokemon.last_attacker,
+                last_attack_damage: pokemon.last_attack_damage,
+                choice_locked: pokemon.choice_locked,
+                mimic_slot: pokemon.mimic_slot.clone(),
+                transform_originals: pokemon.transform_originals.clone(),
+                substitute_hp: pokemon.substitute_hp,
+                field_position: pokemon.field_position,
+                known_moves: pokemon.known_moves.iter().map(|move_instance| (move_instance.move_, move_instance.disabled)).collect(),
+                next_move_action: pokemon.next_move_action.clone()
+            }).collect(),
+            weather: self.weather,
+            weather_counter: running(&self.weather_counter),
+            terrain: self.terrain,
+            sides: [side(&self.min), side(&self.max)]
+        }
     }
 }
 
+/// What a player can see of an on-field Pokemon.
+#[derive(Clone, Copy, Debug)]
+pub struct FieldSummary {
+    pub species: SpeciesID,
+    pub hp_fraction: f64,
+    pub major_status_ailment: MajorStatusAilment,
+    pub stat_stages: [i8; 8]
+}
+
+/// Everything about a position that can affect how a battle goes on from it, for telling whether a battle has come
+/// back around to an earlier position. The turn number is left out, as is PP: PP only ever goes down, so with it no
+/// position could come up twice, and a loop that only PP would break, such as two Pokemon stalling with Recover, is
+/// taken as a repetition. How long a Pokemon has been on the field only counts as far as whether it's its first turn,
+/// and a counter only counts while it's running.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct StateSnapshot {
+    pokemon: Vec<PokemonSnapshot>,
+    weather: Weather,
+    weather_counter: Option<Counter<u16>>,
+    terrain: Terrain,
+    /// The minimizer's side, then the maximizer's.
+    sides: [SideSnapshot; 2]
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct PokemonSnapshot {
+    current_hp: u16,
+    first_type: Type,
+    second_type: Type,
+    roost_types: Option<(Type, Type)>,
+    ability: AbilityID,
+    item: Option<ItemID>,
+    unstaged_stats: [u16; 5],
+    stat_stages: [i8; 8],
+    major_status_ailment: MajorStatusAilment,
+    /// Only kept while the Pokemon has a major status ailment.
+    msa_counter: Option<Counter<u16>>,
+    snore_sleep_talk_counter: u16,
+    confusion_counter: Option<Counter<u16>>,
+    is_flinching: bool,
+    seeded_by: Option<FieldPosition>,
+    is_infatuated: bool,
+    is_cursed: bool,
+    has_nightmare: bool,
+    is_identified: bool,
+    is_trapped: bool,
+    partial_trap_counter: Option<Counter<u16>>,
+    drowsy_counter: u8,
+    is_first_turn_on_field: bool,
+    is_protected: bool,
+    protect_streak: u8,
+    consecutive_move_counter: u8,
+    semi_invulnerable: Option<InvulnKind>,
+    rampage_counter: Option<Counter<u16>>,
+    last_move_used: Option<MoveID>,
+    last_attacker: Option<u8>,
+    last_attack_damage: Option<(MoveCategory, u16)>,
+    choice_locked: Option<MoveID>,
+    mimic_slot: Option<(usize, MoveInstance)>,
+    transform_originals: Option<TransformOriginals>,
+    substitute_hp: Option<u16>,
+    field_position: Option<FieldPosition>,
+    /// Each known move and whether it's disabled.
+    known_moves: Vec<(MoveID, bool)>,
+    next_move_action: Option<Action>
+}
+
+/// A counter as far as it can affect the battle: only one with a target counts towards anything.
+fn running(counter: &Counter<u16>) -> Option<Counter<u16>> {
+    counter.target.map(|_| counter.clone())
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct SideSnapshot {
+    consecutive_switches: u16,
+    baton_pass: Option<BatonPass>,
+    spikes_layers: u8,
+    toxic_spikes_layers: u8,
+    tailwind_counter: Option<Counter<u16>>,
+    delayed_hit: Option<DelayedHit>
+}
+
 #[derive(Clone, Debug)]
 pub struct Agent {
     /// Pokemon owned by this agent that is on the field
     pub on_field: Option<u8>,
     actions: Vec<Action>,
     action_order: Vec<usize>,
-    consecutive_switches: u16
+    consecutive_switches: u16,
+    /// Set when this agent's Pokemon used Baton Pass; applied to whichever Pokemon is sent out next.
+    pub baton_pass: Option<BatonPass>,
+    pub side_conditions: SideConditions
+}
+
+/// Running totals over one team's Pokemon, kept up to date by `pokemon::set_hp` and
+/// `pokemon::set_major_status_ailment` so that evaluating a position doesn't have to go over all 12 Pokemon.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TeamTotals {
+    pub hp_fraction_sum: f64,
+    /// Pokemon that haven't fainted.
+    pub standing: u8,
+    /// Pokemon that haven't fainted and have a major status ailment.
+    pub statused: u8
+}
+
+impl TeamTotals {
+    fn of(team: &[Pokemon]) -> TeamTotals {
+        let mut totals = TeamTotals::default();
+        for pokemon in team {
+            totals.hp_fraction_sum += pokemon.hp_fraction();
+            if !pokemon.is_fainted() {
+                totals.standing += 1;
+                if pokemon.major_status_ailment != MajorStatusAilment::Okay {
+                    totals.statused += 1;
+                }
+            }
+        }
+        totals
+    }
+}
+
+/// Numbers of random draws made while playing out a battle, grouped by what they decided. Useful for auditing where
+/// the variance between battles comes from.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RngDrawCounts {
+    pub critical_hits: u32,
+    pub damage_rolls: u32,
+    /// Coin flips between moves with the same priority and Speed.
+    pub speed_ties: u32,
+    /// Secondary effect chances, status durations and status checks such as full paralysis and confusion.
+    pub status_rolls: u32,
+    /// Picks of which stat an effect changes, such as Acupressure's.
+    pub stat_picks: u32
+}
+
+/// Conditions on an agent's side of the field that affect whichever of its Pokemon are there.
+#[derive(Clone, Debug, Default)]
+pub struct SideConditions {
+    /// 0-3; damages grounded Pokemon switching in.
+    pub spikes_layers: u8,
+    /// 0-2; poisons grounded Pokemon switching in, or badly poisons them with two layers.
+    pub toxic_spikes_layers: u8,
+    /// Doubles the side's Speed while it has a target.
+    pub tailwind_counter: Counter<u16>,
+    /// Future Sight or Doom Desire on its way to whichever Pokemon is on this side when it lands.
+    pub delayed_hit: Option<DelayedHit>
+}
+
+/// A hit from Future Sight or Doom Desire that lands at the end of a later turn. The user's side of the damage
+/// calculation is fixed when the move is used, so the hit lands the same even if the user has since left the field.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct DelayedHit {
+    /// The hit lands at the end of this turn.
+    pub turn: u16,
+    pub user_id: u8,
+    pub move_: MoveID,
+    pub damage_type: Type,
+    pub power: u8,
+    pub calculated_sp_atk: u32,
+    pub sp_atk_stage: i8,
+    pub stab: bool
+}
+
+/// Effects that Baton Pass transfers to the incoming Pokemon.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct BatonPass {
+    /// The Pokemon that used Baton Pass; it cannot be chosen as the recipient.
+    pub user_id: u8,
+    stat_stages: [i8; 8],
+    confusion_counter: Counter<u16>,
+    seeded_by: Option<FieldPosition>,
+    substitute_hp: Option<u16>
+}
+
+impl BatonPass {
+    pub fn new(pokemon: &Pokemon, user_id: u8) -> BatonPass {
+        BatonPass {
+            user_id,
+            stat_stages: pokemon.stat_stages,
+            confusion_counter: pokemon.confusion_counter.clone(),
+            seeded_by: pokemon.seeded_by,
+            substitute_hp: pokemon.substitute_hp
+        }
+    }
 }
 
 // TODO: Store static info outside of Pokemon
 #[derive(Clone, Debug)]
 /// Assumed to be level 100.
 pub struct Pokemon {
+    /// Index into `State::pokemon`, assigned when the state is created. Only used to tell Pokemon apart in display
+    /// text.
+    id: u8,
     pub species: SpeciesID,
     // Types usually match the species' type, but some Pokemon can change types
     first_type: Type,
     second_type: Type,
+    /// The user's types from before it used Roost; they are restored at the end of the turn.
+    roost_types: Option<(Type, Type)>,
     pub gender: Gender,
     nature: Nature,
     ability: AbilityID,
+    item: Option<ItemID>,
     ivs: [u8; 6],
     evs: [u8; 6],
     max_hp: u16,
+    /// Attack through Speed as determined by species, IVs, EVs and nature. Stat stages, status and abilities are
+    /// applied on top of these in `calculated_stat`.
+    unstaged_stats: [u16; 5],
     current_hp: u16,
     stat_stages: [i8; 8],
 
@@ -131,12 +460,47 @@ pub struct Pokemon {
 
     // Minor status ailments
     confusion_counter: Counter<u16>,
-    is_flinching: bool,
+    /// Only lasts for the turn.
+    pub is_flinching: bool,
     /// Position of the Pokemon that seeded this Pokemon.
     pub seeded_by: Option<FieldPosition>,
     pub is_infatuated: bool,
     is_cursed: bool,
     has_nightmare: bool,
+    /// Set by Foresight and Odor Sleuth.
+    pub is_identified: bool,
+    /// Set by Mean Look and Block; cleared when either this Pokemon or the trapper leaves the field.
+    pub is_trapped: bool,
+    /// Counts the turns of Bind, Fire Spin, etc.; has a target while the Pokemon is caught in one.
+    pub partial_trap_counter: Counter<u16>,
+    /// End-of-turn routines left until Yawn puts this Pokemon to sleep; 0 if it isn't drowsy.
+    pub drowsy_counter: u8,
+    /// Turns this Pokemon has finished on the field since it last switched in; 0 during its first turn.
+    pub turns_on_field: u16,
+    /// Set by a successful Protect; only lasts for the turn.
+    pub is_protected: bool,
+    /// Number of successful Protects in a row.
+    pub protect_streak: u8,
+    /// Number of successful uses in a row of the last move used; powers up Rollout and Fury Cutter.
+    pub consecutive_move_counter: u8,
+    /// Set during the charge turn of Fly, Dig and Dive.
+    pub semi_invulnerable: Option<InvulnKind>,
+    /// Counts the turns of Outrage, Thrash, etc.; has a target while the Pokemon is locked into one.
+    pub rampage_counter: Counter<u16>,
+    /// Cleared when the Pokemon leaves the field.
+    pub last_move_used: Option<MoveID>,
+    /// Pokemon that last hit this one with an attack this turn, for Counter and the like. Cleared at the start of
+    /// each turn and when the Pokemon leaves the field.
+    pub last_attacker: Option<u8>,
+    /// Category of the attack from `last_attacker` and the HP it took.
+    pub last_attack_damage: Option<(MoveCategory, u16)>,
+    /// The move a choice item holder is locked into; cleared when the Pokemon leaves the field.
+    pub choice_locked: Option<MoveID>,
+    /// Slot and original contents of the move slot that Mimic replaced.
+    mimic_slot: Option<(usize, MoveInstance)>,
+    /// What the Pokemon had of its own before it used Transform.
+    transform_originals: Option<TransformOriginals>,
+    pub substitute_hp: Option<u16>,
 
     field_position: Option<FieldPosition>,
     known_moves: Vec<MoveInstance>,
@@ -149,6 +513,67 @@ impl Pokemon {
         self.stat_stages[stat_index.as_usize()]
     }
 
+    pub const fn is_transformed(&self) -> bool {
+        self.transform_originals.is_some()
+    }
+
+    /// Every stat stage, indexed by `StatIndex`.
+    pub const fn stat_stages(&self) -> [i8; 8] {
+        self.stat_stages
+    }
+
+    /// Sets every stat stage at once, indexed by `StatIndex`, e.g. to set up a boosted position without raising
+    /// stages one at a time. Fails without changing anything if a stage is outside of -6 to 6 or the HP stage isn't 0.
+    pub fn set_stat_stages(&mut self, stages: [i8; 8]) -> Result<(), String> {
+        if stages[StatIndex::Hp.as_usize()] != 0 {
+            return Err(String::from("HP has no stat stage"));
+        }
+        if let Some(stage) = stages.iter().find(|stage| !(-6..=6).contains(*stage)) {
+            return Err(format!("stat stage of {} is outside of -6 to 6", stage));
+        }
+        for (i, &stage) in stages.iter().enumerate() {
+            self.write_stat_stage(i, stage);
+        }
+        Ok(())
+    }
+
+    /// Every stat stage write goes through here, so that no effect can leave a stage outside of -6 to 6.
+    fn write_stat_stage(&mut self, stat_index: usize, stage: i8) {
+        self.stat_stages[stat_index] = num::clamp(stage, -6, 6);
+    }
+
+    pub fn cache_unstaged_stats(&mut self) {
+        let stat_indices = [StatIndex::Atk, StatIndex::Def, StatIndex::SpAtk, StatIndex::SpDef, StatIndex::Spd];
+        for (i, stat_index) in stat_indices.iter().enumerate() {
+            self.unstaged_stats[i] = pokemon::unstaged_stat(self.species, self.nature, &self.ivs, &self.evs, *stat_index);
+        }
+    }
+
+    pub const fn item(&self) -> Option<ItemID> {
+        self.item
+    }
+
+    pub const fn is_fainted(&self) -> bool {
+        self.current_hp == 0
+    }
+
+    pub const fn is_on_field(&self) -> bool {
+        self.field_position.is_some()
+    }
+
+    pub fn hp_fraction(&self) -> f64 {
+        self.current_hp as f64 / self.max_hp as f64
+    }
+
+    /// Number of pixels filled in the games' 48-pixel HP bar. A Pokemon with any HP left always shows at least
+    /// one pixel.
+    pub fn hp_bar_48ths(&self) -> u8 {
+        if self.is_fainted() {
+            return 0;
+        }
+        max(self.current_hp as u32 * 48 / self.max_hp as u32, 1) as u8
+    }
+
     pub fn known_move(&self, move_index: usize) -> &MoveInstance {
         &self.known_moves[move_index]
     }
@@ -157,19 +582,45 @@ impl Pokemon {
         self.first_type == type_ || self.second_type == type_
     }
 
+    /// Whether this Pokemon is affected by Ground-type moves, terrain and anything else that only reaches Pokemon on
+    /// the ground. Items such as Air Balloon and Iron Ball should be accounted for here once they exist.
+    pub fn is_grounded(&self) -> bool {
+        !self.is_type(Type::Flying) && self.ability != Ability::id_by_name("Levitate").unwrap()
+    }
+
     pub fn can_choose_move(&self, move_index: usize) -> bool {
         let move_instance = &self.known_moves[move_index];
-        self.current_hp > 0 && self.field_position.is_some() && move_instance.pp > 0 && !move_instance.disabled
+        !self.is_fainted() && self.is_on_field() && move_instance.pp > 0 && !move_instance.disabled
+    }
+
+    /// Each known move that has PP left and isn't disabled, with its remaining PP. Unlike `can_choose_move`, this
+    /// doesn't consider whether the Pokemon itself is in a position to move.
+    pub fn usable_moves(&self) -> Vec<(MoveID, u8)> {
+        self.known_moves.iter()
+            .filter(|move_instance| move_instance.pp > 0 && !move_instance.disabled)
+            .map(|move_instance| (move_instance.move_, move_instance.pp))
+            .collect()
     }
 }
 
 impl Display for Pokemon {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        write!(f, "{}{}({}/{})", Species::name(self.species), self.gender.symbol(), self.current_hp, self.max_hp)
+        let side = if self.id < 6 { "min" } else { "max" };
+        write!(f, "{}{} #{} ({})({}/{})", Species::name(self.species), self.gender.symbol(), self.id + 1, side, self.current_hp, self.max_hp)
     }
 }
 
-#[derive(Clone, Debug)]
+/// Parts of a Pokemon that Transform replaces; they are restored when it leaves the field. Types and stat stages are
+/// reset then anyways.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct TransformOriginals {
+    unstaged_stats: [u16; 5],
+    ability: AbilityID,
+    known_moves: Vec<MoveInstance>
+}
+
+/// Serializable so that mid-battle positions, including depleted PP, can be saved and restored.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
 pub struct MoveInstance {
     move_: MoveID,
     pub pp: u8,
@@ -186,7 +637,7 @@ impl From<MoveID> for MoveInstance {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct Counter<T> {
     value: T,
     target: Option<T>
@@ -200,6 +651,10 @@ impl<T: AddAssign + PartialOrd + One + Zero> Counter<T> {
         }
     }
 
+    pub fn has_target(&self) -> bool {
+        self.target.is_some()
+    }
+
     /// Sets the value to zero and the target to None.
     fn clear(&mut self) {
         self.value.set_zero();
@@ -218,7 +673,7 @@ impl<T: AddAssign + PartialOrd + One + Zero> Counter<T> {
     }
 
     /// Returns whether the target value was reached as a result of the addition.
-    /// Clears the counter if it did.
+    /// Clears the counter if it did. A counter without a target never reaches it.
     fn add(&mut self, amount: T) -> bool {
         self.value += amount;
 
@@ -233,8 +688,14 @@ impl<T: AddAssign + PartialOrd + One + Zero> Counter<T> {
     }
 }
 
+impl<T: AddAssign + PartialOrd + One + Zero> Default for Counter<T> {
+    fn default() -> Self {
+        Counter::new(None)
+    }
+}
+
 /// An action selection that will be queued and executed during a turn.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum Action {
     /// An action where the user performs one of its known moves.
     Move {
@@ -254,8 +715,68 @@ pub enum Action {
 }
 
 impl Action {
+    /// A move chosen by the Pokemon with ID `user_id` (0-5 for the minimizer's team, 6-11 for the maximizer's). The
+    /// user must be on the field and know the move; using it costs PP as if the agent had chosen it. Each target
+    /// position must be one the move can hit from the user's position.
+    pub fn move_(state: &State, user_id: u8, move_: MoveID, target_positions: Vec<FieldPosition>) -> Result<Action, String> {
+        if user_id >= 12 {
+            return Err(format!("invalid pokemon ID {}", user_id));
+        }
+        if move_ >= Move::count() {
+            return Err(format!("invalid move ID {}", move_));
+        }
+        let user = state.pokemon_by_id(user_id);
+        let user_pos = user.field_position().ok_or_else(|| format!("pokemon {} is not on the field", user_id))?;
+        let move_index = user.known_moves().iter().position(|move_instance| move_instance.move_() == move_)
+            .ok_or_else(|| format!("pokemon {} does not know {}", user_id, Move::name(move_)))?;
+        if target_positions.is_empty() {
+            return Err(String::from("a move needs at least one target position"));
+        }
+        if let Some(target_pos) = target_positions.iter().find(|target_pos| !Move::targeting(move_).can_hit(user_pos, **target_pos)) {
+            return Err(format!("{} can't hit {:?} from {:?}", Move::name(move_), target_pos, user_pos));
+        }
+        Ok(Action::Move {
+            user_id,
+            move_,
+            move_index: Some(move_index as u8),
+            target_positions
+        })
+    }
+
+    /// A switch of the Pokemon with ID `user_id` for a team member that is neither fainted nor on the field. A
+    /// `user_id` of None sends out the team member without anything being switched out.
+    pub fn switch(state: &State, user_id: Option<u8>, switching_in_id: u8, target_position: FieldPosition) -> Result<Action, String> {
+        if switching_in_id >= 12 {
+            return Err(format!("invalid pokemon ID {}", switching_in_id));
+        }
+        let team_position = if switching_in_id < 6 { FieldPosition::Min } else { FieldPosition::Max };
+        if target_position != team_position {
+            return Err(format!("pokemon {} can't be sent out to {:?}", switching_in_id, target_position));
+        }
+        if let Some(user_id) = user_id {
+            if user_id >= 12 {
+                return Err(format!("invalid pokemon ID {}", user_id));
+            }
+            if (user_id < 6) != (switching_in_id < 6) {
+                return Err(format!("pokemon {} and {} are on different teams", user_id, switching_in_id));
+            }
+            if state.pokemon_by_id(user_id).field_position() != Some(target_position) {
+                return Err(format!("pokemon {} is not on the field at {:?}", user_id, target_position));
+            }
+        }
+        let switching_in = state.pokemon_by_id(switching_in_id);
+        if switching_in.is_fainted() || switching_in.is_on_field() {
+            return Err(format!("pokemon {} can't be switched in", switching_in_id));
+        }
+        Ok(Action::Switch {
+            user_id,
+            switching_in_id,
+            target_position
+        })
+    }
+
     /// Defines how the action queue should be sorted.
-    pub fn action_queue_ordering(state: &State, rng: &mut StdRng, act1: &Action, act2: &Action) -> Ordering {
+    pub fn action_queue_ordering<R: Rng>(state: &State, rng: &mut R, act1: &Action, act2: &Action) -> Ordering {
         match act1 {
             Action::Move {user_id: user_id1, move_: move1, move_index: _, target_positions: _} => {
                 match act2 {
@@ -265,8 +786,13 @@ impl Action {
                         let priority_stage_ord = priority_stage1.cmp(&priority_stage2);
                         match priority_stage_ord {
                             Ordering::Equal => {
-                                let spd1 = pokemon::calculated_stat(state, *user_id1, StatIndex::Spd);
-                                let spd2 = pokemon::calculated_stat(state, *user_id2, StatIndex::Spd);
+                                let quick_claw_ord = pokemon::quick_claw_activates(state, *user_id1, rng).cmp(&pokemon::quick_claw_activates(state, *user_id2, rng));
+                                if quick_claw_ord != Ordering::Equal {
+                                    return quick_claw_ord.reverse();
+                                }
+
+                                let spd1 = pokemon::effective_speed(state, *user_id1);
+                                let spd2 = pokemon::effective_speed(state, *user_id2);
                                 let spd_ord = spd1.cmp(&spd2);
                                 match spd_ord {
                                     Ordering::Equal => if rng.gen_bool(0.5) { Ordering::Less } else { Ordering::Greater },
@@ -295,28 +821,29 @@ impl Action {
         }
     }
 
-    pub fn can_be_performed(&self, state: &mut State, rng: &mut StdRng) -> bool {
+    /// Whether the two actions are moves whose order would be decided by a coin flip. Moves by a Quick Claw holder
+    /// are never considered tied, since the order also depends on whether the Quick Claw activates.
+    pub fn is_speed_tie(state: &State, act1: &Action, act2: &Action) -> bool {
+        let quick_claw = Some(Item::id_by_name("Quick Claw").unwrap());
+        match (act1, act2) {
+            (Action::Move {user_id: user_id1, move_: move1, ..}, Action::Move {user_id: user_id2, move_: move2, ..}) => {
+                state.pokemon_by_id(*user_id1).item != quick_claw
+                    && state.pokemon_by_id(*user_id2).item != quick_claw
+                    && Move::priority_stage(*move1) == Move::priority_stage(*move2)
+                    && pokemon::effective_speed(state, *user_id1) == pokemon::effective_speed(state, *user_id2)
+            },
+            _ => false
+        }
+    }
+
+    /// Whether the action can still go ahead when its turn in the queue comes up, before rolling for anything that
+    /// could stop the user; see `is_stopped_from_moving`. Doesn't change the state, so it can be asked while
+    /// generating actions.
+    pub fn can_be_performed(&self, state: &State) -> bool {
         match self {
             Action::Move {user_id, move_: _, move_index, target_positions: _} => {
-                let user_msa = state.pokemon_by_id(*user_id).major_status_ailment();
-                if user_msa == MajorStatusAilment::Asleep || user_msa == MajorStatusAilment::Frozen || (user_msa == MajorStatusAilment::Paralyzed && rng.gen_bool(0.25)) {
-                    if cfg!(feature = "print-battle") {
-                        let user_display_text = format!("{}", state.pokemon_by_id(*user_id));
-                        state.add_display_text(format!("{}{}", user_display_text, user_msa.display_text_when_blocking_move()));
-                    }
-                    return false;
-                }
-
-                if state.pokemon_by_id(*user_id).is_infatuated && rng.gen_bool(0.5) {
-                    if cfg!(feature = "print-battle") {
-                        let user_display_text = format!("{}", state.pokemon_by_id(*user_id));
-                        state.add_display_text(format!("{} is infatuated with the foe!", user_display_text));
-                    }
-                    return false;
-                }
-
                 let user = state.pokemon_by_id(*user_id);
-                if user.current_hp() == 0 || user.field_position() == None { return false; }
+                if user.is_fainted() || !user.is_on_field() { return false; }
                 match move_index {
                     Some(move_index) => {
                         let move_instance = user.known_move(*move_index as usize);
@@ -329,7 +856,9 @@ impl Action {
         }
     }
 
-    pub fn perform(&self, state: &mut State, action_queue: &[&Action], rng: &mut StdRng) -> bool {
+    /// Carries out the action. `action_queue` holds the actions still waiting to be performed this turn. Returns true
+    /// if the battle ended as a result.
+    pub fn perform<R: Rng>(&self, state: &mut State, action_queue: &[&Action], rng: &mut R) -> bool {
         match self {
             Action::Switch {user_id, switching_in_id, target_position} => {
                 if let Some(user_id) = user_id {
@@ -340,13 +869,48 @@ impl Action {
             Action::Move {user_id, move_: move_id, move_index, target_positions} => {
                 if let Some(move_index) = move_index {
                     pokemon::increment_move_pp(state, *user_id, *move_index, -1);
+                    // A freely chosen move ends any rampage
+                    state.pokemon_by_id_mut(*user_id).rampage_counter.clear();
+                    if pokemon::holds_choice_item(state, *user_id) && state.pokemon_by_id(*user_id).choice_locked.is_none() {
+                        state.pokemon_by_id_mut(*user_id).choice_locked = Some(*move_id);
+                    }
+                }
+                if state.pokemon_by_id(*user_id).last_move_used != Some(*move_id) {
+                    state.pokemon_by_id_mut(*user_id).consecutive_move_counter = 0;
+                }
+                state.pokemon_by_id_mut(*user_id).last_move_used = Some(*move_id);
+                if !Move::effects(*move_id).iter().any(|effect| matches!(effect, MoveEffect::Protect)) {
+                    state.pokemon_by_id_mut(*user_id).protect_streak = 0;
                 }
 
-                if cfg!(feature = "print-battle") {
+                if state.logs(LogLevel::Turns) {
                     let user_display_text = format!("{}", state.pokemon_by_id(*user_id));
                     state.add_display_text(format!("{} used {} on:", user_display_text, Move::name(*move_id)));
                 }
 
+                let charge_kind = Move::effects(*move_id).iter().find_map(|effect| match effect {
+                    MoveEffect::SemiInvulnerableCharge(invuln_kind) => Some(*invuln_kind),
+                    _ => None
+                });
+                if let Some(invuln_kind) = charge_kind {
+                    // The second turn is queued as a move that costs no PP
+                    let user = state.pokemon_by_id_mut(*user_id);
+                    if user.semi_invulnerable.take().is_none() {
+                        user.semi_invulnerable = Some(invuln_kind);
+                        user.next_move_action = Some(Action::Move {
+                            user_id: *user_id,
+                            move_: *move_id,
+                            move_index: None,
+                            target_positions: target_positions.clone()
+                        });
+                        if state.logs(LogLevel::Verbose) {
+                            let user_name = Species::name(state.pokemon_by_id(*user_id).species());
+                            state.add_display_text(format!("{}{}", user_name, invuln_kind.display_text_on_charge()));
+                        }
+                        return false;
+                    }
+                }
+
                 for target_pos in target_positions {
                     let target_id = if *target_pos == FieldPosition::Min {
                         state.min.on_field
@@ -356,32 +920,79 @@ impl Action {
 
                     match target_id {
                         Some(target_id) => {
-                            if cfg!(feature = "print-battle") {
+                            if state.logs(LogLevel::Turns) {
                                 let target_display_text = format!("{}", state.pokemon_by_id(target_id));
                                 state.add_display_text(format!("- {}", target_display_text));
                             }
 
-                            if Move::accuracy(*move_id).do_accuracy_check(state, *user_id, target_id, rng) {
-                                for effect in Move::effects(*move_id) {
+                            // Hazards are laid on the target's side of the field, so they get past Protect and substitutes
+                            let sets_hazard = Move::effects(*move_id).iter().any(|effect| matches!(effect, MoveEffect::Spikes | MoveEffect::ToxicSpikes));
+                            let feints = Move::effects(*move_id).iter().any(|effect| matches!(effect, MoveEffect::Feint));
+                            if target_id != *user_id && state.pokemon_by_id(target_id).is_protected && !feints && !sets_hazard {
+                                if state.logs(LogLevel::Verbose) {
+                                    let target_name = Species::name(state.pokemon_by_id(target_id).species());
+                                    state.add_display_text(format!("{} protected itself!", target_name));
+                                }
+                                state.pokemon_by_id_mut(*user_id).consecutive_move_counter = 0;
+                                move_effects::disrupt_rampage(state, *user_id, rng);
+                                continue;
+                            }
+
+                            let behind_substitute = pokemon::is_behind_substitute(state, target_id, *user_id, *move_id);
+                            let forces_switch = Move::effects(*move_id).iter().any(|effect| matches!(effect, MoveEffect::ForceSwitch));
+                            if behind_substitute && Move::category(*move_id) == MoveCategory::Status && !forces_switch && !sets_hazard {
+                                if state.logs(LogLevel::Verbose) {
+                                    state.add_display_text(EffectResult::Fail.display_text().to_owned());
+                                }
+                                continue;
+                            }
+
+                            if Move::accuracy(*move_id).do_accuracy_check(*move_id, state, *user_id, target_id, rng) {
+                                let (primary_effects, secondary_effects) = Move::primary_and_secondary_effects(*move_id);
+                                let mut continue_to_secondary = true;
+                                for effect in primary_effects {
                                     let result = effect.do_effect(*move_id, state, action_queue, *user_id, target_id, rng);
-                                    if result.has_display_text() {
+                                    if result.has_display_text() && state.logs(LogLevel::Verbose) {
                                         state.add_display_text(result.display_text().to_owned());
                                     }
                                     if state.has_battle_ended() { return true; }
-                                    if state.pokemon_by_id(*user_id).current_hp() == 0 || state.pokemon_by_id(target_id).current_hp() == 0 || result == EffectResult::Fail {
+                                    // A target that is immune to the damage is immune to the rest of the move too; unlike a miss,
+                                    // this is only known once the damage effect has run
+                                    let immune = result == EffectResult::NoEffect && effect.is_damaging();
+                                    if state.pokemon_by_id(*user_id).is_fainted() || state.pokemon_by_id(target_id).is_fainted() || result == EffectResult::Fail || immune {
+                                        continue_to_secondary = false;
                                         break;
                                     }
                                 }
-                            } else if cfg!(feature = "print-battle") {
-                                let target_name = Species::name(state.pokemon_by_id(target_id).species());
-                                state.add_display_text(format!("{} avoided the attack!", target_name));
+
+                                // Secondary effects only apply if the damage went through and both Pokemon are still standing
+                                if continue_to_secondary && !behind_substitute {
+                                    for effect in secondary_effects {
+                                        let result = effect.do_effect(*move_id, state, action_queue, *user_id, target_id, rng);
+                                        if result.has_display_text() && state.logs(LogLevel::Verbose) {
+                                            state.add_display_text(result.display_text().to_owned());
+                                        }
+                                        if state.has_battle_ended() { return true; }
+                                        if state.pokemon_by_id(*user_id).is_fainted() || state.pokemon_by_id(target_id).is_fainted() || result == EffectResult::Fail {
+                                            break;
+                                        }
+                                    }
+                                }
+                            } else {
+                                state.pokemon_by_id_mut(*user_id).consecutive_move_counter = 0;
+                                move_effects::disrupt_rampage(state, *user_id, rng);
+                                if state.logs(LogLevel::Verbose) {
+                                    let target_name = Species::name(state.pokemon_by_id(target_id).species());
+                                    state.add_display_text(format!("{} avoided the attack!", target_name));
+                                }
                             }
                         },
                         None => {
-                            if cfg!(feature = "print-battle") {
+                            if state.logs(LogLevel::Turns) {
                                 state.add_display_text(String::from("- None"));
                                 state.add_display_text(String::from("But it failed!"));
                             }
+                            move_effects::disrupt_rampage(state, *user_id, rng);
                         }
                     }
                 }
@@ -407,17 +1018,19 @@ fn action_cmp(act1: &Action, act2: &Action) -> Ordering {
         Action::Move { user_id: _, move_: act1_move, move_index: _, target_positions: _ } => {
             match act2 {
                 Action::Move { user_id: _, move_: act2_move, move_index: _, target_positions: _ } => {
+                    // Within a category, higher priority moves come first
+                    let priority_ord = Move::priority_stage(*act2_move).cmp(&Move::priority_stage(*act1_move));
                     match Move::category(*act1_move) {
                         MoveCategory::Status => {
                             match Move::category(*act2_move) {
-                                MoveCategory::Status => Ordering::Equal,
+                                MoveCategory::Status => priority_ord,
                                 _ => Ordering::Greater
                             }
                         }
                         _ => {
                             match Move::category(*act2_move) {
                                 MoveCategory::Status => Ordering::Less,
-                                _ => Ordering::Equal
+                                _ => priority_ord
                             }
                         }
                     }
@@ -428,10 +1041,75 @@ fn action_cmp(act1: &Action, act2: &Action) -> Ordering {
     }
 }
 
-fn play_out_turn(state: &mut State, mut action_queue: Vec<&Action>, rng: &mut StdRng) {
+/// Rolls for anything that can stop the Pokemon from using its move this turn, such as paralysis, sleep and
+/// confusion, and adds the resulting display text. A confused Pokemon that hurts itself takes the damage here.
+fn is_stopped_from_moving<R: Rng>(state: &mut State, user_id: u8, rng: &mut R) -> bool {
+    // A frozen Pokemon has a 20% chance to thaw out when it tries to move
+    let frozen = state.pokemon_by_id(user_id).major_status_ailment() == MajorStatusAilment::Frozen;
+    if frozen { state.rng_draws.status_rolls += 1; }
+    if frozen && rng.gen_bool(0.2) {
+        set_major_status_ailment(state, user_id, MajorStatusAilment::Okay);
+        if state.logs(LogLevel::Verbose) {
+            let user_display_text = format!("{}", state.pokemon_by_id(user_id));
+            state.add_display_text(format!("{}{}", user_display_text, MajorStatusAilment::Frozen.display_text_when_cured()));
+        }
+    }
+
+    let user_msa = state.pokemon_by_id(user_id).major_status_ailment();
+    if user_msa == MajorStatusAilment::Paralyzed { state.rng_draws.status_rolls += 1; }
+    if user_msa == MajorStatusAilment::Asleep || user_msa == MajorStatusAilment::Frozen || (user_msa == MajorStatusAilment::Paralyzed && rng.gen_bool(0.25)) {
+        if state.logs(LogLevel::Verbose) {
+            let user_display_text = format!("{}", state.pokemon_by_id(user_id));
+            state.add_display_text(format!("{}{}", user_display_text, user_msa.display_text_when_blocking_move()));
+        }
+        return true;
+    }
+
+    if state.pokemon_by_id(user_id).is_flinching {
+        if state.logs(LogLevel::Verbose) {
+            let user_display_text = format!("{}", state.pokemon_by_id(user_id));
+            state.add_display_text(format!("{} flinched!", user_display_text));
+        }
+        return true;
+    }
+
+    if state.pokemon_by_id(user_id).confusion_counter.has_target() {
+        if state.logs(LogLevel::Verbose) {
+            let user_display_text = format!("{}", state.pokemon_by_id(user_id));
+            state.add_display_text(format!("{} is confused!", user_display_text));
+        }
+        let self_hit_chance = if game_version().gen() <= 6 { 0.5 } else { 1.0 / 3.0 };
+        state.rng_draws.status_rolls += 1;
+        if rng.gen_bool(self_hit_chance) {
+            if state.logs(LogLevel::Verbose) {
+                state.add_display_text(String::from("It hurt itself in its confusion!"));
+            }
+            state.rng_draws.damage_rolls += 1;
+            let damage = move_effects::confusion_damage(state, user_id, rng);
+            pokemon::apply_damage(state, user_id, damage as i16);
+            return true;
+        }
+    }
+
+    if state.pokemon_by_id(user_id).is_infatuated { state.rng_draws.status_rolls += 1; }
+    if state.pokemon_by_id(user_id).is_infatuated && rng.gen_bool(0.5) {
+        if state.logs(LogLevel::Verbose) {
+            let user_display_text = format!("{}", state.pokemon_by_id(user_id));
+            state.add_display_text(format!("{} is infatuated with the foe!", user_display_text));
+        }
+        return true;
+    }
+    false
+}
+
+/// If `max_wins_speed_tie` is given, it decides the order of speed-tied moves instead of a coin flip.
+fn play_out_turn<R: Rng>(state: &mut State, mut action_queue: Vec<&Action>, max_wins_speed_tie: Option<bool>, rng: &mut R) {
     // Only advance turn counter if all agents are actually doing something
-    if !action_queue.iter().any(|act| matches!(act, Action::Nop)) {
-        if cfg!(feature = "print-battle") {
+    let is_full_turn = !action_queue.iter().any(|act| matches!(act, Action::Nop));
+    // Pokemon that switch in during the turn haven't had a turn on the field yet
+    let started_on_field = if is_full_turn { [state.min.on_field, state.max.on_field] } else { [None, None] };
+    if is_full_turn {
+        if state.logs(LogLevel::Turns) {
             let turn_number = state.turn_number;
             state.add_display_text(format!("---- Turn {} ----", turn_number));
         }
@@ -439,19 +1117,71 @@ fn play_out_turn(state: &mut State, mut action_queue: Vec<&Action>, rng: &mut St
         for id in 0..12 {
             pokemon::increment_msa_counter(state, id);
         }
+        for on_field in [state.min.on_field, state.max.on_field].iter().flatten() {
+            pokemon::tick_minor_statuses(state, *on_field);
+        }
 
         state.turn_number += 1;
         if state.weather_counter.inc() {
-            state.add_display_text(String::from(state.weather.display_text_on_disappearance()));
+            if state.logs(LogLevel::Verbose) {
+                state.add_display_text(String::from(state.weather.display_text_on_disappearance()));
+            }
             state.weather = Weather::None;
         }
+        let verbose = state.logs(LogLevel::Verbose);
+        for side_conditions in [&mut state.min.side_conditions, &mut state.max.side_conditions].iter_mut() {
+            if side_conditions.tailwind_counter.inc() && verbose {
+                state.display_text.push(String::from("The tailwind petered out!"));
+            }
+        }
     }
 
+    if max_wins_speed_tie.is_none() && action_queue.len() == 2 && Action::is_speed_tie(state, action_queue[0], action_queue[1]) {
+        state.rng_draws.speed_ties += 1;
+    }
     action_queue.sort_unstable_by(|act1, act2| Action::action_queue_ordering(state, rng, act1, act2));
+    if let Some(max_wins_speed_tie) = max_wins_speed_tie {
+        if action_queue.len() == 2 && Action::is_speed_tie(state, action_queue[0], action_queue[1]) {
+            let max_acts_first = matches!(action_queue[0], Action::Move { user_id, .. } if *user_id >= 6);
+            if max_acts_first != max_wins_speed_tie {
+                action_queue.swap(0, 1);
+            }
+        }
+    }
 
     while !action_queue.is_empty() {
         let action = action_queue.remove(0);
-        if action.can_be_performed(state, rng) && action.perform(state, &action_queue, rng) {
+        let goes_ahead = action.can_be_performed(state) && match action {
+            Action::Move { user_id, .. } => !is_stopped_from_moving(state, *user_id, rng),
+            _ => true
+        };
+        if goes_ahead {
+            if action.perform(state, &action_queue, rng) {
+                return;
+            }
+        } else if let Action::Move { user_id, .. } = action {
+            // A move that is stopped also stops whatever it would have carried on into, such as the second turn of Fly
+            let user = state.pokemon_by_id_mut(*user_id);
+            user.semi_invulnerable = None;
+            user.next_move_action = None;
+            move_effects::disrupt_rampage(state, *user_id, rng);
+        }
+        // The battle can also end before a Pokemon gets to act, e.g. when it hurts itself in confusion
+        if state.has_battle_ended() {
+            return;
+        }
+    }
+
+    for pokemon_id in started_on_field.iter().flatten() {
+        let pokemon = &mut state.pokemon[*pokemon_id as usize];
+        if pokemon.is_on_field() {
+            pokemon.turns_on_field = pokemon.turns_on_field.saturating_add(1);
+        }
+    }
+
+    // Delayed hits land before the other end of turn effects
+    for field_position in [FieldPosition::Min, FieldPosition::Max].iter() {
+        if move_effects::land_delayed_hit(state, *field_position, rng) {
             return;
         }
     }
@@ -465,9 +1195,11 @@ fn play_out_turn(state: &mut State, mut action_queue: Vec<&Action>, rng: &mut St
 
     for on_field in pokemon_on_field {
         if let Some(on_field) = on_field {
+            pokemon::end_roost(state, on_field);
+
             match state.pokemon[on_field as usize].major_status_ailment() {
                 MajorStatusAilment::Poisoned => {
-                    if cfg!(feature = "print-battle") {
+                    if state.logs(LogLevel::Verbose) {
                         let display_text = format!("{} takes damage from poison!", state.pokemon[on_field as usize]);
                         state.add_display_text(display_text);
                     }
@@ -476,7 +1208,7 @@ fn play_out_turn(state: &mut State, mut action_queue: Vec<&Action>, rng: &mut St
                     }
                 }
                 MajorStatusAilment::BadlyPoisoned => {
-                    if cfg!(feature = "print-battle") {
+                    if state.logs(LogLevel::Verbose) {
                         let display_text = format!("{} takes damage from poison!", state.pokemon[on_field as usize]);
                         state.add_display_text(display_text);
                     }
@@ -488,6 +1220,16 @@ fn play_out_turn(state: &mut State, mut action_queue: Vec<&Action>, rng: &mut St
                         return;
                     }
                 }
+                MajorStatusAilment::Burned => {
+                    if state.logs(LogLevel::Verbose) {
+                        let display_text = format!("{} is hurt by its burn!", state.pokemon[on_field as usize]);
+                        state.add_display_text(display_text);
+                    }
+                    let denominator = if game_version().gen() <= 6 { 8 } else { 16 };
+                    if pokemon::apply_damage(state, on_field, max(state.pokemon[on_field as usize].max_hp() / denominator, 1) as i16) {
+                        return;
+                    }
+                }
                 _ => {}
             }
 
@@ -497,7 +1239,7 @@ fn play_out_turn(state: &mut State, mut action_queue: Vec<&Action>, rng: &mut St
                     FieldPosition::Max => state.max.on_field
                 };
                 if let Some(seeder_id) = seeder_id {
-                    if cfg!(feature = "print-battle") {
+                    if state.logs(LogLevel::Verbose) {
                         let display_text = format!("{}'s seed drains energy from {}!", state.pokemon[seeder_id as usize], state.pokemon[on_field as usize]);
                         state.add_display_text(display_text);
                     }
@@ -507,19 +1249,46 @@ fn play_out_turn(state: &mut State, mut action_queue: Vec<&Action>, rng: &mut St
                     }
                 }
             }
+
+            if state.pokemon[on_field as usize].partial_trap_counter.has_target() && !state.pokemon[on_field as usize].is_fainted() {
+                if state.logs(LogLevel::Verbose) {
+                    let display_text = format!("{} is hurt by the trap!", state.pokemon[on_field as usize]);
+                    state.add_display_text(display_text);
+                }
+                let denominator = if game_version().gen() >= 6 { 8 } else { 16 };
+                if pokemon::apply_damage(state, on_field, max(state.pokemon[on_field as usize].max_hp() / denominator, 1) as i16) {
+                    return;
+                }
+                if state.pokemon[on_field as usize].partial_trap_counter.inc() && state.logs(LogLevel::Verbose) {
+                    let display_text = format!("{} was freed from the trap!", state.pokemon[on_field as usize]);
+                    state.add_display_text(display_text);
+                }
+            }
+
+            if state.pokemon[on_field as usize].drowsy_counter > 0 && !state.pokemon[on_field as usize].is_fainted() {
+                state.pokemon[on_field as usize].drowsy_counter -= 1;
+                if state.pokemon[on_field as usize].drowsy_counter == 0 {
+                    pokemon::put_to_sleep(state, on_field, rng);
+                }
+            }
         }
     }
 }
 
+/// Attack through Speed only.
+pub fn unstaged_stat(species: SpeciesID, nature: Nature, ivs: &[u8; 6], evs: &[u8; 6], stat_index: StatIndex) -> u16 {
+    let b = Species::base_stat(species, stat_index) as u32;
+    let i = ivs[stat_index.as_usize()] as u32;
+    let e = evs[stat_index.as_usize()] as u32;
+    ((2 * b + i + e / 4 + 5) as f64 * nature.stat_mod(stat_index)) as u16
+}
+
 pub fn calculated_stat(state: &State, pokemon_id: u8, stat_index: StatIndex) -> u32 {
     let pokemon = state.pokemon_by_id(pokemon_id);
 
     if stat_index == StatIndex::Hp { return pokemon.max_hp as u32; }
 
-    let b = Species::base_stat(pokemon.species, stat_index) as u32;
-    let i = pokemon.ivs[stat_index.as_usize()] as u32;
-    let e = pokemon.evs[stat_index.as_usize()] as u32;
-    let mut calculated_stat = ((2 * b + i + e / 4 + 5) as f64 * pokemon.nature.stat_mod(stat_index)) as u32;
+    let mut calculated_stat = pokemon.unstaged_stats[stat_index.as_usize() - 1] as u32;
 
     if stat_index == StatIndex::Spd {
         if pokemon.major_status_ailment == MajorStatusAilment::Paralyzed {
@@ -531,10 +1300,51 @@ pub fn calculated_stat(state: &State, pokemon_id: u8, stat_index: StatIndex) ->
     calculated_stat
 }
 
+/// The Speed used for turn order: the calculated stat with paralysis and weather abilities applied, scaled by the
+/// Speed stat stage and doubled by Tailwind.
+pub fn effective_speed(state: &State, pokemon_id: u8) -> u32 {
+    let calculated_spd = calculated_stat(state, pokemon_id, StatIndex::Spd);
+    let spd_stage = state.pokemon_by_id(pokemon_id).stat_stage(StatIndex::Spd);
+    let mut effective_speed = (calculated_spd as f64 * move_effects::main_stat_stage_multiplier(spd_stage)) as u32;
+
+    let side_conditions = if pokemon_id < 6 { &state.min.side_conditions } else { &state.max.side_conditions };
+    if side_conditions.tailwind_counter.has_target() { effective_speed *= 2; }
+    if state.pokemon_by_id(pokemon_id).item == Some(Item::id_by_name("Choice Scarf").unwrap()) {
+        effective_speed = (effective_speed as f64 * 1.5) as u32;
+    }
+
+    effective_speed
+}
+
+pub fn holds_choice_item(state: &State, pokemon_id: u8) -> bool {
+    match state.pokemon_by_id(pokemon_id).item {
+        Some(item) => ["Choice Band", "Choice Scarf", "Choice Specs"].iter().any(|&name| item == Item::id_by_name(name).unwrap()),
+        None => false
+    }
+}
+
+/// Rolls the 20% chance for a Quick Claw holder to move first within its priority bracket.
+pub fn quick_claw_activates<R: Rng>(state: &State, pokemon_id: u8, rng: &mut R) -> bool {
+    state.pokemon_by_id(pokemon_id).item == Some(Item::id_by_name("Quick Claw").unwrap()) && rng.gen_bool(0.2)
+}
+
+/// Sends the Pokemon out to the given field position and applies entry hazards to it. Returns true if the battle
+/// ended as a result, which can only happen when the hazards knock out the last Pokemon on its side.
 pub fn add_to_field(state: &mut State, pokemon_id: u8, field_position: FieldPosition) -> bool {
     state.pokemon_by_id_mut(pokemon_id).field_position = Some(field_position);
 
-    if cfg!(feature = "print-battle") {
+    let baton_pass = if pokemon_id < 6 { state.min.baton_pass.take() } else { state.max.baton_pass.take() };
+    if let Some(baton_pass) = baton_pass {
+        let pokemon = state.pokemon_by_id_mut(pokemon_id);
+        for (i, &stage) in baton_pass.stat_stages.iter().enumerate() {
+            pokemon.write_stat_stage(i, stage);
+        }
+        pokemon.confusion_counter = baton_pass.confusion_counter;
+        pokemon.seeded_by = baton_pass.seeded_by;
+        pokemon.substitute_hp = baton_pass.substitute_hp;
+    }
+
+    if state.logs(LogLevel::Turns) {
         let pokemon_display_text = format!("{}", state.pokemon_by_id(pokemon_id));
         state.add_display_text(format!("Adding {} to field position {:?}.", pokemon_display_text, field_position));
     }
@@ -559,9 +1369,44 @@ pub fn add_to_field(state: &mut State, pokemon_id: u8, field_position: FieldPosi
         }
     }
 
+    apply_entry_hazards(state, pokemon_id);
     state.has_battle_ended()
 }
 
+/// Applies the hazards on the Pokemon's side of the field to it as it switches in. A grounded Poison type absorbs
+/// Toxic Spikes instead of being affected.
+fn apply_entry_hazards(state: &mut State, pokemon_id: u8) {
+    if !state.pokemon_by_id(pokemon_id).is_grounded() {
+        return;
+    }
+    let side_conditions = if pokemon_id < 6 { &state.min.side_conditions } else { &state.max.side_conditions };
+    let spikes_layers = side_conditions.spikes_layers;
+    let toxic_spikes_layers = side_conditions.toxic_spikes_layers;
+
+    if spikes_layers > 0 {
+        if state.logs(LogLevel::Verbose) {
+            let display_text = format!("{} is hurt by the spikes!", state.pokemon_by_id(pokemon_id));
+            state.add_display_text(display_text);
+        }
+        let denominator = [8, 6, 4][spikes_layers as usize - 1];
+        if apply_damage(state, pokemon_id, max(state.pokemon_by_id(pokemon_id).max_hp() / denominator, 1) as i16) || state.pokemon_by_id(pokemon_id).is_fainted() {
+            return;
+        }
+    }
+
+    if toxic_spikes_layers > 0 {
+        if state.pokemon_by_id(pokemon_id).is_type(Type::Poison) {
+            if pokemon_id < 6 { state.min.side_conditions.toxic_spikes_layers = 0; } else { state.max.side_conditions.toxic_spikes_layers = 0; }
+            if state.logs(LogLevel::Verbose) {
+                let display_text = format!("{} absorbed the poison spikes!", state.pokemon_by_id(pokemon_id));
+                state.add_display_text(display_text);
+            }
+        } else {
+            poison(state, pokemon_id, toxic_spikes_layers >= 2, false);
+        }
+    }
+}
+
 pub fn remove_from_field(state: &mut State, pokemon_id: u8) {
     remove_minor_status_ailments(state, pokemon_id);
 
@@ -570,23 +1415,48 @@ pub fn remove_from_field(state: &mut State, pokemon_id: u8) {
         let pokemon = state.pokemon_by_id_mut(pokemon_id);
         old_field_pos = pokemon.field_position.unwrap();
         pokemon.stat_stages = [0; 8];
+        // Type changes, e.g. from Roost, only last while the Pokemon is on the field
+        pokemon.first_type = Species::type1(pokemon.species);
+        pokemon.second_type = Species::type2(pokemon.species);
+        pokemon.roost_types = None;
         if game_version().gen() == 3 {
             pokemon.snore_sleep_talk_counter = 0;
         } else if game_version().gen() == 5 && pokemon.major_status_ailment == MajorStatusAilment::Asleep {
             pokemon.msa_counter.zero();
         }
         pokemon.field_position = None;
+        if let Some((move_index, original)) = pokemon.mimic_slot.take() {
+            pokemon.known_moves[move_index] = original;
+        }
+        // Done after Mimic's slot is restored, since a Mimic used while transformed replaced one of the copied moves
+        if let Some(originals) = pokemon.transform_originals.take() {
+            pokemon.unstaged_stats = originals.unstaged_stats;
+            pokemon.ability = originals.ability;
+            pokemon.known_moves = originals.known_moves;
+        }
         for move_instance in &mut pokemon.known_moves {
             move_instance.disabled = false;
         }
         pokemon.next_move_action = None;
+        pokemon.last_move_used = None;
+        pokemon.last_attacker = None;
+        pokemon.last_attack_damage = None;
+        pokemon.choice_locked = None;
+        pokemon.turns_on_field = 0;
     }
 
-    if cfg!(feature = "print-battle") {
+    if state.logs(LogLevel::Turns) {
         let pokemon_display_text = format!("{}", state.pokemon_by_id(pokemon_id));
         state.add_display_text(format!("Removing {} from field position {:?}.", pokemon_display_text, old_field_pos));
     }
 
+    // Whatever this Pokemon trapped is released
+    let opponent = if pokemon_id < 6 { state.max.on_field } else { state.min.on_field };
+    if let Some(opponent) = opponent {
+        state.pokemon_by_id_mut(opponent).is_trapped = false;
+        state.pokemon_by_id_mut(opponent).partial_trap_counter.clear();
+    }
+
     if state.min.on_field == Some(pokemon_id) {
         state.min.on_field = None;
     } else if state.max.on_field == Some(pokemon_id) {
@@ -597,17 +1467,29 @@ pub fn remove_from_field(state: &mut State, pokemon_id: u8) {
     }
 }
 
-pub fn increment_stat_stage(state: &mut State, pokemon_id: u8, stat_index: StatIndex, requested_amount: i8) {
+/// Raises or lowers a stat stage by the requested amount, as far as the -6 to 6 range allows. `source_side` is the
+/// side that caused the change: Clear Body blocks drops caused by the opponent, and Contrary inverts every change.
+pub fn increment_stat_stage(state: &mut State, pokemon_id: u8, stat_index: StatIndex, requested_amount: i8, source_side: Side) {
+    let ability = state.pokemon_by_id(pokemon_id).ability;
+    let requested_amount = if ability == Ability::id_by_name("Contrary").unwrap() { -requested_amount } else { requested_amount };
+    if requested_amount < 0 && source_side != Side::of(pokemon_id) && ability == Ability::id_by_name("Clear Body").unwrap() {
+        if state.logs(LogLevel::Verbose) {
+            let species_name = Species::name(state.pokemon_by_id(pokemon_id).species);
+            state.add_display_text(format!("{}'s Clear Body prevents stat loss!", species_name));
+        }
+        return;
+    }
+
     let old_stat_stage;
     let new_stat_stage;
     {
         let pokemon = state.pokemon_by_id_mut(pokemon_id);
         old_stat_stage = pokemon.stat_stages[stat_index.as_usize()];
         new_stat_stage = num::clamp(old_stat_stage + requested_amount, -6, 6);
-        pokemon.stat_stages[stat_index.as_usize()] = new_stat_stage;
+        pokemon.write_stat_stage(stat_index.as_usize(), new_stat_stage);
     }
 
-    if cfg!(feature = "print-battle") {
+    if state.logs(LogLevel::Verbose) {
         let species_name = Species::name(state.pokemon_by_id(pokemon_id).species);
         let actual_change = new_stat_stage - old_stat_stage;
         match actual_change {
@@ -622,6 +1504,14 @@ pub fn increment_stat_stage(state: &mut State, pokemon_id: u8, stat_index: StatI
     }
 }
 
+/// Sets a stat stage directly, ignoring the current stage. Panics if the stage is outside of -6 to 6.
+pub fn set_stat_stage(state: &mut State, pokemon_id: u8, stat_index: StatIndex, stage: i8) {
+    if !(-6..=6).contains(&stage) {
+        panic!("Stat stage must be between -6 and 6. Given stat stage: {}", stage);
+    }
+    state.pokemon_by_id_mut(pokemon_id).write_stat_stage(stat_index.as_usize(), stage);
+}
+
 /// Returns whether the poisoning was successful.
 pub fn poison(state: &mut State, pokemon_id: u8, toxic: bool, corrosion: bool) -> EffectResult {
     let pokemon = state.pokemon_by_id_mut(pokemon_id);
@@ -631,9 +1521,9 @@ pub fn poison(state: &mut State, pokemon_id: u8, toxic: bool, corrosion: bool) -
     }
 
     if pokemon.major_status_ailment() == MajorStatusAilment::Okay {
-        pokemon.major_status_ailment = if toxic { MajorStatusAilment::BadlyPoisoned } else { MajorStatusAilment::Poisoned };
-        pokemon.msa_counter.clear();
-        if cfg!(feature = "print-battle") {
+        set_major_status_ailment(state, pokemon_id, if toxic { MajorStatusAilment::BadlyPoisoned } else { MajorStatusAilment::Poisoned });
+        state.pokemon_by_id_mut(pokemon_id).msa_counter.clear();
+        if state.logs(LogLevel::Verbose) {
             let species_name = Species::name(state.pokemon_by_id(pokemon_id).species);
             state.add_display_text(format!("{}{}", species_name, if toxic { MajorStatusAilment::BadlyPoisoned.display_text_when_applied() } else { MajorStatusAilment::Poisoned.display_text_when_applied() }));
         }
@@ -643,21 +1533,42 @@ pub fn poison(state: &mut State, pokemon_id: u8, toxic: bool, corrosion: bool) -
     EffectResult::Fail
 }
 
+/// Number of turns Rest's sleep lasts, in every gen. Unlike other sleep, its length isn't random.
+const REST_SLEEP_TURNS: u16 = 2;
+
+/// Fully heals the Pokemon and replaces its major status ailment with sleep that wears off at the start of the third
+/// turn after this one, so that it misses exactly `REST_SLEEP_TURNS` turns. Callers should check that Rest can be
+/// used first.
+pub fn sleep_from_rest(state: &mut State, pokemon_id: u8) {
+    let max_hp = state.pokemon_by_id(pokemon_id).max_hp();
+    set_hp(state, pokemon_id, max_hp);
+    set_major_status_ailment(state, pokemon_id, MajorStatusAilment::Asleep);
+    let pokemon = state.pokemon_by_id_mut(pokemon_id);
+    // The sleep counter is advanced at the start of each turn, including the one the Pokemon wakes up on
+    pokemon.msa_counter = Counter::new(Some(REST_SLEEP_TURNS + 1));
+    // A Snore or Sleep Talk from an earlier sleep must not cut this one short
+    pokemon.snore_sleep_talk_counter = 0;
+
+    if state.logs(LogLevel::Verbose) {
+        let species_name = Species::name(state.pokemon_by_id(pokemon_id).species);
+        state.add_display_text(format!("{} slept and became healthy!", species_name));
+    }
+}
+
 /// Returns whether the Pokemon fell asleep.
-pub fn put_to_sleep(state: &mut State, pokemon_id: u8, rng: &mut StdRng) -> EffectResult {
+pub fn put_to_sleep<R: Rng>(state: &mut State, pokemon_id: u8, rng: &mut R) -> EffectResult {
     let pokemon = state.pokemon_by_id_mut(pokemon_id);
 
     if pokemon.major_status_ailment() == MajorStatusAilment::Okay {
-        pokemon.major_status_ailment = MajorStatusAilment::Asleep;
-        pokemon.msa_counter = Counter::new(Some(
+        state.rng_draws.status_rolls += 1;
+        set_major_status_ailment(state, pokemon_id, MajorStatusAilment::Asleep);
+        state.pokemon_by_id_mut(pokemon_id).msa_counter = Counter::new(Some(
             match game_version().gen() {
-                1 => rng.gen_range(1, 7),
-                2 => rng.gen_range(1, 5),
                 3..=4 => rng.gen_range(2, 5),
                 _ => rng.gen_range(1, 3)
             }
         ));
-        if cfg!(feature = "print-battle") {
+        if state.logs(LogLevel::Verbose) {
             let species_name = Species::name(state.pokemon_by_id(pokemon_id).species);
             state.add_display_text(format!("{}{}", species_name, MajorStatusAilment::Asleep.display_text_when_applied()));
         }
@@ -667,6 +1578,73 @@ pub fn put_to_sleep(state: &mut State, pokemon_id: u8, rng: &mut StdRng) -> Effe
     EffectResult::Fail
 }
 
+/// Returns whether the Pokemon was paralyzed.
+pub fn paralyze(state: &mut State, pokemon_id: u8) -> EffectResult {
+    let pokemon = state.pokemon_by_id_mut(pokemon_id);
+
+    if game_version().gen() >= 6 && pokemon.is_type(Type::Electric) {
+        return EffectResult::NoEffect;
+    }
+
+    if pokemon.major_status_ailment() == MajorStatusAilment::Okay {
+        set_major_status_ailment(state, pokemon_id, MajorStatusAilment::Paralyzed);
+        state.pokemon_by_id_mut(pokemon_id).msa_counter.clear();
+        if state.logs(LogLevel::Verbose) {
+            let species_name = Species::name(state.pokemon_by_id(pokemon_id).species);
+            state.add_display_text(format!("{}{}", species_name, MajorStatusAilment::Paralyzed.display_text_when_applied()));
+        }
+        return EffectResult::Success;
+    }
+
+    EffectResult::Fail
+}
+
+/// Returns whether the Pokemon was burned.
+pub fn burn(state: &mut State, pokemon_id: u8) -> EffectResult {
+    let pokemon = state.pokemon_by_id_mut(pokemon_id);
+
+    if pokemon.is_type(Type::Fire) {
+        return EffectResult::NoEffect;
+    }
+
+    if pokemon.major_status_ailment() == MajorStatusAilment::Okay {
+        set_major_status_ailment(state, pokemon_id, MajorStatusAilment::Burned);
+        state.pokemon_by_id_mut(pokemon_id).msa_counter.clear();
+        if state.logs(LogLevel::Verbose) {
+            let species_name = Species::name(state.pokemon_by_id(pokemon_id).species);
+            state.add_display_text(format!("{}{}", species_name, MajorStatusAilment::Burned.display_text_when_applied()));
+        }
+        return EffectResult::Success;
+    }
+
+    EffectResult::Fail
+}
+
+/// Returns whether the Pokemon was frozen.
+pub fn freeze(state: &mut State, pokemon_id: u8) -> EffectResult {
+    if state.weather == Weather::HarshSunshine {
+        return EffectResult::Fail;
+    }
+
+    let pokemon = state.pokemon_by_id_mut(pokemon_id);
+
+    if pokemon.is_type(Type::Ice) {
+        return EffectResult::NoEffect;
+    }
+
+    if pokemon.major_status_ailment() == MajorStatusAilment::Okay {
+        set_major_status_ailment(state, pokemon_id, MajorStatusAilment::Frozen);
+        state.pokemon_by_id_mut(pokemon_id).msa_counter.clear();
+        if state.logs(LogLevel::Verbose) {
+            let species_name = Species::name(state.pokemon_by_id(pokemon_id).species);
+            state.add_display_text(format!("{}{}", species_name, MajorStatusAilment::Frozen.display_text_when_applied()));
+        }
+        return EffectResult::Success;
+    }
+
+    EffectResult::Fail
+}
+
 pub fn increment_msa_counter(state: &mut State, pokemon_id: u8) {
     let mut msa_cured = false;
     let mut old_msa = MajorStatusAilment::Okay;
@@ -675,23 +1653,79 @@ pub fn increment_msa_counter(state: &mut State, pokemon_id: u8) {
         if pokemon.msa_counter.add(pokemon.snore_sleep_talk_counter + 1) {
             msa_cured = true;
             old_msa = pokemon.major_status_ailment;
-            pokemon.major_status_ailment = MajorStatusAilment::Okay;
         }
         pokemon.snore_sleep_talk_counter = 0;
     }
+    if msa_cured {
+        set_major_status_ailment(state, pokemon_id, MajorStatusAilment::Okay);
+    }
 
-    if msa_cured && cfg!(feature = "print-battle") {
+    if msa_cured && state.logs(LogLevel::Verbose) {
         let species_name = Species::name(state.pokemon_by_id(pokemon_id).species);
         state.add_display_text(format!("{}{}", species_name, old_msa.display_text_when_cured()));
     }
 }
 
+/// Advances the turn-based minor status ailments of an on-field Pokemon and clears the ones that have run out. New
+/// timed statuses should be ticked here. Partial trapping is the exception, since it counts down with its end-of-turn
+/// damage.
+pub fn tick_minor_statuses(state: &mut State, pokemon_id: u8) {
+    let pokemon = state.pokemon_by_id_mut(pokemon_id);
+    pokemon.is_protected = false;
+    pokemon.is_flinching = false;
+    pokemon.last_attacker = None;
+    pokemon.last_attack_damage = None;
+    let confusion_ended = pokemon.confusion_counter.has_target() && pokemon.confusion_counter.inc();
+
+    if confusion_ended && state.logs(LogLevel::Verbose) {
+        let species_name = Species::name(state.pokemon_by_id(pokemon_id).species);
+        state.add_display_text(format!("{} snapped out of its confusion!", species_name));
+    }
+}
+
+/// Sets the Pokemon's HP, which must be at most its max HP, and keeps its team's totals up to date.
+pub fn set_hp(state: &mut State, pokemon_id: u8, hp: u16) {
+    let pokemon = state.pokemon_by_id_mut(pokemon_id);
+    debug_assert!(hp <= pokemon.max_hp, "HP must not exceed max HP.");
+    let change = (hp as f64 - pokemon.current_hp as f64) / pokemon.max_hp as f64;
+    let was_fainted = pokemon.is_fainted();
+    pokemon.current_hp = hp;
+    let is_fainted = pokemon.is_fainted();
+    let statused = pokemon.major_status_ailment != MajorStatusAilment::Okay;
+
+    let totals = state.team_totals_mut(pokemon_id);
+    totals.hp_fraction_sum += change;
+    if was_fainted && !is_fainted {
+        totals.standing += 1;
+        if statused { totals.statused += 1; }
+    } else if !was_fainted && is_fainted {
+        totals.standing -= 1;
+        if statused { totals.statused -= 1; }
+    }
+}
+
+/// Sets the Pokemon's major status ailment and keeps its team's totals up to date. Doesn't touch the ailment's
+/// counter.
+pub fn set_major_status_ailment(state: &mut State, pokemon_id: u8, major_status_ailment: MajorStatusAilment) {
+    let pokemon = state.pokemon_by_id_mut(pokemon_id);
+    let was_counted = !pokemon.is_fainted() && pokemon.major_status_ailment != MajorStatusAilment::Okay;
+    pokemon.major_status_ailment = major_status_ailment;
+    let is_counted = !pokemon.is_fainted() && major_status_ailment != MajorStatusAilment::Okay;
+
+    let totals = state.team_totals_mut(pokemon_id);
+    if !was_counted && is_counted {
+        totals.statused += 1;
+    } else if was_counted && !is_counted {
+        totals.statused -= 1;
+    }
+}
+
 /// The amount can be negative to add HP.
 pub fn apply_damage(state: &mut State, pokemon_id: u8, amount: i16) -> bool {
     let new_hp = state.pokemon_by_id(pokemon_id).current_hp as i16 - amount;
     if new_hp <= 0 {
-        state.pokemon_by_id_mut(pokemon_id).current_hp = 0;
-        if cfg!(feature = "print-battle") {
+        set_hp(state, pokemon_id, 0);
+        if state.logs(LogLevel::Outcomes) {
             let display_text = format!("{} fainted!", state.pokemon_by_id(pokemon_id));
             state.add_display_text(display_text);
         }
@@ -699,8 +1733,8 @@ pub fn apply_damage(state: &mut State, pokemon_id: u8, amount: i16) -> bool {
         return state.has_battle_ended();
     }
 
-    let pokemon = state.pokemon_by_id_mut(pokemon_id);
-    pokemon.current_hp = min(new_hp as u16, pokemon.max_hp);
+    let max_hp = state.pokemon_by_id(pokemon_id).max_hp;
+    set_hp(state, pokemon_id, min(new_hp as u16, max_hp));
     false
 }
 
@@ -717,11 +1751,1037 @@ fn remove_minor_status_ailments(state: &mut State, pokemon_id: u8) {
     pokemon.is_infatuated = false;
     pokemon.is_cursed = false;
     pokemon.has_nightmare = false;
+    pokemon.is_identified = false;
+    pokemon.is_trapped = false;
+    pokemon.partial_trap_counter.clear();
+    pokemon.drowsy_counter = 0;
+    pokemon.is_protected = false;
+    pokemon.protect_streak = 0;
+    pokemon.consecutive_move_counter = 0;
+    pokemon.semi_invulnerable = None;
+    pokemon.rampage_counter.clear();
+    pokemon.substitute_hp = None;
+    end_roost(state, pokemon_id);
+}
+
+/// Removes the Pokemon's Flying type until `end_roost` is called. A pure Flying type becomes typeless in gen 4 and
+/// Normal type afterwards.
+pub fn start_roost(state: &mut State, pokemon_id: u8) {
+    let pokemon = state.pokemon_by_id_mut(pokemon_id);
+    if !pokemon.is_type(Type::Flying) || pokemon.roost_types.is_some() {
+        return;
+    }
+
+    pokemon.roost_types = Some((pokemon.first_type, pokemon.second_type));
+    if pokemon.first_type == Type::Flying && pokemon.second_type == Type::None {
+        pokemon.first_type = if game_version().gen() <= 4 { Type::None } else { Type::Normal };
+    } else if pokemon.first_type == Type::Flying {
+        pokemon.first_type = Type::None;
+    } else {
+        pokemon.second_type = Type::None;
+    }
+}
+
+pub fn end_roost(state: &mut State, pokemon_id: u8) {
+    let pokemon = state.pokemon_by_id_mut(pokemon_id);
+    if let Some((first_type, second_type)) = pokemon.roost_types.take() {
+        pokemon.first_type = first_type;
+        pokemon.second_type = second_type;
+    }
+}
+
+/// Replaces the Pokemon's `mimic_move` slot with `copied_move` until it leaves the field. The copy has 5 PP before gen 5
+/// and full PP afterwards. Returns false if the Pokemon doesn't know `mimic_move`.
+pub fn mimic(state: &mut State, pokemon_id: u8, mimic_move: MoveID, copied_move: MoveID) -> bool {
+    let pokemon = state.pokemon_by_id_mut(pokemon_id);
+    let move_index = match pokemon.known_moves.iter().position(|move_instance| move_instance.move_ == mimic_move) {
+        Some(move_index) => move_index,
+        None => return false
+    };
+
+    let mut copy = MoveInstance::from(copied_move);
+    if game_version().gen() <= 4 {
+        copy.pp = min(copy.pp, 5);
+    }
+    let original = mem::replace(&mut pokemon.known_moves[move_index], copy);
+    pokemon.mimic_slot = Some((move_index, original));
+    true
+}
+
+/// Gives the Pokemon the target's types, stats other than HP, stat stages, ability and moves until it leaves the
+/// field. The copied moves have 5 PP each. A slot replaced by Mimic is restored first, so that the Pokemon's own moves
+/// are the ones that come back.
+pub fn transform(state: &mut State, pokemon_id: u8, target_id: u8) {
+    let target = state.pokemon_by_id(target_id);
+    let (first_type, second_type) = (target.first_type, target.second_type);
+    let unstaged_stats = target.unstaged_stats;
+    let stat_stages = target.stat_stages;
+    let ability = target.ability;
+    let copied_moves = target.known_moves.iter()
+        .map(|move_instance| {
+            let mut copy = MoveInstance::from(move_instance.move_);
+            copy.pp = min(copy.pp, 5);
+            copy
+        })
+        .collect();
+
+    let pokemon = state.pokemon_by_id_mut(pokemon_id);
+    if let Some((move_index, original)) = pokemon.mimic_slot.take() {
+        pokemon.known_moves[move_index] = original;
+    }
+    pokemon.transform_originals = Some(TransformOriginals {
+        unstaged_stats: pokemon.unstaged_stats,
+        ability: pokemon.ability,
+        known_moves: mem::replace(&mut pokemon.known_moves, copied_moves)
+    });
+    pokemon.first_type = first_type;
+    pokemon.second_type = second_type;
+    // Roost would otherwise put the user's own types back at the end of the turn
+    pokemon.roost_types = None;
+    pokemon.unstaged_stats = unstaged_stats;
+    for (i, &stage) in stat_stages.iter().enumerate() {
+        pokemon.write_stat_stage(i, stage);
+    }
+    pokemon.ability = ability;
+}
+
+/// Confuses the Pokemon for 1-4 turns (counted from the next turn), unless it is already confused.
+pub fn confuse<R: Rng>(state: &mut State, pokemon_id: u8, rng: &mut R) {
+    if !state.pokemon_by_id(pokemon_id).confusion_counter.has_target() {
+        state.rng_draws.status_rolls += 1;
+        state.pokemon_by_id_mut(pokemon_id).confusion_counter = Counter::new(Some(rng.gen_range(2, 6)));
+    }
 }
 
 pub fn set_infatuated(state: &mut State, pokemon_id: u8, caused_by: u8) {
     let pokemon_name = Species::name(state.pokemon_by_id(pokemon_id).species());
     let caused_name = Species::name(state.pokemon_by_id(caused_by).species());
-    state.add_display_text(format!("{} became infatuated with {}!", pokemon_name, caused_name));
+    if state.logs(LogLevel::Verbose) {
+        state.add_display_text(format!("{} became infatuated with {}!", pokemon_name, caused_name));
+    }
     state.pokemon_by_id_mut(pokemon_id).is_infatuated = true;
 }
+
+/// Whether a move used on the target would hit its substitute instead. Sound-based moves bypass substitutes from
+/// gen 6 onwards.
+pub fn is_behind_substitute(state: &State, pokemon_id: u8, user_id: u8, move_: MoveID) -> bool {
+    pokemon_id != user_id
+        && state.pokemon_by_id(pokemon_id).substitute_hp.is_some()
+        && !(game_version().gen() >= 6 && Move::sound_based(move_))
+}
+
+/// Returns the amount of damage the substitute actually absorbed.
+pub fn damage_substitute(state: &mut State, pokemon_id: u8, amount: u16) -> u16 {
+    let substitute_hp = state.pokemon_by_id(pokemon_id).substitute_hp.unwrap();
+    if state.logs(LogLevel::Verbose) {
+        let pokemon_name = Species::name(state.pokemon_by_id(pokemon_id).species);
+        state.add_display_text(format!("The substitute took damage for {}!", pokemon_name));
+    }
+
+    if amount >= substitute_hp {
+        state.pokemon_by_id_mut(pokemon_id).substitute_hp = None;
+        if state.logs(LogLevel::Verbose) {
+            let pokemon_name = Species::name(state.pokemon_by_id(pokemon_id).species);
+            state.add_display_text(format!("{}'s substitute faded!", pokemon_name));
+        }
+        substitute_hp
+    } else {
+        state.pokemon_by_id_mut(pokemon_id).substitute_hp = Some(substitute_hp - amount);
+        amount
+    }
+}
+
+/// Whether the Pokemon is free to switch out voluntarily. Ghost types can't be trapped from gen 6 onwards.
+pub fn can_switch_out(state: &State, pokemon_id: u8) -> bool {
+    let pokemon = state.pokemon_by_id(pokemon_id);
+    if game_version().gen() >= 6 && pokemon.is_type(Type::Ghost) {
+        return true;
+    }
+    if pokemon.is_trapped || pokemon.partial_trap_counter.has_target() {
+        return false;
+    }
+
+    let opponent = if pokemon_id < 6 { state.max.on_field } else { state.min.on_field };
+    match opponent {
+        Some(opponent) => state.pokemon_by_id(opponent).ability != Ability::id_by_name("Arena Trap").unwrap() || !pokemon.is_grounded(),
+        None => true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battle_ai::move_effects::EffectResult;
+    use rand::rngs::mock::StepRng;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn fully_paralyzed_fly_user_comes_back_down() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let fly = Action::Move {
+            user_id: 6,
+            move_: Move::id_by_name("Fly").unwrap(),
+            move_index: None,
+            target_positions: vec![FieldPosition::Min]
+        };
+        // A generator that only returns 0 makes every roll succeed, including full paralysis
+        let mut rng = StepRng::new(0, 0);
+
+        play_out_turn(&mut state, vec![&fly], None, &mut rng);
+        assert!(state.pokemon_by_id(6).semi_invulnerable.is_some());
+        let second_turn = state.pokemon_by_id_mut(6).next_move_action.take().unwrap();
+
+        state.pokemon_by_id_mut(6).major_status_ailment = MajorStatusAilment::Paralyzed;
+        play_out_turn(&mut state, vec![&second_turn], None, &mut rng);
+        assert_eq!(state.pokemon_by_id(6).semi_invulnerable, None);
+        assert_eq!(state.pokemon_by_id(6).next_move_action, None);
+
+        // The next Fly charges again instead of striking right away
+        state.pokemon_by_id_mut(6).major_status_ailment = MajorStatusAilment::Okay;
+        play_out_turn(&mut state, vec![&fly], None, &mut rng);
+        assert!(state.pokemon_by_id(6).semi_invulnerable.is_some());
+        assert_eq!(state.pokemon_by_id(0).current_hp(), state.pokemon_by_id(0).max_hp());
+    }
+
+    /// A move the user doesn't need to know, aimed at the other side of the matchup.
+    fn move_action(user_id: u8, move_name: &str) -> Action {
+        let target_position = if user_id < 6 { FieldPosition::Max } else { FieldPosition::Min };
+        Action::Move { user_id, move_: Move::id_by_name(move_name).unwrap(), move_index: None, target_positions: vec![target_position] }
+    }
+
+    #[test]
+    fn feint_hits_through_protect() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let protect = Action::Move { user_id: 0, move_: Move::id_by_name("Protect").unwrap(), move_index: None, target_positions: vec![FieldPosition::Min] };
+        let mut rng = StepRng::new(0, 0);
+
+        let tackle = move_action(6, "Tackle");
+        play_out_turn(&mut state, vec![&protect, &tackle], None, &mut rng);
+        assert_eq!(state.pokemon_by_id(0).current_hp(), state.pokemon_by_id(0).max_hp());
+
+        let feint = move_action(6, "Feint");
+        play_out_turn(&mut state, vec![&protect, &feint], None, &mut rng);
+        let target = state.pokemon_by_id(0);
+        assert!(target.current_hp() < target.max_hp());
+        assert!(!target.is_protected);
+        assert_eq!(target.protect_streak, 0);
+    }
+
+    /// The user's own move, such as Recover, with the user's position as the target.
+    fn self_targeted_action(user_id: u8, move_name: &str) -> Action {
+        let position = if user_id < 6 { FieldPosition::Min } else { FieldPosition::Max };
+        Action::Move { user_id, move_: Move::id_by_name(move_name).unwrap(), move_index: None, target_positions: vec![position] }
+    }
+
+    #[test]
+    fn recover_heals_half_of_max_hp() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let mut rng = StepRng::new(0, 0);
+        pokemon::set_hp(&mut state, 6, 1);
+
+        self_targeted_action(6, "Recover").perform(&mut state, &[], &mut rng);
+        let user = state.pokemon_by_id(6);
+        assert_eq!(user.current_hp(), 1 + user.max_hp() / 2);
+    }
+
+    #[test]
+    fn recover_fails_at_full_hp() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.rules.log_level = LogLevel::Verbose;
+        let mut rng = StepRng::new(0, 0);
+
+        self_targeted_action(6, "Recover").perform(&mut state, &[], &mut rng);
+        assert_eq!(state.pokemon_by_id(6).current_hp(), state.pokemon_by_id(6).max_hp());
+        assert_eq!(state.display_text.last().map(String::as_str), Some("But it failed!"));
+    }
+
+    #[test]
+    fn roost_grounds_a_flying_type_until_the_end_of_the_turn() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let mut rng = StepRng::new(0, 0);
+        state.pokemon_by_id_mut(6).second_type = Type::Flying;
+        pokemon::set_hp(&mut state, 6, 1);
+
+        self_targeted_action(6, "Roost").perform(&mut state, &[], &mut rng);
+        assert!(state.pokemon_by_id(6).is_grounded());
+        assert!(!state.pokemon_by_id(6).is_type(Type::Flying));
+
+        play_out_turn(&mut state, vec![&move_action(0, "Growl")], None, &mut rng);
+        assert!(!state.pokemon_by_id(6).is_grounded());
+        assert!(state.pokemon_by_id(6).is_type(Type::Flying));
+    }
+
+    #[test]
+    fn clear_body_blocks_growl() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.pokemon_by_id_mut(0).ability = Ability::id_by_name("Clear Body").unwrap();
+        let mut rng = StepRng::new(0, 0);
+
+        play_out_turn(&mut state, vec![&move_action(6, "Growl")], None, &mut rng);
+        assert_eq!(state.pokemon_by_id(0).stat_stage(StatIndex::Atk), 0);
+    }
+
+    #[test]
+    fn contrary_turns_a_drop_into_a_boost() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.pokemon_by_id_mut(0).ability = Ability::id_by_name("Contrary").unwrap();
+        let mut rng = StepRng::new(0, 0);
+
+        play_out_turn(&mut state, vec![&move_action(6, "Growl")], None, &mut rng);
+        assert_eq!(state.pokemon_by_id(0).stat_stage(StatIndex::Atk), 1);
+    }
+
+    #[test]
+    fn cached_stats_match_fresh_ones_across_status_changes() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let stat_indices = [StatIndex::Atk, StatIndex::Def, StatIndex::SpAtk, StatIndex::SpDef, StatIndex::Spd];
+        for major_status_ailment in [MajorStatusAilment::Okay, MajorStatusAilment::Paralyzed, MajorStatusAilment::Burned, MajorStatusAilment::Okay] {
+            pokemon::set_major_status_ailment(&mut state, 6, major_status_ailment);
+            let pokemon = state.pokemon_by_id(6);
+            for stat_index in stat_indices {
+                let fresh = pokemon::unstaged_stat(pokemon.species, pokemon.nature, &pokemon.ivs, &pokemon.evs, stat_index) as u32;
+                let paralysis_divisor = if stat_index == StatIndex::Spd && major_status_ailment == MajorStatusAilment::Paralyzed { 4 } else { 1 };
+                assert_eq!(pokemon::calculated_stat(&state, 6, stat_index), fresh / paralysis_divisor, "{:?} while {:?}", stat_index, major_status_ailment);
+            }
+        }
+    }
+
+    #[test]
+    fn running_team_totals_match_a_full_recomputation() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let mut rng = StdRng::seed_from_u64(0);
+        let ailments = [MajorStatusAilment::Okay, MajorStatusAilment::Burned, MajorStatusAilment::Paralyzed, MajorStatusAilment::Asleep];
+        for _ in 0..500 {
+            let pokemon_id = if rng.gen_bool(0.5) { 0 } else { 6 };
+            if rng.gen_bool(0.7) {
+                // Fainting and coming back both change what the totals count
+                let hp = rng.gen_range(0, state.pokemon_by_id(pokemon_id).max_hp() + 1);
+                pokemon::set_hp(&mut state, pokemon_id, hp);
+            } else {
+                pokemon::set_major_status_ailment(&mut state, pokemon_id, ailments[rng.gen_range(0, ailments.len())]);
+            }
+
+            let mut recomputed = state.clone();
+            recomputed.recompute_team_totals();
+            let (running, full) = (state.team_totals(), recomputed.team_totals());
+            for (running, full) in [(running.0, full.0), (running.1, full.1)] {
+                assert!((running.hp_fraction_sum - full.hp_fraction_sum).abs() < 1e-9);
+                assert_eq!((running.standing, running.statused), (full.standing, full.statused));
+            }
+        }
+    }
+
+    #[test]
+    fn snapshot_tells_apart_positions_that_differ_only_in_volatile_state() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.pokemon_by_id_mut(6).major_status_ailment = MajorStatusAilment::Asleep;
+        state.pokemon_by_id_mut(6).msa_counter = Counter::new(Some(3));
+        let changes: [fn(&mut State); 7] = [
+            |state| { state.pokemon_by_id_mut(6).msa_counter.inc(); },
+            |state| state.pokemon_by_id_mut(0).confusion_counter = Counter::new(Some(3)),
+            |state| state.pokemon_by_id_mut(0).substitute_hp = Some(57),
+            |state| state.pokemon_by_id_mut(0).drowsy_counter = 1,
+            |state| state.weather_counter = Counter::new(Some(5)),
+            |state| state.max.side_conditions.spikes_layers = 1,
+            |state| state.min.side_conditions.tailwind_counter = Counter::new(Some(4))
+        ];
+        for (i, change) in changes.iter().enumerate() {
+            let mut changed = state.clone();
+            change(&mut changed);
+            assert_ne!(changed.snapshot(), state.snapshot(), "change {}", i);
+        }
+    }
+
+    #[test]
+    fn snapshot_ignores_pp_and_the_turn_number() {
+        let _data = crate::load_test_data();
+        let state = crate::test_matchup();
+        let mut changed = state.clone();
+        changed.pokemon_by_id_mut(6).known_moves[0].pp -= 1;
+        changed.turn_number += 1;
+        assert_eq!(changed.snapshot(), state.snapshot());
+    }
+
+    #[test]
+    fn sleep_lasts_longer_before_gen_5() {
+        let _data = crate::load_test_data();
+        let state = crate::test_matchup();
+        let sleep_targets = || {
+            let mut targets = (0..100).map(|seed| {
+                let mut state = state.clone();
+                put_to_sleep(&mut state, 0, &mut StdRng::seed_from_u64(seed));
+                state.pokemon_by_id(0).msa_counter.target.unwrap()
+            }).collect::<Vec<u16>>();
+            targets.sort_unstable();
+            targets.dedup();
+            targets
+        };
+
+        let mut dp = crate::GameData::load(GameVersion::DP).unwrap();
+        assert_eq!(dp.with_active(sleep_targets), vec![2, 3, 4]);
+        assert_eq!(sleep_targets(), vec![1, 2]);
+    }
+
+    #[test]
+    fn hp_bar_rounds_down_but_never_to_zero_while_standing() {
+        let _data = crate::load_test_data();
+        let mut pokemon = crate::test_matchup().pokemon_by_id(0).clone();
+        let hp_bar = |pokemon: &mut Pokemon, max_hp, current_hp| {
+            pokemon.max_hp = max_hp;
+            pokemon.current_hp = current_hp;
+            pokemon.hp_bar_48ths()
+        };
+
+        assert_eq!(hp_bar(&mut pokemon, 10, 1), 4);
+        assert_eq!(hp_bar(&mut pokemon, 10, 9), 43);
+        assert_eq!(hp_bar(&mut pokemon, 100, 1), 1);
+        assert_eq!(hp_bar(&mut pokemon, 100, 100), 48);
+        assert_eq!(hp_bar(&mut pokemon, 1, 1), 48);
+        assert_eq!(hp_bar(&mut pokemon, 10, 0), 0);
+    }
+
+    #[test]
+    fn quick_claw_sometimes_moves_its_slower_holder_first() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.pokemon_by_id_mut(0).item = Some(Item::id_by_name("Quick Claw").unwrap());
+        state.pokemon_by_id_mut(6).set_stat_stages([0, 0, 0, 0, 0, 1, 0, 0]).unwrap();
+        let (holder_move, faster_move) = (move_action(0, "Tackle"), move_action(6, "Tackle"));
+
+        let num_first = (0..500)
+            .filter(|&seed| Action::action_queue_ordering(&state, &mut StdRng::seed_from_u64(seed), &holder_move, &faster_move) == Ordering::Less)
+            .count();
+        assert!((50..150).contains(&num_first), "moved first {} times out of 500", num_first);
+    }
+
+    #[test]
+    fn search_tries_a_priority_move_before_a_plain_one() {
+        let _data = crate::load_test_data();
+        let (feint, tackle) = (move_action(6, "Feint"), move_action(6, "Tackle"));
+
+        assert_eq!(action_cmp(&feint, &tackle), Ordering::Less);
+        assert_eq!(action_cmp(&tackle, &feint), Ordering::Greater);
+    }
+
+    #[test]
+    fn depleted_pp_survives_a_serde_round_trip() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let mut rng = StepRng::new(0, 0);
+        let full_pp = state.pokemon_by_id(6).known_move(0).pp;
+        let first_move = Action::Move { user_id: 6, move_: state.pokemon_by_id(6).known_move(0).move_, move_index: Some(0), target_positions: vec![FieldPosition::Min] };
+        for _ in 0..3 {
+            play_out_turn(&mut state, vec![&first_move], None, &mut rng);
+        }
+        state.pokemon_by_id_mut(6).known_moves[1].disabled = true;
+
+        let known_moves = state.pokemon_by_id(6).known_moves();
+        let restored: Vec<MoveInstance> = serde_json::from_str(&serde_json::to_string(known_moves).unwrap()).unwrap();
+        assert_eq!(restored[0].pp, full_pp - 3);
+        let summary = |moves: &[MoveInstance]| moves.iter().map(|instance| (instance.move_, instance.pp, instance.disabled)).collect::<Vec<_>>();
+        assert_eq!(summary(&restored), summary(known_moves));
+    }
+
+    #[test]
+    fn secondary_effect_only_follows_damage_the_target_survives() {
+        let _data = crate::load_test_data();
+        let mut rng = StepRng::new(0, 0);
+        let sludge_bomb = move_action(6, "Sludge Bomb");
+        let sludge_bomb_hitting = |target_at_one_hp: bool, rng: &mut StepRng| {
+            // A second team member keeps the battle going if the target faints
+            let mut state = crate::test_battle(&crate::test_team(2), &crate::test_team(1));
+            let target = state.pokemon_by_id_mut(0);
+            target.first_type = Type::Normal;
+            target.second_type = Type::None;
+            if target_at_one_hp {
+                target.current_hp = 1;
+            }
+            play_out_turn(&mut state, vec![&sludge_bomb], None, rng);
+            state
+        };
+
+        let state = sludge_bomb_hitting(false, &mut rng);
+        assert!(state.pokemon_by_id(0).current_hp() < state.pokemon_by_id(0).max_hp());
+        assert_eq!(state.pokemon_by_id(0).major_status_ailment(), MajorStatusAilment::Poisoned);
+
+        let state = sludge_bomb_hitting(true, &mut rng);
+        assert!(state.pokemon_by_id(0).is_fainted());
+        assert_eq!(state.pokemon_by_id(0).major_status_ailment(), MajorStatusAilment::Okay);
+    }
+
+    #[test]
+    fn trap_ends_when_the_trapper_leaves_the_field() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(2), &crate::test_team(2));
+        state.pokemon_by_id_mut(0).is_trapped = true;
+        assert!(!can_switch_out(&state, 0));
+
+        remove_from_field(&mut state, 6);
+        assert!(!state.pokemon_by_id(0).is_trapped);
+        assert!(can_switch_out(&state, 0));
+    }
+
+    #[test]
+    fn bind_chips_and_locks_in_its_target_until_it_wears_off() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(2), &crate::test_team(2));
+        let mut rng = StepRng::new(0, 0);
+        let bind = MoveEffect::PartialTrap(Type::Normal, 15);
+        // The lowest roll traps for 4 turns
+        bind.do_effect(Move::id_by_name("Tackle").unwrap(), &mut state, &[], 6, 0, &mut rng);
+        let chip = state.pokemon_by_id(0).max_hp() / 8;
+
+        for _ in 0..4 {
+            assert!(!can_switch_out(&state, 0));
+            let hp_before = state.pokemon_by_id(0).current_hp();
+            play_out_turn(&mut state, vec![], None, &mut rng);
+            assert_eq!(state.pokemon_by_id(0).current_hp(), hp_before - chip);
+        }
+
+        assert!(can_switch_out(&state, 0));
+        let hp_before = state.pokemon_by_id(0).current_hp();
+        play_out_turn(&mut state, vec![], None, &mut rng);
+        assert_eq!(state.pokemon_by_id(0).current_hp(), hp_before);
+    }
+
+    #[test]
+    fn confusion_wears_off_once_its_counter_reaches_the_target() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.pokemon_by_id_mut(0).confusion_counter = Counter::new(Some(2));
+        state.pokemon_by_id_mut(0).is_protected = true;
+
+        tick_minor_statuses(&mut state, 0);
+        assert!(state.pokemon_by_id(0).confusion_counter.has_target());
+        assert!(!state.pokemon_by_id(0).is_protected);
+        tick_minor_statuses(&mut state, 0);
+        assert!(!state.pokemon_by_id(0).confusion_counter.has_target());
+    }
+
+    #[test]
+    fn weather_without_a_target_never_clears() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let mut rng = StepRng::new(0, 0);
+        state.rules.log_level = LogLevel::Verbose;
+        state.weather = Weather::HarshSunshine;
+        state.weather_counter = Counter::new(None);
+
+        for _ in 0..20 {
+            play_out_turn(&mut state, vec![], None, &mut rng);
+        }
+        assert_eq!(state.weather, Weather::HarshSunshine);
+        let disappearance = Weather::HarshSunshine.display_text_on_disappearance();
+        assert!(!state.display_text.iter().any(|text| text == disappearance));
+    }
+
+    #[test]
+    fn weather_with_a_target_clears_after_that_many_turns() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let mut rng = StepRng::new(0, 0);
+        state.weather = Weather::HarshSunshine;
+        state.weather_counter = Counter::new(Some(5));
+
+        for _ in 0..4 {
+            play_out_turn(&mut state, vec![], None, &mut rng);
+        }
+        assert_eq!(state.weather, Weather::HarshSunshine);
+        play_out_turn(&mut state, vec![], None, &mut rng);
+        assert_eq!(state.weather, Weather::None);
+    }
+
+    #[test]
+    fn flying_types_and_levitate_holders_are_not_grounded() {
+        let _data = crate::load_test_data();
+        let mut pokemon = crate::test_matchup().pokemon_by_id(0).clone();
+        pokemon.first_type = Type::Fire;
+        pokemon.second_type = Type::None;
+        assert!(pokemon.is_grounded());
+
+        pokemon.second_type = Type::Flying;
+        assert!(!pokemon.is_grounded());
+        pokemon.second_type = Type::None;
+        pokemon.ability = Ability::id_by_name("Levitate").unwrap();
+        assert!(!pokemon.is_grounded());
+    }
+
+    #[test]
+    fn toxic_spikes_poison_grounded_switch_ins_and_are_absorbed_by_poison_types() {
+        let _data = crate::load_test_data();
+        // The status of a switch-in of the given types over the given number of layers, and the layers left afterwards
+        let switch_in = |layers, first_type, second_type| {
+            let mut state = crate::test_battle(&crate::test_team(2), &crate::test_team(1));
+            remove_from_field(&mut state, 0);
+            state.min.side_conditions.toxic_spikes_layers = layers;
+            state.pokemon_by_id_mut(1).first_type = first_type;
+            state.pokemon_by_id_mut(1).second_type = second_type;
+            add_to_field(&mut state, 1, FieldPosition::Min);
+            (state.pokemon_by_id(1).major_status_ailment(), state.min.side_conditions.toxic_spikes_layers)
+        };
+
+        assert_eq!(switch_in(1, Type::Grass, Type::None), (MajorStatusAilment::Poisoned, 1));
+        assert_eq!(switch_in(2, Type::Grass, Type::None), (MajorStatusAilment::BadlyPoisoned, 2));
+        assert_eq!(switch_in(2, Type::Grass, Type::Flying), (MajorStatusAilment::Okay, 2));
+        assert_eq!(switch_in(2, Type::Grass, Type::Poison), (MajorStatusAilment::Okay, 0));
+    }
+
+    #[test]
+    fn effective_speed_applies_paralysis_stages_and_chlorophyll() {
+        let _data = crate::load_test_data();
+        let state = crate::test_matchup();
+        let base_speed = effective_speed(&state, 0);
+        let speed_with = |change: fn(&mut State)| {
+            let mut state = state.clone();
+            change(&mut state);
+            effective_speed(&state, 0)
+        };
+
+        assert_eq!(speed_with(|state| state.pokemon_by_id_mut(0).major_status_ailment = MajorStatusAilment::Paralyzed), base_speed / 4);
+        assert_eq!(speed_with(|state| state.pokemon_by_id_mut(0).set_stat_stages([0, 0, 0, 0, 0, 2, 0, 0]).unwrap()), base_speed * 2);
+        assert_eq!(speed_with(|state| {
+            state.pokemon_by_id_mut(0).ability = Ability::id_by_name("Chlorophyll").unwrap();
+            state.weather = Weather::HarshSunshine;
+        }), base_speed * 2);
+        assert_eq!(speed_with(|state| state.pokemon_by_id_mut(0).ability = Ability::id_by_name("Chlorophyll").unwrap()), base_speed);
+    }
+
+    #[test]
+    fn tailwind_lets_the_slower_side_outspeed_for_four_turns() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let mut rng = StepRng::new(0, 0);
+        state.pokemon_by_id_mut(6).set_stat_stages([0, 0, 0, 0, 0, 1, 0, 0]).unwrap();
+        assert!(effective_speed(&state, 0) < effective_speed(&state, 6));
+
+        MoveEffect::Tailwind.do_effect(Move::id_by_name("Growl").unwrap(), &mut state, &[], 0, 0, &mut rng);
+        for _ in 0..3 {
+            play_out_turn(&mut state, vec![], None, &mut rng);
+            assert!(effective_speed(&state, 0) > effective_speed(&state, 6));
+        }
+        play_out_turn(&mut state, vec![], None, &mut rng);
+        assert!(effective_speed(&state, 0) < effective_speed(&state, 6));
+    }
+
+    #[test]
+    fn choice_scarf_boosts_speed_by_half() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let speed = effective_speed(&state, 0);
+
+        state.pokemon_by_id_mut(0).item = Some(Item::id_by_name("Choice Scarf").unwrap());
+        assert_eq!(effective_speed(&state, 0), (speed as f64 * 1.5) as u32);
+    }
+
+    #[test]
+    fn field_summary_reflects_damage_status_and_stages() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let mut rng = StepRng::new(0, 0);
+        state.pokemon_by_id_mut(0).second_type = Type::None;
+        let toxic = move_action(6, "Toxic");
+        let growl = move_action(0, "Growl");
+        for _ in 0..2 {
+            play_out_turn(&mut state, vec![&toxic, &growl], None, &mut rng);
+        }
+
+        let [min, max] = state.field_summary();
+        let (min, max) = (min.unwrap(), max.unwrap());
+        assert_eq!(min.major_status_ailment, MajorStatusAilment::BadlyPoisoned);
+        assert!(min.hp_fraction < 1.0);
+        assert_eq!(min.hp_fraction, state.pokemon_by_id(0).hp_fraction());
+        assert_eq!(max.major_status_ailment, MajorStatusAilment::Okay);
+        assert_eq!(max.hp_fraction, 1.0);
+        assert_eq!(max.stat_stages[1], -2);
+    }
+
+    #[test]
+    fn fainted_and_on_field_follow_hp_and_position() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(2), &crate::test_team(1));
+        assert!(state.pokemon_by_id(0).is_on_field() && !state.pokemon_by_id(0).is_fainted());
+        assert!(!state.pokemon_by_id(1).is_on_field() && !state.pokemon_by_id(1).is_fainted());
+
+        state.pokemon_by_id_mut(1).current_hp = 0;
+        assert!(state.pokemon_by_id(1).is_fainted());
+    }
+
+    #[test]
+    fn rollout_escalates_with_each_hit_and_resets_after_a_miss() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let moves_json = r#"[{"name": "Rollout", "type": "Rock", "category": "Physical", "accuracy": {"Standard": 90}, "targeting": "SingleAdjacentPokemon",
+            "max_pp": 20, "priority_stage": 0, "sound_based": false, "effects": [{"ConsecutiveBoost": ["Rock", 30, 16]}]}]"#;
+        let mut moves = crate::battle_ai::move_::parse_moves(String::from("moves.json"), moves_json).unwrap();
+
+        crate::battle_ai::move_::swap_moves(&mut moves);
+        let rollout = move_action(6, "Rollout");
+        // The lowest roll always hits, the highest always misses
+        let mut damage_dealt = |hits: bool| {
+            let max_hp = state.pokemon_by_id(0).max_hp();
+            state.pokemon_by_id_mut(0).current_hp = max_hp;
+            rollout.perform(&mut state, &[], &mut StepRng::new(if hits { 0 } else { u64::MAX }, 0));
+            max_hp - state.pokemon_by_id(0).current_hp()
+        };
+        let escalating: Vec<u16> = (0..3).map(|_| damage_dealt(true)).collect();
+        let missed = damage_dealt(false);
+        let after_miss = damage_dealt(true);
+        crate::battle_ai::move_::swap_moves(&mut moves);
+        assert!(escalating[0] < escalating[1] && escalating[1] < escalating[2], "{:?}", escalating);
+        assert_eq!(missed, 0);
+        assert_eq!(after_miss, escalating[0]);
+    }
+
+    #[test]
+    fn constructed_actions_can_be_performed() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(2), &crate::test_team(1));
+        let mut rng = StepRng::new(0, 0);
+        let growl_index = state.pokemon_by_id(6).known_moves().iter().position(|move_instance| Move::name(move_instance.move_()) == "Growl").unwrap();
+        let pp = state.pokemon_by_id(6).known_moves()[growl_index].pp;
+
+        let growl = Action::move_(&state, 6, Move::id_by_name("Growl").unwrap(), vec![FieldPosition::Min]).unwrap();
+        assert!(growl.can_be_performed(&state));
+        growl.perform(&mut state, &[], &mut rng);
+        assert_eq!(state.pokemon_by_id(0).stat_stage(StatIndex::Atk), -1);
+        assert_eq!(state.pokemon_by_id(6).known_moves()[growl_index].pp, pp - 1);
+
+        let switch = Action::switch(&state, Some(0), 1, FieldPosition::Min).unwrap();
+        switch.perform(&mut state, &[], &mut rng);
+        assert!(state.pokemon_by_id(1).is_on_field() && !state.pokemon_by_id(0).is_on_field());
+    }
+
+    #[test]
+    fn action_constructors_reject_impossible_actions() {
+        let _data = crate::load_test_data();
+        let state = crate::test_battle(&crate::test_team(2), &crate::test_team(1));
+
+        assert!(Action::move_(&state, 6, Move::id_by_name("Tackle").unwrap(), vec![FieldPosition::Min]).is_err());
+        assert!(Action::move_(&state, 1, Move::id_by_name("Growl").unwrap(), vec![FieldPosition::Max]).is_err());
+        assert!(Action::switch(&state, Some(0), 0, FieldPosition::Min).is_err());
+        assert!(Action::switch(&state, Some(0), 7, FieldPosition::Min).is_err());
+    }
+
+    #[test]
+    fn future_sight_lands_two_turns_later_even_after_the_user_leaves() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(1), &crate::test_team(2));
+        let mut rng = StepRng::new(0, 0);
+        let moves_json = r#"[{"name": "Future Sight", "type": "Psychic", "category": "Special", "accuracy": {"Standard": 100}, "targeting": "SingleAdjacentPokemon",
+            "max_pp": 10, "priority_stage": 0, "sound_based": false, "effects": [{"DelayedDamage": ["Psychic", 120]}]}]"#;
+        let mut moves = crate::battle_ai::move_::parse_moves(String::from("moves.json"), moves_json).unwrap();
+        let max_hp = state.pokemon_by_id(0).max_hp();
+
+        crate::battle_ai::move_::swap_moves(&mut moves);
+        play_out_turn(&mut state, vec![&move_action(6, "Future Sight")], None, &mut rng);
+        let hp_after_use = state.pokemon_by_id(0).current_hp();
+        let switch = Action::Switch { user_id: Some(6), switching_in_id: 7, target_position: FieldPosition::Max };
+        play_out_turn(&mut state, vec![&switch], None, &mut rng);
+        let hp_a_turn_later = state.pokemon_by_id(0).current_hp();
+        play_out_turn(&mut state, vec![], None, &mut rng);
+        crate::battle_ai::move_::swap_moves(&mut moves);
+        assert_eq!(hp_after_use, max_hp);
+        assert_eq!(hp_a_turn_later, max_hp);
+        assert!(state.pokemon_by_id(0).current_hp() < max_hp);
+    }
+
+    #[test]
+    fn secondary_effect_is_skipped_against_an_immune_target() {
+        let _data = crate::load_test_data();
+        let moves_json = r#"[{"name": "Rock Smash", "type": "Fighting", "category": "Physical", "accuracy": {"Standard": 100}, "targeting": "SingleAdjacentPokemon",
+            "max_pp": 15, "priority_stage": 0, "sound_based": false, "effects": [{"StdDamage": ["Fighting", 40, 0]}, {"IncTargetStatStage": ["Def", -1]}]}]"#;
+        let mut moves = crate::battle_ai::move_::parse_moves(String::from("moves.json"), moves_json).unwrap();
+        let target_of_type = |target_type: Type| {
+            let mut state = crate::test_matchup();
+            state.pokemon_by_id_mut(0).first_type = target_type;
+            state.pokemon_by_id_mut(0).second_type = Type::None;
+            state
+        };
+        let (mut normal_target, mut ghost_target) = (target_of_type(Type::Normal), target_of_type(Type::Ghost));
+
+        crate::battle_ai::move_::swap_moves(&mut moves);
+        let rock_smash = move_action(6, "Rock Smash");
+        rock_smash.perform(&mut normal_target, &[], &mut StepRng::new(0, 0));
+        rock_smash.perform(&mut ghost_target, &[], &mut StepRng::new(0, 0));
+        crate::battle_ai::move_::swap_moves(&mut moves);
+        assert_eq!(normal_target.pokemon_by_id(0).stat_stage(StatIndex::Def), -1);
+        assert_eq!(ghost_target.pokemon_by_id(0).stat_stage(StatIndex::Def), 0);
+    }
+
+    #[test]
+    fn depleted_move_is_not_usable() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        assert_eq!(state.pokemon_by_id(0).usable_moves().len(), 4);
+
+        increment_move_pp(&mut state, 0, 1, -100);
+        let usable: Vec<MoveID> = state.pokemon_by_id(0).usable_moves().iter().map(|(move_, _)| *move_).collect();
+        let known: Vec<MoveID> = state.pokemon_by_id(0).known_moves().iter().map(MoveInstance::move_).collect();
+        assert_eq!(usable, [known[0], known[2], known[3]]);
+    }
+
+    #[test]
+    fn yawn_puts_the_target_to_sleep_at_the_end_of_the_next_turn() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let mut rng = StepRng::new(0, 0);
+        let growl = Move::id_by_name("Growl").unwrap();
+
+        assert!(MoveEffect::Yawn.do_effect(growl, &mut state, &[], 6, 0, &mut rng) == EffectResult::Success);
+        play_out_turn(&mut state, vec![], None, &mut rng);
+        assert_eq!(state.pokemon_by_id(0).major_status_ailment(), MajorStatusAilment::Okay);
+        play_out_turn(&mut state, vec![], None, &mut rng);
+        assert_eq!(state.pokemon_by_id(0).major_status_ailment(), MajorStatusAilment::Asleep);
+    }
+
+    #[test]
+    fn yawn_fails_against_a_statused_target() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        state.pokemon_by_id_mut(0).major_status_ailment = MajorStatusAilment::Burned;
+
+        assert!(MoveEffect::Yawn.do_effect(Move::id_by_name("Growl").unwrap(), &mut state, &[], 6, 0, &mut StepRng::new(0, 0)) == EffectResult::Fail);
+        assert_eq!(state.pokemon_by_id(0).drowsy_counter, 0);
+    }
+
+    #[test]
+    fn poison_takes_on_normal_types_but_not_steel_types() {
+        let _data = crate::load_test_data();
+        let poisoned = |target_type: Type| {
+            let mut state = crate::test_matchup();
+            state.pokemon_by_id_mut(0).first_type = target_type;
+            state.pokemon_by_id_mut(0).second_type = Type::None;
+            let result = poison(&mut state, 0, false, false);
+            (result, state.pokemon_by_id(0).major_status_ailment())
+        };
+
+        assert!(poisoned(Type::Normal) == (EffectResult::Success, MajorStatusAilment::Poisoned));
+        assert!(poisoned(Type::Steel) == (EffectResult::NoEffect, MajorStatusAilment::Okay));
+    }
+
+    #[test]
+    fn fake_out_only_works_on_the_first_turn_after_switching_in() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(1), &crate::test_team(2));
+        let mut rng = StepRng::new(0, 0);
+        let moves_json = r#"[{"name": "Fake Out", "type": "Normal", "category": "Physical", "accuracy": {"Standard": 100}, "targeting": "SingleAdjacentPokemon",
+            "max_pp": 10, "priority_stage": 3, "sound_based": false, "effects": [{"FirstTurnOnly": {"StdDamage": ["Normal", 40, 0]}}]}]"#;
+        let mut moves = crate::battle_ai::move_::parse_moves(String::from("moves.json"), moves_json).unwrap();
+        let max_hp = state.pokemon_by_id(0).max_hp();
+
+        crate::battle_ai::move_::swap_moves(&mut moves);
+        let switch = Action::Switch { user_id: Some(6), switching_in_id: 7, target_position: FieldPosition::Max };
+        play_out_turn(&mut state, vec![&switch], None, &mut rng);
+        play_out_turn(&mut state, vec![&move_action(7, "Fake Out")], None, &mut rng);
+        let hp_after_first_use = state.pokemon_by_id(0).current_hp();
+        play_out_turn(&mut state, vec![&move_action(7, "Fake Out")], None, &mut rng);
+        crate::battle_ai::move_::swap_moves(&mut moves);
+        assert!(hp_after_first_use < max_hp);
+        assert_eq!(state.pokemon_by_id(0).current_hp(), hp_after_first_use);
+    }
+
+    #[test]
+    fn rest_sleeps_for_a_fixed_number_of_turns_in_every_gen() {
+        let _data = crate::load_test_data();
+        let state = crate::test_matchup();
+        let turns_asleep = || {
+            let mut state = state.clone();
+            set_hp(&mut state, 0, 1);
+            state.pokemon_by_id_mut(0).snore_sleep_talk_counter = 1;
+            sleep_from_rest(&mut state, 0);
+            let mut turns = 0;
+            while state.pokemon_by_id(0).major_status_ailment() == MajorStatusAilment::Asleep {
+                increment_msa_counter(&mut state, 0);
+                turns += 1;
+            }
+            turns
+        };
+
+        let mut frlg = crate::GameData::load(GameVersion::FRLG).unwrap();
+        assert_eq!(frlg.with_active(turns_asleep), REST_SLEEP_TURNS + 1);
+        assert_eq!(turns_asleep(), REST_SLEEP_TURNS + 1);
+    }
+
+    #[test]
+    fn stat_stages_outside_the_range_or_on_hp_are_rejected() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let pokemon = state.pokemon_by_id_mut(6);
+        assert!(pokemon.set_stat_stages([0, 7, 0, 0, 0, 0, 0, 0]).is_err());
+        assert!(pokemon.set_stat_stages([0, 0, 0, 0, 0, 0, 0, -7]).is_err());
+        assert!(pokemon.set_stat_stages([1, 0, 0, 0, 0, 0, 0, 0]).is_err());
+        assert_eq!(pokemon.stat_stages(), [0; 8]);
+
+        pokemon.set_stat_stages([0, 6, -6, 1, -1, 2, -2, 3]).unwrap();
+        assert_eq!(pokemon.stat_stages(), [0, 6, -6, 1, -1, 2, -2, 3]);
+    }
+
+    #[test]
+    fn changed_types_revert_after_switching_out_and_back_in() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(1), &crate::test_team(2));
+        let mut rng = StepRng::new(0, 0);
+        // As if the Pokemon had been Soaked
+        state.pokemon_by_id_mut(6).first_type = Type::Water;
+        state.pokemon_by_id_mut(6).second_type = Type::None;
+
+        let switch_out = Action::Switch { user_id: Some(6), switching_in_id: 7, target_position: FieldPosition::Max };
+        play_out_turn(&mut state, vec![&switch_out], None, &mut rng);
+        let switch_back = Action::Switch { user_id: Some(7), switching_in_id: 6, target_position: FieldPosition::Max };
+        play_out_turn(&mut state, vec![&switch_back], None, &mut rng);
+        assert!(state.pokemon_by_id(6).is_on_field());
+        assert_eq!((state.pokemon_by_id(6).first_type(), state.pokemon_by_id(6).second_type()), (Type::Grass, Type::Poison));
+    }
+
+    #[test]
+    fn stat_stage_changes_stay_within_six_stages() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(1), &crate::test_team(2));
+        increment_stat_stage(&mut state, 6, StatIndex::Atk, 12, Side::Max);
+        increment_stat_stage(&mut state, 6, StatIndex::Def, -12, Side::Min);
+        set_stat_stage(&mut state, 6, StatIndex::Spd, 6);
+        increment_stat_stage(&mut state, 6, StatIndex::Spd, 1, Side::Max);
+        assert_eq!(state.pokemon_by_id(6).stat_stages(), [0, 6, -6, 0, 0, 6, 0, 0]);
+
+        // Even a Baton Pass that somehow held out-of-range stages can't hand them on
+        let mut baton_pass = BatonPass::new(state.pokemon_by_id(6), 6);
+        baton_pass.stat_stages = [0, 9, -9, 0, 0, 0, 0, 0];
+        remove_from_field(&mut state, 6);
+        state.max.baton_pass = Some(baton_pass);
+        add_to_field(&mut state, 7, FieldPosition::Max);
+        assert_eq!(state.pokemon_by_id(7).stat_stages(), [0, 6, -6, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn same_species_on_opposite_sides_display_different_labels() {
+        let _data = crate::load_test_data();
+        let state = crate::test_matchup();
+        let (min, max) = (state.pokemon_by_id(0).to_string(), state.pokemon_by_id(6).to_string());
+        assert!(min.starts_with("Bulbasaur") && max.starts_with("Bulbasaur"));
+        assert!(min.contains("#1 (min)"), "{}", min);
+        assert!(max.contains("#7 (max)"), "{}", max);
+    }
+
+    #[test]
+    fn transformed_pokemon_copies_the_target_until_it_switches_out() {
+        let _data = crate::load_test_data();
+        let mut minimizer = crate::test_team(1);
+        minimizer.members[0].moves = vec![Move::id_by_name("Tackle").unwrap(), Move::id_by_name("Growl").unwrap()];
+        let mut state = crate::test_battle(&minimizer, &crate::test_team(2));
+        state.pokemon_by_id_mut(0).unstaged_stats[0] = 250;
+        let (own_atk, own_moves) = (calculated_stat(&state, 6, StatIndex::Atk), state.pokemon_by_id(6).known_moves().to_vec());
+
+        transform(&mut state, 6, 0);
+        assert_eq!(calculated_stat(&state, 6, StatIndex::Atk), 250);
+        let copied_moves: Vec<MoveID> = state.pokemon_by_id(6).known_moves().iter().map(|known_move| known_move.move_()).collect();
+        assert_eq!(copied_moves, vec![Move::id_by_name("Tackle").unwrap(), Move::id_by_name("Growl").unwrap()]);
+        assert!(state.pokemon_by_id(6).known_moves().iter().all(|known_move| known_move.pp == 5));
+
+        let switch = Action::Switch { user_id: Some(6), switching_in_id: 7, target_position: FieldPosition::Max };
+        play_out_turn(&mut state, vec![&switch], None, &mut StepRng::new(0, 0));
+        assert!(!state.pokemon_by_id(6).is_transformed());
+        assert_eq!(calculated_stat(&state, 6, StatIndex::Atk), own_atk);
+        assert_eq!(state.pokemon_by_id(6).known_moves(), own_moves.as_slice());
+    }
+
+    #[test]
+    fn outcomes_log_level_records_only_the_faint() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_battle(&crate::test_team(1), &crate::test_team(1));
+        set_hp(&mut state, 0, 1);
+        let knocked_out_with = |log_level| {
+            let mut state = state.clone();
+            state.rules.log_level = log_level;
+            play_out_turn(&mut state, vec![&move_action(6, "Tackle")], None, &mut StepRng::new(0, 0));
+            state.display_text
+        };
+
+        let outcomes = knocked_out_with(LogLevel::Outcomes);
+        assert_eq!(outcomes.len(), 1, "{:?}", outcomes);
+        assert!(outcomes[0].ends_with("fainted!"), "{:?}", outcomes);
+        assert!(knocked_out_with(LogLevel::Verbose).len() > 1);
+        assert!(knocked_out_with(LogLevel::Off).is_empty());
+    }
+
+    #[test]
+    fn rampage_moves_lock_the_user_in_then_confuse_it() {
+        let _data = crate::load_test_data();
+        for name in ["Outrage", "Thrash", "Petal Dance"].iter() {
+            let mut state = crate::test_matchup();
+            // Weak, non-critical hits so that the target survives both turns
+            state.rules.chance_policy = ChancePolicy::MinimumDamage;
+            state.pokemon_by_id_mut(6).set_stat_stages([0, -6, 0, -6, 0, 0, 0, 0]).unwrap();
+            // The lowest roll locks the user in for 2 turns
+            let mut rng = StepRng::new(0, 0);
+
+            move_action(6, name).perform(&mut state, &[], &mut rng);
+            let repeat = state.pokemon_by_id_mut(6).next_move_action.take();
+            assert_eq!(repeat, Some(move_action(6, name)), "{}", name);
+            assert!(!state.pokemon_by_id(6).confusion_counter.has_target(), "{}", name);
+
+            repeat.unwrap().perform(&mut state, &[], &mut rng);
+            assert_eq!(state.pokemon_by_id(6).next_move_action, None, "{}", name);
+            assert!(state.pokemon_by_id(6).confusion_counter.has_target(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn protected_target_on_the_last_rampage_turn_ends_it_with_confusion() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let mut rng = StepRng::new(0, 0);
+
+        move_action(6, "Outrage").perform(&mut state, &[], &mut rng);
+        let repeat = state.pokemon_by_id_mut(6).next_move_action.take().unwrap();
+        state.pokemon_by_id_mut(0).is_protected = true;
+        repeat.perform(&mut state, &[], &mut rng);
+
+        assert_eq!(state.pokemon_by_id(6).next_move_action, None);
+        assert!(!state.pokemon_by_id(6).rampage_counter.has_target());
+        assert!(state.pokemon_by_id(6).confusion_counter.has_target());
+    }
+
+    #[test]
+    fn miss_before_the_last_rampage_turn_ends_it_without_confusion() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        let mut rng = StepRng::new(0, 0);
+
+        move_action(6, "Outrage").perform(&mut state, &[], &mut rng);
+        let repeat = state.pokemon_by_id_mut(6).next_move_action.take().unwrap();
+        // Stretch the lock to 3 turns, one of them done, so that the next turn isn't the last
+        let mut rampage_counter = Counter::new(Some(3));
+        rampage_counter.inc();
+        state.pokemon_by_id_mut(6).rampage_counter = rampage_counter;
+        // A target in the air can't be hit by Outrage
+        state.pokemon_by_id_mut(0).semi_invulnerable = Some(InvulnKind::Airborne);
+        repeat.perform(&mut state, &[], &mut rng);
+
+        assert_eq!(state.pokemon_by_id(6).next_move_action, None);
+        assert!(!state.pokemon_by_id(6).rampage_counter.has_target());
+        assert!(!state.pokemon_by_id(6).confusion_counter.has_target());
+    }
+
+    #[test]
+    fn rampage_does_not_start_against_an_immune_target() {
+        let _data = crate::load_test_data();
+        let mut state = crate::test_matchup();
+        // Normal-type Thrash can't touch a Ghost
+        state.pokemon_by_id_mut(0).first_type = Type::Ghost;
+
+        move_action(6, "Thrash").perform(&mut state, &[], &mut StepRng::new(0, 0));
+        assert_eq!(state.pokemon_by_id(6).next_move_action, None);
+        assert!(!state.pokemon_by_id(6).rampage_counter.has_target());
+    }
+}
@@ -1,29 +1,32 @@
 use pokemon_battle_analysis_v5::{GameVersion, move_, species};
 use pokemon_battle_analysis_v5::battle_ai::pokemon::TeamBuild;
 use pokemon_battle_analysis_v5::battle_ai::state;
+use pokemon_battle_analysis_v5::battle_ai::state::BattleRules;
 use std::iter;
+use std::process;
 use std::ops::Div;
 use std::time::Instant;
+use std::sync::atomic::Ordering;
 use rand::rngs::StdRng;
-use rand::SeedableRng;
+use rand::{Rng, SeedableRng};
 use pokemon_battle_analysis_v5::combinatorial_optim::Solver;
 
-fn _single_battle(num_samples: u32) {
-    let mut rng: StdRng = SeedableRng::from_seed([0; 32]);
+fn _single_battle<R: Rng + SeedableRng>(num_samples: u32) {
+    let mut rng = R::seed_from_u64(0);
     let teams: Vec<TeamBuild> = iter::repeat_with(|| TeamBuild::new(&mut rng))
         .take(2 * num_samples as usize)
         .collect();
 
-    unsafe { state::NUM_STATE_COPIES = 0; }
+    state::NUM_STATE_COPIES.store(0, Ordering::Relaxed);
     let start_time = Instant::now();
     for i in 0..num_samples as usize {
         println!("{}", i);
-        state::run_battle(&teams[i], &teams[i + num_samples as usize], &mut rng);
+        state::run_battle(&teams[i], &teams[i + num_samples as usize], &BattleRules::default(), &mut rng);
     }
 
     let dur = start_time.elapsed();
-    let nsc = unsafe { state::NUM_STATE_COPIES };
-    println!("---- Single Battle ----");
+    let nsc = state::NUM_STATE_COPIES.load(Ordering::Relaxed);
+    println!("---- Single Battle ({}) ----", std::any::type_name::<R>());
     println!("AI level: {:?}", state::AI_LEVEL);
     println!("Num samples: {:?}", num_samples);
     println!("Elapsed time: {:?}", dur);
@@ -54,10 +57,13 @@ fn _combinatorial_optim(iters: u32) {
 fn main() {
     unsafe {
         pokemon_battle_analysis_v5::GAME_VERSION = GameVersion::XY;
-        move_::initialize_moves();
-        species::initialize_species();
+    }
+    if let Err(err) = move_::initialize_moves().and_then(|_| species::initialize_species()) {
+        eprintln!("Failed to load game data: {}", err);
+        process::exit(1);
     }
 
-    //_single_battle(30);
+    //_single_battle::<StdRng>(30);
+    //_single_battle::<rand::rngs::SmallRng>(30);
     //_combinatorial_optim(30);
 }
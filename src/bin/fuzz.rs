@@ -0,0 +1,75 @@
+use std::env;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+
+use pokemon_battle_analysis_v5::{GameVersion, move_, species};
+use pokemon_battle_analysis_v5::battle_ai::pokemon::TeamBuild;
+use pokemon_battle_analysis_v5::battle_ai::state;
+use pokemon_battle_analysis_v5::battle_ai::state::{BattleRules, State};
+use pokemon_battle_analysis_v5::move_::Move;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Runs seeded battles between random teams and checks that the engine never panics or reaches an impossible state.
+/// Takes the number of battles as an optional argument. Exits with an error after listing the seeds of any failed
+/// battles, so that they can be replayed.
+fn main() {
+    let num_battles: u64 = match env::args().nth(1) {
+        Some(arg) => arg.parse().unwrap_or_else(|_| {
+            eprintln!("Number of battles must be a non-negative integer, got '{}'", arg);
+            process::exit(1);
+        }),
+        None => 1000
+    };
+
+    unsafe {
+        pokemon_battle_analysis_v5::GAME_VERSION = GameVersion::XY;
+    }
+    if let Err(err) = move_::initialize_moves().and_then(|_| species::initialize_species()) {
+        eprintln!("Failed to load game data: {}", err);
+        process::exit(1);
+    }
+
+    // Failures are reported below, so the default panic message would only be noise
+    panic::set_hook(Box::new(|_| {}));
+    let mut failed_seeds = Vec::new();
+    for seed in 0..num_battles {
+        if let Err(err) = panic::catch_unwind(AssertUnwindSafe(|| fuzz_battle(seed))) {
+            let message = err.downcast_ref::<String>().map(String::as_str)
+                .or_else(|| err.downcast_ref::<&str>().copied())
+                .unwrap_or("unknown panic");
+            eprintln!("Seed {} failed: {}", seed, message);
+            failed_seeds.push(seed);
+        }
+    }
+
+    if failed_seeds.is_empty() {
+        println!("All {} battles passed", num_battles);
+    } else {
+        eprintln!("{} of {} battles failed: {:?}", failed_seeds.len(), num_battles, failed_seeds);
+        process::exit(1);
+    }
+}
+
+fn fuzz_battle(seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let minimizer = TeamBuild::with_size(rng.gen_range(1, 7), &mut rng);
+    let maximizer = TeamBuild::with_size(rng.gen_range(1, 7), &mut rng);
+    let rules = BattleRules {
+        search_depth: 1,
+        ..BattleRules::default()
+    };
+
+    let payoff = state::run_battle_observed(&minimizer, &maximizer, &rules, |state, _| check_invariants(state), &mut rng);
+    assert!((-1.0..=1.0).contains(&payoff), "Payoff {} is outside of [-1, 1]", payoff);
+}
+
+fn check_invariants(state: &State) {
+    for (pokemon_id, pokemon) in state.pokemon.iter().enumerate() {
+        assert!(pokemon.current_hp() <= pokemon.max_hp(), "Pokemon {} has {} HP, above its max of {}", pokemon_id, pokemon.current_hp(), pokemon.max_hp());
+        for move_instance in pokemon.known_moves() {
+            let max_pp = Move::max_pp(move_instance.move_());
+            assert!(move_instance.pp <= max_pp, "Pokemon {} has {} PP for {}, above its max of {}", pokemon_id, move_instance.pp, Move::name(move_instance.move_()), max_pp);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use std::fs;
+use std::process;
 
 use csv::WriterBuilder;
 #[cfg(not(target_env = "msvc"))]
@@ -21,8 +22,13 @@ fn main() {
     // TODO: Parse game version from args
     unsafe {
         pokemon_battle_analysis_v5::GAME_VERSION = GameVersion::XY;
-        move_::initialize_moves();
-        species::initialize_species();
+    }
+    if let Err(err) = move_::initialize_moves().and_then(|_| species::initialize_species()) {
+        eprintln!("Failed to load game data: {}", err);
+        process::exit(1);
+    }
+    if let Err(problems) = pokemon_battle_analysis_v5::validate_game_data() {
+        panic!("Invalid game data:\n{}", problems.join("\n"));
     }
 
     let mut rng: StdRng = SeedableRng::from_seed([0; 32]);
@@ -35,7 +41,7 @@ fn main() {
         }
     };
 
-    let pb_header = "species,gender,nature,ability,iv_1,iv_2,iv_3,iv_4,iv_5,iv_6,ev_1,ev_2,ev_3,ev_4,ev_5,ev_6,move_1,move_2,move_3,move_4";
+    let pb_header = "species,gender,nature,ability,item,iv_1,iv_2,iv_3,iv_4,iv_5,iv_6,ev_1,ev_2,ev_3,ev_4,ev_5,ev_6,move_1,move_2,move_3,move_4";
     let mut tb_header = vec!["fitness", "fit_variance", "num_samples", "prob_worse_than_best"];
     for _ in 0..6 {
         for s in pb_header.split(',') { tb_header.push(s); }
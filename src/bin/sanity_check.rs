@@ -1,16 +1,21 @@
+use std::process;
+
 use pokemon_battle_analysis_v5::{GameVersion, move_, species};
 use pokemon_battle_analysis_v5::battle_ai::pokemon::TeamBuild;
 use pokemon_battle_analysis_v5::battle_ai::state;
+use pokemon_battle_analysis_v5::battle_ai::state::BattleRules;
 use rand::rngs::StdRng;
 use rand::SeedableRng;
 
 fn main() {
     unsafe {
         pokemon_battle_analysis_v5::GAME_VERSION = GameVersion::XY;
-        move_::initialize_moves();
-        species::initialize_species();
+    }
+    if let Err(err) = move_::initialize_moves().and_then(|_| species::initialize_species()) {
+        eprintln!("Failed to load game data: {}", err);
+        process::exit(1);
     }
 
     let mut rng: StdRng = SeedableRng::from_seed([0; 32]);
-    state::run_battle(&TeamBuild::new(&mut rng), &TeamBuild::new(&mut rng), &mut rng);
+    state::run_battle(&TeamBuild::new(&mut rng), &TeamBuild::new(&mut rng), &BattleRules::default(), &mut rng);
 }
@@ -1,10 +1,15 @@
-use rand::prelude::StdRng;
 use serde::{Deserialize, Serialize};
+use serde::de::IntoDeserializer;
 use crate::battle_ai::pokemon::TeamBuild;
-use crate::battle_ai::state;
+use crate::battle_ai::state::{self, State};
+use crate::battle_ai::data::{Side, Weather};
+use crate::battle_ai::move_effects::{self, MoveEffect};
 use rand::Rng;
 use rand::distributions::Distribution;
-use std::iter;
+use std::{iter, slice};
+use std::collections::HashMap;
+use std::path::Path;
+use csv::{ReaderBuilder, StringRecord};
 use statrs::distribution::{Normal, Univariate, StudentsT};
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -17,16 +22,32 @@ pub struct Solution {
 }
 
 impl Solution {
-    fn new(rng: &mut StdRng) -> Solution {
+    fn new<R: Rng>(team_size: usize, rng: &mut R) -> Solution {
+        Solution::with_team_build(TeamBuild::with_size(team_size, rng))
+    }
+
+    fn with_team_build(team_build: TeamBuild) -> Solution {
         Solution {
             fitness: -1.0,
             fit_variance: 0.0,
             num_samples: 0,
             prob_worse_than_best: 0.0,
-            team_build: TeamBuild::new(rng)
+            team_build
         }
     }
 
+    /// Whether this solution has enough fitness samples to be compared against others. Until then, it can neither
+    /// reproduce nor be culled.
+    const fn is_evaluated(&self) -> bool {
+        self.num_samples >= MIN_SAMPLES
+    }
+
+    /// What solutions are ranked by: the fitness minus `variance_penalty` standard deviations of the fitness samples,
+    /// so that a positive penalty favors teams whose results are consistent.
+    fn score(&self, variance_penalty: f64) -> f64 {
+        self.fitness - variance_penalty * self.fit_variance.sqrt()
+    }
+
     /// Update this solution with a new fitness sample.
     fn update(&mut self, fitness_sample: f64) {
         match self.num_samples {
@@ -47,7 +68,7 @@ impl Solution {
     }
 
     /// Returns the probability that this solution performs worse than `other`.
-    fn prob_worse_than(&self, other: &Solution, rng: &mut StdRng) -> f64 {
+    fn prob_worse_than<R: Rng>(&self, other: &Solution, rng: &mut R) -> f64 {
         if almost::zero(self.fit_variance) && almost::zero(other.fit_variance) {
             return if self.fitness > other.fitness {
                 0.0
@@ -61,13 +82,18 @@ impl Solution {
         if self.num_samples > 30 && other.num_samples > 30 {
             Normal::new(self.fitness - other.fitness, (self.fit_variance + other.fit_variance).sqrt()).unwrap().cdf(0.0)
         } else {
-            let t_dist_1 = StudentsT::new(self.fitness, self.fit_variance.sqrt(), (self.num_samples - 1) as f64).unwrap();
-            let t_dist_2 = StudentsT::new(other.fitness, other.fit_variance.sqrt(), (other.num_samples - 1) as f64).unwrap();
+            // A solution whose samples were all the same, e.g. all mirror matches, always scores its fitness
+            let t_dist = |sol: &Solution| (!almost::zero(sol.fit_variance))
+                .then(|| StudentsT::new(sol.fitness, sol.fit_variance.sqrt(), (sol.num_samples - 1) as f64).unwrap());
+            let t_dist_1 = t_dist(self);
+            let t_dist_2 = t_dist(other);
 
             const MONTE_CARLO_NUM: usize = 100000;
             let mut count = 0;
             for _ in 0..MONTE_CARLO_NUM {
-                if t_dist_1.sample(rng) - t_dist_2.sample(rng) < 0.0 {
+                let sample_1 = t_dist_1.as_ref().map_or(self.fitness, |t_dist| t_dist.sample(rng));
+                let sample_2 = t_dist_2.as_ref().map_or(other.fitness, |t_dist| t_dist.sample(rng));
+                if sample_1 - sample_2 < 0.0 {
                     count += 1;
                 }
             }
@@ -77,94 +103,416 @@ impl Solution {
     }
 }
 
+/// Number of fitness samples a solution needs before it is compared against other solutions.
+const MIN_SAMPLES: usize = 2;
+
+/// Number of top solutions in each meta that are never culled.
+const ELITE_COUNT: usize = 3;
+
+/// The state of a `Solver` after one iteration; see `Solver::record_history`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct SolverSnapshot {
+    /// Fitness of the best evaluated solution in the maximizer meta, or None if none have been evaluated yet.
+    pub best_fitness: Option<f64>,
+    /// Number of solutions in the maximizer meta.
+    pub meta_size: usize,
+    pub fitness_func_evals: usize
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct Solver {
     fitness_func_evals: usize,
+    /// Pairings of identical teams, which are scored as even without a battle. These count toward the budget of
+    /// `run_until` along with `fitness_func_evals`.
+    #[serde(default)]
+    mirror_matches: usize,
+    /// Chance that any given pair of solutions is evaluated against each other in an iteration. If None,
+    /// 1/sqrt(maximizer meta size * minimizer meta size) is used, so about that many evaluations are done per
+    /// iteration.
+    interaction_density: Option<f64>,
     maximizer_meta: Vec<Solution>,
-    minimizer_meta: Vec<Solution>
+    minimizer_meta: Vec<Solution>,
+    /// If true, the solutions in `maximizer_meta` play against each other and `minimizer_meta` is unused.
+    #[serde(default)]
+    single_meta: bool,
+    /// If true, each battle's fitness sample is its `BattleOutcome` payoff rather than the HP-based payoff.
+    #[serde(default)]
+    win_loss_fitness: bool,
+    /// Number of EVs moved at a time when mutating a build; see `TeamBuild::mutated_child_with_ev_step`.
+    #[serde(default = "default_ev_step")]
+    ev_step: u8,
+    /// Solutions are ranked by `Solution::score` with this penalty; 0 ranks them by fitness alone.
+    #[serde(default)]
+    variance_penalty: f64,
+    #[serde(default)]
+    record_history: bool,
+    /// One snapshot per iteration done while `record_history` was on.
+    #[serde(default)]
+    history: Vec<SolverSnapshot>
+}
+
+const fn default_ev_step() -> u8 {
+    4
 }
 
 impl Solver {
-    pub fn new(rng: &mut StdRng) -> Solver {
+    pub fn new<R: Rng>(rng: &mut R) -> Solver {
+        Solver::with_config(100, 6, rng)
+    }
+
+    /// `pop_size` is the initial number of solutions in each meta; `team_size` is the number of
+    /// Pokemon on each team, at most 6.
+    pub fn with_config<R: Rng>(pop_size: usize, team_size: usize, rng: &mut R) -> Solver {
+        Solver {
+            fitness_func_evals: 0,
+            mirror_matches: 0,
+            interaction_density: None,
+            maximizer_meta: iter::repeat_with(|| Solution::new(team_size, rng)).take(pop_size).collect(),
+            minimizer_meta: iter::repeat_with(|| Solution::new(team_size, rng)).take(pop_size).collect(),
+            single_meta: false,
+            win_loss_fitness: false,
+            ev_step: default_ev_step(),
+            variance_penalty: 0.0,
+            record_history: false,
+            history: Vec::new()
+        }
+    }
+
+    /// Same as `with_config`, except that there is one meta whose solutions play against each other, as in an open
+    /// metagame. Each solution's fitness is its mean payoff against the rest of the meta. This avoids two separate
+    /// metas chasing each other in circles.
+    pub fn new_single_meta<R: Rng>(pop_size: usize, team_size: usize, rng: &mut R) -> Solver {
+        if pop_size < 2 {
+            panic!("A single meta needs at least 2 solutions. Given population size: {}", pop_size);
+        }
         Solver {
             fitness_func_evals: 0,
-            maximizer_meta: iter::repeat_with(|| Solution::new(rng)).take(100).collect(),
-            minimizer_meta: iter::repeat_with(|| Solution::new(rng)).take(100).collect()
+            mirror_matches: 0,
+            interaction_density: None,
+            maximizer_meta: iter::repeat_with(|| Solution::new(team_size, rng)).take(pop_size).collect(),
+            minimizer_meta: Vec::new(),
+            single_meta: true,
+            win_loss_fitness: false,
+            ev_step: default_ev_step(),
+            variance_penalty: 0.0,
+            record_history: false,
+            history: Vec::new()
+        }
+    }
+
+    /// Starts each meta with the given teams, then pads it with random teams until it has at least
+    /// `fill_to` solutions. Random teams are the same size as the seed teams, or 6 if there are no
+    /// seed teams. Fails if the seed teams aren't all the same size.
+    pub fn from_seed_teams<R: Rng>(maximizer_seeds: Vec<TeamBuild>, minimizer_seeds: Vec<TeamBuild>, fill_to: usize, rng: &mut R) -> Result<Solver, String> {
+        let mut seed_sizes = maximizer_seeds.iter().chain(minimizer_seeds.iter()).map(|team_build| team_build.members.len());
+        let team_size = seed_sizes.next().unwrap_or(6);
+        if let Some(other_size) = seed_sizes.find(|&size| size != team_size) {
+            return Err(format!("seed teams must all be the same size, but have sizes {} and {}", team_size, other_size));
+        }
+
+        let mut seeded_meta = |seeds: Vec<TeamBuild>| -> Result<Vec<Solution>, String> {
+            for team_build in &seeds {
+                team_build.validate()?;
+            }
+            let mut meta: Vec<Solution> = seeds.into_iter().map(Solution::with_team_build).collect();
+            while meta.len() < fill_to {
+                meta.push(Solution::new(team_size, rng));
+            }
+            Ok(meta)
+        };
+
+        Ok(Solver {
+            fitness_func_evals: 0,
+            mirror_matches: 0,
+            interaction_density: None,
+            maximizer_meta: seeded_meta(maximizer_seeds)?,
+            minimizer_meta: seeded_meta(minimizer_seeds)?,
+            single_meta: false,
+            win_loss_fitness: false,
+            ev_step: default_ev_step(),
+            variance_penalty: 0.0,
+            record_history: false,
+            history: Vec::new()
+        })
+    }
+
+    /// Replaces a meta with the teams in a CSV file laid out like the `maximizer_meta.csv` that the main binary
+    /// writes: a header row, then one row per solution. The fitness columns are ignored, so every team starts over
+    /// as a fresh solution with no samples. A single-meta solver imports into its one meta for either side. Fails
+    /// without changing anything if the file can't be read or holds an invalid team.
+    pub fn import_meta_csv<P: AsRef<Path>>(&mut self, path: P, which: Side) -> Result<(), String> {
+        let mut reader = ReaderBuilder::new()
+            .flexible(true)
+            .from_path(path).map_err(|err| err.to_string())?;
+        let mut meta = Vec::new();
+        for (i, record) in reader.records().enumerate() {
+            // Line 1 is the header
+            let team_build = record.map_err(|err| err.to_string())
+                .and_then(|record| team_build_from_csv_record(&record))
+                .and_then(|team_build| team_build.validate().map(|_| team_build))
+                .map_err(|err| format!("line {}: {}", i + 2, err))?;
+            meta.push(Solution::with_team_build(team_build));
+        }
+        if meta.is_empty() {
+            return Err(String::from("file has no teams"));
+        }
+
+        match which {
+            Side::Min if !self.single_meta => self.minimizer_meta = meta,
+            _ => self.maximizer_meta = meta
+        }
+        Ok(())
+    }
+
+    pub const fn fitness_func_evals(&self) -> usize {
+        self.fitness_func_evals
+    }
+
+    pub const fn interaction_density(&self) -> Option<f64> {
+        self.interaction_density
+    }
+
+    /// Higher densities sample more matchups per iteration at the cost of slower iterations. Must be in (0, 1].
+    pub fn set_interaction_density(&mut self, interaction_density: f64) -> Result<(), String> {
+        if !(interaction_density > 0.0 && interaction_density <= 1.0) {
+            return Err(format!("interaction density must be in (0, 1]; got {}", interaction_density));
+        }
+        self.interaction_density = Some(interaction_density);
+        Ok(())
+    }
+
+    pub const fn is_single_meta(&self) -> bool {
+        self.single_meta
+    }
+
+    pub const fn uses_win_loss_fitness(&self) -> bool {
+        self.win_loss_fitness
+    }
+
+    /// If set, solutions are ranked by win rate: each battle counts as 1, -1 or 0 for a win, loss or draw, instead of
+    /// by how much HP was left. Matchups that are close on HP then separate in fewer samples, but HP margins that
+    /// don't change who wins are ignored.
+    pub fn set_win_loss_fitness(&mut self, win_loss_fitness: bool) {
+        self.win_loss_fitness = win_loss_fitness;
+    }
+
+    pub const fn ev_step(&self) -> u8 {
+        self.ev_step
+    }
+
+    /// Sets how many EVs mutations move at a time, either 4 (the default) or 1.
+    pub fn set_ev_step(&mut self, ev_step: u8) -> Result<(), String> {
+        if ev_step != 1 && ev_step != 4 {
+            return Err(format!("EV step must be 1 or 4; got {}", ev_step));
+        }
+        self.ev_step = ev_step;
+        Ok(())
+    }
+
+    pub const fn variance_penalty(&self) -> f64 {
+        self.variance_penalty
+    }
+
+    /// Makes the metas favor consistent teams by ranking solutions by fitness minus this many standard deviations of
+    /// their fitness samples. 0, the default, ranks them by fitness alone.
+    pub fn set_variance_penalty(&mut self, variance_penalty: f64) -> Result<(), String> {
+        if !variance_penalty.is_finite() || variance_penalty < 0.0 {
+            return Err(format!("variance penalty must be finite and non-negative; got {}", variance_penalty));
         }
+        self.variance_penalty = variance_penalty;
+        Ok(())
+    }
+
+    pub const fn records_history(&self) -> bool {
+        self.record_history
+    }
+
+    /// While on, each iteration appends a snapshot of the solver to `history`, so that its convergence can be plotted
+    /// afterwards. Turning it off keeps the snapshots taken so far.
+    pub fn record_history(&mut self, on: bool) {
+        self.record_history = on;
+    }
+
+    pub fn history(&self) -> &[SolverSnapshot] {
+        &self.history
     }
 
     pub fn maximizer_meta(&self) -> &[Solution] {
         &self.maximizer_meta
     }
 
+    /// The same as `maximizer_meta` for a single-meta solver.
     pub fn minimizer_meta(&self) -> &[Solution] {
-        &self.minimizer_meta
+        if self.single_meta { &self.maximizer_meta } else { &self.minimizer_meta }
+    }
+
+    pub fn do_iter<R: Rng>(&mut self, rng: &mut R) {
+        if self.single_meta {
+            self.do_single_meta_iter(rng);
+        } else {
+            self.do_two_meta_iter(rng);
+        }
+
+        if self.record_history {
+            let snapshot = SolverSnapshot {
+                best_fitness: self.maximizer_meta.iter()
+                    .filter(|sol| sol.is_evaluated())
+                    .map(|sol| sol.fitness)
+                    .max_by(|fit1, fit2| fit1.partial_cmp(fit2).unwrap()),
+                meta_size: self.maximizer_meta.len(),
+                fitness_func_evals: self.fitness_func_evals
+            };
+            self.history.push(snapshot);
+        }
     }
 
-    pub fn do_iter(&mut self, rng: &mut StdRng) {
+    fn do_two_meta_iter<R: Rng>(&mut self, rng: &mut R) {
         // Evaluate solutions in one meta against those in the other, updating their fitnesses.
-        let interaction_chance = 1.0 / ((self.maximizer_meta.len() * self.minimizer_meta.len()) as f64).sqrt();
+        let interaction_chance = self.interaction_density
+            .unwrap_or_else(|| 1.0 / ((self.maximizer_meta.len() * self.minimizer_meta.len()) as f64).sqrt());
         for max_sol in self.maximizer_meta.iter_mut() {
             for min_sol in self.minimizer_meta.iter_mut() {
                 if rng.gen_bool(interaction_chance) {
-                    let fitness_sample = state::run_battle(&min_sol.team_build, &max_sol.team_build, rng);
-                    self.fitness_func_evals += 1;
+                    // A mirror match is even by symmetry, so there's no need to simulate it
+                    let fitness_sample = if min_sol.team_build == max_sol.team_build {
+                        self.mirror_matches += 1;
+                        0.0
+                    } else {
+                        self.fitness_func_evals += 1;
+                        Solver::battle_payoff(&min_sol.team_build, &max_sol.team_build, self.win_loss_fitness, rng)
+                    };
                     max_sol.update(fitness_sample);
                     min_sol.update(-fitness_sample);
                 }
             }
         }
 
-        Solver::update_meta(&mut self.maximizer_meta, rng);
-        Solver::update_meta(&mut self.minimizer_meta, rng);
+        Solver::update_meta(&mut self.maximizer_meta, self.ev_step, self.variance_penalty, rng);
+        Solver::update_meta(&mut self.minimizer_meta, self.ev_step, self.variance_penalty, rng);
     }
 
-    fn update_meta(meta: &mut Vec<Solution>, rng: &mut StdRng) {
+    /// Evaluates pairs of solutions in the meta against each other, updating their fitnesses, then updates the meta.
+    /// Which solution plays as the maximizer is chosen at random, so neither side's quirks favor either solution.
+    fn do_single_meta_iter<R: Rng>(&mut self, rng: &mut R) {
+        let meta_size = self.maximizer_meta.len();
+        let interaction_chance = self.interaction_density
+            .unwrap_or_else(|| 1.0 / meta_size as f64);
+        for i in 0..meta_size {
+            for j in (i + 1)..meta_size {
+                if rng.gen_bool(interaction_chance) {
+                    let (first, rest) = self.maximizer_meta.split_at_mut(j);
+                    let (sol1, sol2) = (&mut first[i], &mut rest[0]);
+                    // A mirror match is even by symmetry, so there's no need to simulate it
+                    let fitness_sample = if sol1.team_build == sol2.team_build {
+                        self.mirror_matches += 1;
+                        0.0
+                    } else {
+                        self.fitness_func_evals += 1;
+                        if rng.gen_bool(0.5) {
+                            Solver::battle_payoff(&sol2.team_build, &sol1.team_build, self.win_loss_fitness, rng)
+                        } else {
+                            -Solver::battle_payoff(&sol1.team_build, &sol2.team_build, self.win_loss_fitness, rng)
+                        }
+                    };
+                    sol1.update(fitness_sample);
+                    sol2.update(-fitness_sample);
+                }
+            }
+        }
+
+        Solver::update_meta(&mut self.maximizer_meta, self.ev_step, self.variance_penalty, rng);
+    }
+
+    /// Payoff to the maximizer of one battle under the standard rules.
+    fn battle_payoff<R: Rng>(minimizer: &TeamBuild, maximizer: &TeamBuild, win_loss: bool, rng: &mut R) -> f64 {
+        if win_loss {
+            state::run_battle_outcome(minimizer, maximizer, &BattleRules::default(), rng).payoff()
+        } else {
+            state::run_battle(minimizer, maximizer, &BattleRules::default(), rng)
+        }
+    }
+
+    /// Iterates until at least `max_evals` fitness function evaluations have been done in total, then
+    /// returns the highest-scoring team in the maximizer meta; see `set_variance_penalty`. Mirror matches count as
+    /// evaluations here even though no battle is run. Stops early if there are no pairs of solutions to evaluate, as
+    /// with a single meta of one team, since the metas can't change without them.
+    pub fn run_until<R: Rng>(&mut self, max_evals: usize, rng: &mut R) -> TeamBuild {
+        while self.fitness_func_evals + self.mirror_matches < max_evals && self.has_pairings() {
+            self.do_iter(rng);
+        }
+
+        let variance_penalty = self.variance_penalty;
+        self.maximizer_meta.iter()
+            .max_by(|sol1, sol2| sol1.score(variance_penalty).partial_cmp(&sol2.score(variance_penalty)).unwrap())
+            .unwrap()
+            .team_build
+            .clone()
+    }
+
+    /// Whether an iteration has any pairs of solutions to evaluate against each other.
+    fn has_pairings(&self) -> bool {
+        if self.single_meta {
+            self.maximizer_meta.len() >= 2
+        } else {
+            !self.maximizer_meta.is_empty() && !self.minimizer_meta.is_empty()
+        }
+    }
+
+    fn update_meta<R: Rng>(meta: &mut Vec<Solution>, ev_step: u8, variance_penalty: f64, rng: &mut R) {
         let num_sols = meta.len();
 
         // Each solution may create a child according to its probability of performing better than the best solution.
-        meta.sort_unstable_by(|sol1, sol2| sol2.fitness.partial_cmp(&sol1.fitness).unwrap());
+        // Evaluated solutions are sorted ahead of unevaluated ones so that the best solution is an evaluated one if
+        // there are any.
+        meta.sort_unstable_by(|sol1, sol2| {
+            sol2.is_evaluated().cmp(&sol1.is_evaluated())
+                .then_with(|| sol2.score(variance_penalty).partial_cmp(&sol1.score(variance_penalty)).unwrap())
+        });
         for i in 0..num_sols {
-            if meta[i].num_samples < 2 || meta[0].num_samples < 2 {
+            if !meta[i].is_evaluated() || !meta[0].is_evaluated() {
                 meta[i].prob_worse_than_best = 0.0;
             } else {
                 meta[i].prob_worse_than_best = meta[i].prob_worse_than(&meta[0], rng);
                 if rng.gen_bool((1.0 - meta[i].prob_worse_than_best) / num_sols as f64) {
-                    let child = meta[i].team_build.mutated_child(rng);
+                    let child = meta[i].team_build.mutated_child_with_ev_step(ev_step, rng);
 
                     // Check if there is already a solution stored with the chosen team.
                     // If there isn't, create a new solution and add it to the meta.
                     if meta.iter().find(|sol| sol.team_build == child).is_none() {
-                        meta.push(Solution {
-                            fitness: -1.0,
-                            fit_variance: 0.0,
-                            num_samples: 0,
-                            prob_worse_than_best: 0.0,
-                            team_build: child
-                        });
+                        meta.push(Solution::with_team_build(child));
                     }
                 }
             }
         }
 
-        // Remove solutions that are not likely to be better than the best solution.
-        let p_cutoff = meta[0].fitness / 4.0 + 0.75;
-        meta.retain(|sol| sol.prob_worse_than_best < p_cutoff);
+        // Remove solutions that are not likely to be better than the best solution. The fittest solutions are always
+        // kept, since noisy comparisons could otherwise cull the best solution itself.
+        // Scores below -1, the lowest possible fitness, can only come from the variance penalty
+        let p_cutoff = meta[0].score(variance_penalty).max(-1.0) / 4.0 + 0.75;
+        let mut rank = 0;
+        meta.retain(|sol| {
+            rank += 1;
+            rank <= ELITE_COUNT || sol.prob_worse_than_best < p_cutoff
+        });
     }
 }
 
 impl From<&PokemonBuild> for Pokemon {
     fn from(pb: &PokemonBuild) -> Self {
-        Pokemon {
+        let mut pokemon = Pokemon {
+            id: 0,
             species: pb.species,
             first_type: Species::type1(pb.species),
             second_type: Species::type2(pb.species),
+            roost_types: None,
             gender: pb.gender,
             nature: pb.nature,
             ability: pb.ability,
+            item: pb.item,
             ivs: pb.ivs,
-            evs: pb.ivs,
+            evs: pb.evs,
             max_hp: pb.max_hp(),
+            unstaged_stats: [0; 5],
             current_hp: pb.max_hp(),
             stat_stages: [0; 8],
             major_status_ailment: MajorStatusAilment::Okay,
@@ -179,10 +527,29 @@ impl From<&PokemonBuild> for Pokemon {
             is_infatuated: false,
             is_cursed: false,
             has_nightmare: false,
+            is_identified: false,
+            is_trapped: false,
+            partial_trap_counter: Counter::new(None),
+            drowsy_counter: 0,
+            turns_on_field: 0,
+            is_protected: false,
+            protect_streak: 0,
+            consecutive_move_counter: 0,
+            semi_invulnerable: None,
+            rampage_counter: Counter::new(None),
+            last_move_used: None,
+            last_attacker: None,
+            last_attack_damage: None,
+            choice_locked: None,
+            mimic_slot: None,
+            transform_originals: None,
+            substitute_hp: None,
             field_position: None,
             known_moves: pb.moves.iter().map(|move_| MoveInstance::from(*move_)).collect(),
             next_move_action: None
-        }
+        };
+        pokemon.cache_unstaged_stats();
+        pokemon
     }
 }
 
@@ -194,11 +561,13 @@ pub struct PokemonBuild {
     pub gender: Gender,
     pub nature: Nature,
     pub ability: AbilityID,
+    pub item: Option<ItemID>,
     pub ivs: [u8; 6],
     /// EVs are assigned as 127 groups of 4 points each, totaling 508 points. This is two less than
     /// the actual limit of 510, but the extra two points are wasted anyways due to how stats are
     /// calculated. Furthermore, restricting the EVs to multiples of 4 reduces the number of
-    /// possible team builds by a factor of ~778 quadrillion.
+    /// possible team builds by a factor of ~778 quadrillion. Mutations keep the EVs in multiples of 4
+    /// unless they are made with an EV step of 1.
     pub evs: [u8; 6],
     /// Contains 1-4 moves.
     pub moves: Vec<MoveID>
@@ -206,10 +575,10 @@ pub struct PokemonBuild {
 
 impl PokemonBuild {
     pub const fn num_vars() -> usize {
-        20
+        21
     }
 
-    pub fn new(rng: &mut StdRng) -> PokemonBuild {
+    pub fn new<R: Rng>(rng: &mut R) -> PokemonBuild {
         let mut evs = [0; 6];
         let mut ev_sum = 0;
         while ev_sum < 508 {
@@ -221,11 +590,14 @@ impl PokemonBuild {
         }
 
         let species = Species::random_species(rng);
+        let moves = Species::random_move_set(species, rng);
+        debug_assert!(!moves.is_empty(), "A build must know at least one move.");
         PokemonBuild {
             species,
             gender: Species::random_gender(species, rng),
             nature: Nature::random_nature(rng),
             ability: Species::random_ability(species, rng),
+            item: Item::random_item(rng),
             ivs: [
                 rng.gen_range(0, 32),
                 rng.gen_range(0, 32),
@@ -235,10 +607,45 @@ impl PokemonBuild {
                 rng.gen_range(0, 32)
             ],
             evs,
-            moves: Species::random_move_set(species, rng),
+            moves,
         }
     }
 
+    /// Every legal build of a species with perfect IVs and no EVs, over all genders, natures, abilities, items and
+    /// sets of as many moves as it can know. The number of builds grows combinatorially with the size of the move
+    /// pool, so this is only feasible for tiny data sets, such as when brute-forcing the Solver on a toy problem.
+    pub fn enumerate(species: SpeciesID) -> impl Iterator<Item = PokemonBuild> {
+        let genders: Vec<Gender> = [Gender::Male, Gender::Female, Gender::None].iter().copied()
+            .filter(|gender| Species::can_have_gender(species, *gender))
+            .collect();
+        let items: Vec<Option<ItemID>> = iter::once(None).chain((0..Item::count()).map(|i| Some(i as ItemID))).collect();
+        let move_pool = Species::move_pool(species);
+        let move_sets = move_combinations(move_pool, move_pool.len().min(4));
+
+        let mut builds = Vec::new();
+        for &gender in &genders {
+            for &nature in Nature::all() {
+                for &ability in Species::abilities(species) {
+                    for &item in &items {
+                        for moves in &move_sets {
+                            builds.push(PokemonBuild {
+                                species,
+                                gender,
+                                nature,
+                                ability,
+                                item,
+                                ivs: [31; 6],
+                                evs: [0; 6],
+                                moves: moves.clone()
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        builds.into_iter()
+    }
+
     pub const fn species(&self) -> SpeciesID {
         self.species
     }
@@ -255,6 +662,10 @@ impl PokemonBuild {
         self.ability
     }
 
+    pub const fn item(&self) -> Option<ItemID> {
+        self.item
+    }
+
     pub const fn ivs(&self) -> &[u8] {
         &self.ivs
     }
@@ -263,13 +674,232 @@ impl PokemonBuild {
         &self.evs
     }
 
+    /// Fails without changing anything if the value is above 31.
+    pub fn set_iv(&mut self, stat_index: StatIndex, value: u8) -> Result<(), String> {
+        if stat_index.as_usize() >= 6 {
+            return Err(format!("{:?} has no IV", stat_index));
+        }
+        if value > 31 {
+            return Err(format!("IV of {} is above 31", value));
+        }
+        self.ivs[stat_index.as_usize()] = value;
+        Ok(())
+    }
+
+    /// Fails without changing anything if the value is above 252 or if it would bring the EV total above 508. Other EVs
+    /// are never lowered to make room; lower them first.
+    pub fn set_ev(&mut self, stat_index: StatIndex, value: u8) -> Result<(), String> {
+        if stat_index.as_usize() >= 6 {
+            return Err(format!("{:?} has no EV", stat_index));
+        }
+        if value > 252 {
+            return Err(format!("EV of {} is above 252", value));
+        }
+        let other_evs: u16 = self.evs.iter().enumerate()
+            .filter(|(i, _)| *i != stat_index.as_usize())
+            .map(|(_, &ev)| ev as u16)
+            .sum();
+        if other_evs + value as u16 > 508 {
+            return Err(format!("EV of {} would bring the EV total to {}, above 508", value, other_evs + value as u16));
+        }
+        self.evs[stat_index.as_usize()] = value;
+        Ok(())
+    }
+
     pub fn moves(&self) -> &[MoveID] {
         &self.moves
     }
 
+    /// Checks that this build could actually be used in the current game version.
+    pub fn validate(&self) -> Result<(), String> {
+        let species_name = Species::name(self.species);
+        if !Species::can_have_gender(self.species, self.gender) {
+            return Err(format!("{} cannot have gender {:?}", species_name, self.gender));
+        }
+        if !Species::abilities(self.species).contains(&self.ability) {
+            return Err(format!("{} cannot have ability '{}'", species_name, Ability::name(self.ability)));
+        }
+        check_ivs(&self.ivs).map_err(|e| format!("{} {}", species_name, e))?;
+        check_evs(&self.evs).map_err(|e| format!("{} {}", species_name, e))?;
+        if self.moves.is_empty() || self.moves.len() > 4 {
+            return Err(format!("{} must know 1-4 moves", species_name));
+        }
+        for (i, move_) in self.moves.iter().enumerate() {
+            if !Species::move_pool(self.species).contains(move_) {
+                return Err(format!("{} cannot learn '{}'", species_name, Move::name(*move_)));
+            }
+            if self.moves[..i].contains(move_) {
+                return Err(format!("{} knows '{}' more than once", species_name, Move::name(*move_)));
+            }
+        }
+        Ok(())
+    }
+
     pub fn max_hp(&self) -> u16 {
         2 * Species::base_stat(self.species, StatIndex::Hp) as u16 + self.ivs[StatIndex::Hp.as_usize()] as u16 + self.evs[StatIndex::Hp.as_usize()] as u16 / 4 + 110
     }
+
+    /// The stat at level 100 before any in-battle modifiers.
+    pub fn computed_stat(&self, stat_index: StatIndex) -> u16 {
+        match stat_index {
+            StatIndex::Hp => self.max_hp(),
+            _ => pokemon::unstaged_stat(self.species, self.nature, &self.ivs, &self.evs, stat_index)
+        }
+    }
+
+    /// HP times Defense, the usual measure of how well the build takes physical hits.
+    pub fn physical_bulk(&self) -> u32 {
+        self.computed_stat(StatIndex::Hp) as u32 * self.computed_stat(StatIndex::Def) as u32
+    }
+
+    /// HP times Special Defense, the usual measure of how well the build takes special hits.
+    pub fn special_bulk(&self) -> u32 {
+        self.computed_stat(StatIndex::Hp) as u32 * self.computed_stat(StatIndex::SpDef) as u32
+    }
+
+    /// Expected damage of this build's strongest move against the defender when both are fresh on the field, averaged
+    /// over the damage roll and the chance of a critical hit. Moves whose power depends on the battle, such as Low
+    /// Kick, aren't counted. 0 if no move can damage the defender.
+    pub fn avg_damage_vs(&self, defender: &PokemonBuild, weather: Weather) -> f64 {
+        let mut state = State::matchup_on_field(self, defender);
+        state.weather = weather;
+        self.moves.iter()
+            .filter(|&&move_| Move::category(move_) != MoveCategory::Status)
+            .filter_map(|&move_| {
+                let (damage_type, power, critical_hit_stage_bonus) = match Move::effects(move_).iter().find(|effect| effect.is_damaging())? {
+                    MoveEffect::StdDamage(damage_type, power, critical_hit_stage_bonus) => (*damage_type, *power, *critical_hit_stage_bonus),
+                    effect => (Move::type_(move_), effect.power()?, 0)
+                };
+                Some(move_effects::expected_damage(&state, 6, 0, damage_type, Move::category(move_), power as u16, critical_hit_stage_bonus))
+            })
+            .fold(0.0, f64::max)
+    }
+}
+
+/// Checks that every IV is at most 31.
+fn check_ivs(ivs: &[u8; 6]) -> Result<(), String> {
+    match ivs.iter().position(|&iv| iv > 31) {
+        Some(i) => Err(format!("has IV {} in stat {}, above 31", ivs[i], i)),
+        None => Ok(())
+    }
+}
+
+/// Checks that every EV is no higher than 252, and that they total at most 508.
+fn check_evs(evs: &[u8; 6]) -> Result<(), String> {
+    if let Some(i) = evs.iter().position(|&ev| ev > 252) {
+        return Err(format!("has EV {} in stat {}, which is above 252", evs[i], i));
+    }
+    let total: u16 = evs.iter().map(|&ev| ev as u16).sum();
+    if total > 508 {
+        return Err(format!("has an EV total of {}, above 508", total));
+    }
+    Ok(())
+}
+
+/// All ways to choose `size` moves from `move_pool`, keeping the moves in move pool order.
+fn move_combinations(move_pool: &[MoveID], size: usize) -> Vec<Vec<MoveID>> {
+    if size == 0 {
+        return vec![Vec::new()];
+    }
+
+    let mut combinations = Vec::new();
+    for (i, &move_) in move_pool.iter().enumerate() {
+        for mut rest in move_combinations(&move_pool[i + 1..], size - 1) {
+            rest.insert(0, move_);
+            combinations.push(rest);
+        }
+    }
+    combinations
+}
+
+/// Creates a specific `PokemonBuild`. Anything left unset gets a default: the species' first ability,
+/// the first possible gender out of male, female and genderless, a neutral nature, no item, perfect IVs, no EVs and the first 4 moves of its
+/// move pool.
+pub struct PokemonBuildBuilder {
+    species: SpeciesID,
+    gender: Option<Gender>,
+    nature: Nature,
+    ability: Option<AbilityID>,
+    item: Option<ItemID>,
+    ivs: [u8; 6],
+    evs: [u8; 6],
+    moves: Option<Vec<MoveID>>
+}
+
+impl PokemonBuildBuilder {
+    pub const fn new(species: SpeciesID) -> PokemonBuildBuilder {
+        PokemonBuildBuilder {
+            species,
+            gender: None,
+            nature: Nature::Hardy,
+            ability: None,
+            item: None,
+            ivs: [31; 6],
+            evs: [0; 6],
+            moves: None
+        }
+    }
+
+    pub fn species(mut self, species: SpeciesID) -> PokemonBuildBuilder {
+        self.species = species;
+        self
+    }
+
+    pub fn gender(mut self, gender: Gender) -> PokemonBuildBuilder {
+        self.gender = Some(gender);
+        self
+    }
+
+    pub fn nature(mut self, nature: Nature) -> PokemonBuildBuilder {
+        self.nature = nature;
+        self
+    }
+
+    pub fn ability(mut self, ability: AbilityID) -> PokemonBuildBuilder {
+        self.ability = Some(ability);
+        self
+    }
+
+    pub fn item(mut self, item: Option<ItemID>) -> PokemonBuildBuilder {
+        self.item = item;
+        self
+    }
+
+    pub fn ivs(mut self, ivs: [u8; 6]) -> PokemonBuildBuilder {
+        self.ivs = ivs;
+        self
+    }
+
+    pub fn evs(mut self, evs: [u8; 6]) -> PokemonBuildBuilder {
+        self.evs = evs;
+        self
+    }
+
+    pub fn moves(mut self, moves: Vec<MoveID>) -> PokemonBuildBuilder {
+        self.moves = Some(moves);
+        self
+    }
+
+    pub fn build(self) -> Result<PokemonBuild, String> {
+        let species = self.species;
+        let gender = self.gender.unwrap_or_else(|| {
+            [Gender::Male, Gender::Female, Gender::None].iter().copied()
+                .find(|gender| Species::can_have_gender(species, *gender))
+                .unwrap()
+        });
+        let pokemon_build = PokemonBuild {
+            species,
+            gender,
+            nature: self.nature,
+            ability: self.ability.unwrap_or_else(|| Species::abilities(species)[0]),
+            item: self.item,
+            ivs: self.ivs,
+            evs: self.evs,
+            moves: self.moves.unwrap_or_else(|| Species::move_pool(species).iter().copied().take(4).collect())
+        };
+        pokemon_build.validate()?;
+        Ok(pokemon_build)
+    }
 }
 
 impl PartialEq for PokemonBuild {
@@ -283,6 +913,7 @@ impl PartialEq for PokemonBuild {
             && self.gender == other.gender
             && self.nature == other.nature
             && self.ability == other.ability
+            && self.item == other.item
             && self.ivs == other.ivs
             && self.evs == other.evs
     }
@@ -292,31 +923,65 @@ impl TryFrom<PokemonBuildSerde<'_>> for PokemonBuild {
     type Error = String;
 
     fn try_from(pb_serde: PokemonBuildSerde) -> Result<Self, Self::Error> {
+        let species_name = pb_serde.species;
+        let ivs = pb_serde.ivs.into_array(31)?;
+        let evs = pb_serde.evs.into_array(0)?;
+        check_ivs(&ivs).map_err(|e| format!("{} {}", species_name, e))?;
+        check_evs(&evs).map_err(|e| format!("{} {}", species_name, e))?;
         Ok(PokemonBuild {
             species: Species::id_by_name(pb_serde.species)?,
             gender: pb_serde.gender,
             nature: pb_serde.nature,
             ability: Ability::id_by_name(pb_serde.ability)?,
-            ivs: pb_serde.ivs,
-            evs: pb_serde.evs,
-            moves: vec![
-                Move::id_by_name(pb_serde.move1)?,
-                Move::id_by_name(pb_serde.move2)?,
-                Move::id_by_name(pb_serde.move3)?,
-                Move::id_by_name(pb_serde.move4)?
-            ]
+            item: pb_serde.item.filter(|item| !item.is_empty()).map(Item::id_by_name).transpose()?,
+            ivs,
+            evs,
+            // Builds with fewer than 4 moves are saved with the rest left blank
+            moves: [pb_serde.move1, pb_serde.move2, pb_serde.move3, pb_serde.move4].iter()
+                .filter(|move_name| !move_name.is_empty())
+                .map(|move_name| Move::id_by_name(move_name))
+                .collect::<Result<_, _>>()?
         })
     }
 }
 
+/// IVs or EVs in a team file, either as an array in stat order or as a map from short stat names (see
+/// `StatIndex::by_short_name`) to values, e.g. `{"atk": 252, "spe": 252, "hp": 4}`. Builds are always saved in the
+/// array form.
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum StatSpread {
+    Positional([u8; 6]),
+    Named(HashMap<String, u8>)
+}
+
+impl StatSpread {
+    /// Stats missing from a named spread get `default`.
+    fn into_array(self, default: u8) -> Result<[u8; 6], String> {
+        match self {
+            StatSpread::Positional(values) => Ok(values),
+            StatSpread::Named(named_values) => {
+                let mut values = [default; 6];
+                for (name, value) in named_values {
+                    values[StatIndex::by_short_name(&name)?.as_usize()] = value;
+                }
+                Ok(values)
+            }
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 struct PokemonBuildSerde<'d> {
     species: &'d str,
     gender: Gender,
     nature: Nature,
     ability: &'d str,
-    ivs: [u8; 6],
-    evs: [u8; 6],
+    /// Builds saved before items existed have no item.
+    #[serde(default, borrow)]
+    item: Option<&'d str>,
+    ivs: StatSpread,
+    evs: StatSpread,
     move1: &'d str,
     move2: &'d str,
     move3: &'d str,
@@ -331,8 +996,9 @@ impl From<PokemonBuild> for PokemonBuildSerde<'_> {
             gender: pokemon_build.gender,
             nature: pokemon_build.nature,
             ability: Ability::name(pokemon_build.ability),
-            ivs: pokemon_build.ivs,
-            evs: pokemon_build.evs,
+            item: pokemon_build.item.map(Item::name),
+            ivs: StatSpread::Positional(pokemon_build.ivs),
+            evs: StatSpread::Positional(pokemon_build.evs),
             move1: moves.get(0).map(|&move_| Move::name(move_)).unwrap_or(""),
             move2: moves.get(1).map(|&move_| Move::name(move_)).unwrap_or(""),
             move3: moves.get(2).map(|&move_| Move::name(move_)).unwrap_or(""),
@@ -341,17 +1007,136 @@ impl From<PokemonBuild> for PokemonBuildSerde<'_> {
     }
 }
 
+/// Fields that each solution's fitness statistics take up at the start of a row of an exported meta.
+const CSV_SOLUTION_FIELDS: usize = 4;
+/// Fields that each team member takes up in a row of an exported meta, in the order of `PokemonBuildSerde`.
+const CSV_POKEMON_FIELDS: usize = 21;
+
+fn team_build_from_csv_record(record: &StringRecord) -> Result<TeamBuild, String> {
+    let pokemon_fields: Vec<&str> = record.iter().skip(CSV_SOLUTION_FIELDS).collect();
+    if !pokemon_fields.len().is_multiple_of(CSV_POKEMON_FIELDS) {
+        return Err(format!("has {} fields, but each team member takes {} after the first {}", record.len(), CSV_POKEMON_FIELDS, CSV_SOLUTION_FIELDS));
+    }
+    let members = pokemon_fields.chunks(CSV_POKEMON_FIELDS)
+        .map(pokemon_build_from_csv_fields)
+        .collect::<Result<_, _>>()?;
+    Ok(TeamBuild { members })
+}
+
+fn pokemon_build_from_csv_fields(fields: &[&str]) -> Result<PokemonBuild, String> {
+    let stat_spread = |start: usize| -> Result<StatSpread, String> {
+        let mut values = [0; 6];
+        for (value, field) in values.iter_mut().zip(&fields[start..start + 6]) {
+            *value = field.parse().map_err(|_| format!("'{}' is not a valid IV or EV", field))?;
+        }
+        Ok(StatSpread::Positional(values))
+    };
+    PokemonBuild::try_from(PokemonBuildSerde {
+        species: fields[0],
+        gender: unit_variant_from_csv_field(fields[1])?,
+        nature: unit_variant_from_csv_field(fields[2])?,
+        ability: fields[3],
+        item: Some(fields[4]),
+        ivs: stat_spread(5)?,
+        evs: stat_spread(11)?,
+        move1: fields[17],
+        move2: fields[18],
+        move3: fields[19],
+        move4: fields[20]
+    })
+}
+
+/// Parses an enum such as `Gender` from the variant name that it was exported as.
+fn unit_variant_from_csv_field<'a, T: Deserialize<'a>>(field: &'a str) -> Result<T, String> {
+    T::deserialize(field.into_deserializer()).map_err(|err: serde::de::value::Error| err.to_string())
+}
+
 #[derive(Clone, Debug, Eq, Deserialize, Serialize)]
 pub struct TeamBuild {
-    pub members: [PokemonBuild; 6]
+    pub members: Vec<PokemonBuild>
 }
 
 impl TeamBuild {
-    pub const fn num_vars() -> usize {
-        PokemonBuild::num_vars() * 6
+    pub fn num_vars(&self) -> usize {
+        PokemonBuild::num_vars() * self.members.len()
+    }
+
+    pub fn new<R: Rng>(rng: &mut R) -> TeamBuild {
+        TeamBuild::with_size(6, rng)
+    }
+
+    pub fn members(&self) -> &[PokemonBuild] {
+        &self.members
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, PokemonBuild> {
+        self.members.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, PokemonBuild> {
+        self.members.iter_mut()
+    }
+
+    /// Each member's species and Speed before any in-battle modifiers, fastest first.
+    pub fn speed_tiers(&self) -> Vec<(SpeciesID, u32)> {
+        let mut speed_tiers: Vec<(SpeciesID, u32)> = self.members.iter()
+            .map(|member| (member.species, member.computed_stat(StatIndex::Spd) as u32))
+            .collect();
+        speed_tiers.sort_by(|(_, spd1), (_, spd2)| spd2.cmp(spd1));
+        speed_tiers
+    }
+
+    /// Two teams are equal if their leads are equal and the rest of their team members are found
+    /// on both teams, in any order. The lead is separate since it is always the first Pokemon sent
+    /// out. The rest of the team can be freely switched in and out of battle. `PartialEq` compares
+    /// teams as if both were led by their first member.
+    pub fn eq_with_leads(&self, lead: usize, other: &TeamBuild, other_lead: usize) -> bool {
+        if self.members.len() != other.members.len() || self.members[lead] != other.members[other_lead] {
+            return false;
+        }
+        // Each of the other team's members can only be matched once, so that teams with duplicate
+        // members are compared correctly
+        let mut matched = vec![false; other.members.len()];
+        matched[other_lead] = true;
+        self.members.iter().enumerate()
+            .filter(|&(i, _)| i != lead)
+            .all(|(_, team_member)| {
+                match other.members.iter().enumerate().position(|(j, b)| !matched[j] && b == team_member) {
+                    Some(j) => {
+                        matched[j] = true;
+                        true
+                    }
+                    None => false
+                }
+            })
+    }
+
+    /// Each member's species, physical bulk and special bulk, in team order.
+    pub fn bulk_profile(&self) -> Vec<(SpeciesID, u32, u32)> {
+        self.members.iter()
+            .map(|member| (member.species, member.physical_bulk(), member.special_bulk()))
+            .collect()
+    }
+
+    /// Loaded species that no member can hit super effectively with a damaging move of one of its own types, i.e.
+    /// one that gets STAB. These are the species that wall the team.
+    pub fn uncovered_species(&self) -> Vec<SpeciesID> {
+        let stab_move_types: Vec<Type> = self.members.iter()
+            .flat_map(|member| member.moves.iter()
+                .filter(|&&move_| Move::category(move_) != MoveCategory::Status)
+                .map(|&move_| Move::type_(move_))
+                .filter(move |&move_type| move_type == Species::type1(member.species) || move_type == Species::type2(member.species)))
+            .collect();
+        (0..Species::count())
+            .filter(|&species| !stab_move_types.iter().any(|move_type| move_type.effectiveness(Species::type1(species), Species::type2(species)) > 1.1))
+            .collect()
     }
 
-    pub fn new(rng: &mut StdRng) -> TeamBuild {
+    pub fn with_size<R: Rng>(team_size: usize, rng: &mut R) -> TeamBuild {
+        if !(1..=6).contains(&team_size) {
+            panic!("Team size must be between 1 and 6. Given team size: {}", team_size);
+        }
+
         let mut non_duplicates = Vec::new();
         let mut new_member = || -> PokemonBuild {
             let mut result = PokemonBuild::new(rng);
@@ -365,31 +1150,36 @@ impl TeamBuild {
         };
 
         TeamBuild {
-            members: [
-                new_member(),
-                new_member(),
-                new_member(),
-                new_member(),
-                new_member(),
-                new_member()
-            ]
+            members: iter::repeat_with(new_member).take(team_size).collect()
         }
     }
 
-    pub fn mutated_child(&self, rng: &mut StdRng) -> TeamBuild {
-        let member_num = rng.gen_range(0, 6);
+    pub fn mutated_child<R: Rng>(&self, rng: &mut R) -> TeamBuild {
+        self.mutated_child_with_ev_step(4, rng)
+    }
+
+    /// Same as `mutated_child`, except that EV transfers move `ev_step` points, which must be 1 or 4. Since a stat
+    /// only changes every 4 EVs, a step of 1 often gives an equivalent build, but it lets spreads with leftover points
+    /// be tuned toward exact benchmarks.
+    pub fn mutated_child_with_ev_step<R: Rng>(&self, ev_step: u8, rng: &mut R) -> TeamBuild {
+        if ev_step != 1 && ev_step != 4 {
+            panic!("EV step must be 1 or 4. Given EV step: {}", ev_step);
+        }
+
+        let member_num = rng.gen_range(0, self.members.len());
         let build_to_mutate = &self.members[member_num];
 
         // Each variable's mutation rate is proportional to the number of other choices for that variable.
         let mutation_rates = [
             (
-                Species::count() as usize - 1
+                Species::buildable_species().len() - 1
                     - self.members.iter().filter(|b| !Species::allow_duplicates(b.species)).count()
                     + if !Species::allow_duplicates(build_to_mutate.species) { 1 } else { 0 }
             ) as f64,
             if Species::has_male_and_female(build_to_mutate.species) { 1.0 } else { 0.0 },
             24.0,
             (Species::abilities(build_to_mutate.species).len() - 1) as f64,
+            Item::count() as f64,
             31.0 * 6.0,
             30.0 + 30.0,
             {
@@ -424,13 +1214,19 @@ impl TeamBuild {
                 }
             },
             4 => {
+                let old_item = child_build.item;
+                while child_build.item == old_item {
+                    child_build.item = Item::random_item(rng);
+                }
+            },
+            5 => {
                 let i = rng.gen_range(0, 6);
                 let old_iv = child_build.ivs[i];
                 while child_build.ivs[i] == old_iv {
                     child_build.ivs[i] = rng.gen_range(0, 32);
                 }
             },
-            5 => {
+            6 => {
                 if rng.gen_bool(0.5) {
                     let i = rng.gen_range(0, 6);
                     let mut j = rng.gen_range(0, 6);
@@ -440,15 +1236,15 @@ impl TeamBuild {
                     child_build.evs.swap(i, j);
                 } else {
                     let mut from = rng.gen_range(0, 6);
-                    while child_build.evs[from] < 4 {
+                    while child_build.evs[from] < ev_step {
                         from = rng.gen_range(0, 6);
                     }
                     let mut to = rng.gen_range(0, 6);
-                    while to == from || child_build.evs[to] >= 252 {
+                    while to == from || child_build.evs[to] > 252 - ev_step {
                         to = rng.gen_range(0, 6);
                     }
-                    child_build.evs[from] -= 4;
-                    child_build.evs[to] += 4;
+                    child_build.evs[from] -= ev_step;
+                    child_build.evs[to] += ev_step;
                 }
             },
             _ => {
@@ -462,19 +1258,696 @@ impl TeamBuild {
         }
         child
     }
+
+    /// Lists the differences from this team to `other`, comparing members in the same positions.
+    pub fn diff(&self, other: &TeamBuild) -> Vec<TeamDiff> {
+        let mut diffs = Vec::new();
+        if self.members.len() != other.members.len() {
+            diffs.push(TeamDiff::Size(self.members.len(), other.members.len()));
+        }
+
+        for (member, (old, new)) in self.members.iter().zip(other.members.iter()).enumerate() {
+            if old.species != new.species { diffs.push(TeamDiff::Species(member, old.species, new.species)); }
+            if old.gender != new.gender { diffs.push(TeamDiff::Gender(member, old.gender, new.gender)); }
+            if old.nature != new.nature { diffs.push(TeamDiff::Nature(member, old.nature, new.nature)); }
+            if old.ability != new.ability { diffs.push(TeamDiff::Ability(member, old.ability, new.ability)); }
+            if old.item != new.item { diffs.push(TeamDiff::Item(member, old.item, new.item)); }
+            for i in 0..6 {
+                if old.ivs[i] != new.ivs[i] { diffs.push(TeamDiff::Iv(member, i, old.ivs[i], new.ivs[i])); }
+                if old.evs[i] != new.evs[i] { diffs.push(TeamDiff::Ev(member, i, old.evs[i], new.evs[i])); }
+            }
+
+            // Move order doesn't matter, so pair up each removed move with an added one.
+            let mut removed = old.moves.iter().copied().filter(|move_| !new.moves.contains(move_));
+            let mut added = new.moves.iter().copied().filter(|move_| !old.moves.contains(move_));
+            loop {
+                match (removed.next(), added.next()) {
+                    (None, None) => break,
+                    (old_move, new_move) => diffs.push(TeamDiff::Move(member, old_move, new_move))
+                }
+            }
+        }
+
+        diffs
+    }
+
+    /// Checks that each member is valid and that the team itself is allowed.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.members.is_empty() || self.members.len() > 6 {
+            return Err(format!("team must have 1-6 members, but has {}", self.members.len()));
+        }
+        for (i, member) in self.members.iter().enumerate() {
+            member.validate()?;
+            if !Species::allow_duplicates(member.species) && self.members[..i].iter().any(|b| b.species == member.species) {
+                return Err(format!("team has more than one {}", Species::name(member.species)));
+            }
+        }
+        Ok(())
+    }
+
+    /// For each defending type, the best effectiveness that any team member's damaging moves can
+    /// achieve against it.
+    pub fn offensive_coverage(&self) -> HashMap<Type, f64> {
+        let mut coverage = HashMap::new();
+        for &defending_type in Type::all() {
+            let best = self.members.iter()
+                .flat_map(|member| member.moves.iter())
+                .filter(|&&move_| Move::category(move_) != MoveCategory::Status)
+                .map(|&move_| Move::type_(move_).effectiveness(defending_type, Type::None))
+                .fold(0.0, f64::max);
+            coverage.insert(defending_type, best);
+        }
+        coverage
+    }
+
+    /// For each attacking type, the highest effectiveness the team takes from it.
+    pub fn defensive_profile(&self) -> HashMap<Type, f64> {
+        let mut profile = HashMap::new();
+        for &attacking_type in Type::all() {
+            let worst = self.members.iter()
+                .map(|member| attacking_type.effectiveness(Species::type1(member.species), Species::type2(member.species)))
+                .fold(0.0, f64::max);
+            profile.insert(attacking_type, worst);
+        }
+        profile
+    }
+}
+
+impl<'a> IntoIterator for &'a TeamBuild {
+    type Item = &'a PokemonBuild;
+    type IntoIter = slice::Iter<'a, PokemonBuild>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.members.iter()
+    }
+}
+
+/// A single difference between two team builds; see `TeamBuild::diff`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TeamDiff {
+    /// (old_size: usize, new_size: usize)
+    Size(usize, usize),
+    /// (member: usize, old: SpeciesID, new: SpeciesID)
+    Species(usize, SpeciesID, SpeciesID),
+    /// (member: usize, old: Gender, new: Gender)
+    Gender(usize, Gender, Gender),
+    /// (member: usize, old: Nature, new: Nature)
+    Nature(usize, Nature, Nature),
+    /// (member: usize, old: AbilityID, new: AbilityID)
+    Ability(usize, AbilityID, AbilityID),
+    /// (member: usize, old: Option<ItemID>, new: Option<ItemID>)
+    Item(usize, Option<ItemID>, Option<ItemID>),
+    /// (member: usize, stat_index: usize, old: u8, new: u8)
+    Iv(usize, usize, u8, u8),
+    /// (member: usize, stat_index: usize, old: u8, new: u8)
+    Ev(usize, usize, u8, u8),
+    /// (member: usize, old: Option<MoveID>, new: Option<MoveID>)
+    Move(usize, Option<MoveID>, Option<MoveID>)
 }
 
 impl PartialEq for TeamBuild {
     fn eq(&self, other: &Self) -> bool {
-        // Two teams are equal if their party leaders are equal and the rest of their team members
-        // are found on both teams, in any order. The party leader is separate since they are always
-        // the first Pokemon sent out. The rest of the team can be freely switched in and out of
-        // battle.
-        for team_member in self.members[1..6].iter() {
-            if !other.members[1..6].contains(team_member) {
-                return false;
+        self.eq_with_leads(0, other, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn mirror_matches_are_scored_zero_without_a_battle() {
+        let _data = crate::load_test_data();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut solver = Solver::from_seed_teams(vec![crate::test_team(1)], vec![crate::test_team(1)], 1, &mut rng).unwrap();
+        solver.set_interaction_density(1.0).unwrap();
+
+        solver.do_iter(&mut rng);
+        assert_eq!(solver.fitness_func_evals(), 0);
+        let mirrored = solver.maximizer_meta().iter().find(|sol| sol.team_build == crate::test_team(1)).unwrap();
+        assert_eq!(mirrored.fitness, 0.0);
+        assert_eq!(mirrored.num_samples, 1);
+    }
+
+    #[test]
+    fn grass_moves_leave_a_gap_against_steel() {
+        let _data = crate::load_test_data();
+        let coverage = crate::test_team(1).offensive_coverage();
+        assert_eq!(coverage[&Type::Water], 2.0);
+        assert_eq!(coverage[&Type::Steel], 0.5);
+    }
+
+    #[test]
+    fn defensive_profile_has_the_grass_poison_weaknesses() {
+        let _data = crate::load_test_data();
+        let profile = crate::test_team(1).defensive_profile();
+        assert_eq!(profile[&Type::Fire], 2.0);
+        assert_eq!(profile[&Type::Psychic], 2.0);
+        assert_eq!(profile[&Type::Grass], 0.25);
+    }
+
+    #[test]
+    fn single_point_ev_mutations_keep_the_ev_limits() {
+        let _data = crate::load_test_data();
+        let mut rng = StdRng::seed_from_u64(0);
+        let species = Species::id_by_name("Bulbasaur").unwrap();
+        let mut team = TeamBuild { members: vec![PokemonBuildBuilder::new(species).evs([252, 252, 4, 0, 0, 0]).build().unwrap()] };
+        let mut saw_single_point_transfer = false;
+        for _ in 0..1000 {
+            team = team.mutated_child_with_ev_step(1, &mut rng);
+            team.validate().unwrap();
+            let evs = team.members[0].evs();
+            assert_eq!(evs.iter().map(|&ev| ev as u16).sum::<u16>(), 508);
+            saw_single_point_transfer |= evs.iter().any(|&ev| ev % 4 != 0);
+        }
+        assert!(saw_single_point_transfer);
+    }
+
+    #[test]
+    fn seed_teams_start_in_the_meta_and_a_winning_one_survives() {
+        let _data = crate::load_test_data();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut tackle_team = crate::test_team(1);
+        tackle_team.members[0].moves = vec![Move::id_by_name("Tackle").unwrap()];
+        let mut growl_team = crate::test_team(1);
+        growl_team.members[0].moves = vec![Move::id_by_name("Growl").unwrap()];
+
+        let mut solver = Solver::from_seed_teams(vec![tackle_team.clone()], vec![growl_team.clone()], 4, &mut rng).unwrap();
+        assert_eq!(solver.maximizer_meta()[0].team_build, tackle_team);
+        assert_eq!(solver.minimizer_meta()[0].team_build, growl_team);
+        assert!(solver.maximizer_meta().len() >= 4);
+
+        solver.set_interaction_density(1.0).unwrap();
+        solver.do_iter(&mut rng);
+        assert!(solver.maximizer_meta().iter().any(|sol| sol.team_build == tackle_team));
+    }
+
+    #[test]
+    fn seed_teams_of_different_sizes_are_rejected() {
+        let _data = crate::load_test_data();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut pair = crate::test_team(1);
+        pair.members.push(pair.members[0].clone());
+
+        let error = Solver::from_seed_teams(vec![crate::test_team(1)], vec![pair], 4, &mut rng).err().unwrap();
+        assert!(error.contains("same size"), "{}", error);
+    }
+
+    #[test]
+    fn speed_tiers_list_every_member_fastest_first() {
+        let _data = crate::load_test_data();
+        let species = Species::id_by_name("Bulbasaur").unwrap();
+        let team = TeamBuild {
+            members: [80, 0, 252, 40, 120, 200].iter()
+                .map(|&spd_evs| PokemonBuildBuilder::new(species).evs([0, 0, 0, 0, 0, spd_evs]).build().unwrap())
+                .collect()
+        };
+
+        let speed_tiers = team.speed_tiers();
+        assert_eq!(speed_tiers.len(), 6);
+        assert!(speed_tiers.windows(2).all(|pair| pair[0].1 > pair[1].1), "{:?}", speed_tiers);
+        assert_eq!(speed_tiers[0].1, team.members[2].computed_stat(StatIndex::Spd) as u32);
+    }
+
+    #[test]
+    fn variance_penalty_ranks_the_consistent_team_first() {
+        let _data = crate::load_test_data();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut streaky = Solution::with_team_build(crate::test_team(1));
+        for fitness_sample in [1.0, 0.0, 1.0, 0.0] {
+            streaky.update(fitness_sample);
+        }
+        let mut consistent_team = crate::test_team(1);
+        consistent_team.members[0].moves = vec![Move::id_by_name("Tackle").unwrap()];
+        let mut consistent = Solution::with_team_build(consistent_team.clone());
+        for fitness_sample in [0.5, 0.4, 0.6, 0.5] {
+            consistent.update(fitness_sample);
+        }
+        let mut meta = vec![streaky, consistent];
+
+        Solver::update_meta(&mut meta, 4, 1.0, &mut rng);
+        assert!(meta[0].team_build == consistent_team);
+    }
+
+    #[test]
+    fn meta_size_stays_near_the_configured_pop_size() {
+        let _data = crate::load_test_data();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut solver = Solver::with_config(10, 1, &mut rng);
+        assert_eq!(solver.maximizer_meta().len(), 10);
+        assert_eq!(solver.minimizer_meta().len(), 10);
+        assert!(solver.maximizer_meta().iter().all(|sol| sol.team_build.members.len() == 1));
+
+        solver.set_interaction_density(0.05).unwrap();
+        for _ in 0..3 {
+            solver.do_iter(&mut rng);
+            for meta in [solver.maximizer_meta(), solver.minimizer_meta()] {
+                assert!((ELITE_COUNT..=20).contains(&meta.len()), "meta of {} solutions", meta.len());
             }
         }
-        self.members[0] == other.members[0]
+    }
+
+    #[test]
+    fn run_until_stops_once_the_budget_is_spent() {
+        let _data = crate::load_test_data();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut solver = Solver::with_config(4, 1, &mut rng);
+
+        let best = solver.run_until(10, &mut rng);
+        assert!(solver.fitness_func_evals() >= 10);
+        assert_eq!(best.validate(), Ok(()));
+        assert!(solver.maximizer_meta().iter().any(|sol| sol.team_build == best));
+    }
+
+    #[test]
+    fn builder_makes_a_competitive_spread() {
+        let _data = crate::load_test_data();
+        let build = PokemonBuildBuilder::new(Species::id_by_name("Bulbasaur").unwrap())
+            .nature(Nature::Modest)
+            .evs([4, 0, 0, 252, 0, 252])
+            .build()
+            .unwrap();
+
+        let stats = [StatIndex::Hp, StatIndex::Atk, StatIndex::Def, StatIndex::SpAtk, StatIndex::SpDef, StatIndex::Spd]
+            .map(|stat_index| build.computed_stat(stat_index));
+        assert_eq!(stats, [232, 120, 134, 251, 166, 189]);
+    }
+
+    #[test]
+    fn diff_reports_a_single_changed_variable() {
+        let _data = crate::load_test_data();
+        let team = crate::test_team(2);
+        let mut changed = team.clone();
+        changed.members[1].nature = Nature::Modest;
+
+        assert_eq!(team.diff(&changed), vec![TeamDiff::Nature(1, Nature::Hardy, Nature::Modest)]);
+        assert!(team.diff(&team).is_empty());
+    }
+
+    #[test]
+    fn mutated_child_differs_in_one_variable() {
+        let _data = crate::load_test_data();
+        let mut rng = StdRng::seed_from_u64(0);
+        let team = TeamBuild::with_size(2, &mut rng);
+        for _ in 0..50 {
+            let diffs = team.diff(&team.mutated_child(&mut rng));
+            // Moving EVs between stats is one mutation, but changes two of them
+            match diffs.as_slice() {
+                [_] => {},
+                [TeamDiff::Ev(member1, ..), TeamDiff::Ev(member2, ..)] => assert_eq!(member1, member2),
+                _ => panic!("{:?}", diffs)
+            }
+        }
+    }
+
+    #[test]
+    fn battle_stats_come_from_the_builds_evs() {
+        let _data = crate::load_test_data();
+        let build = PokemonBuildBuilder::new(Species::id_by_name("Bulbasaur").unwrap())
+            .ivs([31, 0, 31, 31, 31, 31])
+            .evs([4, 0, 0, 252, 0, 252])
+            .build()
+            .unwrap();
+
+        let pokemon = Pokemon::from(&build);
+        for stat_index in [StatIndex::Atk, StatIndex::Def, StatIndex::SpAtk, StatIndex::SpDef, StatIndex::Spd] {
+            assert_eq!(pokemon.unstaged_stats[stat_index.as_usize() - 1], build.computed_stat(stat_index), "{:?}", stat_index);
+        }
+    }
+
+    #[test]
+    fn team_iterates_over_its_members_in_order() {
+        let _data = crate::load_test_data();
+        let mut team = crate::test_team(3);
+        let bulbasaur = team.members[0].species;
+        for (i, member) in team.iter_mut().enumerate() {
+            member.species = bulbasaur + i as SpeciesID;
+        }
+
+        let expected: Vec<SpeciesID> = (0..3).map(|i| bulbasaur + i).collect();
+        assert_eq!(team.iter().map(|member| member.species).collect::<Vec<_>>(), expected);
+        assert_eq!((&team).into_iter().map(|member| member.species).collect::<Vec<_>>(), expected);
+        assert_eq!(team.members().len(), 3);
+    }
+
+    #[test]
+    fn interaction_density_sets_the_battles_per_iteration() {
+        let _data = crate::load_test_data();
+        let evals_at_density = |interaction_density| {
+            let mut rng = StdRng::seed_from_u64(0);
+            let mut solver = Solver::with_config(2, 1, &mut rng);
+            solver.set_interaction_density(interaction_density).unwrap();
+            solver.do_iter(&mut rng);
+            solver.fitness_func_evals()
+        };
+
+        // None of the generated teams mirror each other, so full density battles every pairing
+        assert_eq!(evals_at_density(1.0), 4);
+        assert!(evals_at_density(0.25) < 4);
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(Solver::with_config(2, 1, &mut rng).set_interaction_density(0.0).is_err());
+    }
+
+    #[test]
+    fn best_solution_survives_its_own_cutoff() {
+        let _data = crate::load_test_data();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut best = Solution::with_team_build(crate::test_team(1));
+        // A team that always loses sets a cutoff of 0.5, which its even odds against itself would reach
+        best.update(-1.0);
+        best.update(-1.0);
+        let mut meta = vec![best.clone()];
+        for i in 1..4 {
+            let mut team = crate::test_team(1);
+            team.members[0].evs[0] = 4 * i;
+            meta.push(Solution::with_team_build(team));
+        }
+
+        for _ in 0..10 {
+            Solver::update_meta(&mut meta, 4, 0.0, &mut rng);
+            assert!(meta.iter().any(|sol| sol.team_build == best.team_build));
+        }
+    }
+
+    #[test]
+    fn fresh_solution_is_not_culled_until_it_has_enough_samples() {
+        let _data = crate::load_test_data();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut meta: Vec<Solution> = (0..5).map(|i| {
+            let mut team = crate::test_team(1);
+            team.members[0].evs[0] = 4 * i;
+            Solution::with_team_build(team)
+        }).collect();
+        for sol in meta.iter_mut().skip(1) {
+            sol.update(0.5);
+            sol.update(0.5);
+        }
+        let fresh = meta[0].team_build.clone();
+        meta[0].update(-1.0);
+
+        for _ in 0..10 {
+            Solver::update_meta(&mut meta, 4, 0.0, &mut rng);
+            assert!(meta.iter().any(|sol| sol.team_build == fresh));
+        }
+        // A second loss makes it comparable, and it is clearly worse than the rest
+        meta.iter_mut().find(|sol| sol.team_build == fresh).unwrap().update(-1.0);
+        Solver::update_meta(&mut meta, 4, 0.0, &mut rng);
+        assert!(meta.iter().all(|sol| sol.team_build != fresh));
+    }
+
+    #[test]
+    fn enumeration_covers_every_combination_of_choices() {
+        let _data = crate::load_test_data();
+        let species_json = r#"[{"name": "Bulbasaur", "type1": "Grass", "type2": "Poison", "abilities": ["Overgrow"],
+            "base_stats": [45, 49, 49, 65, 65, 45], "weight": 69, "male_chance": 1000, "female_chance": 0, "allow_duplicates": true,
+            "move_pool": ["Growl", "Razor Leaf", "Tackle", "Toxic", "Vine Whip"]}]"#;
+        let mut species: Vec<Species> = serde_json::from_str(species_json).unwrap();
+
+        crate::battle_ai::species::swap_species(&mut species);
+        let builds: Vec<PokemonBuild> = PokemonBuild::enumerate(0).collect();
+        let all_valid = builds.iter().all(|build| build.validate().is_ok());
+        crate::battle_ai::species::swap_species(&mut species);
+        // One gender and ability, every nature, no item or any one item, and the 5 ways to leave out one of the moves
+        assert_eq!(builds.len(), Nature::all().len() * (Item::count() + 1) * 5);
+        assert!(all_valid);
+    }
+
+    #[test]
+    fn ivs_and_evs_can_be_set_one_stat_at_a_time() {
+        let _data = crate::load_test_data();
+        let mut build = crate::test_team(1).members.remove(0);
+
+        assert_eq!(build.set_iv(StatIndex::Spd, 0), Ok(()));
+        assert_eq!(build.set_ev(StatIndex::Atk, 252), Ok(()));
+        assert_eq!(build.ivs[StatIndex::Spd.as_usize()], 0);
+        assert_eq!(build.evs[StatIndex::Atk.as_usize()], 252);
+    }
+
+    #[test]
+    fn iv_above_31_is_rejected() {
+        let _data = crate::load_test_data();
+        let mut build = crate::test_team(1).members.remove(0);
+        let ivs = build.ivs;
+
+        assert!(build.set_iv(StatIndex::Hp, 32).is_err());
+        assert_eq!(build.ivs, ivs);
+    }
+
+    #[test]
+    fn ev_over_the_total_budget_is_rejected() {
+        let _data = crate::load_test_data();
+        let mut build = crate::test_team(1).members.remove(0);
+        build.evs = [0; 6];
+        build.set_ev(StatIndex::Atk, 252).unwrap();
+        build.set_ev(StatIndex::Spd, 252).unwrap();
+
+        let error = build.set_ev(StatIndex::Hp, 8).unwrap_err();
+        assert!(error.contains("above 508"), "{}", error);
+        assert_eq!(build.evs[StatIndex::Hp.as_usize()], 0);
+        assert_eq!(build.set_ev(StatIndex::Hp, 4), Ok(()));
+    }
+
+    #[test]
+    fn ev_that_is_not_a_multiple_of_4_can_be_set() {
+        let _data = crate::load_test_data();
+        let mut build = crate::test_team(1).members.remove(0);
+        build.evs = [0; 6];
+
+        assert_eq!(build.set_ev(StatIndex::Def, 5), Ok(()));
+        assert_eq!(build.evs[StatIndex::Def.as_usize()], 5);
+        assert!(build.set_ev(StatIndex::Def, 253).is_err());
+    }
+
+    #[test]
+    fn loading_a_build_checks_its_ivs_and_evs() {
+        let _data = crate::load_test_data();
+        let load = |ivs: &str, evs: &str| serde_json::from_str::<PokemonBuild>(&format!(
+            r#"{{"species": "Bulbasaur", "gender": "Male", "nature": "Adamant", "ability": "Overgrow", "item": "", "ivs": {}, "evs": {},
+                "move1": "Tackle", "move2": "", "move3": "", "move4": ""}}"#, ivs, evs));
+
+        assert!(load("[31, 31, 31, 31, 31, 31]", "[252, 252, 4, 0, 0, 0]").is_ok());
+        let error = load("[31, 32, 31, 31, 31, 31]", "[0, 0, 0, 0, 0, 0]").unwrap_err().to_string();
+        assert!(error.contains("Bulbasaur has IV 32 in stat 1, above 31"), "{}", error);
+        let error = load("[31, 31, 31, 31, 31, 31]", "[252, 252, 8, 0, 0, 0]").unwrap_err().to_string();
+        assert!(error.contains("Bulbasaur has an EV total of 512, above 508"), "{}", error);
+    }
+
+    #[test]
+    fn species_without_moves_are_never_generated() {
+        let _data = crate::load_test_data();
+        let species_json = r#"[
+            {"name": "Bulbasaur", "type1": "Grass", "type2": "Poison", "abilities": ["Overgrow"], "base_stats": [45, 49, 49, 65, 65, 45],
+                "weight": 69, "male_chance": 875, "female_chance": 125, "allow_duplicates": true, "move_pool": []},
+            {"name": "Ivysaur", "type1": "Grass", "type2": "Poison", "abilities": ["Overgrow"], "base_stats": [60, 62, 63, 80, 80, 60],
+                "weight": 130, "male_chance": 875, "female_chance": 125, "allow_duplicates": true, "move_pool": ["Tackle"]}]"#;
+        let mut species: Vec<Species> = serde_json::from_str(species_json).unwrap();
+
+        crate::battle_ai::species::swap_species(&mut species);
+        let builds: Vec<PokemonBuild> = (0..50).map(|seed| PokemonBuild::new(&mut StdRng::seed_from_u64(seed))).collect();
+        crate::battle_ai::species::swap_species(&mut species);
+        assert!(builds.iter().all(|build| build.species == 1 && build.moves.len() == 1));
+    }
+
+    #[test]
+    fn teams_equal_under_the_default_lead_differ_with_other_leads() {
+        let _data = crate::load_test_data();
+        let mut team = crate::test_team(3);
+        team.members[1].moves = vec![Move::id_by_name("Tackle").unwrap()];
+        team.members[2].moves = vec![Move::id_by_name("Growl").unwrap()];
+        let mut reordered = team.clone();
+        reordered.members.swap(1, 2);
+
+        assert!(team == reordered);
+        assert!(!team.eq_with_leads(1, &reordered, 1));
+        assert!(team.eq_with_leads(1, &reordered, 2));
+    }
+
+    #[test]
+    fn each_member_is_matched_only_once() {
+        let _data = crate::load_test_data();
+        let mut team = crate::test_team(3);
+        team.members[2].moves = vec![Move::id_by_name("Tackle").unwrap()];
+        let mut other = team.clone();
+        other.members[1] = other.members[2].clone();
+
+        assert!(team != other);
+    }
+
+    #[test]
+    fn wall_is_bulkier_than_a_frail_sweeper() {
+        let _data = crate::load_test_data();
+        let species_json = r#"[
+            {"name": "Pikachu", "type1": "Electric", "type2": "None", "abilities": ["Static"], "base_stats": [35, 55, 40, 50, 50, 90],
+                "weight": 60, "male_chance": 500, "female_chance": 500, "allow_duplicates": true, "move_pool": ["Tackle"]},
+            {"name": "Snorlax", "type1": "Normal", "type2": "None", "abilities": ["Thick Fat"], "base_stats": [160, 110, 65, 65, 110, 30],
+                "weight": 4600, "male_chance": 875, "female_chance": 125, "allow_duplicates": true, "move_pool": ["Tackle"]}]"#;
+        let mut species: Vec<Species> = serde_json::from_str(species_json).unwrap();
+
+        crate::battle_ai::species::swap_species(&mut species);
+        let team = TeamBuild { members: vec![
+            PokemonBuildBuilder::new(0).build().unwrap(),
+            PokemonBuildBuilder::new(1).build().unwrap()
+        ] };
+        let profile = team.bulk_profile();
+        crate::battle_ai::species::swap_species(&mut species);
+        let (sweeper, wall) = (profile[0], profile[1]);
+        assert!(wall.1 > 2 * sweeper.1, "{:?}", profile);
+        assert!(wall.2 > 2 * sweeper.2, "{:?}", profile);
+    }
+
+    #[test]
+    fn single_meta_credits_the_team_that_beats_the_rest() {
+        let _data = crate::load_test_data();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut strong = crate::test_team(1);
+        strong.members[0].moves = vec![Move::id_by_name("Tackle").unwrap()];
+        let mut weak = crate::test_team(1);
+        weak.members[0].moves = vec![Move::id_by_name("Growl").unwrap()];
+        let mut solver = Solver::new_single_meta(2, 1, &mut rng);
+        solver.maximizer_meta = vec![Solution::with_team_build(strong.clone()), Solution::with_team_build(weak.clone())];
+        solver.set_interaction_density(1.0).unwrap();
+
+        solver.do_iter(&mut rng);
+        assert_eq!(solver.fitness_func_evals(), 1);
+        assert!(solver.minimizer_meta().iter().zip(solver.maximizer_meta()).all(|(a, b)| a.team_build == b.team_build));
+        let fitness_of = |team: &TeamBuild| solver.maximizer_meta.iter().find(|solution| solution.team_build == *team).map(|solution| solution.fitness);
+        assert!(fitness_of(&strong).unwrap() > 0.0);
+        assert!(fitness_of(&weak).is_none_or(|fitness| fitness < 0.0));
+    }
+
+    #[test]
+    fn win_loss_fitness_samples_only_whole_outcomes() {
+        let _data = crate::load_test_data();
+        let mut tackler = crate::test_team(1);
+        tackler.members[0].moves = vec![Move::id_by_name("Tackle").unwrap()];
+        let mut faster = tackler.clone();
+        faster.members[0].evs[5] = 252;
+        let payoffs = |win_loss| (0..5)
+            .map(|seed| Solver::battle_payoff(&tackler, &faster, win_loss, &mut StdRng::seed_from_u64(seed)))
+            .collect::<Vec<f64>>();
+
+        let discrete = payoffs(true);
+        assert!(discrete.iter().all(|payoff| [-1.0, 0.0, 1.0].contains(payoff)), "{:?}", discrete);
+        let continuous = payoffs(false);
+        assert!(continuous.iter().any(|payoff| payoff.fract() != 0.0), "{:?}", continuous);
+    }
+
+    #[test]
+    fn history_has_a_snapshot_per_recorded_iteration() {
+        let _data = crate::load_test_data();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut solver = Solver::with_config(4, 1, &mut rng);
+        solver.do_iter(&mut rng);
+        assert!(solver.history().is_empty());
+
+        solver.record_history(true);
+        for _ in 0..3 {
+            solver.do_iter(&mut rng);
+        }
+        let history = solver.history();
+        assert_eq!(history.len(), 3);
+        assert!(history.windows(2).all(|pair| pair[0].fitness_func_evals <= pair[1].fitness_func_evals));
+        assert_eq!(history[2].fitness_func_evals, solver.fitness_func_evals());
+    }
+
+    #[test]
+    fn grass_steel_species_walls_a_grass_poison_attacker() {
+        let _data = crate::load_test_data();
+        let mut team = crate::test_team(1);
+        team.members[0].moves = ["Vine Whip", "Sludge Bomb", "Tackle"].iter().map(|name| Move::id_by_name(name).unwrap()).collect();
+        let species_json = r#"[
+            {"name": "Bulbasaur", "type1": "Grass", "type2": "Poison", "abilities": ["Overgrow"], "base_stats": [45, 49, 49, 65, 65, 45],
+                "weight": 69, "male_chance": 875, "female_chance": 125, "allow_duplicates": true, "move_pool": ["Tackle"]},
+            {"name": "Trapinch", "type1": "Ground", "type2": "None", "abilities": ["Arena Trap"], "base_stats": [45, 100, 45, 45, 45, 10],
+                "weight": 150, "male_chance": 500, "female_chance": 500, "allow_duplicates": true, "move_pool": ["Tackle"]},
+            {"name": "Ferrothorn", "type1": "Grass", "type2": "Steel", "abilities": ["Iron Barbs"], "base_stats": [74, 94, 131, 54, 116, 20],
+                "weight": 1100, "male_chance": 500, "female_chance": 500, "allow_duplicates": true, "move_pool": ["Tackle"]}]"#;
+        let mut species: Vec<Species> = serde_json::from_str(species_json).unwrap();
+
+        crate::battle_ai::species::swap_species(&mut species);
+        let uncovered = team.uncovered_species();
+        crate::battle_ai::species::swap_species(&mut species);
+        // Only Trapinch is weak to Grass or Poison
+        assert_eq!(uncovered, vec![0, 2]);
+    }
+
+    #[test]
+    fn attacker_deals_more_damage_to_a_frail_defender_than_a_bulky_one() {
+        let _data = crate::load_test_data();
+        let species_json = r#"[
+            {"name": "Bulbasaur", "type1": "Grass", "type2": "Poison", "abilities": ["Overgrow"], "base_stats": [45, 49, 49, 65, 65, 45],
+                "weight": 69, "male_chance": 875, "female_chance": 125, "allow_duplicates": true, "move_pool": ["Tackle"]},
+            {"name": "Pikachu", "type1": "Electric", "type2": "None", "abilities": ["Static"], "base_stats": [35, 55, 40, 50, 50, 90],
+                "weight": 60, "male_chance": 500, "female_chance": 500, "allow_duplicates": true, "move_pool": ["Tackle"]},
+            {"name": "Snorlax", "type1": "Normal", "type2": "None", "abilities": ["Thick Fat"], "base_stats": [160, 110, 65, 65, 110, 30],
+                "weight": 4600, "male_chance": 875, "female_chance": 125, "allow_duplicates": true, "move_pool": ["Tackle"]}]"#;
+        let mut species: Vec<Species> = serde_json::from_str(species_json).unwrap();
+
+        crate::battle_ai::species::swap_species(&mut species);
+        let [attacker, frail, bulky] = [0, 1, 2].map(|species| PokemonBuildBuilder::new(species).build().unwrap());
+        let (vs_frail, vs_bulky) = (attacker.avg_damage_vs(&frail, Weather::None), attacker.avg_damage_vs(&bulky, Weather::None));
+        crate::battle_ai::species::swap_species(&mut species);
+        assert!(vs_bulky > 0.0);
+        assert!(vs_frail > vs_bulky, "{} against the frail defender, {} against the bulky one", vs_frail, vs_bulky);
+    }
+
+    #[test]
+    fn exported_meta_imports_as_the_same_teams() {
+        let _data = crate::load_test_data();
+        let mut rng = StdRng::seed_from_u64(0);
+        let exporter = Solver::with_config(4, 2, &mut rng);
+        let path = std::env::temp_dir().join("exported_meta_imports_as_the_same_teams.csv");
+        // Written like the main binary's maximizer_meta.csv, but with a shorter header since the teams are smaller
+        let mut writer = csv::WriterBuilder::new().has_headers(false).flexible(true).from_path(&path).unwrap();
+        writer.write_record(["fitness", "fit_variance", "num_samples", "prob_worse_than_best"]).unwrap();
+        for sol in exporter.maximizer_meta() {
+            writer.serialize(sol).unwrap();
+        }
+        writer.flush().unwrap();
+
+        let mut importer = Solver::with_config(4, 2, &mut StdRng::seed_from_u64(1));
+        let imported = importer.import_meta_csv(&path, Side::Max);
+        std::fs::remove_file(&path).unwrap();
+        imported.unwrap();
+        let team_builds = |solver: &Solver| solver.maximizer_meta().iter().map(|sol| sol.team_build.clone()).collect::<Vec<TeamBuild>>();
+        assert_eq!(team_builds(&importer), team_builds(&exporter));
+        assert!(importer.maximizer_meta().iter().all(|sol| sol.num_samples == 0));
+    }
+
+    #[test]
+    fn build_saved_before_items_loads_without_an_item() {
+        let _data = crate::load_test_data();
+        let build: PokemonBuild = serde_json::from_str(r#"{"species": "Bulbasaur", "gender": "Male", "nature": "Adamant", "ability": "Overgrow",
+            "ivs": [31, 31, 31, 31, 31, 31], "evs": [252, 252, 4, 0, 0, 0], "move1": "Tackle", "move2": "", "move3": "", "move4": ""}"#).unwrap();
+
+        assert_eq!(build.item, None);
+        let round_trip: PokemonBuild = serde_json::from_str(&serde_json::to_string(&build).unwrap()).unwrap();
+        assert!(round_trip == build);
+    }
+
+    #[test]
+    fn run_until_stops_when_a_single_meta_has_one_team() {
+        let _data = crate::load_test_data();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut solver = Solver::new_single_meta(2, 1, &mut rng);
+        solver.maximizer_meta.truncate(1);
+        let only_team = solver.maximizer_meta[0].team_build.clone();
+
+        assert!(solver.run_until(10, &mut rng) == only_team);
+        assert_eq!(solver.fitness_func_evals(), 0);
+    }
+
+    #[test]
+    fn run_until_counts_mirror_matches_toward_its_budget() {
+        let _data = crate::load_test_data();
+        let mut rng = StdRng::seed_from_u64(0);
+        let team = crate::test_team(1);
+        let mut solver = Solver::from_seed_teams(vec![team.clone()], vec![team], 1, &mut rng).unwrap();
+
+        solver.run_until(10, &mut rng);
+        assert!(solver.fitness_func_evals() + solver.mirror_matches >= 10);
+        assert!(solver.mirror_matches > 0);
     }
 }
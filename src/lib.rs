@@ -1,19 +1,27 @@
 use std::cmp::max;
+use std::collections::HashSet;
 use std::fmt::Debug;
 use std::intrinsics::transmute;
+use std::mem;
 use std::ops::AddAssign;
+use std::panic;
+#[cfg(test)]
+use std::sync::{Mutex, MutexGuard, Once, PoisonError};
+use std::thread;
+use std::time::Instant;
 
 use num::{One, Zero};
-use rand::prelude::StdRng;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
 use serde::{Deserialize, Serialize};
+use statrs::distribution::{StudentsT, Univariate};
 
 use crate::move_::MoveCategory;
 
 pub mod battle_ai;
 pub mod combinatorial_optim;
 
-fn choose_weighted_index(weights: &[f64], rng: &mut StdRng) -> usize {
+fn choose_weighted_index<R: Rng>(weights: &[f64], rng: &mut R) -> usize {
     if weights.is_empty() || weights.iter().any(|d| !almost::zero(*d) && *d < 0.0) {
         panic!("Weights must be non-negative. Given weights: {:?}", weights);
     }
@@ -26,6 +34,225 @@ fn choose_weighted_index(weights: &[f64], rng: &mut StdRng) -> usize {
     weights.len() - 1
 }
 
+/// Checks the loaded moves and species for problems that would otherwise only show up mid-battle. Should be called
+/// after the moves and species have been initialized.
+pub fn validate_game_data() -> Result<(), Vec<String>> {
+    let mut problems = move_::validate_moves();
+    problems.extend(species::validate_species());
+    if problems.is_empty() { Ok(()) } else { Err(problems) }
+}
+
+/// The moves and species of one game version. The engine reads whichever version's data is loaded into its globals,
+/// so only one version can be active at a time, but a single process can hold several and take turns activating
+/// them, e.g. to compare a matchup across generations.
+pub struct GameData {
+    version: GameVersion,
+    moves: Vec<Move>,
+    species: Vec<Species>
+}
+
+impl GameData {
+    /// Loads the data of `version` without disturbing the active data.
+    pub fn load(version: GameVersion) -> Result<GameData, DataLoadError> {
+        let mut game_data = GameData {
+            version,
+            moves: Vec::new(),
+            species: Vec::new()
+        };
+        game_data.with_active(|| move_::initialize_moves().and_then(|_| species::initialize_species()))?;
+        Ok(game_data)
+    }
+
+    pub const fn version(&self) -> &GameVersion {
+        &self.version
+    }
+
+    /// Makes this the active game data while `f` runs, then restores the previously active data. Move and species
+    /// IDs are only meaningful within the version they came from, so team builds should be created and used inside
+    /// `f`.
+    pub fn with_active<T, F: FnOnce() -> T>(&mut self, f: F) -> T {
+        /// Swaps the data back when dropped, so that the previous data is restored even if `f` panics.
+        struct Restore<'a>(&'a mut GameData);
+
+        impl Drop for Restore<'_> {
+            fn drop(&mut self) {
+                self.0.swap_with_active();
+            }
+        }
+
+        self.swap_with_active();
+        let _restore = Restore(self);
+        f()
+    }
+
+    fn swap_with_active(&mut self) {
+        unsafe {
+            mem::swap(&mut GAME_VERSION, &mut self.version);
+        }
+        move_::swap_moves(&mut self.moves);
+        species::swap_species(&mut self.species);
+    }
+}
+
+/// Per-battle settings. `BattleRules::default()` gives the standard rules.
+#[derive(Clone, Copy, Debug)]
+pub struct BattleRules {
+    /// How many turns ahead the agents search.
+    pub search_depth: u8,
+    /// If set, the battle is cut off after this many turns and the payoff is the agents' evaluation of the final state.
+    pub max_turns: Option<u16>,
+    /// If false, Pokemon can only be switched in to replace fainted ones, so each agent is committed to its lead
+    /// until it faints. Useful for evaluating pure matchups.
+    pub allow_switching: bool,
+    /// If the battle ends with one side defeated, the payoff is scaled by this raised to the number of turns taken.
+    /// A value slightly less than 1.0 rewards winning quickly.
+    pub turn_discount: f64,
+    /// Maximum number of turns in a row that an agent may switch voluntarily.
+    pub consecutive_switch_cap: u16,
+    /// How critical hits and damage rolls are resolved.
+    pub chance_policy: ChancePolicy,
+    /// Index into the minimizer's team of the Pokemon that is sent out first.
+    pub min_lead: usize,
+    /// Index into the maximizer's team of the Pokemon that is sent out first.
+    pub max_lead: usize,
+    /// How the agents weigh the parts of a position when the search stops short of the end of the battle.
+    pub heuristic_weights: HeuristicWeights,
+    /// If set, each search generates at most this many new positions. Past that, the positions still to be searched
+    /// are evaluated with the heuristic as if the depth limit had been reached, which bounds the memory the search
+    /// tree takes up at the cost of a shallower search.
+    pub max_search_nodes: Option<u64>,
+    /// If true, Pokemon have no abilities or items and weather can't be set, so battles come down to moves, stats and
+    /// status. Useful as a stable baseline when benchmarking the search.
+    pub pure_stats: bool,
+    /// How the maximizer chooses its actions.
+    pub max_policy: AgentPolicy,
+    /// How the minimizer chooses its actions.
+    pub min_policy: AgentPolicy,
+    /// If true, a battle that comes back to a position it has already been in (see `StateSnapshot`) is declared a
+    /// draw with a payoff of 0. This ends stall loops sooner than `max_turns`.
+    pub draw_on_repetition: bool,
+    /// How much battle text is recorded. Any that is recorded is printed as the battle is played out.
+    pub log_level: LogLevel
+}
+
+impl Default for BattleRules {
+    fn default() -> Self {
+        BattleRules {
+            search_depth: AI_LEVEL,
+            max_turns: None,
+            allow_switching: true,
+            turn_discount: 1.0,
+            consecutive_switch_cap: CONSECUTIVE_SWITCH_CAP,
+            chance_policy: ChancePolicy::default(),
+            min_lead: 0,
+            max_lead: 0,
+            heuristic_weights: HeuristicWeights::default(),
+            max_search_nodes: None,
+            pure_stats: false,
+            max_policy: AgentPolicy::default(),
+            min_policy: AgentPolicy::default(),
+            draw_on_repetition: false,
+            log_level: LogLevel::default()
+        }
+    }
+}
+
+/// Relative weights of the terms of the search's position evaluation. Each term compares the two sides on a scale
+/// from -1 to 1, and the evaluation is their weighted average, so only the ratios between the weights matter. They
+/// must be non-negative and not all zero.
+#[derive(Clone, Copy, Debug)]
+pub struct HeuristicWeights {
+    /// Remaining HP fractions, summed over each team.
+    pub hp: f64,
+    /// Number of Pokemon left standing with a major status ailment, which counts against their side.
+    pub status: f64,
+    /// Stat stages of the Pokemon on the field.
+    pub stages: f64,
+    /// How effective the types of each Pokemon on the field are against the other's.
+    pub type_matchup: f64,
+    /// Number of Pokemon left standing.
+    pub faint_count: f64
+}
+
+impl HeuristicWeights {
+    fn validate(&self) -> Result<(), String> {
+        let weights = [self.hp, self.status, self.stages, self.type_matchup, self.faint_count];
+        if weights.iter().any(|weight| weight.is_nan() || *weight < 0.0) {
+            return Err(format!("heuristic weights must be non-negative: {:?}", self));
+        }
+        if almost::zero(weights.iter().sum::<f64>()) {
+            return Err(String::from("heuristic weights can't all be zero"));
+        }
+        Ok(())
+    }
+}
+
+impl Default for HeuristicWeights {
+    /// Mostly the difference in remaining HP, with a small bonus for having more Pokemon left standing so that equal
+    /// HP totals are not considered a tie.
+    fn default() -> Self {
+        HeuristicWeights {
+            hp: 0.9,
+            status: 0.0,
+            stages: 0.0,
+            type_matchup: 0.0,
+            faint_count: 0.1
+        }
+    }
+}
+
+/// How the chance events of a turn are resolved without branching the search tree. Only critical hits and damage
+/// rolls are covered; other chance events are always sampled.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ChancePolicy {
+    /// One outcome is sampled, so the agents act as if they knew it ahead of time.
+    #[default]
+    SampleOnce,
+    /// Damage is the expected value over critical hits and bucketed damage rolls.
+    Expectation,
+    /// Every outcome is the worst one for the maximizer.
+    Pessimistic,
+    /// Every outcome is the best one for the maximizer.
+    Optimistic,
+    /// Every attack, from either side, deals its minimum damage: no critical hit and the lowest damage roll. Useful
+    /// for checking whether a KO is guaranteed.
+    MinimumDamage,
+    /// Every attack is a critical hit and damage rolls are sampled. Only meant for exercising the critical hit code
+    /// paths, such as critical hits ignoring unfavorable stat stages.
+    AllCriticalHits
+}
+
+/// How an agent chooses its actions over the course of a battle. Either way, the search still runs each turn, so
+/// that the other agent can play its equilibrium strategy.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum AgentPolicy {
+    /// Samples its action from its side of the Nash equilibrium.
+    #[default]
+    Optimal,
+    /// Picks uniformly at random among its legal actions. Pitting a team against this shows how it fares against
+    /// weak opponents rather than perfect ones.
+    Random
+}
+
+/// How much battle text is recorded and printed. Each level includes the ones before it.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum LogLevel {
+    Off,
+    /// Faints and the end of the battle.
+    Outcomes,
+    /// Also each turn's header, the moves used and Pokemon entering and leaving the field.
+    Turns,
+    /// Everything, including damage, status and stat changes.
+    Verbose
+}
+
+impl Default for LogLevel {
+    /// Verbose with the print-battle feature and off otherwise.
+    fn default() -> Self {
+        if cfg!(feature = "print-battle") { LogLevel::Verbose } else { LogLevel::Off }
+    }
+}
+
 /// Run a battle from an initial state; the maximizer and minimizer use game theory to choose their actions. All
 /// state-space branching due to chance events during the course of each turn has been removed to reduce
 /// computational complexity. Instead, one potential outcome is simply chosen at random (weighted appropriately),
@@ -33,47 +260,1262 @@ fn choose_weighted_index(weights: &[f64], rng: &mut StdRng) -> usize {
 /// average out to what one would obtain from a full state-space/probability tree search, but expect high variance
 /// between individual trials. Returns a heuristic value between -1.0 and 1.0 signifying how well the maximizer did;
 /// 0.0 would be a tie. The minimizer's value is its negation.
-pub fn run_battle(minimizer: &TeamBuild, maximizer: &TeamBuild, rng: &mut StdRng) -> f64 {
-    let mut state = Box::new(
-        State::new({
-                       let mut min_team = minimizer.members.iter();
-                       let mut max_team = maximizer.members.iter();
-                       [
-                           Pokemon::from(min_team.next().unwrap()),
-                           Pokemon::from(min_team.next().unwrap()),
-                           Pokemon::from(min_team.next().unwrap()),
-                           Pokemon::from(min_team.next().unwrap()),
-                           Pokemon::from(min_team.next().unwrap()),
-                           Pokemon::from(min_team.next().unwrap()),
-                           Pokemon::from(max_team.next().unwrap()),
-                           Pokemon::from(max_team.next().unwrap()),
-                           Pokemon::from(max_team.next().unwrap()),
-                           Pokemon::from(max_team.next().unwrap()),
-                           Pokemon::from(max_team.next().unwrap()),
-                           Pokemon::from(max_team.next().unwrap())
-                       ]
-                   }, Weather::default(), Terrain::default()));
-
-    if cfg!(feature = "print-battle") {
+pub fn run_battle<R: Rng>(minimizer: &TeamBuild, maximizer: &TeamBuild, rules: &BattleRules, rng: &mut R) -> f64 {
+    run_battle_observed(minimizer, maximizer, rules, |_, _| {}, rng)
+}
+
+/// Same as `run_battle`, except that critical hits and damage rolls are not sampled. Damage is instead the expected
+/// value over all critical hit outcomes and the 85-100% damage roll, quantized into four buckets. This lowers the
+/// variance between trials. Other chance events, such as accuracy checks and secondary effects, are still sampled.
+pub fn run_battle_expectimax<R: Rng>(minimizer: &TeamBuild, maximizer: &TeamBuild, rules: &BattleRules, rng: &mut R) -> f64 {
+    let rules = BattleRules {
+        chance_policy: ChancePolicy::Expectation,
+        ..*rules
+    };
+    run_battle(minimizer, maximizer, &rules, rng)
+}
+
+/// Same as `run_battle`, except that `observer` is called at each decision with the current state and the Nash
+/// equilibrium the agents are about to sample their actions from. Useful for studying how the agents weigh their
+/// options; e.g. `ZeroSumNashEq::entropy` tells forced positions apart from flexible ones.
+pub fn run_battle_observed<R: Rng, F: FnMut(&State, &ZeroSumNashEq)>(minimizer: &TeamBuild, maximizer: &TeamBuild, rules: &BattleRules, mut observer: F, rng: &mut R) -> f64 {
+    let mut decision_rng = StdRng::seed_from_u64(rng.gen());
+    run_battle_to_end(minimizer, maximizer, rules, |state, nash_eq, _, _| observer(state, nash_eq), rng, &mut decision_rng).0
+}
+
+/// Same as `run_battle`, except that the agents sample their actions from their mixed strategies with `decision_rng`,
+/// leaving `rng` to the search and the chance events of each turn. Battles with the same `rng` seed therefore play
+/// out the same way until the agents first choose differently.
+pub fn run_battle_with_decision_rng<R: Rng, D: Rng>(minimizer: &TeamBuild, maximizer: &TeamBuild, rules: &BattleRules, rng: &mut R, decision_rng: &mut D) -> f64 {
+    run_battle_to_end(minimizer, maximizer, rules, |_, _, _, _| {}, rng, decision_rng).0
+}
+
+/// Same as `run_battle`, except that it also counts the random draws made along the way the battle actually went,
+/// grouped by what they decided. Draws made while the agents search ahead are not counted.
+pub fn run_battle_trace<R: Rng>(minimizer: &TeamBuild, maximizer: &TeamBuild, rules: &BattleRules, rng: &mut R) -> (f64, RngDrawCounts) {
+    let mut decision_rng = StdRng::seed_from_u64(rng.gen());
+    let (payoff, state) = run_battle_to_end(minimizer, maximizer, rules, |_, _, _, _| {}, rng, &mut decision_rng);
+    (payoff, state.rng_draws)
+}
+
+/// One decision of the agents in a battle, as recorded by `run_battle_full_trace`.
+#[derive(Clone, Debug)]
+pub struct DecisionRecord {
+    /// Turn number of the position the agents decided from.
+    pub turn: u16,
+    /// Each of the maximizer's actions with the probability its strategy gave it.
+    pub max_strategy: Vec<(Action, f64)>,
+    /// Each of the minimizer's actions with the probability its strategy gave it.
+    pub min_strategy: Vec<(Action, f64)>,
+    pub max_action: Action,
+    pub min_action: Action
+}
+
+/// Same as `run_battle`, except that it also records every decision the agents made, in order, so that a particular
+/// battle can be archived and looked over later.
+pub fn run_battle_full_trace<R: Rng>(minimizer: &TeamBuild, maximizer: &TeamBuild, rules: &BattleRules, rng: &mut R) -> (f64, Vec<DecisionRecord>) {
+    let mut decision_rng = StdRng::seed_from_u64(rng.gen());
+    let mut records = Vec::new();
+    let record_decision = |state: &State, nash_eq: &ZeroSumNashEq, max_choice: usize, min_choice: usize| {
+        let (max_actions, min_actions) = state.ordered_actions();
+        records.push(DecisionRecord {
+            turn: state.turn_number(),
+            max_action: max_actions[max_choice].clone(),
+            min_action: min_actions[min_choice].clone(),
+            max_strategy: max_actions.into_iter().zip(nash_eq.max_player_strategy.iter().copied()).collect(),
+            min_strategy: min_actions.into_iter().zip(nash_eq.min_player_strategy.iter().copied()).collect()
+        });
+    };
+    let (payoff, _) = run_battle_to_end(minimizer, maximizer, rules, record_decision, rng, &mut decision_rng);
+    (payoff, records)
+}
+
+/// Returns the discounted payoff along with the final state.
+fn run_battle_to_end<R: Rng, D: Rng, F: FnMut(&State, &ZeroSumNashEq, usize, usize)>(minimizer: &TeamBuild, maximizer: &TeamBuild, rules: &BattleRules, observer: F, rng: &mut R, decision_rng: &mut D) -> (f64, Box<State>) {
+    let mut state = initial_state(minimizer, maximizer, rules);
+
+    if state.logs(LogLevel::Outcomes) {
         println!("<<<< BATTLE BEGIN >>>>");
         state.print_display_text();
     }
 
-    let mut nash_eq = smab_search(&mut state, -1.0, 1.0, AI_LEVEL, rng);
+    let nash_eq = play_out_battle(&mut state, rules.search_depth, observer, rng, decision_rng);
+
+    if state.logs(LogLevel::Outcomes) {
+        println!("<<<< BATTLE END >>>>");
+    }
+
+    let payoff = if state.has_battle_ended() {
+        nash_eq.expected_payoff * rules.turn_discount.powi(state.turn_number() as i32)
+    } else {
+        nash_eq.expected_payoff
+    };
+    (payoff, state)
+}
+
+/// How a battle ended.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BattleOutcome {
+    MaximizerWin,
+    MinimizerWin,
+    /// Both teams were defeated at once, or the battle was cut off by the `max_turns` or `draw_on_repetition` rule.
+    Draw
+}
+
+impl BattleOutcome {
+    fn of(state: &State) -> BattleOutcome {
+        let min_defeated = state.pokemon[0..6].iter().all(|pokemon| pokemon.is_fainted());
+        let max_defeated = state.pokemon[6..12].iter().all(|pokemon| pokemon.is_fainted());
+        match (min_defeated, max_defeated) {
+            (true, false) => BattleOutcome::MaximizerWin,
+            (false, true) => BattleOutcome::MinimizerWin,
+            _ => BattleOutcome::Draw
+        }
+    }
+
+    /// 1 for a maximizer win, -1 for a minimizer win and 0 for a draw.
+    pub fn payoff(self) -> f64 {
+        match self {
+            BattleOutcome::MaximizerWin => 1.0,
+            BattleOutcome::MinimizerWin => -1.0,
+            BattleOutcome::Draw => 0.0
+        }
+    }
+}
+
+/// Same as `run_battle`, except that only who won is returned, not by how much.
+pub fn run_battle_outcome<R: Rng>(minimizer: &TeamBuild, maximizer: &TeamBuild, rules: &BattleRules, rng: &mut R) -> BattleOutcome {
+    let mut decision_rng = StdRng::seed_from_u64(rng.gen());
+    let (_, state) = run_battle_to_end(minimizer, maximizer, rules, |_, _, _, _| {}, rng, &mut decision_rng);
+    BattleOutcome::of(&state)
+}
+
+/// The agents' evaluation of a matchup before any action is taken: the expected payoff of the Nash equilibrium found
+/// by searching `depth` turns ahead from the start of the battle under the standard rules. Unlike `run_battle`, the
+/// battle isn't played out, so this is cheap, but it's only as good as the search's heuristic.
+pub fn initial_assessment<R: Rng>(minimizer: &TeamBuild, maximizer: &TeamBuild, depth: u8, rng: &mut R) -> f64 {
+    let mut state = initial_state(minimizer, maximizer, &BattleRules::default());
+    smab_search(&mut state, -1.0, 1.0, depth, rng).expected_payoff
+}
+
+/// Searches `state` with iterative deepening until `max_depth` is reached or `deadline` passes, and returns the
+/// Nash equilibrium of the deepest search that finished in time. A search one turn deep always finishes, so the
+/// result is valid even if the deadline has already passed. The agents' actions must already have been generated,
+/// as they are for any position reached in a battle.
+pub fn search_with_deadline<R: Rng>(state: &mut State, max_depth: u8, deadline: Instant, rng: &mut R) -> ZeroSumNashEq {
+    if max_depth == 0 {
+        panic!("Search depth must be positive");
+    }
+
+    let mut nash_eq = smab_search(state, -1.0, 1.0, 1, rng);
+    for depth in 2..=max_depth {
+        if Instant::now() >= deadline {
+            break;
+        }
+        // An unfinished search still reorders the actions, which would misalign the last finished result
+        let action_orders = (state.max.action_order.clone(), state.min.action_order.clone());
+        let node_limit = node_limit(state);
+        let deeper_nash_eq = smab_search_until(state, -1.0, 1.0, depth, Some(deadline), node_limit, rng);
+        if Instant::now() >= deadline {
+            state.max.action_order = action_orders.0;
+            state.min.action_order = action_orders.1;
+            break;
+        }
+        nash_eq = deeper_nash_eq;
+    }
+    nash_eq
+}
+
+/// How much searching `shallow` and `deep` turns ahead from the given position agree on what the maximizer should
+/// do: 1 minus the total variation distance between the maximizer's equilibrium strategies at the two depths. 1.0
+/// means the strategies are identical and 0.0 means they share no actions, so this shows how much a deeper search
+/// is worth. Panics if either depth is 0.
+pub fn depth_agreement<R: Rng>(state: &State, shallow: u8, deep: u8, rng: &mut R) -> f64 {
+    if shallow == 0 || deep == 0 {
+        panic!("Search depth must be positive");
+    }
+
+    let mut state = Box::new(state.copy_game_state());
+    generate_actions(&mut state);
+    // Each search reorders the actions, so the strategies are mapped back to the actions they refer to
+    let mut max_strategy_at = |depth: u8| -> Vec<f64> {
+        let nash_eq = smab_search(&mut state, -1.0, 1.0, depth, rng);
+        let mut strategy = vec![0.0; state.max.actions.len()];
+        for (&action_index, &probability) in state.max.action_order.iter().zip(nash_eq.max_player_strategy.iter()) {
+            strategy[action_index] = probability;
+        }
+        strategy
+    };
+    let shallow_strategy = max_strategy_at(shallow);
+    let deep_strategy = max_strategy_at(deep);
 
-    while !state.max.actions.is_empty() && !state.min.actions.is_empty() {
+    let total_variation_distance = shallow_strategy.iter()
+        .zip(deep_strategy.iter())
+        .map(|(p, q)| (p - q).abs())
+        .sum::<f64>() / 2.0;
+    1.0 - total_variation_distance
+}
+
+/// Estimates how many more turns a battle lasts from the given position by playing it out `trials` times as in
+/// `run_battle`, with both agents searching `depth` turns ahead. Playouts cut off by the state's `max_turns` or
+/// `draw_on_repetition` rule count the turns played up to the cutoff.
+pub fn expected_turns<R: Rng>(state: &State, depth: u8, trials: u32, rng: &mut R) -> f64 {
+    if trials == 0 {
+        panic!("Number of trials must be positive");
+    }
+
+    let mut decision_rng = StdRng::seed_from_u64(rng.gen());
+    let mut total_turns = 0;
+    for _ in 0..trials {
+        let mut playout = Box::new(state.copy_game_state());
+        generate_actions(&mut playout);
+        play_out_battle(&mut playout, depth, |_, _, _, _| {}, rng, &mut decision_rng);
+        total_turns += (playout.turn_number() - state.turn_number()) as u32;
+    }
+    total_turns as f64 / trials as f64
+}
+
+/// Results of `head_to_head`, from the point of view of team A.
+#[derive(Clone, Copy, Debug)]
+pub struct HeadToHead {
+    pub mean_payoff: f64,
+    /// Sample variance of the payoffs.
+    pub payoff_variance: f64,
+    pub wins: usize,
+    pub losses: usize,
+    pub draws: usize
+}
+
+/// Derives the seed of the `index`th item of a batch run from the run's base seed. Each 8-byte word of the base seed
+/// is mixed with the index through the SplitMix64 finalizer, so consecutive indices give unrelated seeds and each
+/// item's seed depends on nothing but the base seed and its own index.
+pub fn seed_for(base: [u8; 32], index: u64) -> [u8; 32] {
+    fn split_mix(mut x: u64) -> u64 {
+        x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        x ^ (x >> 31)
+    }
+
+    let mut seed = [0; 32];
+    for (word_num, (seed_word, base_word)) in seed.chunks_exact_mut(8).zip(base.chunks_exact(8)).enumerate() {
+        let mut base_bytes = [0; 8];
+        base_bytes.copy_from_slice(base_word);
+        let base_word = u64::from_le_bytes(base_bytes);
+        let mixed = split_mix(base_word ^ split_mix(index.wrapping_mul(4).wrapping_add(word_num as u64)));
+        seed_word.copy_from_slice(&mixed.to_le_bytes());
+    }
+    seed
+}
+
+/// Battles team A, playing as `team_a_side`, against team B on the other side `n` times under the standard rules.
+/// Battle `i` is seeded with `seed_for(base_seed, i)`, so the results are reproducible. Identical teams aren't battled
+/// at all; the result is `n` draws.
+pub fn head_to_head(team_a: &TeamBuild, team_a_side: Side, team_b: &TeamBuild, n: usize, base_seed: [u8; 32]) -> HeadToHead {
+    if n == 0 {
+        panic!("Number of battles must be positive");
+    }
+
+    // A mirror match is even by symmetry, so there's no need to simulate it
+    if team_a == team_b {
+        return HeadToHead {
+            mean_payoff: 0.0,
+            payoff_variance: 0.0,
+            wins: 0,
+            losses: 0,
+            draws: n
+        };
+    }
+
+    let rules = BattleRules::default();
+    let payoffs: Vec<f64> = (0..n).map(|i| {
+        let mut rng = StdRng::from_seed(seed_for(base_seed, i as u64));
+        match team_a_side {
+            Side::Min => -run_battle(team_a, team_b, &rules, &mut rng),
+            Side::Max => run_battle(team_b, team_a, &rules, &mut rng)
+        }
+    }).collect();
+
+    let mean_payoff = payoffs.iter().sum::<f64>() / n as f64;
+    let payoff_variance = if n > 1 {
+        payoffs.iter().map(|payoff| (payoff - mean_payoff).powi(2)).sum::<f64>() / (n - 1) as f64
+    } else {
+        0.0
+    };
+    HeadToHead {
+        mean_payoff,
+        payoff_variance,
+        wins: payoffs.iter().filter(|&&payoff| !almost::zero(payoff) && payoff > 0.0).count(),
+        losses: payoffs.iter().filter(|&&payoff| !almost::zero(payoff) && payoff < 0.0).count(),
+        draws: payoffs.iter().filter(|&&payoff| almost::zero(payoff)).count()
+    }
+}
+
+/// Plays every pair of `teams` against each other with `head_to_head`, `battles_per_pair` times, and ranks the teams by
+/// their mean payoff over all of their battles. Returns each team's index and mean payoff, best first. Pair `k` is
+/// given `seed_for(base_seed, k)` as its base seed, so the results are reproducible. The battles are run
+/// one after another, since the game data and state counters are process-wide statics.
+pub fn round_robin(teams: &[TeamBuild], battles_per_pair: usize, base_seed: [u8; 32]) -> Vec<(usize, f64)> {
+    if teams.len() < 2 {
+        panic!("A round robin needs at least 2 teams, got {}", teams.len());
+    }
+
+    let mut total_payoffs = vec![0.0; teams.len()];
+    let mut pair_num: u64 = 0;
+    for i in 0..teams.len() {
+        for j in i + 1..teams.len() {
+            let mean_payoff = head_to_head(&teams[i], Side::Max, &teams[j], battles_per_pair, seed_for(base_seed, pair_num)).mean_payoff;
+            total_payoffs[i] += mean_payoff;
+            total_payoffs[j] -= mean_payoff;
+            pair_num += 1;
+        }
+    }
+
+    // Every team plays the same number of pairs, each of which is weighted equally
+    let num_opponents = (teams.len() - 1) as f64;
+    let mut ranking: Vec<(usize, f64)> = total_payoffs.into_iter()
+        .map(|total_payoff| total_payoff / num_opponents)
+        .enumerate()
+        .collect();
+    ranking.sort_by(|(_, payoff1), (_, payoff2)| payoff2.partial_cmp(payoff1).unwrap());
+    ranking
+}
+
+/// Results of `evaluate_matchup_batch`, from the point of view of team A.
+#[derive(Clone, Copy, Debug)]
+pub struct MatchupStats {
+    pub mean_payoff: f64,
+    /// Sample variance of the payoffs.
+    pub payoff_variance: f64,
+    /// 95% confidence interval of the expected payoff, from Student's t-distribution.
+    pub confidence_interval: (f64, f64),
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize
+}
+
+/// Same as `head_to_head`, but the battles are split among all available cores and the results include a 95%
+/// confidence interval of the expected payoff. Battle `i` is still seeded with `seed_for(base_seed, i)`, so the
+/// results don't depend on the number of threads. The game data must be loaded and `GAME_VERSION` set beforehand.
+/// Needs at least 2 battles for the interval.
+pub fn evaluate_matchup_batch(team_a: &TeamBuild, team_b: &TeamBuild, n: usize, base_seed: [u8; 32]) -> MatchupStats {
+    if n < 2 {
+        panic!("Number of battles must be at least 2, got {}", n);
+    }
+
+    let rules = BattleRules::default();
+    let num_threads = thread::available_parallelism().map_or(1, |num_threads| num_threads.get()).min(n);
+    let chunk_size = n.div_ceil(num_threads);
+    // Each thread plays a contiguous range of battles, and the ranges are put back together in order
+    let payoffs: Vec<f64> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..n).step_by(chunk_size).map(|start| {
+            scope.spawn(move || {
+                (start..(start + chunk_size).min(n)).map(|i| {
+                    let mut rng = StdRng::from_seed(seed_for(base_seed, i as u64));
+                    run_battle(team_b, team_a, &rules, &mut rng)
+                }).collect::<Vec<f64>>()
+            })
+        }).collect();
+        handles.into_iter()
+            .flat_map(|handle| handle.join().unwrap_or_else(|err| panic::resume_unwind(err)))
+            .collect()
+    });
+
+    let mean_payoff = payoffs.iter().sum::<f64>() / n as f64;
+    let payoff_variance = payoffs.iter().map(|payoff| (payoff - mean_payoff).powi(2)).sum::<f64>() / (n - 1) as f64;
+    let std_error = (payoff_variance / n as f64).sqrt();
+    let half_width = if almost::zero(std_error) { 0.0 } else { students_t_quantile(0.975, (n - 1) as f64) * std_error };
+    MatchupStats {
+        mean_payoff,
+        payoff_variance,
+        confidence_interval: (mean_payoff - half_width, mean_payoff + half_width),
+        wins: payoffs.iter().filter(|&&payoff| !almost::zero(payoff) && payoff > 0.0).count(),
+        draws: payoffs.iter().filter(|&&payoff| almost::zero(payoff)).count(),
+        losses: payoffs.iter().filter(|&&payoff| !almost::zero(payoff) && payoff < 0.0).count()
+    }
+}
+
+/// Value that the given fraction of the standard Student's t-distribution with the given degrees of freedom lies below.
+/// statrs has no inverse CDF for the distribution, so it's found by bisection.
+fn students_t_quantile(fraction: f64, freedom: f64) -> f64 {
+    let t_dist = StudentsT::new(0.0, 1.0, freedom).unwrap();
+    let mut low = -1000.0;
+    let mut high = 1000.0;
+    for _ in 0..100 {
+        let mid = (low + high) / 2.0;
+        if t_dist.cdf(mid) < fraction {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    (low + high) / 2.0
+}
+
+/// A single output of a random number generator, as recorded by `RecordingRng`.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum RngDraw {
+    U32(u32),
+    U64(u64),
+    Bytes(Vec<u8>)
+}
+
+/// Wraps a random number generator and records everything it outputs, so that a battle can be replayed exactly with
+/// `ReplayRng`. The draws can be serialized to keep them around.
+pub struct RecordingRng<R: RngCore> {
+    rng: R,
+    draws: Vec<RngDraw>
+}
+
+impl<R: RngCore> RecordingRng<R> {
+    pub fn new(rng: R) -> RecordingRng<R> {
+        RecordingRng {
+            rng,
+            draws: Vec::new()
+        }
+    }
+
+    pub fn draws(&self) -> &[RngDraw] {
+        &self.draws
+    }
+
+    pub fn into_draws(self) -> Vec<RngDraw> {
+        self.draws
+    }
+}
+
+impl<R: RngCore> RngCore for RecordingRng<R> {
+    fn next_u32(&mut self) -> u32 {
+        let value = self.rng.next_u32();
+        self.draws.push(RngDraw::U32(value));
+        value
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let value = self.rng.next_u64();
+        self.draws.push(RngDraw::U64(value));
+        value
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest);
+        self.draws.push(RngDraw::Bytes(dest.to_vec()));
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)?;
+        self.draws.push(RngDraw::Bytes(dest.to_vec()));
+        Ok(())
+    }
+}
+
+/// Outputs the draws recorded by a `RecordingRng` in order. Panics if it's asked for a different kind of draw than
+/// was recorded or runs out of draws, since that means the replay has diverged from the recording.
+pub struct ReplayRng {
+    draws: Vec<RngDraw>,
+    next_draw: usize
+}
+
+impl ReplayRng {
+    pub fn new(draws: Vec<RngDraw>) -> ReplayRng {
+        ReplayRng {
+            draws,
+            next_draw: 0
+        }
+    }
+
+    /// Whether every recorded draw has been replayed.
+    pub fn is_finished(&self) -> bool {
+        self.next_draw == self.draws.len()
+    }
+
+    fn next_recorded(&mut self) -> &RngDraw {
+        let draw = match self.draws.get(self.next_draw) {
+            Some(draw) => draw,
+            None => panic!("Replay ran out of recorded draws after {} draws", self.draws.len())
+        };
+        self.next_draw += 1;
+        draw
+    }
+}
+
+impl RngCore for ReplayRng {
+    fn next_u32(&mut self) -> u32 {
+        match self.next_recorded() {
+            RngDraw::U32(value) => *value,
+            draw => panic!("Replay diverged: expected a u32 draw, but {:?} was recorded", draw)
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self.next_recorded() {
+            RngDraw::U64(value) => *value,
+            draw => panic!("Replay diverged: expected a u64 draw, but {:?} was recorded", draw)
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self.next_recorded() {
+            RngDraw::Bytes(bytes) if bytes.len() == dest.len() => dest.copy_from_slice(bytes),
+            draw => panic!("Replay diverged: expected {} bytes, but {:?} was recorded", dest.len(), draw)
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Plays out the battle from `state` until it ends, reaches the turn limit or repeats a position under the
+/// `draw_on_repetition` rule, leaving `state` as the final position. Returns the agents' Nash equilibrium in the final
+/// position, or a payoff of 0 with no strategies for a repetition. The agents sample their actions with `decision_rng`.
+/// `observer` is called at each decision with the current state, the Nash equilibrium found for it and the indices
+/// sampled from the maximizer's and minimizer's strategies.
+fn play_out_battle<R: Rng, D: Rng, F: FnMut(&State, &ZeroSumNashEq, usize, usize)>(state: &mut Box<State>, depth: u8, mut observer: F, rng: &mut R, decision_rng: &mut D) -> ZeroSumNashEq {
+    // There's nothing to decide, so the search would only evaluate the final position anyway
+    if state.has_battle_ended() {
+        return ZeroSumNashEq {
+            max_player_strategy: Vec::new(),
+            min_player_strategy: Vec::new(),
+            expected_payoff: heuristic_value(state)
+        };
+    }
+
+    let max_turns = state.rules.max_turns;
+    let mut seen_positions = HashSet::new();
+    let mut nash_eq = smab_search(state, -1.0, 1.0, depth, rng);
+
+    while !state.max.actions.is_empty() && !state.min.actions.is_empty() && max_turns.is_none_or(|max_turns| state.turn_number() < max_turns) {
+        if state.rules.draw_on_repetition && !seen_positions.insert(state.snapshot()) {
+            if state.logs(LogLevel::Outcomes) { println!("  The battle is repeating itself! It's a draw."); }
+            return ZeroSumNashEq {
+                max_player_strategy: Vec::new(),
+                min_player_strategy: Vec::new(),
+                expected_payoff: 0.0
+            };
+        }
+        let maximizer_choice = choose_index(state.rules.max_policy, &nash_eq.max_player_strategy, decision_rng);
+        let minimizer_choice = choose_index(state.rules.min_policy, &nash_eq.min_player_strategy, decision_rng);
+        observer(state, &nash_eq, maximizer_choice, minimizer_choice);
+
+        let child = state.remove_child(maximizer_choice, minimizer_choice, rng);
+        *state = child;
+        if state.logs(LogLevel::Outcomes) { state.print_display_text(); }
+        nash_eq = smab_search(state, -1.0, 1.0, depth, rng);
+    }
+
+    nash_eq
+}
+
+/// Index of the action an agent following `policy` chooses, given its equilibrium strategy.
+fn choose_index<D: Rng>(policy: AgentPolicy, strategy: &[f64], decision_rng: &mut D) -> usize {
+    match policy {
+        AgentPolicy::Optimal => choose_weighted_index(strategy, decision_rng),
+        AgentPolicy::Random => decision_rng.gen_range(0, strategy.len())
+    }
+}
+
+fn initial_state(minimizer: &TeamBuild, maximizer: &TeamBuild, rules: &BattleRules) -> Box<State> {
+    if rules.min_lead >= minimizer.members.len() || rules.max_lead >= maximizer.members.len() {
+        panic!("Lead indices ({}, {}) are out of range for team sizes ({}, {})", rules.min_lead, rules.max_lead, minimizer.members.len(), maximizer.members.len());
+    }
+    if let Err(err) = rules.heuristic_weights.validate() {
+        panic!("Invalid battle rules: {}", err);
+    }
+
+    let mut state = Box::new(
+        State::new([
+                       team_member(minimizer, 0),
+                       team_member(minimizer, 1),
+                       team_member(minimizer, 2),
+                       team_member(minimizer, 3),
+                       team_member(minimizer, 4),
+                       team_member(minimizer, 5),
+                       team_member(maximizer, 0),
+                       team_member(maximizer, 1),
+                       team_member(maximizer, 2),
+                       team_member(maximizer, 3),
+                       team_member(maximizer, 4),
+                       team_member(maximizer, 5)
+                   ], Weather::default(), Terrain::default(), *rules));
+    if rules.pure_stats {
+        strip_abilities_and_items(&mut state);
+    }
+    // The field starts out empty, so the only legal actions are sending out the leads.
+    debug_assert!(state.max.actions.iter().chain(state.min.actions.iter()).all(|action| matches!(action, Action::Switch { user_id: None, .. })), "Initial actions must be send-outs.");
+    state
+}
+
+/// Teams with fewer than 6 members are padded with fainted Pokemon, which can never be sent out.
+fn team_member(team_build: &TeamBuild, i: usize) -> Pokemon {
+    match team_build.members.get(i) {
+        Some(pokemon_build) => Pokemon::from(pokemon_build),
+        None => {
+            let mut placeholder = Pokemon::from(&team_build.members[0]);
+            placeholder.current_hp = 0;
+            placeholder
+        }
+    }
+}
+
+/// Takes away every Pokemon's ability and item for the `pure_stats` rule.
+fn strip_abilities_and_items(state: &mut State) {
+    let no_ability = Ability::id_by_name("No Ability").unwrap();
+    for pokemon in state.pokemon.iter_mut() {
+        pokemon.ability = no_ability;
+        pokemon.item = None;
+    }
+}
+
+impl State {
+    /// A state with the attacker on the maximizer's side and the defender on the minimizer's, both already on the
+    /// field under the standard rules, for calling effects on in isolation. The attacker has ID 6 and the defender
+    /// ID 0; the other slots are fainted placeholders. No actions are generated, so the state can't be searched.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn single_matchup(attacker: PokemonBuild, defender: PokemonBuild) -> State {
+        State::matchup_on_field(&attacker, &defender)
+    }
+
+    /// The state behind `single_matchup`, for the analysis helpers that need to run effects outside of a battle.
+    pub(crate) fn matchup_on_field(attacker: &PokemonBuild, defender: &PokemonBuild) -> State {
+        let minimizer = TeamBuild { members: vec![defender.clone()] };
+        let maximizer = TeamBuild { members: vec![attacker.clone()] };
+        let mut state = initial_state(&minimizer, &maximizer, &BattleRules::default());
+        pokemon::add_to_field(&mut state, 0, FieldPosition::Min);
+        pokemon::add_to_field(&mut state, 6, FieldPosition::Max);
+        for agent in [&mut state.min, &mut state.max].iter_mut() {
+            agent.actions.clear();
+            agent.action_order.clear();
+        }
+        state.children.clear();
+        state.speed_tie_children.clear();
+        *state
+    }
+}
+
+/// A predetermined sequence of choices for the minimizer, useful for checking exact battle outcomes. Each entry
+/// is an index into the minimizer's available actions on that turn.
+pub struct ScriptedAgent<'a> {
+    pub team_build: &'a TeamBuild,
+    pub script: Vec<usize>
+}
+
+/// Run a battle where the minimizer follows a script and the maximizer uses game theory as in `run_battle`.
+/// Returns an error if a scripted action does not exist in the current position or if the script runs out before
+/// the battle ends.
+pub fn run_battle_vs_scripted<R: Rng>(minimizer: &ScriptedAgent, maximizer: &TeamBuild, rules: &BattleRules, rng: &mut R) -> Result<f64, String> {
+    run_scripted_battle(minimizer, maximizer, rules, |line| println!("{}", line), rng)
+}
+
+/// Same as `run_battle_vs_scripted`, except that the battle text is returned line by line instead of printed, so that
+/// a scripted line can be checked turn by turn.
+pub fn run_battle_vs_scripted_logged<R: Rng>(minimizer: &ScriptedAgent, maximizer: &TeamBuild, rules: &BattleRules, rng: &mut R) -> Result<(f64, Vec<String>), String> {
+    let mut log = Vec::new();
+    let payoff = run_scripted_battle(minimizer, maximizer, rules, |line| log.push(line), rng)?;
+    Ok((payoff, log))
+}
+
+/// `log` is given each line of battle text as it would be printed.
+fn run_scripted_battle<R: Rng, F: FnMut(String)>(minimizer: &ScriptedAgent, maximizer: &TeamBuild, rules: &BattleRules, mut log: F, rng: &mut R) -> Result<f64, String> {
+    let mut state = initial_state(minimizer.team_build, maximizer, rules);
+
+    if state.logs(LogLevel::Outcomes) {
+        log(String::from("<<<< BATTLE BEGIN >>>>"));
+        log_display_text(&state, &mut log);
+    }
+
+    let mut nash_eq = smab_search(&mut state, -1.0, 1.0, rules.search_depth, rng);
+    let mut script = minimizer.script.iter();
+
+    while !state.max.actions.is_empty() && !state.min.actions.is_empty() && rules.max_turns.is_none_or(|max_turns| state.turn_number() < max_turns) {
         let maximizer_choice = choose_weighted_index(&nash_eq.max_player_strategy, rng);
-        let minimizer_choice = choose_weighted_index(&nash_eq.min_player_strategy, rng);
+        let minimizer_choice = match script.next() {
+            Some(&choice) if choice < state.min.actions.len() => choice,
+            Some(&choice) => return Err(format!("scripted action {} is illegal; only {} actions are available", choice, state.min.actions.len())),
+            None => return Err(String::from("script ended before the battle did"))
+        };
 
         let child = state.remove_child(maximizer_choice, minimizer_choice, rng);
         state = child;
-        if cfg!(feature = "print-battle") { state.print_display_text(); }
-        nash_eq = smab_search(&mut state, -1.0, 1.0, AI_LEVEL, rng);
+        if state.logs(LogLevel::Outcomes) { log_display_text(&state, &mut log); }
+        nash_eq = smab_search(&mut state, -1.0, 1.0, rules.search_depth, rng);
     }
 
-    if cfg!(feature = "print-battle") {
-        println!("<<<< BATTLE END >>>>");
+    if state.logs(LogLevel::Outcomes) {
+        log(String::from("<<<< BATTLE END >>>>"));
+    }
+
+    Ok(nash_eq.expected_payoff)
+}
+
+/// Passes the state's display text to `log` one line at a time, indented the same as `State::print_display_text`.
+fn log_display_text<F: FnMut(String)>(state: &State, log: &mut F) {
+    for text in state.display_text() {
+        text.lines().for_each(|line| log(format!("  {}", line)));
+    }
+}
+
+/// Held by every unit test that uses game data; see `load_test_data`.
+#[cfg(test)]
+static TEST_DATA: Mutex<()> = Mutex::new(());
+
+/// Loads the X/Y game data that the unit tests are written against, once per test binary. The returned guard runs
+/// the tests that use game data one at a time, so that a test can switch `GAME_VERSION` without the others seeing
+/// it; keep it for the whole test, and put the X/Y version back before it's dropped.
+#[cfg(test)]
+pub(crate) fn load_test_data() -> MutexGuard<'static, ()> {
+    static LOAD: Once = Once::new();
+    let guard = TEST_DATA.lock().unwrap_or_else(PoisonError::into_inner);
+    LOAD.call_once(|| {
+        unsafe { GAME_VERSION = GameVersion::XY; }
+        move_::initialize_moves().unwrap();
+        species::initialize_species().unwrap();
+    });
+    guard
+}
+
+/// A team of `size` default Bulbasaur. Needs the guard from `load_test_data`.
+#[cfg(test)]
+pub(crate) fn test_team(size: usize) -> TeamBuild {
+    let bulbasaur = PokemonBuildBuilder::new(Species::id_by_name("Bulbasaur").unwrap()).build().unwrap();
+    TeamBuild { members: vec![bulbasaur; size] }
+}
+
+/// Two default Bulbasaur facing each other in a `State::single_matchup`, with the attacker as ID 6 and the defender as
+/// ID 0. Needs the guard from `load_test_data`.
+#[cfg(test)]
+pub(crate) fn test_matchup() -> State {
+    let bulbasaur = || test_team(1).members.remove(0);
+    State::single_matchup(bulbasaur(), bulbasaur())
+}
+
+/// A battle between the given teams with their leads, IDs 0 and 6, on the field and no actions generated yet. Needs
+/// the guard from `load_test_data`.
+#[cfg(test)]
+pub(crate) fn test_battle(minimizer: &TeamBuild, maximizer: &TeamBuild) -> Box<State> {
+    let mut state = initial_state(minimizer, maximizer, &BattleRules::default());
+    pokemon::add_to_field(&mut state, 0, FieldPosition::Min);
+    pokemon::add_to_field(&mut state, 6, FieldPosition::Max);
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic;
+
+    use super::*;
+
+    /// A one-Pokemon team of Bulbasaur that only knows the given moves, whether or not it can learn them.
+    fn bulbasaur_team(moves: &[&str]) -> TeamBuild {
+        let mut team = test_team(1);
+        team.members[0].moves = moves.iter().map(|name| Move::id_by_name(name).unwrap()).collect();
+        team
+    }
+
+    fn repetition_rules() -> BattleRules {
+        BattleRules {
+            search_depth: 1,
+            max_turns: Some(50),
+            draw_on_repetition: true,
+            ..BattleRules::default()
+        }
+    }
+
+    #[test]
+    fn recover_stall_is_a_draw_by_repetition() {
+        let _data = load_test_data();
+        let team = bulbasaur_team(&["Recover"]);
+        let mut last_turn = 0;
+        let payoff = run_battle_observed(&team, &team, &repetition_rules(), |state, _| last_turn = state.turn_number(), &mut StdRng::seed_from_u64(0));
+        assert_eq!(payoff, 0.0);
+        // Well before the turn limit, and before Recover runs out of PP
+        assert!(last_turn < 5, "repetition found on turn {}", last_turn);
+    }
+
+    #[test]
+    fn battle_that_makes_progress_is_not_a_draw_by_repetition() {
+        let _data = load_test_data();
+        let team = bulbasaur_team(&["Tackle"]);
+        let outcome = run_battle_outcome(&team, &team, &repetition_rules(), &mut StdRng::seed_from_u64(0));
+        assert_ne!(outcome, BattleOutcome::Draw);
+    }
+
+    #[test]
+    fn mirror_matchup_interval_straddles_zero() {
+        let _data = load_test_data();
+        let team = bulbasaur_team(&["Tackle"]);
+        let stats = evaluate_matchup_batch(&team, &team, 20, [0; 32]);
+        let (low, high) = stats.confidence_interval;
+        assert!(low < 0.0 && 0.0 < high, "{:?}", stats);
+    }
+
+    #[test]
+    fn dominant_matchup_interval_is_positive() {
+        let _data = load_test_data();
+        let stats = evaluate_matchup_batch(&bulbasaur_team(&["Tackle"]), &bulbasaur_team(&["Growl"]), 20, [0; 32]);
+        assert!(stats.confidence_interval.0 > 0.0, "{:?}", stats);
+        assert_eq!(stats.wins, 20);
+    }
+
+    #[test]
+    fn head_to_head_scores_team_a_on_either_side() {
+        let _data = load_test_data();
+        let tackle = bulbasaur_team(&["Tackle"]);
+        let growl = bulbasaur_team(&["Growl"]);
+        for side in [Side::Min, Side::Max] {
+            let results = head_to_head(&tackle, side, &growl, 5, [0; 32]);
+            assert_eq!(results.wins, 5, "{:?}", side);
+            assert!(results.mean_payoff > 0.0, "{:?}", side);
+        }
+    }
+
+    #[test]
+    fn paralysis_cuts_speed_by_the_active_version_rules() {
+        let _data = load_test_data();
+        let speed_ratio = || {
+            let mut state = test_matchup();
+            let speed = pokemon::effective_speed(&state, 6);
+            pokemon::paralyze(&mut state, 6);
+            speed as f64 / pokemon::effective_speed(&state, 6) as f64
+        };
+
+        let mut usum = GameData::load(GameVersion::USUM).unwrap();
+        let usum_ratio = usum.with_active(speed_ratio);
+        assert_eq!(*game_version(), GameVersion::XY);
+        assert!((speed_ratio() - 4.0).abs() < 0.1);
+        assert!((usum_ratio - 2.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn active_game_data_is_restored_after_a_panic() {
+        let _data = load_test_data();
+        let mut usum = GameData::load(GameVersion::USUM).unwrap();
+
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| usum.with_active(|| panic!("mid-battle panic"))));
+        assert!(result.is_err());
+        assert_eq!(*game_version(), GameVersion::XY);
+        assert_eq!(*usum.version(), GameVersion::USUM);
+    }
+
+    #[test]
+    fn battle_runs_with_a_small_rng() {
+        let _data = load_test_data();
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(0);
+        let payoff = run_battle(&bulbasaur_team(&["Tackle", "Growl"]), &bulbasaur_team(&["Giga Drain"]), &BattleRules::default(), &mut rng);
+        assert!((-1.0..=1.0).contains(&payoff), "{}", payoff);
+    }
+
+    #[test]
+    fn scripted_growls_lose_to_tackles() {
+        let _data = load_test_data();
+        let growler = bulbasaur_team(&["Growl"]);
+        let minimizer = ScriptedAgent { team_build: &growler, script: vec![0; 100] };
+        let rules = BattleRules { search_depth: 1, ..BattleRules::default() };
+
+        let payoff = run_battle_vs_scripted(&minimizer, &bulbasaur_team(&["Tackle"]), &rules, &mut StdRng::seed_from_u64(0));
+        // Growl does no damage, so the maximizer wins with its only Pokemon at full HP
+        assert_eq!(payoff, Ok(1.0 / 6.0));
+    }
+
+    #[test]
+    fn scripted_ko_line_is_logged_turn_by_turn() {
+        let _data = load_test_data();
+        let growler = bulbasaur_team(&["Growl"]);
+        let minimizer = ScriptedAgent { team_build: &growler, script: vec![0; 100] };
+        let rules = BattleRules { search_depth: 1, log_level: LogLevel::Turns, ..BattleRules::default() };
+
+        let (payoff, log) = run_battle_vs_scripted_logged(&minimizer, &bulbasaur_team(&["Tackle"]), &rules, &mut StdRng::seed_from_u64(0)).unwrap();
+        assert_eq!(payoff, 1.0 / 6.0);
+        assert_eq!(log.first().map(String::as_str), Some("<<<< BATTLE BEGIN >>>>"));
+        assert_eq!(log.last().map(String::as_str), Some("<<<< BATTLE END >>>>"));
+        // The minimizer only ever Growls and the maximizer only ever Tackles, without taking any damage
+        let moves_used: Vec<&String> = log.iter().filter(|line| line.contains(" used ")).collect();
+        assert!(moves_used.len() >= 2, "{:#?}", log);
+        assert!(moves_used.iter().all(|line| line.contains("(min)") == line.ends_with("used Growl on:")), "{:#?}", log);
+        assert!(log.iter().filter(|line| line.contains("(max)")).all(|line| line.contains("(231/231)")), "{:#?}", log);
+        let faints: Vec<&String> = log.iter().filter(|line| line.ends_with("fainted!")).collect();
+        assert_eq!(faints.len(), 1, "{:#?}", log);
+        assert!(faints[0].contains("(min)(0/231)"), "{:#?}", log);
+    }
+
+    #[test]
+    fn scripted_action_out_of_range_is_an_error() {
+        let _data = load_test_data();
+        let growler = bulbasaur_team(&["Growl"]);
+        let minimizer = ScriptedAgent { team_build: &growler, script: vec![0, 1] };
+        let rules = BattleRules { search_depth: 1, ..BattleRules::default() };
+
+        let error = run_battle_vs_scripted(&minimizer, &bulbasaur_team(&["Tackle"]), &rules, &mut StdRng::seed_from_u64(0)).unwrap_err();
+        assert!(error.contains("illegal"), "{}", error);
+    }
+
+    #[test]
+    fn turn_discount_pays_more_for_a_faster_win() {
+        let _data = load_test_data();
+        let growler = bulbasaur_team(&["Growl"]);
+        let tackler = bulbasaur_team(&["Tackle"]);
+        let mut banded_tackler = tackler.clone();
+        banded_tackler.members[0].item = Some(Item::id_by_name("Choice Band").unwrap());
+        let rules = BattleRules { search_depth: 1, turn_discount: 0.9, ..BattleRules::default() };
+        let discounted_win = |maximizer: &TeamBuild| {
+            let mut turns = 0;
+            let payoff = run_battle_observed(&growler, maximizer, &rules, |state, _| turns = state.turn_number(), &mut StdRng::seed_from_u64(0));
+            (payoff, turns)
+        };
+
+        // Both win at full HP, so only the number of turns differs
+        let (payoff, turns) = discounted_win(&tackler);
+        let (banded_payoff, banded_turns) = discounted_win(&banded_tackler);
+        assert!(banded_turns < turns, "{} vs {} turns", banded_turns, turns);
+        assert!(banded_payoff > payoff && payoff > 0.0, "{} vs {}", banded_payoff, payoff);
+    }
+
+    #[test]
+    fn fainted_pokemon_are_replaced_with_switching_disabled() {
+        let _data = load_test_data();
+        let (mut growlers, mut tacklers) = (bulbasaur_team(&["Growl"]), bulbasaur_team(&["Tackle"]));
+        growlers.members.push(growlers.members[0].clone());
+        tacklers.members.push(tacklers.members[0].clone());
+        let rules = BattleRules { search_depth: 1, allow_switching: false, ..BattleRules::default() };
+
+        let outcome = run_battle_outcome(&growlers, &tacklers, &rules, &mut StdRng::seed_from_u64(0));
+        assert_eq!(outcome, BattleOutcome::MaximizerWin);
+    }
+
+    #[test]
+    fn default_rules_reproduce_the_recorded_payoff() {
+        let _data = load_test_data();
+        let (growler, tackler) = (bulbasaur_team(&["Growl", "Tackle"]), bulbasaur_team(&["Tackle"]));
+
+        // Recorded with this seed; a change means the defaults no longer play battles the same way
+        let payoff = run_battle(&growler, &tackler, &BattleRules::default(), &mut StdRng::seed_from_u64(0));
+        assert_eq!(payoff, -0.07056277056277055);
+    }
+
+    #[test]
+    fn observer_sees_each_decision_once() {
+        let _data = load_test_data();
+        let team = bulbasaur_team(&["Tackle", "Growl"]);
+        let rules = BattleRules { search_depth: 1, ..BattleRules::default() };
+        let mut turns_seen = Vec::new();
+
+        run_battle_observed(&team, &team, &rules, |state, nash_eq| {
+            turns_seen.push(state.turn_number());
+            for strategy in [&nash_eq.max_player_strategy, &nash_eq.min_player_strategy] {
+                assert!((strategy.iter().sum::<f64>() - 1.0).abs() < 1e-9, "{:?}", strategy);
+            }
+        }, &mut StdRng::seed_from_u64(0));
+        assert!(turns_seen.len() > 1);
+        assert_eq!(turns_seen, (0..turns_seen.len() as u16).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn expectimax_payoffs_vary_less_than_sampled_ones() {
+        let _data = load_test_data();
+        let slower = bulbasaur_team(&["Tackle"]);
+        // A faster maximizer rules out speed ties, which are still sampled
+        let mut faster = bulbasaur_team(&["Tackle"]);
+        faster.members[0].evs[5] = 252;
+        let rules = BattleRules { search_depth: 1, ..BattleRules::default() };
+        let variance = |run: fn(&TeamBuild, &TeamBuild, &BattleRules, &mut StdRng) -> f64| {
+            let payoffs: Vec<f64> = (0..40).map(|seed| run(&slower, &faster, &rules, &mut StdRng::seed_from_u64(seed))).collect();
+            let mean = payoffs.iter().sum::<f64>() / payoffs.len() as f64;
+            payoffs.iter().map(|payoff| (payoff - mean).powi(2)).sum::<f64>() / payoffs.len() as f64
+        };
+
+        let sampled = variance(run_battle);
+        let expected = variance(run_battle_expectimax);
+        assert!(expected < sampled, "expectimax variance {} is not below sampled variance {}", expected, sampled);
+    }
+
+    #[test]
+    fn chosen_lead_is_sent_out_first() {
+        let _data = load_test_data();
+        let rules = BattleRules { max_lead: 2, ..BattleRules::default() };
+        let mut state = initial_state(&test_team(1), &test_team(3), &rules);
+
+        let send_out = state.max.actions[0].clone();
+        assert_eq!(state.max.actions.len(), 1);
+        send_out.perform(&mut state, &[], &mut StdRng::seed_from_u64(0));
+        assert_eq!(state.pokemon_by_id(8).field_position(), Some(FieldPosition::Max));
+        assert_eq!(state.pokemon_by_id(6).field_position(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn lead_outside_the_team_is_rejected() {
+        let _data = load_test_data();
+        let rules = BattleRules { max_lead: 3, ..BattleRules::default() };
+        initial_state(&test_team(1), &test_team(3), &rules);
+    }
+
+    #[test]
+    fn root_actions_are_only_the_send_outs() {
+        let _data = load_test_data();
+        let state = initial_state(&test_team(3), &test_team(2), &BattleRules::default());
+
+        assert_eq!(state.min.actions, vec![Action::Switch { user_id: None, switching_in_id: 0, target_position: FieldPosition::Min }]);
+        assert_eq!(state.max.actions, vec![Action::Switch { user_id: None, switching_in_id: 6, target_position: FieldPosition::Max }]);
+    }
+
+    #[test]
+    fn a_foe_at_one_hp_falls_in_a_turn() {
+        let _data = load_test_data();
+        let team = bulbasaur_team(&["Tackle"]);
+        let mut state = test_battle(&team, &team);
+        pokemon::set_hp(&mut state, 0, 1);
+
+        let turns = expected_turns(&state, 1, 10, &mut StdRng::seed_from_u64(0));
+        assert_eq!(turns, 1.0);
     }
 
-    nash_eq.expected_payoff
+    #[test]
+    fn identical_teams_come_out_about_even() {
+        let _data = load_test_data();
+        let team = bulbasaur_team(&["Tackle"]);
+        // Gender makes no difference to a Tackle-only battle, but it keeps the teams from being skipped as a mirror match
+        let mut other_gender = team.clone();
+        other_gender.members[0].gender = if team.members[0].gender == Gender::Male { Gender::Female } else { Gender::Male };
+        assert!(team != other_gender);
+
+        let results = head_to_head(&team, Side::Max, &other_gender, 20, [0; 32]);
+        assert_eq!(results.wins + results.losses + results.draws, 20);
+        assert!(results.wins > 0 && results.losses > 0, "{:?}", results);
+        assert!(results.mean_payoff.abs() < 0.05, "{:?}", results);
+    }
+
+    #[test]
+    fn chance_policies_bracket_the_sampled_payoff() {
+        let _data = load_test_data();
+        let slower = bulbasaur_team(&["Tackle"]);
+        let mut faster = bulbasaur_team(&["Tackle"]);
+        faster.members[0].evs[5] = 252;
+        let mean_payoff = |chance_policy| {
+            let rules = BattleRules { search_depth: 1, chance_policy, ..BattleRules::default() };
+            let payoffs: Vec<f64> = (0..20).map(|seed| run_battle(&slower, &faster, &rules, &mut StdRng::seed_from_u64(seed))).collect();
+            assert!(payoffs.iter().all(|payoff| (-1.0..=1.0).contains(payoff)), "{:?}: {:?}", chance_policy, payoffs);
+            payoffs.iter().sum::<f64>() / payoffs.len() as f64
+        };
+
+        let (pessimistic, sampled, optimistic) = (mean_payoff(ChancePolicy::Pessimistic), mean_payoff(ChancePolicy::SampleOnce), mean_payoff(ChancePolicy::Optimistic));
+        assert!(pessimistic <= sampled && sampled <= optimistic, "{} {} {}", pessimistic, sampled, optimistic);
+        assert!(pessimistic < optimistic);
+        for chance_policy in [ChancePolicy::Expectation, ChancePolicy::MinimumDamage, ChancePolicy::AllCriticalHits] {
+            mean_payoff(chance_policy);
+        }
+    }
+
+    #[test]
+    fn dominant_team_is_assessed_as_ahead() {
+        let _data = load_test_data();
+        let (tackler, growler) = (bulbasaur_team(&["Tackle"]), bulbasaur_team(&["Growl"]));
+
+        assert!(initial_assessment(&growler, &tackler, 2, &mut StdRng::seed_from_u64(0)) > 0.0);
+        assert!(initial_assessment(&tackler, &growler, 2, &mut StdRng::seed_from_u64(0)) < 0.0);
+    }
+
+    #[test]
+    fn battle_without_chance_draws_reports_none() {
+        let _data = load_test_data();
+        let slower = bulbasaur_team(&["Tackle"]);
+        let mut faster = bulbasaur_team(&["Tackle"]);
+        faster.members[0].evs[5] = 252;
+        let draws = |chance_policy| {
+            let rules = BattleRules { search_depth: 1, chance_policy, ..BattleRules::default() };
+            run_battle_trace(&slower, &faster, &rules, &mut StdRng::seed_from_u64(0)).1
+        };
+
+        let sampled = draws(ChancePolicy::SampleOnce);
+        assert!(sampled.critical_hits > 0 && sampled.damage_rolls > 0, "{:?}", sampled);
+        // Minimum damage never rolls for critical hits or damage, and the maximizer always moves first
+        let fixed = draws(ChancePolicy::MinimumDamage);
+        assert_eq!((fixed.critical_hits, fixed.damage_rolls, fixed.speed_ties), (0, 0, 0), "{:?}", fixed);
+    }
+
+    #[test]
+    fn replaying_a_recorded_battle_reproduces_it() {
+        let _data = load_test_data();
+        let team = test_team(2);
+        let rules = BattleRules { search_depth: 1, ..BattleRules::default() };
+        let mut recording = RecordingRng::new(StdRng::seed_from_u64(0));
+        let (payoff, decisions) = run_battle_full_trace(&team, &team, &rules, &mut recording);
+
+        let mut replay = ReplayRng::new(recording.into_draws());
+        let (replayed_payoff, replayed_decisions) = run_battle_full_trace(&team, &team, &rules, &mut replay);
+        assert!(replay.is_finished());
+        assert_eq!(replayed_payoff, payoff);
+        assert_eq!(format!("{:?}", replayed_decisions), format!("{:?}", decisions));
+    }
+
+    #[test]
+    fn decision_seed_leaves_forced_battles_untouched() {
+        let _data = load_test_data();
+        let team = bulbasaur_team(&["Tackle"]);
+        let rules = BattleRules { search_depth: 1, ..BattleRules::default() };
+        // With one action each, the decisions are forced, so only the effect draws can shape the battle
+        let battle = |decision_seed| {
+            let mut rng = RecordingRng::new(StdRng::seed_from_u64(0));
+            let payoff = run_battle_with_decision_rng(&team, &team, &rules, &mut rng, &mut StdRng::seed_from_u64(decision_seed));
+            (payoff, rng.into_draws())
+        };
+
+        let (payoff, draws) = battle(0);
+        for decision_seed in 1..5 {
+            assert!(battle(decision_seed) == (payoff, draws.clone()), "decision seed {}", decision_seed);
+        }
+    }
+
+    #[test]
+    fn dominant_team_tops_the_round_robin() {
+        let _data = load_test_data();
+        let mut dominant = bulbasaur_team(&["Tackle"]);
+        dominant.members[0].evs = [252, 252, 0, 0, 0, 4];
+        let teams = [bulbasaur_team(&["Tackle"]), bulbasaur_team(&["Tackle", "Growl"]), dominant];
+
+        let ranking = round_robin(&teams, 1, [0; 32]);
+        assert_eq!(ranking.len(), 3);
+        assert_eq!(ranking[0].0, 2, "{:?}", ranking);
+        assert!(ranking.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+    }
+
+    #[test]
+    fn passed_deadline_still_gives_a_one_turn_result() {
+        let _data = load_test_data();
+        let mut state = test_battle(&test_team(3), &test_team(3));
+        generate_actions(&mut state);
+        let one_turn = smab_search(&mut state.clone(), -1.0, 1.0, 1, &mut StdRng::seed_from_u64(0));
+
+        let start = Instant::now();
+        let nash_eq = search_with_deadline(&mut state, 10, start, &mut StdRng::seed_from_u64(0));
+        assert!(start.elapsed().as_secs_f64() < 1.0);
+        assert_eq!(nash_eq.max_player_strategy.len(), state.max.actions.len());
+        assert_eq!(nash_eq.min_player_strategy.len(), state.min.actions.len());
+        assert!((nash_eq.max_player_strategy.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert_eq!(nash_eq.expected_payoff, one_turn.expected_payoff);
+    }
+
+    #[test]
+    fn seed_for_is_deterministic_and_spreads_consecutive_indices() {
+        let base = [7; 32];
+        assert_eq!(seed_for(base, 3), seed_for(base, 3));
+
+        let seeds: Vec<[u8; 32]> = (0..1000).map(|index| seed_for(base, index)).collect();
+        assert_eq!(seeds.iter().collect::<HashSet<_>>().len(), seeds.len());
+        let ones = |seed: &[u8; 32]| seed.iter().map(|byte| byte.count_ones()).sum::<u32>();
+        let mean_ones = seeds.iter().map(ones).sum::<u32>() as f64 / seeds.len() as f64;
+        assert!((124.0..132.0).contains(&mean_ones), "{}", mean_ones);
+        // Neighbours share no more bits than unrelated seeds would
+        let mean_distance = seeds.windows(2)
+            .map(|pair| pair[0].iter().zip(pair[1].iter()).map(|(a, b)| (a ^ b).count_ones()).sum::<u32>())
+            .sum::<u32>() as f64 / (seeds.len() - 1) as f64;
+        assert!((124.0..132.0).contains(&mean_distance), "{}", mean_distance);
+    }
+
+    #[test]
+    fn finished_battle_is_scored_without_a_search() {
+        let _data = load_test_data();
+        let team = test_team(6);
+        let mut state = test_battle(&team, &team);
+        for pokemon_id in 0..6 {
+            pokemon::set_hp(&mut state, pokemon_id, 0);
+        }
+
+        let mut decisions = 0;
+        let nash_eq = play_out_battle(&mut state, 1, |_, _, _, _| decisions += 1, &mut StdRng::seed_from_u64(0), &mut StdRng::seed_from_u64(0));
+        assert_eq!(decisions, 0);
+        assert!(nash_eq.max_player_strategy.is_empty() && nash_eq.min_player_strategy.is_empty());
+        assert_eq!(nash_eq.expected_payoff, 1.0);
+    }
+
+    #[test]
+    fn full_trace_records_one_legal_decision_per_turn() {
+        let _data = load_test_data();
+        let team = test_team(2);
+        let rules = BattleRules { search_depth: 1, ..BattleRules::default() };
+        let (_, records) = run_battle_full_trace(&team, &team, &rules, &mut StdRng::seed_from_u64(0));
+        // The same seed plays the same battle, so its decision points can be looked at alongside the trace
+        let mut decision_states = Vec::new();
+        run_battle_observed(&team, &team, &rules, |state, _| decision_states.push(state.clone()), &mut StdRng::seed_from_u64(0));
+
+        assert_eq!(records.len(), decision_states.len());
+        for (record, state) in records.iter().zip(&decision_states) {
+            assert_eq!(record.turn, state.turn_number());
+            assert!(state.is_legal(Side::Max, &record.max_action), "{:?}", record);
+            assert!(state.is_legal(Side::Min, &record.min_action), "{:?}", record);
+        }
+    }
+
+    #[test]
+    fn pure_stats_takes_away_chlorophyll_and_the_weather() {
+        let _data = load_test_data();
+        let team = bulbasaur_team(&["Sunny Day", "Tackle"]);
+        let rules = BattleRules { search_depth: 1, pure_stats: true, ..BattleRules::default() };
+        let mut state = initial_state(&team, &team, &rules);
+        assert!(state.pokemon.iter().all(|pokemon| pokemon.ability() == Ability::id_by_name("No Ability").unwrap() && pokemon.item().is_none()));
+
+        pokemon::add_to_field(&mut state, 0, FieldPosition::Min);
+        pokemon::add_to_field(&mut state, 6, FieldPosition::Max);
+        let sunny_day = Action::Move { user_id: 6, move_: Move::id_by_name("Sunny Day").unwrap(), move_index: None, target_positions: vec![FieldPosition::Max] };
+        sunny_day.perform(&mut state, &[], &mut StdRng::seed_from_u64(0));
+        assert_eq!(state.weather, Weather::default());
+
+        let payoff = run_battle(&team, &team, &rules, &mut StdRng::seed_from_u64(0));
+        assert!((-1.0..=1.0).contains(&payoff));
+    }
+
+    #[test]
+    fn depths_agree_when_one_move_is_best_at_both() {
+        let _data = load_test_data();
+        let state = test_battle(&bulbasaur_team(&["Growl"]), &bulbasaur_team(&["Tackle", "Growl"]));
+        let agreement = depth_agreement(&state, 1, 2, &mut StdRng::seed_from_u64(0));
+        assert!(almost::equal(agreement, 1.0), "agreement {}", agreement);
+    }
+
+    #[test]
+    fn depths_diverge_on_a_move_that_only_pays_off_later() {
+        let _data = load_test_data();
+        // Fly does nothing the turn it is used but hits much harder than Tackle the turn after
+        let state = test_battle(&bulbasaur_team(&["Growl"]), &bulbasaur_team(&["Tackle", "Fly"]));
+        let agreement = depth_agreement(&state, 1, 2, &mut StdRng::seed_from_u64(0));
+        assert!(agreement < 0.5, "agreement {}", agreement);
+    }
+
+    #[test]
+    fn random_opponent_does_worse_than_an_optimal_one() {
+        let _data = load_test_data();
+        let team = bulbasaur_team(&["Tackle", "Growl"]);
+        let mean_payoff = |min_policy| {
+            let rules = BattleRules { search_depth: 1, min_policy, ..BattleRules::default() };
+            (0..10).map(|seed| run_battle(&team, &team, &rules, &mut StdRng::seed_from_u64(seed))).sum::<f64>() / 10.0
+        };
+
+        assert!(mean_payoff(AgentPolicy::Random) > mean_payoff(AgentPolicy::Optimal));
+    }
+
+    #[test]
+    fn both_teams_down_ends_the_battle_in_a_draw() {
+        let _data = load_test_data();
+        let team = test_team(1);
+        let mut state = test_battle(&team, &team);
+        pokemon::set_hp(&mut state, 0, 0);
+        pokemon::set_hp(&mut state, 6, 0);
+
+        let nash_eq = play_out_battle(&mut state, 1, |_, _, _, _| {}, &mut StdRng::seed_from_u64(0), &mut StdRng::seed_from_u64(0));
+        assert_eq!(nash_eq.expected_payoff, 0.0);
+        assert_eq!(BattleOutcome::of(&state), BattleOutcome::Draw);
+    }
+
+    #[test]
+    fn mirror_match_is_all_draws_without_a_battle() {
+        let _data = load_test_data();
+        let team = bulbasaur_team(&["Tackle"]);
+
+        let copies_before = NUM_STATE_COPIES.load(atomic::Ordering::Relaxed);
+        let results = head_to_head(&team, Side::Min, &team.clone(), 20, [0; 32]);
+        assert_eq!(NUM_STATE_COPIES.load(atomic::Ordering::Relaxed), copies_before);
+        assert_eq!((results.wins, results.losses, results.draws), (0, 0, 20));
+        assert_eq!(results.mean_payoff, 0.0);
+        assert_eq!(results.payoff_variance, 0.0);
+    }
 }